@@ -1,8 +1,42 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_build::configure()
         .protoc_arg("--experimental_allow_proto3_optional")
-        .build_client(false)
+        // the grpc client is only generated if the client feature is enabled
+        .build_client(std::env::var_os("CARGO_FEATURE_CLIENT").is_some())
+        // the grpc server is only generated if the grpc server feature is enabled
+        .build_server(std::env::var_os("CARGO_FEATURE_GRPC_SERVER").is_some())
         .type_attribute(".", "#[derive(serde::Serialize,serde::Deserialize)]")
+        // the image bytes are shared with the cache entries instead of being copied
+        .bytes([
+            "SkinResponse.bytes",
+            "CapeResponse.bytes",
+            "HeadResponse.bytes",
+            "TextureResponse.bytes",
+        ])
+        .field_attribute("ProfileRequest.fields", "#[serde(default)]")
+        .field_attribute("HeadRequest.rgba", "#[serde(default)]")
+        .field_attribute("HeadRequest.scale", "#[serde(default)]")
+        .field_attribute("UuidsRequest.include_not_found", "#[serde(default)]")
+        .field_attribute("UuidsRequest.preserve_case", "#[serde(default)]")
+        .field_attribute("UuidsRequest.include_status", "#[serde(default)]")
+        .field_attribute("UuidsRequest.exclude_sanctioned", "#[serde(default)]")
+        // the statuses are only returned if requested
+        .field_attribute(
+            "UuidsResponse.statuses",
+            "#[serde(default, skip_serializing_if = \"::std::collections::HashMap::is_empty\")]",
+        )
+        .field_attribute("SkinRequest.url_only", "#[serde(default)]")
+        .field_attribute("SkinRequest.force_default", "#[serde(default)]")
+        .field_attribute("HeadRequest.force_default", "#[serde(default)]")
+        .field_attribute("ColorsRequest.head", "#[serde(default)]")
+        .field_attribute("ColorsRequest.count", "#[serde(default)]")
+        .field_attribute("CapeRequest.url_only", "#[serde(default)]")
+        .field_attribute("CapeRequest.animated", "#[serde(default)]")
+        .field_attribute("CapeRequest.frames", "#[serde(default)]")
+        // the binary uuids are only used by grpc, the rest gateway uses the uuids in string form
+        .field_attribute("uuid_bin", "#[serde(default)]")
+        .field_attribute("UuidResponse.uuid_bin", "#[serde(skip_serializing)]")
+        .field_attribute("ProfileResponse.uuid_bin", "#[serde(skip_serializing)]")
         .compile_protos(&["proto/profile.proto"], &["proto"])?;
     Ok(())
 }
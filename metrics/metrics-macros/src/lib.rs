@@ -2,18 +2,73 @@ use darling::ast::NestedMeta;
 use darling::util::IdentString;
 use darling::{Error, FromMeta};
 use proc_macro::TokenStream;
-use quote::quote;
-use std::collections::HashMap;
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{Expr, LitStr, Meta, Token};
 
 #[derive(Debug, FromMeta)]
 struct MetricsMacroArgs {
     #[darling(default)]
     metric: String,
     #[darling(default)]
-    labels: Option<HashMap<String, String>>,
+    labels: Option<Labels>,
     handler: IdentString,
 }
 
+/// A label value is either a static string (`request_type = "uuid"`) or an expression that is
+/// evaluated on every call (`request_type = %request_type`). Expressions have to implement
+/// [ToString](std::string::ToString).
+#[derive(Debug)]
+enum LabelValue {
+    Static(LitStr),
+    Dynamic(Expr),
+}
+
+#[derive(Debug)]
+struct Label {
+    key: syn::Ident,
+    value: LabelValue,
+}
+
+impl Parse for Label {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let key = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let value = match input.peek(Token![%]) {
+            true => {
+                input.parse::<Token![%]>()?;
+                LabelValue::Dynamic(input.parse()?)
+            }
+            false => LabelValue::Static(input.parse()?),
+        };
+        Ok(Self { key, value })
+    }
+}
+
+#[derive(Debug)]
+struct Labels(Vec<Label>);
+
+impl FromMeta for Labels {
+    fn from_meta(item: &Meta) -> darling::Result<Self> {
+        let list = item.require_list()?;
+        let labels = list
+            .parse_args_with(Punctuated::<Label, Token![,]>::parse_terminated)
+            .map_err(Error::from)?;
+        Ok(Self(labels.into_iter().collect()))
+    }
+}
+
+/// Emits a `MetricsEvent` to the handler after every call of the annotated function.
+///
+/// ```rs
+/// #[metrics::metrics(
+///     metric = "cache_get",
+///     labels(cache_variant = "redis", request_type = %request_type),
+///     handler = metrics_get_handler
+/// )]
+/// async fn get(&self, request_type: &str, key: String) -> Option<Entry<D>> { ... }
+/// ```
 #[proc_macro_attribute]
 pub fn metrics(args: TokenStream, input: TokenStream) -> TokenStream {
     metrics_impl(args.into(), input.into()).into()
@@ -47,7 +102,7 @@ fn metrics_impl(
     let fn_vis = &input_fn.vis;
     let fn_block = &input_fn.block;
     let metric = args.metric;
-    let labels = args.labels.unwrap_or_else(HashMap::new);
+    let labels = args.labels.map(|labels| labels.0).unwrap_or_default();
     let handler = args.handler;
 
     let inner_fn = match fn_head.asyncness {
@@ -59,10 +114,27 @@ fn metrics_impl(
         },
     };
 
-    let label_keys = labels.keys();
-    let label_values = labels.values();
+    // dynamic label values are evaluated before the function body takes ownership of the arguments
+    let mut label_bindings = vec![];
+    let mut label_keys = vec![];
+    let mut label_values = vec![];
+    for (i, label) in labels.into_iter().enumerate() {
+        label_keys.push(label.key.to_string());
+        match label.value {
+            LabelValue::Static(value) => label_values.push(quote! { #value }),
+            LabelValue::Dynamic(expr) => {
+                let binding = format_ident!("__metrics_label_{}", i);
+                label_bindings.push(quote! {
+                    let #binding = ::std::string::ToString::to_string(&(#expr));
+                });
+                label_values.push(quote! { #binding.as_str() });
+            }
+        }
+    }
+
     let result = quote! {
         #fn_vis #fn_head {
+            #(#label_bindings)*
             let start = ::std::time::Instant::now();
             let result = #inner_fn;
 
@@ -80,3 +152,51 @@ fn metrics_impl(
 
     result
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dynamic_label_bound_before_body() {
+        // given
+        let args = quote! { metric = "test", labels(request_type = %key.len()), handler = handle };
+        let input = quote! { fn test(key: String) -> String { key } };
+
+        // when
+        let output = metrics_impl(args, input).to_string();
+
+        // then
+        let binding = output.find("let __metrics_label_0").unwrap();
+        let body = output.find("{ key }").unwrap();
+        assert!(binding < body);
+        assert!(output.contains("(\"request_type\" , __metrics_label_0 . as_str ())"));
+    }
+
+    #[test]
+    fn missing_handler() {
+        // given
+        let args = quote! { metric = "test" };
+        let input = quote! { fn test() {} };
+
+        // when
+        let output = metrics_impl(args, input).to_string();
+
+        // then
+        assert!(output.contains("compile_error"));
+        assert!(output.contains("handler"));
+    }
+
+    #[test]
+    fn invalid_label() {
+        // given
+        let args = quote! { metric = "test", labels(request_type = 1), handler = handle };
+        let input = quote! { fn test() {} };
+
+        // when
+        let output = metrics_impl(args, input).to_string();
+
+        // then
+        assert!(output.contains("compile_error"));
+    }
+}
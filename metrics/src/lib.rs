@@ -3,10 +3,147 @@ use std::fmt::Debug;
 pub use metrics_macros::metrics;
 pub use std::collections::HashMap;
 
+/// A [MetricsEvent] is emitted by functions annotated with the [metrics] macro after every call. It
+/// is passed to the configured handler, which records it (e.g. as prometheus histogram).
 #[derive(Debug)]
 pub struct MetricsEvent<'a, T> {
+    /// The metric name of the annotated function.
     pub metric: &'static str,
-    pub labels: HashMap<&'static str, &'static str>,
+
+    /// The labels of the call. Dynamic labels are evaluated on every call.
+    pub labels: HashMap<&'static str, &'a str>,
+
+    /// The duration of the call in seconds.
     pub time: f64,
+
+    /// The result of the call.
     pub result: &'a T,
 }
+
+#[cfg(test)]
+extern crate self as metrics;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// A recorded [MetricsEvent] with owned labels.
+    type Recorded = (&'static str, HashMap<&'static str, String>, String);
+
+    thread_local! {
+        static EVENTS: RefCell<Vec<Recorded>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn record<T: Debug>(event: MetricsEvent<T>) {
+        let labels = event
+            .labels
+            .iter()
+            .map(|(key, value)| (*key, value.to_string()))
+            .collect();
+        EVENTS.with(|events| {
+            events
+                .borrow_mut()
+                .push((event.metric, labels, format!("{:?}", event.result)))
+        });
+    }
+
+    fn take_events() -> Vec<Recorded> {
+        EVENTS.with(|events| events.take())
+    }
+
+    #[metrics(metric = "static", labels(request_type = "uuid"), handler = record)]
+    fn static_labels(value: u32) -> u32 {
+        value + 1
+    }
+
+    #[metrics(metric = "dynamic", labels(kind = "test", request_type = %request_type), handler = record)]
+    fn dynamic_labels(request_type: String) -> usize {
+        // the argument is moved into the body after the label was evaluated
+        let owned = request_type;
+        owned.len()
+    }
+
+    #[metrics(metric = "async", labels(request_type = %request_type), handler = record)]
+    async fn async_labels(request_type: &str) -> Option<&str> {
+        Some(request_type)
+    }
+
+    #[metrics(metric = "unlabeled", handler = record)]
+    fn unlabeled() {}
+
+    #[test]
+    fn static_label() {
+        // given
+        take_events();
+
+        // when
+        let result = static_labels(1);
+
+        // then
+        assert_eq!(2, result);
+        let events = take_events();
+        assert_eq!(1, events.len());
+        assert_eq!("static", events[0].0);
+        assert_eq!(
+            HashMap::from([("request_type", "uuid".to_string())]),
+            events[0].1
+        );
+        assert_eq!("2", events[0].2);
+    }
+
+    #[test]
+    fn dynamic_label() {
+        // given
+        take_events();
+
+        // when
+        dynamic_labels("profile".to_string());
+        dynamic_labels("skin".to_string());
+
+        // then
+        let events = take_events();
+        assert_eq!(2, events.len());
+        assert_eq!(
+            HashMap::from([
+                ("kind", "test".to_string()),
+                ("request_type", "profile".to_string())
+            ]),
+            events[0].1
+        );
+        assert_eq!("7", events[0].2);
+        assert_eq!(Some(&"skin".to_string()), events[1].1.get("request_type"));
+    }
+
+    #[tokio::test]
+    async fn dynamic_label_async() {
+        // given
+        take_events();
+
+        // when
+        let result = async_labels("cape").await;
+
+        // then
+        assert_eq!(Some("cape"), result);
+        let events = take_events();
+        assert_eq!(1, events.len());
+        assert_eq!("async", events[0].0);
+        assert_eq!(Some(&"cape".to_string()), events[0].1.get("request_type"));
+        assert_eq!("Some(\"cape\")", events[0].2);
+    }
+
+    #[test]
+    fn no_labels() {
+        // given
+        take_events();
+
+        // when
+        unlabeled();
+
+        // then
+        let events = take_events();
+        assert_eq!(1, events.len());
+        assert_eq!("unlabeled", events[0].0);
+        assert!(events[0].1.is_empty());
+    }
+}
@@ -0,0 +1,276 @@
+//! The access_log module provides the structured access log of the rest and gRPC server. If enabled,
+//! every request is logged as a single event with the target `access_log`. Besides the route,
+//! caller and status, the event contains the cache outcomes and upstream (mojang) calls of the
+//! request. They are collected in an [AccessRecord] that is bound to the task handling the request.
+//!
+//! Contrary to the tracing spans, the access log contains exactly one event per request and can
+//! therefore be used as an audit log.
+
+use crate::cache::entry::Cached;
+#[cfg(feature = "grpc-server")]
+use crate::identity::IdentityResolver;
+#[cfg(feature = "grpc-server")]
+use crate::proxy::request_client_ip;
+#[cfg(feature = "grpc-server")]
+use crate::settings::Settings;
+#[cfg(feature = "grpc-server")]
+use futures::future::BoxFuture;
+#[cfg(feature = "grpc-server")]
+use ipnet::IpNet;
+use std::fmt::Debug;
+use std::future::Future;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "grpc-server")]
+use std::task::{Context, Poll};
+use std::time::Duration;
+#[cfg(feature = "grpc-server")]
+use std::time::Instant;
+#[cfg(feature = "grpc-server")]
+use tonic::codegen::http;
+#[cfg(feature = "grpc-server")]
+use tonic::transport::server::TcpConnectInfo;
+use tracing::info;
+
+tokio::task_local! {
+    /// The [AccessRecord] of the request that is handled by the current task.
+    static ACCESS_RECORD: Arc<Mutex<AccessRecord>>;
+}
+
+/// An [AccessRecord] collects the cache outcomes and upstream calls of a single request.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct AccessRecord {
+    /// The number of cache hits.
+    pub cache_hits: u64,
+
+    /// The number of expired cache entries.
+    pub cache_expired: u64,
+
+    /// The number of cache misses.
+    pub cache_misses: u64,
+
+    /// The mojang api endpoints that were called (e.g. `profile`).
+    pub upstream: Vec<&'static str>,
+}
+
+/// Runs a future (e.g. a request handler) with a new [AccessRecord] and returns its output together
+/// with the collected [AccessRecord].
+pub async fn scope<F: Future>(future: F) -> (F::Output, AccessRecord) {
+    let record = Arc::new(Mutex::new(AccessRecord::default()));
+    let output = ACCESS_RECORD.scope(Arc::clone(&record), future).await;
+    let record = std::mem::take(&mut *record.lock().unwrap());
+    (output, record)
+}
+
+/// Updates the [AccessRecord] of the current task. Outside a [scope] (e.g. background refreshes),
+/// nothing is recorded.
+fn update(f: impl FnOnce(&mut AccessRecord)) {
+    let _ = ACCESS_RECORD.try_with(|record| f(&mut record.lock().unwrap()));
+}
+
+/// Records the outcome of a cache request.
+pub fn record_cache<D>(cached: &Cached<D>)
+where
+    D: Clone + Debug + Eq + PartialEq,
+{
+    update(|record| match cached {
+        Cached::Hit(_) => record.cache_hits += 1,
+        Cached::Expired(_) => record.cache_expired += 1,
+        Cached::Miss => record.cache_misses += 1,
+    });
+}
+
+/// Records a call of a mojang api endpoint.
+pub fn record_upstream(endpoint: &'static str) {
+    update(|record| record.upstream.push(endpoint));
+}
+
+/// An [AccessEntry] is a single finished request of the access log.
+#[derive(Debug)]
+pub struct AccessEntry {
+    /// The protocol of the request (`rest` or `grpc`).
+    pub protocol: &'static str,
+
+    /// The http method of the request.
+    pub method: String,
+
+    /// The matched route (rest) or the full method path (gRPC) of the request.
+    pub route: String,
+
+    /// The identity of the caller (the api key header or `anonymous`).
+    pub client: String,
+
+    /// The ip address of the caller (behind trusted proxies, the address of the client).
+    pub ip: Option<IpAddr>,
+
+    /// The http status code (rest) or gRPC status code (gRPC) of the response.
+    pub status: u16,
+
+    /// The time until the response (headers) was ready.
+    pub latency: Duration,
+
+    /// The cache outcomes and upstream calls of the request.
+    pub record: AccessRecord,
+}
+
+impl AccessEntry {
+    /// Logs the [AccessEntry] with the target `access_log`.
+    pub fn log(&self) {
+        info!(
+            target: "access_log",
+            protocol = self.protocol,
+            method = self.method,
+            route = self.route,
+            client = self.client,
+            ip = self.ip.map(display),
+            status = self.status,
+            latency_ms = self.latency.as_secs_f64() * 1000.0,
+            cache_hits = self.record.cache_hits,
+            cache_expired = self.record.cache_expired,
+            cache_misses = self.record.cache_misses,
+            upstream = self.record.upstream.join(","),
+            "{} {} {}",
+            self.method,
+            self.route,
+            self.status
+        );
+    }
+}
+
+/// The [AccessLogLayer] is a tower layer for the gRPC server that logs every request to the access
+/// log. It does nothing if the access log is disabled.
+#[cfg(feature = "grpc-server")]
+#[derive(Debug, Clone)]
+pub struct AccessLogLayer {
+    enabled: bool,
+    identity: IdentityResolver,
+    trusted: Arc<[IpNet]>,
+}
+
+#[cfg(feature = "grpc-server")]
+impl AccessLogLayer {
+    /// Creates a new [AccessLogLayer] from the application [Settings]. The caller is identified with
+    /// the [IdentityResolver].
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            enabled: settings.access_log.enabled,
+            identity: IdentityResolver::new(settings),
+            trusted: Arc::from(settings.proxy.trusted.as_slice()),
+        }
+    }
+}
+
+#[cfg(feature = "grpc-server")]
+impl<S> tower::Layer<S> for AccessLogLayer {
+    type Service = AccessLogService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AccessLogService {
+            layer: self.clone(),
+            inner,
+        }
+    }
+}
+
+/// The [AccessLogService] is the tower service of the [AccessLogLayer].
+#[cfg(feature = "grpc-server")]
+#[derive(Debug, Clone)]
+pub struct AccessLogService<S> {
+    layer: AccessLogLayer,
+    inner: S,
+}
+
+#[cfg(feature = "grpc-server")]
+impl<S, B, R> tower::Service<http::Request<B>> for AccessLogService<S>
+where
+    S: tower::Service<http::Request<B>, Response = http::Response<R>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        // the polled (ready) service handles the request, a clone takes its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        if !self.layer.enabled {
+            return Box::pin(inner.call(request));
+        }
+
+        let method = request.method().to_string();
+        let route = request.uri().path().to_string();
+        let peer = request
+            .extensions()
+            .get::<TcpConnectInfo>()
+            .and_then(|info| info.remote_addr());
+        let client = self.layer.identity.resolve(request.headers(), peer);
+        let ip = request_client_ip(peer, request.headers(), &self.layer.trusted);
+        Box::pin(async move {
+            let start = Instant::now();
+            let (response, record) = scope(inner.call(request)).await;
+            // errors are sent as trailers-only responses, otherwise the status is ok
+            let status = match &response {
+                Ok(response) => response
+                    .headers()
+                    .get("grpc-status")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(0),
+                Err(_) => tonic::Code::Unknown as u16,
+            };
+            AccessEntry {
+                protocol: "grpc",
+                method,
+                route,
+                client,
+                ip,
+                status,
+                latency: start.elapsed(),
+                record,
+            }
+            .log();
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::entry::Dated;
+
+    #[tokio::test]
+    async fn scope_records() {
+        // given
+        let entry = Dated::at(Some(42), 0);
+
+        // when
+        let (output, record) = scope(async {
+            record_cache(&Cached::Hit(entry.clone()));
+            record_cache(&Cached::Expired(entry));
+            record_cache::<u64>(&Cached::Miss);
+            record_upstream("profile");
+            42
+        })
+        .await;
+        record_upstream("uuid");
+
+        // then
+        assert_eq!(42, output);
+        assert_eq!(
+            AccessRecord {
+                cache_hits: 1,
+                cache_expired: 1,
+                cache_misses: 1,
+                upstream: vec!["profile"],
+            },
+            record
+        );
+    }
+}
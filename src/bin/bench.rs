@@ -0,0 +1,314 @@
+//! The `xenos-bench` binary drives the [Service] with a configurable load against the
+//! [testing mojang api](MojangTestingApi) and reports the latency percentiles, the cache hit ratio and
+//! the upstream (mojang) requests. The keys are drawn from a zipfian distribution, so that a few hot
+//! keys are requested concurrently (cache stampede) while the long tail misses the cache.
+//!
+//! The service is configured like Xenos itself (config file and environment variables), e.g.
+//!
+//! ```sh
+//! cargo run --release --features bench --bin xenos-bench -- --requests 100000 --concurrency 64 --keys 5000 --operation head
+//! ```
+
+use bytes::Bytes;
+use prometheus::proto::MetricFamily;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use xenos::cache::entry::HeadKey;
+use xenos::cache::level::moka::MokaCache;
+use xenos::cache::level::no::NoCache;
+use xenos::mojang::testing::{MojangTestingApi, TestingProfile};
+use xenos::mojang::STEVE_SKIN;
+use xenos::service::Service;
+use xenos::settings::Settings;
+use xenos::ServiceBuilder;
+
+/// The [Service] under load, using the [MojangTestingApi] with the generated profiles.
+type BenchService = Service<MokaCache, NoCache, MojangTestingApi<'static>>;
+
+/// The [Operation] is the service call that is performed for each request.
+#[derive(Debug, Clone, Copy)]
+enum Operation {
+    Uuid,
+    Profile,
+    Skin,
+    Head,
+}
+
+impl Operation {
+    /// Gets the request type of the operation (as used by the cache metrics).
+    fn request_type(&self) -> &'static str {
+        match self {
+            Operation::Uuid => "uuid",
+            Operation::Profile => "profile",
+            Operation::Skin => "skin",
+            Operation::Head => "head",
+        }
+    }
+}
+
+impl FromStr for Operation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uuid" => Ok(Operation::Uuid),
+            "profile" => Ok(Operation::Profile),
+            "skin" => Ok(Operation::Skin),
+            "head" => Ok(Operation::Head),
+            _ => Err(format!("unknown operation {}", s)),
+        }
+    }
+}
+
+/// The [Options] of the benchmark, parsed from the command line arguments.
+#[derive(Debug)]
+struct Options {
+    /// The total number of requests.
+    requests: usize,
+    /// The number of concurrent workers.
+    concurrency: usize,
+    /// The number of distinct keys (profiles).
+    keys: usize,
+    /// The exponent of the zipfian key distribution (`0` is uniform).
+    zipf: f64,
+    /// The service call that is performed for each request.
+    operation: Operation,
+    /// The minimum and maximum latency of the testing mojang api.
+    latency: (Duration, Duration),
+}
+
+impl Options {
+    /// Parses the [Options] from the command line arguments (`--name value`).
+    fn parse() -> Result<Self, String> {
+        let mut options = Options {
+            requests: 10_000,
+            concurrency: 32,
+            keys: 1_000,
+            zipf: 1.0,
+            operation: Operation::Profile,
+            latency: (Duration::from_millis(20), Duration::from_millis(50)),
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(name) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for {}", name))?;
+            let invalid = || format!("invalid value {} for {}", value, name);
+            match name.as_str() {
+                "--requests" => options.requests = value.parse().map_err(|_| invalid())?,
+                "--concurrency" => options.concurrency = value.parse().map_err(|_| invalid())?,
+                "--keys" => options.keys = value.parse().map_err(|_| invalid())?,
+                "--zipf" => options.zipf = value.parse().map_err(|_| invalid())?,
+                "--operation" => options.operation = value.parse()?,
+                "--latency" => {
+                    // the latency is a range in milliseconds, e.g. `20:50`
+                    let (min, max) = value.split_once(':').unwrap_or((&value, &value));
+                    options.latency = (
+                        Duration::from_millis(min.parse().map_err(|_| invalid())?),
+                        Duration::from_millis(max.parse().map_err(|_| invalid())?),
+                    );
+                }
+                _ => return Err(format!("unknown option {}", name)),
+            }
+        }
+        if options.keys == 0 || options.concurrency == 0 {
+            return Err("keys and concurrency have to be positive".to_string());
+        }
+        Ok(options)
+    }
+}
+
+/// The [WorkerResult] holds the latencies and failures of a single worker.
+#[derive(Debug, Default)]
+struct WorkerResult {
+    latencies: Vec<Duration>,
+    failures: usize,
+    keys: HashSet<usize>,
+}
+
+/// Generates the testing profiles. They are leaked, as the [MojangTestingApi] references them for the
+/// lifetime of the benchmark.
+fn generate_profiles(keys: usize) -> &'static [TestingProfile] {
+    let profiles: Vec<TestingProfile> = (0..keys)
+        .map(|key| {
+            let id = Uuid::from_u128(key as u128 + 1);
+            let skin: Bytes = STEVE_SKIN;
+            TestingProfile::new(id, &format!("bench_{}", key), Some(skin), None)
+        })
+        .collect();
+    Box::leak(profiles.into_boxed_slice())
+}
+
+/// Performs a single request of the [Operation] for a profile. Returns whether the request succeeded.
+async fn request(service: &BenchService, operation: Operation, profile: &TestingProfile) -> bool {
+    let uuid = &profile.profile.id;
+    match operation {
+        Operation::Uuid => service.get_uuid(&profile.profile.name).await.is_ok(),
+        Operation::Profile => service.get_profile(uuid).await.is_ok(),
+        Operation::Skin => service.get_skin(uuid).await.is_ok(),
+        Operation::Head => service.get_head(&HeadKey::new(*uuid, false)).await.is_ok(),
+    }
+}
+
+/// Runs a worker that performs requests until the total number of requests is reached.
+async fn run_worker(
+    service: Arc<BenchService>,
+    profiles: &'static [TestingProfile],
+    distribution: WeightedIndex<f64>,
+    options: Arc<Options>,
+    counter: Arc<AtomicUsize>,
+    seed: u64,
+) -> WorkerResult {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut result = WorkerResult::default();
+    while counter.fetch_add(1, Ordering::SeqCst) < options.requests {
+        let key = distribution.sample(&mut rng);
+        let start = Instant::now();
+        if !request(&service, options.operation, &profiles[key]).await {
+            result.failures += 1;
+        }
+        result.latencies.push(start.elapsed());
+        result.keys.insert(key);
+    }
+    result
+}
+
+/// Gets the number of cache get requests by their result (hit, expired, miss) for a request type.
+fn cache_results(families: &[MetricFamily], request_type: &str) -> (u64, u64, u64) {
+    let mut results = (0, 0, 0);
+    let family = families
+        .iter()
+        .find(|family| family.get_name() == "xenos_cache_get_duration_seconds");
+    for metric in family.map(|family| family.get_metric()).unwrap_or_default() {
+        let label = |name: &str| {
+            metric
+                .get_label()
+                .iter()
+                .find(|label| label.get_name() == name)
+                .map(|label| label.get_value().to_string())
+        };
+        if label("cache_variant").as_deref() != Some("cache")
+            || label("request_type").as_deref() != Some(request_type)
+        {
+            continue;
+        }
+        let count = metric.get_histogram().get_sample_count();
+        match label("cache_result").as_deref() {
+            Some("hit") => results.0 += count,
+            Some("expired") => results.1 += count,
+            Some("miss") => results.2 += count,
+            _ => {}
+        }
+    }
+    results
+}
+
+/// Gets a percentile (between `0` and `1`) of sorted latencies.
+fn percentile(sorted: &[Duration], percentile: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let index = ((sorted.len() as f64 * percentile).ceil() as usize).clamp(1, sorted.len());
+    sorted[index - 1]
+}
+
+/// Runs the benchmark and prints its report.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let options = Arc::new(Options::parse()?);
+    let settings = Arc::new(Settings::new()?);
+    let profiles = generate_profiles(options.keys);
+
+    // the weight of the n-th key is proportional to 1/n^s (zipfian)
+    let weights = (1..=options.keys).map(|rank| 1.0 / (rank as f64).powf(options.zipf));
+    let distribution = WeightedIndex::new(weights)?;
+
+    let mojang = profiles
+        .iter()
+        .fold(MojangTestingApi::new(), |mojang, profile| {
+            mojang.add_profile(profile)
+        });
+    mojang.set_latency(options.latency.0, options.latency.1);
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(async {
+            let service = Arc::new(ServiceBuilder::new(settings).mojang(mojang).build());
+            let counter = Arc::new(AtomicUsize::new(0));
+
+            let start = Instant::now();
+            let workers: Vec<_> = (0..options.concurrency)
+                .map(|worker| {
+                    tokio::spawn(run_worker(
+                        Arc::clone(&service),
+                        profiles,
+                        distribution.clone(),
+                        Arc::clone(&options),
+                        Arc::clone(&counter),
+                        worker as u64,
+                    ))
+                })
+                .collect();
+            let mut result = WorkerResult::default();
+            for worker in workers {
+                let worker = worker.await?;
+                result.latencies.extend(worker.latencies);
+                result.failures += worker.failures;
+                result.keys.extend(worker.keys);
+            }
+            let elapsed = start.elapsed();
+
+            let mut latencies = result.latencies;
+            latencies.sort();
+            let (hits, expired, misses) =
+                cache_results(&prometheus::gather(), options.operation.request_type());
+            let upstream = service.mojang().requests();
+            let lookups = (hits + expired + misses).max(1);
+
+            println!("operation:          {:?}", options.operation);
+            println!("requests:           {}", latencies.len());
+            println!("failures:           {}", result.failures);
+            println!("concurrency:        {}", options.concurrency);
+            println!(
+                "distinct keys:      {} of {}",
+                result.keys.len(),
+                options.keys
+            );
+            println!("duration:           {:.2?}", elapsed);
+            println!(
+                "throughput:         {:.0} req/s",
+                latencies.len() as f64 / elapsed.as_secs_f64()
+            );
+            for (name, p) in [("p50", 0.5), ("p90", 0.9), ("p99", 0.99), ("p999", 0.999)] {
+                println!(
+                    "latency {:<11}{:.2?}",
+                    format!("{}:", name),
+                    percentile(&latencies, p)
+                );
+            }
+            println!(
+                "latency max:        {:.2?}",
+                latencies.last().copied().unwrap_or_default()
+            );
+            println!(
+                "cache hit ratio:    {:.2}% ({} hits, {} expired, {} misses)",
+                hits as f64 / lookups as f64 * 100.0,
+                hits,
+                expired,
+                misses
+            );
+            println!("upstream requests:  {}", upstream);
+            println!(
+                "upstream per key:   {:.2}",
+                upstream as f64 / result.keys.len().max(1) as f64
+            );
+            Ok(())
+        })
+}
@@ -0,0 +1,301 @@
+use crate::cache::clock::{Clock, SystemClock};
+use crate::cache::level::moka::MokaCache;
+use crate::cache::level::no::NoCache;
+use crate::cache::level::CacheLevel;
+use crate::cache::Cache;
+#[cfg(feature = "history")]
+use crate::history::PostgresHistory;
+#[cfg(not(feature = "static-testing"))]
+use crate::mojang::api::MojangApi;
+#[cfg(feature = "static-testing")]
+use crate::mojang::testing::MojangTestingApi;
+use crate::mojang::Mojang;
+use crate::service::Service;
+use crate::settings::Settings;
+#[cfg(feature = "rest-server")]
+use axum::Router;
+use std::sync::Arc;
+#[cfg(feature = "grpc-server")]
+use tonic::service::Interceptor;
+#[cfg(feature = "grpc-server")]
+use tonic::{Request, Status};
+
+/// The default [Mojang] implementation of the [ServiceBuilder]. It is either the actual mojang api or
+/// a testing api for integration tests.
+#[cfg(not(feature = "static-testing"))]
+pub type DefaultMojang = MojangApi;
+
+/// The default [Mojang] implementation of the [ServiceBuilder]. It is either the actual mojang api or
+/// a testing api for integration tests.
+#[cfg(feature = "static-testing")]
+pub type DefaultMojang = MojangTestingApi<'static>;
+
+/// The [ServiceBuilder] assembles a [Service] from its cache levels, [Mojang] implementation and
+/// [application settings](Settings). It allows embedding Xenos into existing applications without
+/// going through [start](crate::start). The servers can then be built with `rest_router`
+/// and `grpc_profile_server`.
+///
+/// By default, it uses a [MokaCache] as local cache, no remote cache and the [DefaultMojang] api.
+///
+/// ```rs
+/// let service = ServiceBuilder::new(settings)
+///     .remote_cache(RedisCache::new(redis_manager, &settings.cache.redis))
+///     .build();
+/// let router = rest_router(Arc::new(service));
+/// ```
+pub struct ServiceBuilder<L, R, M>
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    settings: Arc<Settings>,
+    local_cache: L,
+    remote_cache: R,
+    mojang: M,
+    clock: Arc<dyn Clock>,
+    #[cfg(feature = "history")]
+    history: Option<PostgresHistory>,
+}
+
+impl ServiceBuilder<MokaCache, NoCache, DefaultMojang> {
+    /// Creates a new [ServiceBuilder] with the default cache levels and [Mojang] implementation.
+    pub fn new(settings: Arc<Settings>) -> Self {
+        #[cfg(not(feature = "static-testing"))]
+        let mojang = MojangApi::with_endpoints(&settings.mojang_api);
+        #[cfg(feature = "static-testing")]
+        let mojang = MojangTestingApi::with_profiles();
+
+        Self {
+            local_cache: MokaCache::new(
+                settings
+                    .cache
+                    .moka
+                    .clone()
+                    .with_capabilities(&settings.capabilities),
+            ),
+            remote_cache: NoCache,
+            mojang,
+            clock: Arc::new(SystemClock),
+            #[cfg(feature = "history")]
+            history: None,
+            settings,
+        }
+    }
+}
+
+impl<L, R, M> ServiceBuilder<L, R, M>
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    /// Replaces the local [CacheLevel] (e.g. [MokaCache]).
+    pub fn local_cache<L2: CacheLevel>(self, local_cache: L2) -> ServiceBuilder<L2, R, M> {
+        ServiceBuilder {
+            settings: self.settings,
+            local_cache,
+            remote_cache: self.remote_cache,
+            mojang: self.mojang,
+            clock: self.clock,
+            #[cfg(feature = "history")]
+            history: self.history,
+        }
+    }
+
+    /// Replaces the remote [CacheLevel] (e.g. [RedisCache](crate::cache::level::redis::RedisCache)).
+    pub fn remote_cache<R2: CacheLevel>(self, remote_cache: R2) -> ServiceBuilder<L, R2, M> {
+        ServiceBuilder {
+            settings: self.settings,
+            local_cache: self.local_cache,
+            remote_cache,
+            mojang: self.mojang,
+            clock: self.clock,
+            #[cfg(feature = "history")]
+            history: self.history,
+        }
+    }
+
+    /// Replaces the [Mojang] implementation.
+    pub fn mojang<M2: Mojang>(self, mojang: M2) -> ServiceBuilder<L, R, M2> {
+        ServiceBuilder {
+            settings: self.settings,
+            local_cache: self.local_cache,
+            remote_cache: self.remote_cache,
+            mojang,
+            clock: self.clock,
+            #[cfg(feature = "history")]
+            history: self.history,
+        }
+    }
+
+    /// Replaces the [Clock] of the cache (e.g. for deterministic tests).
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Adds a [profile history](PostgresHistory) to the [Service].
+    #[cfg(feature = "history")]
+    pub fn history(mut self, history: PostgresHistory) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Builds the [Service] from the configured components.
+    pub fn build(self) -> Service<L, R, M> {
+        let cache = Cache::new(
+            self.settings.cache.entries.clone(),
+            self.local_cache,
+            self.remote_cache,
+        )
+        .with_clock(self.clock)
+        .with_capabilities(self.settings.capabilities.clone())
+        .with_tenant_expiry(
+            self.settings
+                .tenancy
+                .cache_entries(&self.settings.cache.entries),
+        );
+        let service = Service::new(self.settings, cache, self.mojang);
+        #[cfg(feature = "history")]
+        let service = match self.history {
+            Some(history) => service.with_history(history),
+            None => service,
+        };
+        service
+    }
+}
+
+/// A [RestCustomizer] customizes the rest [Router] (e.g. adds axum layers).
+#[cfg(feature = "rest-server")]
+type RestCustomizer = Arc<dyn Fn(Router) -> Router + Send + Sync>;
+
+/// A [GrpcInterceptor] is a tonic interceptor of the gRPC server.
+#[cfg(feature = "grpc-server")]
+type GrpcInterceptor = Arc<dyn Fn(Request<()>) -> Result<Request<()>, Status> + Send + Sync>;
+
+/// The [ServerCustomizer] registers cross-cutting middleware (e.g. auth, tenancy or custom logging) on
+/// the servers that are built inside [start_with](crate::start_with), so that embedders do not have to
+/// rebuild the servers themselves. The rest customizers are applied to the complete rest [Router] (in
+/// the order of registration), the gRPC interceptors are called for each gRPC request (in the order of
+/// registration) after the built-in layers.
+///
+/// ```rs
+/// let customizer = ServerCustomizer::new()
+///     .rest(|router| router.layer(middleware::from_fn(auth)))
+///     .grpc_interceptor(|request| check_auth(request));
+/// xenos::start_with(settings, customizer).await?;
+/// ```
+#[derive(Clone, Default)]
+pub struct ServerCustomizer {
+    #[cfg(feature = "rest-server")]
+    rest: Vec<RestCustomizer>,
+    #[cfg(feature = "grpc-server")]
+    grpc: Vec<GrpcInterceptor>,
+}
+
+impl ServerCustomizer {
+    /// Creates a new [ServerCustomizer] that does not customize the servers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a customizer of the rest [Router] (e.g. to add axum layers).
+    #[cfg(feature = "rest-server")]
+    pub fn rest(mut self, customizer: impl Fn(Router) -> Router + Send + Sync + 'static) -> Self {
+        self.rest.push(Arc::new(customizer));
+        self
+    }
+
+    /// Registers an interceptor of the gRPC server. Requests that the interceptor rejects are answered
+    /// with its [Status].
+    #[cfg(feature = "grpc-server")]
+    pub fn grpc_interceptor(
+        mut self,
+        interceptor: impl Fn(Request<()>) -> Result<Request<()>, Status> + Send + Sync + 'static,
+    ) -> Self {
+        self.grpc.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Applies all registered rest customizers to a [Router].
+    #[cfg(feature = "rest-server")]
+    pub fn customize_rest(&self, router: Router) -> Router {
+        self.rest
+            .iter()
+            .fold(router, |router, customizer| customizer(router))
+    }
+
+    /// Gets the [Interceptor] that calls all registered gRPC interceptors.
+    #[cfg(feature = "grpc-server")]
+    pub fn grpc_interceptors(&self) -> GrpcInterceptors {
+        GrpcInterceptors(Arc::from(self.grpc.as_slice()))
+    }
+}
+
+/// The [GrpcInterceptors] are the registered gRPC interceptors of a [ServerCustomizer] as a single
+/// [Interceptor]. The interceptors are called in the order of registration until one rejects the
+/// request.
+#[cfg(feature = "grpc-server")]
+#[derive(Clone)]
+pub struct GrpcInterceptors(Arc<[GrpcInterceptor]>);
+
+#[cfg(feature = "grpc-server")]
+impl Interceptor for GrpcInterceptors {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        for interceptor in self.0.iter() {
+            request = interceptor(request)?;
+        }
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::clock::ManualClock;
+    use crate::mojang::testing::MojangTestingApi;
+
+    #[tokio::test]
+    async fn build_with_components() {
+        // given
+        let settings = Arc::new(Settings::default());
+        let clock = Arc::new(ManualClock::new(1000));
+
+        // when
+        let service = ServiceBuilder::new(settings)
+            .remote_cache(NoCache)
+            .mojang(MojangTestingApi::with_profiles())
+            .clock(clock)
+            .build();
+
+        // then
+        let result = service.get_uuid("Hydrofin").await;
+        assert!(matches!(result, Ok(dated) if dated.timestamp == 1000));
+    }
+
+    #[cfg(feature = "grpc-server")]
+    #[test]
+    #[allow(clippy::result_large_err)]
+    fn grpc_interceptors_ordered() {
+        // given
+        let customizer = ServerCustomizer::new()
+            .grpc_interceptor(|mut request| {
+                request
+                    .metadata_mut()
+                    .insert("x-first", "1".parse().unwrap());
+                Ok(request)
+            })
+            .grpc_interceptor(|request| match request.metadata().get("x-first") {
+                Some(_) => Err(Status::permission_denied("rejected")),
+                None => Ok(request),
+            });
+        let mut interceptors = customizer.grpc_interceptors();
+
+        // when
+        let result = interceptors.call(Request::new(()));
+
+        // then
+        assert!(matches!(result, Err(status) if status.message() == "rejected"));
+    }
+}
@@ -0,0 +1,59 @@
+use crate::cache::entry::now_seconds;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// A [Clock] is the source of the current time for the [Cache](crate::cache::Cache) and the
+/// [Service](crate::service::Service). It is used to date new cache entries and to check whether
+/// cache entries have expired. It can be injected to write deterministic expiry tests.
+pub trait Clock: Debug + Send + Sync {
+    /// Gets the current unix time in seconds.
+    fn now_seconds(&self) -> u64;
+}
+
+/// The [SystemClock] is a [Clock] that uses the system time. It is used by default.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_seconds(&self) -> u64 {
+        now_seconds()
+    }
+}
+
+/// The [ManualClock] is a [Clock] that only advances if told so. It is intended for testing.
+///
+/// ```rs
+/// let clock = Arc::new(ManualClock::new(0));
+/// let cache = Cache::new(...).with_clock(clock.clone());
+/// clock.advance(Duration::from_secs(60));
+/// ```
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicU64,
+}
+
+impl ManualClock {
+    /// Creates a new [ManualClock] starting at the unix time in seconds.
+    pub fn new(now: u64) -> Self {
+        Self {
+            now: AtomicU64::new(now),
+        }
+    }
+
+    /// Sets the current unix time in seconds.
+    pub fn set(&self, now: u64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    /// Advances the current time by a [Duration] (in whole seconds).
+    pub fn advance(&self, duration: Duration) {
+        self.now.fetch_add(duration.as_secs(), Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_seconds(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
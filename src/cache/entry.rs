@@ -1,8 +1,10 @@
 use crate::cache::entry::Cached::{Expired, Hit, Miss};
 use crate::mojang::Profile;
 use crate::settings;
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
-use std::fmt::Debug;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::Arc;
 use std::time::SystemTime;
 use uuid::Uuid;
 
@@ -27,6 +29,11 @@ where
 
     /// The created data.
     pub data: D,
+
+    /// The name of the fallback provider that served the data. It is `None` if the data was served
+    /// by mojang (or the cache). Data of fallback providers is never cached.
+    #[serde(skip)]
+    pub provider: Option<String>,
 }
 
 impl<D> Dated<D>
@@ -36,7 +43,27 @@ where
     /// Gets the current age of the [Dated]. The age of a [Dated] is the relative time from which
     /// the cache entry was created **until now**.
     pub fn current_age(&self) -> u64 {
-        now_seconds() - self.timestamp
+        self.age_at(now_seconds())
+    }
+
+    /// Gets the age of the [Dated] at a unix time in seconds (e.g. from a [Clock](crate::cache::clock::Clock)).
+    pub fn age_at(&self, now: u64) -> u64 {
+        now.saturating_sub(self.timestamp)
+    }
+
+    /// Creates a new [Dated] from its data, using the provided unix time in seconds as its creation time.
+    pub fn at(data: D, timestamp: u64) -> Self {
+        Dated {
+            timestamp,
+            data,
+            provider: None,
+        }
+    }
+
+    /// Marks the [Dated] as served by a fallback provider.
+    pub fn with_provider(mut self, provider: &str) -> Self {
+        self.provider = Some(provider.to_string());
+        self
     }
 }
 
@@ -49,6 +76,7 @@ where
         Dated {
             timestamp: now_seconds(),
             data: value,
+            provider: None,
         }
     }
 }
@@ -95,6 +123,7 @@ where
             Some(data) => Ok(Dated {
                 timestamp: self.timestamp,
                 data,
+                provider: self.provider,
             }),
         }
     }
@@ -102,11 +131,16 @@ where
     /// Checks whether the [Entry] has **now** expired. An [Entry] is expired if its [Entry::current_age]
     /// is **greater or equal** the provided expiry.
     pub fn is_expired(&self, expiry: &settings::CacheEntry) -> bool {
+        self.is_expired_at(expiry, now_seconds())
+    }
+
+    /// Checks whether the [Entry] has expired at a unix time in seconds. See [Entry::is_expired].
+    pub fn is_expired_at(&self, expiry: &settings::CacheEntry, now: u64) -> bool {
         let exp = match &self.data {
             None => expiry.exp_empty,
             Some(_) => expiry.exp,
         };
-        self.current_age() >= exp.as_secs()
+        self.age_at(now) >= exp.as_secs()
     }
 }
 
@@ -137,9 +171,19 @@ where
     /// Creates a new [Cached] from an [Entry] using some expiry. It uses [Entry::is_expired] to decide
     /// whether an [Entry] has expired.
     pub fn with_expiry(opt: Option<Entry<D>>, expiry: &settings::CacheEntry) -> Cached<D> {
+        Self::with_expiry_at(opt, expiry, now_seconds())
+    }
+
+    /// Creates a new [Cached] from an [Entry] using some expiry at a unix time in seconds. See
+    /// [Cached::with_expiry].
+    pub fn with_expiry_at(
+        opt: Option<Entry<D>>,
+        expiry: &settings::CacheEntry,
+        now: u64,
+    ) -> Cached<D> {
         match opt {
             None => Miss,
-            Some(entry) if entry.is_expired(expiry) => Expired(entry),
+            Some(entry) if entry.is_expired_at(expiry, now) => Expired(entry),
             Some(entry) => Hit(entry),
         }
     }
@@ -168,25 +212,141 @@ pub struct UuidData {
 /// A [ProfileData] is a [Profile].
 pub type ProfileData = Profile;
 
-/// A [SkinData] is a profile skin with metadata.
+/// A [SkinData] is a profile skin with metadata. A suppressed skin is the default skin that replaces
+/// a banned skin.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct SkinData {
-    pub bytes: Vec<u8>,
+    pub bytes: Bytes,
     pub model: String,
     pub default: bool,
+    #[serde(default)]
+    pub suppressed: bool,
 }
 
-/// A [CapeData] is a profile cape.
+/// A [CapeData] is a profile cape. The texture id is absent for capes that were cached before it
+/// was recorded.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct CapeData {
-    pub bytes: Vec<u8>,
+    pub bytes: Bytes,
+    #[serde(default)]
+    pub texture_id: Option<String>,
 }
 
-/// A [HeadData] is a profile skin's head.
+/// A [SkinUrlData] is the mojang texture url of a profile skin with metadata (without the skin bytes).
+/// The url and texture id are absent for default (and suppressed) skins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkinUrlData {
+    pub url: Option<String>,
+    pub texture_id: Option<String>,
+    pub model: String,
+    pub default: bool,
+    pub suppressed: bool,
+}
+
+/// A [CapeUrlData] is the mojang texture url of a profile cape (without the cape bytes).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapeUrlData {
+    pub url: String,
+    pub texture_id: String,
+}
+
+/// A [HeadData] is a profile skin's head. A suppressed head is the default head that replaces the
+/// head of a banned skin.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct HeadData {
-    pub bytes: Vec<u8>,
+    pub bytes: Bytes,
     pub default: bool,
+    #[serde(default)]
+    pub suppressed: bool,
+}
+
+/// The [HeadStyle] is the render style of a head image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum HeadStyle {
+    /// The head is encoded as PNG.
+    #[default]
+    Png,
+
+    /// The head is encoded as raw RGBA pixels (row by row).
+    Rgba,
+}
+
+impl Display for HeadStyle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeadStyle::Png => write!(f, "png"),
+            HeadStyle::Rgba => write!(f, "rgba"),
+        }
+    }
+}
+
+/// A [HeadKey] identifies a (rendered) head in the cache. The native head is the 8x8 PNG head that is
+/// built from the skin, all other sizes and styles are rendered from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HeadKey {
+    pub uuid: Uuid,
+    pub overlay: bool,
+    pub size: u32,
+    pub style: HeadStyle,
+}
+
+impl HeadKey {
+    /// The size (width and height) of the native head in pixels.
+    pub const NATIVE_SIZE: u32 = 8;
+
+    /// Creates a new [HeadKey] of the native head of a profile with or without its overlay.
+    pub fn new(uuid: Uuid, overlay: bool) -> Self {
+        Self {
+            uuid,
+            overlay,
+            size: Self::NATIVE_SIZE,
+            style: HeadStyle::default(),
+        }
+    }
+
+    /// Creates a new [HeadKey] of a head of a profile with or without its overlay that is rendered with
+    /// an integer scale of the native head and a [HeadStyle].
+    pub fn rendered(uuid: Uuid, overlay: bool, scale: u32, style: HeadStyle) -> Self {
+        Self {
+            uuid,
+            overlay,
+            size: Self::NATIVE_SIZE * scale,
+            style,
+        }
+    }
+
+    /// Gets the integer scale of the head relative to the native head.
+    pub fn scale(&self) -> u32 {
+        self.size / Self::NATIVE_SIZE
+    }
+
+    /// Checks whether the key identifies the native head (8x8 PNG).
+    pub fn is_native(&self) -> bool {
+        self.size == Self::NATIVE_SIZE && self.style == HeadStyle::Png
+    }
+}
+
+/// A [ChecksumData] holds the (lowercase hex) SHA-256 checksums of a profile skin and head. The
+/// texture id is only present for custom skins.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChecksumData {
+    pub texture_id: Option<String>,
+    pub skin: String,
+    pub head: String,
+    pub default: bool,
+}
+
+/// A [TextureData] is a texture (e.g. skin or cape) that is identified by its texture id.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TextureData {
+    pub bytes: Bytes,
+}
+
+/// A [BlockedServersData] is the blocked servers list of mojang. It consists of the SHA1 hashes of
+/// the blocked hostname patterns.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BlockedServersData {
+    pub hashes: Arc<[String]>,
 }
 
 /// Gets the current time in seconds.
@@ -1,10 +1,16 @@
-use crate::cache::entry::Dated;
+use crate::cache::entry::{Dated, HeadKey};
+#[cfg(feature = "redis")]
+use crate::cache::{batch_request_type, CACHE_BATCH_RESULT_COUNTER};
 use crate::cache::{
-    CapeData, Entry, HeadData, ProfileData, SkinData, UuidData, CACHE_AGE_HISTOGRAM,
-    CACHE_GET_HISTOGRAM, CACHE_SET_HISTOGRAM,
+    BlockedServersData, CapeData, Entry, HeadData, ProfileData, SkinData, TextureData, UuidData,
+    CACHE_AGE_HISTOGRAM, CACHE_GET_HISTOGRAM, CACHE_SET_HISTOGRAM,
 };
+use crate::statsd;
 use metrics::MetricsEvent;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::time::Duration;
 use tracing::warn;
 use uuid::Uuid;
 
@@ -13,12 +19,16 @@ pub mod no;
 #[cfg(feature = "redis")]
 pub mod redis;
 
-fn metrics_get_handler<T: Clone + Debug + Eq>(event: MetricsEvent<Option<Entry<T>>>) {
-    let cache_result = match event.result {
+fn cache_result<T: Clone + Debug + Eq>(result: &Option<Entry<T>>) -> &'static str {
+    match result {
         None => "miss",
         Some(Dated { data: Some(_), .. }) => "filled",
         Some(Dated { data: None, .. }) => "empty",
-    };
+    }
+}
+
+fn metrics_get_handler<T: Clone + Debug + Eq>(event: MetricsEvent<Option<Entry<T>>>) {
+    let cache_result = cache_result(event.result);
     let Some(request_type) = event.labels.get("request_type") else {
         warn!("Failed to retrieve label 'request_type' for metric!");
         return;
@@ -30,12 +40,69 @@ fn metrics_get_handler<T: Clone + Debug + Eq>(event: MetricsEvent<Option<Entry<T
     CACHE_GET_HISTOGRAM
         .with_label_values(&[cache_variant, request_type, cache_result])
         .observe(event.time);
+    statsd::timing(
+        "cache.get",
+        &[
+            ("cache_variant", cache_variant),
+            ("request_type", request_type),
+            ("cache_result", cache_result),
+        ],
+        event.time,
+    );
 
-    if let Some(dated) = event.result {
+    observe_age(cache_variant, request_type, event.result);
+}
+
+fn observe_age<T: Clone + Debug + Eq>(
+    cache_variant: &str,
+    request_type: &str,
+    result: &Option<Entry<T>>,
+) {
+    if let Some(dated) = result {
         CACHE_AGE_HISTOGRAM
             .with_label_values(&[cache_variant, request_type])
             .observe(dated.current_age() as f64);
+        statsd::timing(
+            "cache.age",
+            &[
+                ("cache_variant", cache_variant),
+                ("request_type", request_type),
+            ],
+            dated.current_age() as f64,
+        );
+    }
+}
+
+#[cfg(feature = "redis")]
+fn metrics_get_batch_handler<T: Clone + Debug + Eq>(event: MetricsEvent<Vec<Option<Entry<T>>>>) {
+    let Some(request_type) = event.labels.get("request_type") else {
+        warn!("Failed to retrieve label 'request_type' for metric!");
+        return;
+    };
+    let Some(cache_variant) = event.labels.get("cache_variant") else {
+        warn!("Failed to retrieve label 'cache_variant' for metric!");
+        return;
+    };
+    // the results are counted per entry, but the latency is only observed once per batch
+    for result in event.result {
+        CACHE_BATCH_RESULT_COUNTER
+            .with_label_values(&[cache_variant, request_type, cache_result(result)])
+            .inc();
+        observe_age(cache_variant, request_type, result);
     }
+    let batch_request_type = batch_request_type(request_type);
+    CACHE_GET_HISTOGRAM
+        .with_label_values(&[cache_variant, &batch_request_type, "batch"])
+        .observe(event.time);
+    statsd::timing(
+        "cache.get",
+        &[
+            ("cache_variant", cache_variant),
+            ("request_type", &batch_request_type),
+            ("cache_result", "batch"),
+        ],
+        event.time,
+    );
 }
 
 fn metrics_set_handler<T: Clone + Debug + Eq>(event: MetricsEvent<T>) {
@@ -50,6 +117,81 @@ fn metrics_set_handler<T: Clone + Debug + Eq>(event: MetricsEvent<T>) {
     CACHE_SET_HISTOGRAM
         .with_label_values(&[cache_variant, request_type])
         .observe(event.time);
+    statsd::timing(
+        "cache.set",
+        &[
+            ("cache_variant", cache_variant),
+            ("request_type", request_type),
+        ],
+        event.time,
+    );
+}
+
+/// A [KeyPattern] selects cache entries by their key for purging. The pattern is a glob (`*` matches any
+/// characters, `?` matches a single character) over the entry keys without the configured redis prefix,
+/// e.g. `head.<uuid>.*` for all heads of a profile or `uuid.*` for all uuids.
+#[derive(Debug, Clone)]
+pub struct KeyPattern {
+    glob: String,
+    regex: Regex,
+}
+
+impl KeyPattern {
+    /// Parses a [KeyPattern] from a glob. Returns [None] if the glob is empty or contains characters
+    /// other than alphanumerics, `.`, `_`, `-` and the wildcards.
+    pub fn new(glob: &str) -> Option<Self> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '*' | '?');
+        if glob.is_empty() || !glob.chars().all(valid) {
+            return None;
+        }
+        let regex = glob
+            .chars()
+            .map(|c| match c {
+                '*' => ".*".to_string(),
+                '?' => ".".to_string(),
+                c => regex::escape(&c.to_string()),
+            })
+            .collect::<String>();
+        Some(Self {
+            glob: glob.to_string(),
+            regex: Regex::new(&format!("^{}$", regex)).ok()?,
+        })
+    }
+
+    /// Gets the glob of the [KeyPattern].
+    pub fn glob(&self) -> &str {
+        &self.glob
+    }
+
+    /// Checks whether the [KeyPattern] matches an entry key.
+    pub fn matches(&self, key: &str) -> bool {
+        self.regex.is_match(key)
+    }
+}
+
+/// A [PinnedError] is an error of changing the pinned profiles of a [CacheLevel].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinnedError {
+    /// The maximum number of pinned profiles is reached.
+    Exceeded,
+
+    /// The pinned profiles could not be changed because the [CacheLevel] is unreachable.
+    Unavailable,
+}
+
+/// Builds the key of a [HeadKey] (without prefix). The native head uses the key format of previous
+/// versions (without size and style), so that existing redis entries remain valid after an upgrade.
+pub fn head_key(key: &HeadKey) -> String {
+    match key.is_native() {
+        true => format!("head.{}.{}", key.uuid.simple(), key.overlay),
+        false => format!(
+            "head.{}.{}.{}.{}",
+            key.uuid.simple(),
+            key.overlay,
+            key.size,
+            key.style
+        ),
+    }
 }
 
 /// A [CacheLevel] is a thread-safe cache level of a multi-level cache.
@@ -79,6 +221,13 @@ pub trait LocalCacheLevel {
     /// Sets some optional [ProfileData] to the [CacheLevel] for a profile [Uuid].
     async fn set_profile(&self, key: &Uuid, entry: Entry<ProfileData>);
 
+    /// Gets some [UuidData] from the [CacheLevel] for multiple case-insensitive usernames at once. The
+    /// entries are returned in the order of the usernames.
+    async fn get_uuids_batch(&self, keys: &[String]) -> Vec<Option<Entry<UuidData>>>;
+
+    /// Sets some optional [UuidData] to the [CacheLevel] for multiple case-insensitive usernames at once.
+    async fn set_uuids_batch(&self, entries: Vec<(String, Entry<UuidData>)>);
+
     /// Gets some [SkinData] from the [CacheLevel] for a profile [Uuid].
     async fn get_skin(&self, key: &Uuid) -> Option<Entry<SkinData>>;
 
@@ -92,8 +241,92 @@ pub trait LocalCacheLevel {
     async fn set_cape(&self, key: &Uuid, entry: Entry<CapeData>);
 
     /// Gets some [HeadData] from the [CacheLevel] for a profile [Uuid] with or without its overlay.
-    async fn get_head(&self, key: &(Uuid, bool)) -> Option<Entry<HeadData>>;
+    async fn get_head(&self, key: &HeadKey) -> Option<Entry<HeadData>>;
 
     /// Sets some optional [HeadData] to the [CacheLevel] for a profile [Uuid] with or without its overlay.
-    async fn set_head(&self, key: &(Uuid, bool), entry: Entry<HeadData>);
+    async fn set_head(&self, key: &HeadKey, entry: Entry<HeadData>);
+
+    /// Gets some [TextureData] from the [CacheLevel] for a (lowercase) texture id.
+    async fn get_texture(&self, key: &str) -> Option<Entry<TextureData>>;
+
+    /// Sets some optional [TextureData] to the [CacheLevel] for a (lowercase) texture id.
+    async fn set_texture(&self, key: &str, entry: Entry<TextureData>);
+
+    /// Gets the [BlockedServersData] from the [CacheLevel].
+    async fn get_blocked_servers(&self) -> Option<Entry<BlockedServersData>>;
+
+    /// Sets the [BlockedServersData] to the [CacheLevel].
+    async fn set_blocked_servers(&self, entry: Entry<BlockedServersData>);
+
+    /// Tries to acquire a short-lived fetch lock for a key (e.g. `profile.<uuid>`). Returns whether the
+    /// lock was acquired. [CacheLevels](CacheLevel) without (distributed) locking always acquire the lock.
+    async fn try_lock(&self, key: &str) -> bool;
+
+    /// Tries to acquire the short-lived fetch locks for multiple keys at once. Returns whether each lock
+    /// was acquired (in the order of the keys), see [try_lock](CacheLevel::try_lock).
+    async fn try_lock_batch(&self, keys: &[String]) -> Vec<bool>;
+
+    /// Releases the fetch locks for multiple keys at once (e.g. after the fetch completed), see
+    /// [try_lock](CacheLevel::try_lock).
+    async fn unlock(&self, keys: &[String]);
+
+    /// Increments the usage counter of a client in a quota window (e.g. `daily.2024-08-13`) and returns
+    /// the new count. The counters of a window expire after the time-to-live. Returns [None] if the
+    /// [CacheLevel] does not support usage counters.
+    async fn incr_usage(&self, window: &str, client: &str, ttl: Duration) -> Option<u64>;
+
+    /// Gets the usage counters of all clients in a quota window. Returns [None] if the [CacheLevel]
+    /// does not support usage counters.
+    async fn get_usage(&self, window: &str) -> Option<HashMap<String, u64>>;
+
+    /// Adds a profile [Uuid] to (or removes it from) the pinned profiles that are kept fresh on a
+    /// schedule. A profile is only added if less than `max` profiles are pinned, the check and the
+    /// addition are atomic. Returns [None] if the [CacheLevel] does not support pinned profiles.
+    async fn set_pinned(
+        &self,
+        key: &Uuid,
+        pinned: bool,
+        max: usize,
+    ) -> Option<Result<(), PinnedError>>;
+
+    /// Gets all pinned profile [Uuids](Uuid). Returns [None] if the [CacheLevel] does not support
+    /// pinned profiles.
+    async fn get_pinned(&self) -> Option<HashSet<Uuid>>;
+
+    /// Deletes all entries whose key matches the [KeyPattern] and returns the number of deleted
+    /// entries. Locks, usage counters and pinned profiles are not affected.
+    async fn purge(&self, pattern: &KeyPattern) -> u64;
+
+    /// Checks whether the [CacheLevel] is reachable. [CacheLevels](CacheLevel) without any remote
+    /// connection are always reachable.
+    async fn ping(&self) -> bool;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn key_pattern_matches() {
+        // given
+        let pattern = KeyPattern::new("head.09879557e47945a9b434a56377674627.*").unwrap();
+
+        // when
+        let native = pattern.matches("head.09879557e47945a9b434a56377674627.true");
+        let rendered = pattern.matches("head.09879557e47945a9b434a56377674627.false.64.png");
+        let other = pattern.matches("skin.09879557e47945a9b434a56377674627");
+
+        // then
+        assert!(native);
+        assert!(rendered);
+        assert!(!other);
+        assert!(KeyPattern::new("uuid.hydrofi?")
+            .unwrap()
+            .matches("uuid.hydrofin"));
+        assert!(!KeyPattern::new("uuid.hydrofi?")
+            .unwrap()
+            .matches("uuid.hydrofin2"));
+        assert!(KeyPattern::new("").is_none());
+        assert!(KeyPattern::new("head.[ab]*").is_none());
+    }
 }
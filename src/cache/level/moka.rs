@@ -1,155 +1,813 @@
-use crate::cache::entry::{CapeData, Entry, HeadData, ProfileData, SkinData, UuidData};
-use crate::cache::level::{metrics_get_handler, metrics_set_handler, CacheLevel};
+use crate::cache::entry::{
+    BlockedServersData, CapeData, Entry, HeadData, HeadKey, ProfileData, SkinData, TextureData,
+    UuidData,
+};
+use crate::cache::level::{
+    head_key, metrics_get_handler, metrics_set_handler, CacheLevel, KeyPattern, PinnedError,
+};
 use crate::settings;
+use crate::settings::MokaCacheEntry;
+use lazy_static::lazy_static;
 use moka::future::Cache;
+use moka::notification::RemovalCause;
+use moka::Expiry;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
+lazy_static! {
+    /// A counter for the evicted moka cache entries by their cause (`capacity`, `expired` or
+    /// `explicit`).
+    static ref CACHE_EVICTION_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "xenos_cache_evictions_total",
+        "The evicted cache entries by their cause.",
+        &["cache_variant", "request_type", "cause"]
+    )
+    .unwrap();
+}
+
+/// A [Shared] entry is a cache [Entry] behind an [Arc]. The moka caches hold shared entries, so that
+/// the clones of the entries within moka (e.g. on reads, evictions and iterations) only increase the
+/// reference count. Entries are cloned once they are handed out of the cache, but their large payloads
+/// (image bytes, profile properties and blocked server hashes) are shared and not copied.
+type Shared<D> = Arc<Entry<D>>;
+
+/// [MokaExpiry] is a per-entry [Expiry] policy for moka. It combines time-to-live and time-to-idle and
+/// uses the `*_empty` durations of the [MokaCacheEntry] for entries without data.
+#[derive(Debug, Clone)]
+struct MokaExpiry {
+    settings: MokaCacheEntry,
+}
+
+impl MokaExpiry {
+    /// Gets the time-to-live and time-to-idle for an [Entry], based on whether it has some data.
+    fn durations<D>(&self, entry: &Entry<D>) -> (Duration, Duration)
+    where
+        D: Clone + Debug + Eq + PartialEq,
+    {
+        match entry.has_some() {
+            true => (self.settings.ttl, self.settings.tti),
+            false => (self.settings.ttl_empty, self.settings.tti_empty),
+        }
+    }
+}
+
+impl<K, D> Expiry<K, Shared<D>> for MokaExpiry
+where
+    D: Clone + Debug + Eq + PartialEq,
+{
+    fn expire_after_create(&self, _: &K, entry: &Shared<D>, _: Instant) -> Option<Duration> {
+        let (ttl, tti) = self.durations(entry);
+        Some(ttl.min(tti))
+    }
+
+    fn expire_after_read(
+        &self,
+        _: &K,
+        entry: &Shared<D>,
+        read_at: Instant,
+        _: Option<Duration>,
+        last_modified_at: Instant,
+    ) -> Option<Duration> {
+        // the time-to-live is relative to the last modification while the time-to-idle is reset
+        let (ttl, tti) = self.durations(entry);
+        let ttl_left = ttl.saturating_sub(read_at.saturating_duration_since(last_modified_at));
+        Some(ttl_left.min(tti))
+    }
+
+    fn expire_after_update(
+        &self,
+        _: &K,
+        entry: &Shared<D>,
+        _: Instant,
+        _: Option<Duration>,
+    ) -> Option<Duration> {
+        let (ttl, tti) = self.durations(entry);
+        Some(ttl.min(tti))
+    }
+}
+
+/// [UsageWindow] holds the usage counters of all clients in a single quota window.
+#[derive(Debug)]
+struct UsageWindow {
+    ttl: Duration,
+    counters: Mutex<HashMap<String, u64>>,
+}
+
+/// [UsageExpiry] is a per-entry [Expiry] policy for moka that expires a [UsageWindow] after its
+/// time-to-live.
+#[derive(Debug, Clone)]
+struct UsageExpiry;
+
+impl<K> Expiry<K, Arc<UsageWindow>> for UsageExpiry {
+    fn expire_after_create(
+        &self,
+        _: &K,
+        window: &Arc<UsageWindow>,
+        _: Instant,
+    ) -> Option<Duration> {
+        Some(window.ttl)
+    }
+}
+
+/// [Weighted] data has an (estimated) size in bytes. It is used to weigh the entries of moka caches
+/// with a capacity in bytes.
+trait Weighted {
+    /// Gets the (estimated) size of the data in bytes.
+    fn weight(&self) -> usize;
+}
+
+impl Weighted for UuidData {
+    fn weight(&self) -> usize {
+        self.username.len() + size_of::<Uuid>()
+    }
+}
+
+impl Weighted for ProfileData {
+    fn weight(&self) -> usize {
+        let properties: usize = self
+            .properties
+            .iter()
+            .map(|prop| {
+                prop.name.len() + prop.value.len() + prop.signature.as_ref().map_or(0, String::len)
+            })
+            .sum();
+        let actions: usize = self.profile_actions.iter().map(String::len).sum();
+        size_of::<Uuid>() + self.name.len() + properties + actions
+    }
+}
+
+impl Weighted for SkinData {
+    fn weight(&self) -> usize {
+        self.bytes.len() + self.model.len()
+    }
+}
+
+impl Weighted for CapeData {
+    fn weight(&self) -> usize {
+        self.bytes.len() + self.texture_id.as_ref().map_or(0, String::len)
+    }
+}
+
+impl Weighted for HeadData {
+    fn weight(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl Weighted for TextureData {
+    fn weight(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl Weighted for BlockedServersData {
+    fn weight(&self) -> usize {
+        self.hashes.iter().map(String::len).sum()
+    }
+}
+
+/// Gets the (estimated) size of a cache entry in bytes, including the size of its key.
+fn entry_weight<K, D>(_: &K, entry: &Shared<D>) -> u32
+where
+    D: Clone + Debug + Eq + PartialEq + Weighted,
+{
+    let weight = size_of::<K>() + size_of::<Entry<D>>() + entry.data.as_ref().map_or(0, D::weight);
+    weight.try_into().unwrap_or(u32::MAX)
+}
+
+/// Builds a new moka [Cache] for a cache entry type using a per-entry [MokaExpiry] policy. The capacity
+/// is either the number of entries or, if configured, the (estimated) size of the entries in bytes.
+/// Evictions are counted by their cause.
+fn build_cache<K, D>(request_type: &'static str, settings: &MokaCacheEntry) -> Cache<K, Shared<D>>
+where
+    K: std::hash::Hash + Eq + Send + Sync + 'static,
+    D: Clone + Debug + Eq + PartialEq + Send + Sync + Weighted + 'static,
+{
+    let expiry = MokaExpiry {
+        settings: settings.clone(),
+    };
+    let mut builder =
+        Cache::builder()
+            .expire_after(expiry)
+            .eviction_listener(move |_, _, cause| {
+                if let Some(cause) = eviction_cause(cause) {
+                    CACHE_EVICTION_COUNTER
+                        .with_label_values(&["moka", request_type, cause])
+                        .inc();
+                }
+            });
+    if let Some(initial_capacity) = settings.initial_capacity {
+        builder = builder.initial_capacity(initial_capacity);
+    }
+    match settings.max_bytes {
+        Some(max_bytes) => builder
+            .max_capacity(max_bytes)
+            .weigher(entry_weight::<K, D>)
+            .build(),
+        None => builder.max_capacity(settings.cap).build(),
+    }
+}
+
+/// Gets the cause label of an evicted [Entry]. Replaced entries are not evicted. Moka does not tell
+/// the time-to-live and time-to-idle apart and counts both from the insertion into moka (not from the
+/// timestamp of the entry), so all expired entries share a single cause.
+fn eviction_cause(cause: RemovalCause) -> Option<&'static str> {
+    match cause {
+        RemovalCause::Size => Some("capacity"),
+        RemovalCause::Explicit => Some("explicit"),
+        RemovalCause::Replaced => None,
+        RemovalCause::Expired => Some("expired"),
+    }
+}
+
+/// Invalidates all entries of a moka [Cache] whose key matches the [KeyPattern] and returns their
+/// number.
+async fn purge_cache<K, D>(
+    cache: &Cache<K, Shared<D>>,
+    pattern: &KeyPattern,
+    key: impl Fn(&K) -> String,
+) -> u64
+where
+    K: std::hash::Hash + Eq + Send + Sync + 'static,
+    D: Clone + Debug + Eq + PartialEq + Send + Sync + 'static,
+{
+    let keys: Vec<_> = cache
+        .iter()
+        .map(|(key, _)| key)
+        .filter(|entry| pattern.matches(&key(entry)))
+        .collect();
+    for entry in &keys {
+        cache.invalidate(entry.as_ref()).await;
+    }
+    keys.len() as u64
+}
+
 /// [Moka Cache](MokaCache) is a [CacheLevel] implementation using moka. It is a thread-safe,
 /// futures-aware concurrent in-memory cache. The cache has a configurable maximum capacity and additional
-/// expiration (delete) policies with time-to-live and time-to-idle.
+/// per-entry expiration (delete) policies with time-to-live and time-to-idle. Empty entries use their
+/// own (usually shorter) time-to-live and time-to-idle.
 #[derive(Debug)]
 pub struct MokaCache {
-    #[allow(dead_code)] // will be used in the future for per-element ttl/tti
-    settings: settings::MokaCache,
     // caches
-    uuids: Cache<String, Entry<UuidData>>,
-    profiles: Cache<Uuid, Entry<ProfileData>>,
-    skins: Cache<Uuid, Entry<SkinData>>,
-    capes: Cache<Uuid, Entry<CapeData>>,
-    heads: Cache<(Uuid, bool), Entry<HeadData>>,
+    uuids: Cache<String, Shared<UuidData>>,
+    profiles: Cache<Uuid, Shared<ProfileData>>,
+    skins: Cache<Uuid, Shared<SkinData>>,
+    capes: Cache<Uuid, Shared<CapeData>>,
+    heads: Cache<HeadKey, Shared<HeadData>>,
+    textures: Cache<String, Shared<TextureData>>,
+    blocked_servers: Cache<(), Shared<BlockedServersData>>,
+    usage: Cache<String, Arc<UsageWindow>>,
+    pinned: Mutex<HashSet<Uuid>>,
 }
 
 impl MokaCache {
     pub fn new(settings: settings::MokaCache) -> Self {
         Self {
-            settings: settings.clone(),
-            uuids: Cache::builder()
-                .max_capacity(settings.entries.uuid.cap)
-                .time_to_live(settings.entries.uuid.ttl)
-                .time_to_idle(settings.entries.uuid.tti)
-                .build(),
-            profiles: Cache::builder()
-                .max_capacity(settings.entries.profile.cap)
-                .time_to_live(settings.entries.profile.ttl)
-                .time_to_idle(settings.entries.profile.tti)
-                .build(),
-            skins: Cache::builder()
-                .max_capacity(settings.entries.skin.cap)
-                .time_to_live(settings.entries.skin.ttl)
-                .time_to_idle(settings.entries.skin.tti)
-                .build(),
-            capes: Cache::builder()
-                .max_capacity(settings.entries.cape.cap)
-                .time_to_live(settings.entries.cape.ttl)
-                .time_to_idle(settings.entries.cape.tti)
-                .build(),
-            heads: Cache::builder()
-                .max_capacity(settings.entries.head.cap)
-                .time_to_live(settings.entries.head.ttl)
-                .time_to_idle(settings.entries.head.tti)
-                .build(),
+            uuids: build_cache("uuid", &settings.entries.uuid),
+            profiles: build_cache("profile", &settings.entries.profile),
+            skins: build_cache("skin", &settings.entries.skin),
+            capes: build_cache("cape", &settings.entries.cape),
+            heads: build_cache("head", &settings.entries.head),
+            textures: build_cache("texture", &settings.entries.texture),
+            blocked_servers: build_cache("blocked_servers", &settings.entries.blocked_servers),
+            usage: Cache::builder().expire_after(UsageExpiry).build(),
+            pinned: Mutex::new(HashSet::new()),
         }
     }
-}
 
-impl CacheLevel for MokaCache {
-    #[tracing::instrument(skip(self))]
+    /// Utility for getting some [Entry] from one of the moka caches. Hits are cloned out of the cache,
+    /// but their payloads are [Shared].
+    #[tracing::instrument(skip(self, cache))]
     #[metrics::metrics(
         metric = "cache_get",
-        labels(cache_variant = "moka", request_type = "uuid"),
+        labels(cache_variant = "moka", request_type = %request_type),
         handler = metrics_get_handler
     )]
-    async fn get_uuid(&self, key: &str) -> Option<Entry<UuidData>> {
-        self.uuids.get(key).await
+    async fn get<K, D>(
+        &self,
+        request_type: &str,
+        cache: &Cache<K, Shared<D>>,
+        key: K,
+    ) -> Option<Entry<D>>
+    where
+        K: std::hash::Hash + Eq + Debug + Send + Sync + 'static,
+        D: Clone + Debug + Eq + PartialEq + Send + Sync + 'static,
+    {
+        cache.get(&key).await.map(Arc::unwrap_or_clone)
     }
 
-    #[tracing::instrument(skip(self))]
+    /// Utility for setting some [Entry] to one of the moka caches.
+    #[tracing::instrument(skip(self, cache))]
     #[metrics::metrics(
         metric = "cache_set",
-        labels(cache_variant = "moka", request_type = "uuid"),
+        labels(cache_variant = "moka", request_type = %request_type),
         handler = metrics_set_handler
     )]
+    async fn set<K, D>(
+        &self,
+        request_type: &str,
+        cache: &Cache<K, Shared<D>>,
+        key: K,
+        entry: Entry<D>,
+    ) where
+        K: std::hash::Hash + Eq + Debug + Send + Sync + 'static,
+        D: Clone + Debug + Eq + PartialEq + Send + Sync + 'static,
+    {
+        cache.insert(key, Arc::new(entry)).await
+    }
+}
+
+impl CacheLevel for MokaCache {
+    #[tracing::instrument(skip(self))]
+    async fn get_uuid(&self, key: &str) -> Option<Entry<UuidData>> {
+        self.get("uuid", &self.uuids, key.to_lowercase()).await
+    }
+
+    #[tracing::instrument(skip(self))]
     async fn set_uuid(&self, key: &str, entry: Entry<UuidData>) {
-        self.uuids.insert(key.to_string(), entry).await
+        self.set("uuid", &self.uuids, key.to_lowercase(), entry)
+            .await
     }
 
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_get",
-        labels(cache_variant = "moka", request_type = "profile"),
-        handler = metrics_get_handler
-    )]
     async fn get_profile(&self, key: &Uuid) -> Option<Entry<ProfileData>> {
-        self.profiles.get(key).await
+        self.get("profile", &self.profiles, *key).await
     }
 
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_set",
-        labels(cache_variant = "moka", request_type = "profile"),
-        handler = metrics_set_handler
-    )]
     async fn set_profile(&self, key: &Uuid, entry: Entry<ProfileData>) {
-        self.profiles.insert(*key, entry).await
+        self.set("profile", &self.profiles, *key, entry).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_uuids_batch(&self, keys: &[String]) -> Vec<Option<Entry<UuidData>>> {
+        let mut entries = Vec::with_capacity(keys.len());
+        for key in keys {
+            entries.push(self.get_uuid(key).await);
+        }
+        entries
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn set_uuids_batch(&self, entries: Vec<(String, Entry<UuidData>)>) {
+        for (key, entry) in entries {
+            self.set_uuid(&key, entry).await;
+        }
     }
 
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_get",
-        labels(cache_variant = "moka", request_type = "skin"),
-        handler = metrics_get_handler
-    )]
     async fn get_skin(&self, key: &Uuid) -> Option<Entry<SkinData>> {
-        self.skins.get(key).await
+        self.get("skin", &self.skins, *key).await
     }
 
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_set",
-        labels(cache_variant = "moka", request_type = "skin"),
-        handler = metrics_set_handler
-    )]
     async fn set_skin(&self, key: &Uuid, entry: Entry<SkinData>) {
-        self.skins.insert(*key, entry).await
+        self.set("skin", &self.skins, *key, entry).await
     }
 
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_get",
-        labels(cache_variant = "moka", request_type = "cape"),
-        handler = metrics_get_handler
-    )]
     async fn get_cape(&self, key: &Uuid) -> Option<Entry<CapeData>> {
-        self.capes.get(key).await
+        self.get("cape", &self.capes, *key).await
     }
 
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_set",
-        labels(cache_variant = "moka", request_type = "cape"),
-        handler = metrics_set_handler
-    )]
     async fn set_cape(&self, uuid: &Uuid, entry: Entry<CapeData>) {
-        self.capes.insert(*uuid, entry).await
+        self.set("cape", &self.capes, *uuid, entry).await
     }
 
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_get",
-        labels(cache_variant = "moka", request_type = "head"),
-        handler = metrics_get_handler
-    )]
-    async fn get_head(&self, key: &(Uuid, bool)) -> Option<Entry<HeadData>> {
-        self.heads.get(key).await
+    async fn get_head(&self, key: &HeadKey) -> Option<Entry<HeadData>> {
+        self.get("head", &self.heads, *key).await
     }
 
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_set",
-        labels(cache_variant = "moka", request_type = "head"),
-        handler = metrics_set_handler
-    )]
-    async fn set_head(&self, key: &(Uuid, bool), entry: Entry<HeadData>) {
-        self.heads.insert(*key, entry).await
+    async fn set_head(&self, key: &HeadKey, entry: Entry<HeadData>) {
+        self.set("head", &self.heads, *key, entry).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_texture(&self, key: &str) -> Option<Entry<TextureData>> {
+        self.get("texture", &self.textures, key.to_string()).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn set_texture(&self, key: &str, entry: Entry<TextureData>) {
+        self.set("texture", &self.textures, key.to_string(), entry)
+            .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_blocked_servers(&self) -> Option<Entry<BlockedServersData>> {
+        self.get("blocked_servers", &self.blocked_servers, ()).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn set_blocked_servers(&self, entry: Entry<BlockedServersData>) {
+        self.set("blocked_servers", &self.blocked_servers, (), entry)
+            .await
+    }
+
+    async fn try_lock(&self, _: &str) -> bool {
+        // moka is a local cache, there are no other instances to coordinate with
+        true
+    }
+
+    async fn try_lock_batch(&self, keys: &[String]) -> Vec<bool> {
+        // moka is a local cache, there are no other instances to coordinate with
+        vec![true; keys.len()]
+    }
+
+    async fn unlock(&self, _: &[String]) {}
+
+    #[tracing::instrument(skip(self))]
+    async fn incr_usage(&self, window: &str, client: &str, ttl: Duration) -> Option<u64> {
+        let window = self
+            .usage
+            .get_with(window.to_string(), async {
+                Arc::new(UsageWindow {
+                    ttl,
+                    counters: Mutex::new(HashMap::new()),
+                })
+            })
+            .await;
+        let mut counters = window.counters.lock().expect("usage counters poisoned");
+        let count = counters.entry(client.to_string()).or_default();
+        *count += 1;
+        Some(*count)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_usage(&self, window: &str) -> Option<HashMap<String, u64>> {
+        let usage = match self.usage.get(window).await {
+            Some(window) => window
+                .counters
+                .lock()
+                .expect("usage counters poisoned")
+                .clone(),
+            None => HashMap::new(),
+        };
+        Some(usage)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn set_pinned(
+        &self,
+        key: &Uuid,
+        pinned: bool,
+        max: usize,
+    ) -> Option<Result<(), PinnedError>> {
+        let mut keys = self.pinned.lock().expect("pinned profiles poisoned");
+        if !pinned {
+            keys.remove(key);
+            return Some(Ok(()));
+        }
+        if !keys.contains(key) && keys.len() >= max {
+            return Some(Err(PinnedError::Exceeded));
+        }
+        keys.insert(*key);
+        Some(Ok(()))
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_pinned(&self) -> Option<HashSet<Uuid>> {
+        Some(
+            self.pinned
+                .lock()
+                .expect("pinned profiles poisoned")
+                .clone(),
+        )
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn purge(&self, pattern: &KeyPattern) -> u64 {
+        let uuids = purge_cache(&self.uuids, pattern, |key| format!("uuid.{}", key)).await;
+        let profiles = purge_cache(&self.profiles, pattern, |key| {
+            format!("profile.{}", key.simple())
+        })
+        .await;
+        let skins = purge_cache(&self.skins, pattern, |key| format!("skin.{}", key.simple())).await;
+        let capes = purge_cache(&self.capes, pattern, |key| format!("cape.{}", key.simple())).await;
+        let heads = purge_cache(&self.heads, pattern, head_key).await;
+        let textures = purge_cache(&self.textures, pattern, |key| format!("texture.{}", key)).await;
+        let blocked_servers = purge_cache(&self.blocked_servers, pattern, |_| {
+            "blocked_servers".to_string()
+        })
+        .await;
+        uuids + profiles + skins + capes + heads + textures + blocked_servers
+    }
+
+    async fn ping(&self) -> bool {
+        // moka is a local cache, it is always reachable
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::entry::Dated;
+    use crate::mojang::ProfileProperty;
+    use crate::settings::CacheEntries;
+    use crate::tenant;
+    use bytes::Bytes;
+    use std::time::Duration;
+    use uuid::uuid;
+
+    fn new_moka_settings(entry: MokaCacheEntry) -> settings::MokaCache {
+        settings::MokaCache {
+            entries: CacheEntries {
+                uuid: entry.clone(),
+                profile: entry.clone(),
+                skin: entry.clone(),
+                cape: entry.clone(),
+                head: entry.clone(),
+                texture: entry.clone(),
+                blocked_servers: entry.clone(),
+            },
+        }
+    }
+
+    fn new_uuid_data() -> UuidData {
+        UuidData {
+            username: "Hydrofin".to_string(),
+            uuid: uuid!("09879557e47945a9b434a56377674627"),
+        }
+    }
+
+    #[tokio::test]
+    async fn get_shares_payload() {
+        // given
+        let cache = MokaCache::new(new_moka_settings(MokaCacheEntry {
+            cap: 10,
+            max_bytes: None,
+            initial_capacity: None,
+            ttl: Duration::from_secs(100),
+            ttl_empty: Duration::from_secs(100),
+            tti: Duration::from_secs(100),
+            tti_empty: Duration::from_secs(100),
+        }));
+        let uuid = uuid!("09879557e47945a9b434a56377674627");
+        let profile = ProfileData {
+            id: uuid,
+            name: "Hydrofin".to_string(),
+            properties: vec![ProfileProperty {
+                name: "textures".to_string(),
+                value: "e30=".to_string(),
+                signature: Some("signature".to_string()),
+            }]
+            .into(),
+            profile_actions: vec![],
+        };
+        let skin = SkinData {
+            bytes: Bytes::from(vec![0u8; 1024]),
+            model: "classic".to_string(),
+            default: false,
+            suppressed: false,
+        };
+        cache.set_profile(&uuid, Dated::from(Some(profile))).await;
+        cache.set_skin(&uuid, Dated::from(Some(skin))).await;
+
+        // when
+        let first_profile = cache.get_profile(&uuid).await.unwrap().data.unwrap();
+        let second_profile = cache.get_profile(&uuid).await.unwrap().data.unwrap();
+        let first_skin = cache.get_skin(&uuid).await.unwrap().data.unwrap();
+        let second_skin = cache.get_skin(&uuid).await.unwrap().data.unwrap();
+
+        // then
+        assert!(Arc::ptr_eq(
+            &first_profile.properties,
+            &second_profile.properties
+        ));
+        assert_eq!(first_skin.bytes.as_ptr(), second_skin.bytes.as_ptr());
+    }
+
+    #[tokio::test]
+    async fn tenant_shared() {
+        // given
+        let cache = MokaCache::new(new_moka_settings(MokaCacheEntry {
+            cap: 10,
+            max_bytes: None,
+            initial_capacity: None,
+            ttl: Duration::from_secs(100),
+            ttl_empty: Duration::from_secs(100),
+            tti: Duration::from_secs(100),
+            tti_empty: Duration::from_secs(100),
+        }));
+        let entry = Dated::from(Some(new_uuid_data()));
+        tenant::scope(
+            Some(Arc::from("network")),
+            cache.set_uuid("hydrofin", entry),
+        )
+        .await;
+
+        // when
+        let other = tenant::scope(Some(Arc::from("other")), cache.get_uuid("hydrofin")).await;
+        let default = cache.get_uuid("hydrofin").await;
+
+        // then
+        assert!(matches!(other, Some(entry) if entry.data == Some(new_uuid_data())));
+        assert!(matches!(default, Some(entry) if entry.data == Some(new_uuid_data())));
+    }
+
+    #[tokio::test]
+    async fn purge_pattern() {
+        // given
+        let cache = MokaCache::new(new_moka_settings(MokaCacheEntry {
+            cap: 10,
+            max_bytes: None,
+            initial_capacity: None,
+            ttl: Duration::from_secs(100),
+            ttl_empty: Duration::from_secs(100),
+            tti: Duration::from_secs(100),
+            tti_empty: Duration::from_secs(100),
+        }));
+        let uuid = new_uuid_data().uuid;
+        let rendered = HeadKey {
+            size: 64,
+            ..HeadKey::new(uuid, false)
+        };
+        cache
+            .set_head(&HeadKey::new(uuid, true), Dated::from(None))
+            .await;
+        cache.set_head(&rendered, Dated::from(None)).await;
+        cache.set_skin(&uuid, Dated::from(None)).await;
+        cache.set_uuid("hydrofin", Dated::from(None)).await;
+
+        // when
+        let heads = cache
+            .purge(&KeyPattern::new(&format!("head.{}.*", uuid.simple())).unwrap())
+            .await;
+        let uuids = cache.purge(&KeyPattern::new("uuid.*").unwrap()).await;
+
+        // then
+        assert_eq!(2, heads);
+        assert_eq!(1, uuids);
+        assert!(cache.get_head(&rendered).await.is_none());
+        assert!(cache.get_skin(&uuid).await.is_some());
+        assert!(cache.get_uuid("hydrofin").await.is_none());
+    }
+
+    fn new_expiry(ttl: u64, ttl_empty: u64, tti: u64, tti_empty: u64) -> MokaExpiry {
+        MokaExpiry {
+            settings: MokaCacheEntry {
+                cap: 10,
+                max_bytes: None,
+                initial_capacity: None,
+                ttl: Duration::from_secs(ttl),
+                ttl_empty: Duration::from_secs(ttl_empty),
+                tti: Duration::from_secs(tti),
+                tti_empty: Duration::from_secs(tti_empty),
+            },
+        }
+    }
+
+    #[test]
+    fn expire_empty_separately() {
+        // given
+        let expiry = new_expiry(100, 5, 50, 100);
+        let some: Shared<UuidData> = Arc::new(Dated::from(Some(new_uuid_data())));
+        let none: Shared<UuidData> = Arc::new(Dated::from(None));
+        let created = Instant::now();
+
+        // when
+        let some = expiry.expire_after_create(&"hydrofin", &some, created);
+        let none = expiry.expire_after_create(&"xxslayer42xx", &none, created);
+
+        // then
+        assert_eq!(Some(Duration::from_secs(50)), some);
+        assert_eq!(Some(Duration::from_secs(5)), none);
+    }
+
+    #[test]
+    fn expire_tti_reset_on_read() {
+        // given
+        let expiry = new_expiry(100, 100, 10, 10);
+        let entry: Shared<UuidData> = Arc::new(Dated::from(Some(new_uuid_data())));
+        let created = Instant::now();
+
+        // when
+        let first = expiry.expire_after_read(
+            &"hydrofin",
+            &entry,
+            created + Duration::from_secs(8),
+            None,
+            created,
+        );
+        let second = expiry.expire_after_read(
+            &"hydrofin",
+            &entry,
+            created + Duration::from_secs(16),
+            None,
+            created,
+        );
+
+        // then
+        assert_eq!(Some(Duration::from_secs(10)), first);
+        assert_eq!(Some(Duration::from_secs(10)), second);
+    }
+
+    #[test]
+    fn expire_ttl_not_reset_on_read() {
+        // given
+        let expiry = new_expiry(15, 15, 10, 10);
+        let entry: Shared<UuidData> = Arc::new(Dated::from(Some(new_uuid_data())));
+        let created = Instant::now();
+
+        // when
+        let first = expiry.expire_after_read(
+            &"hydrofin",
+            &entry,
+            created + Duration::from_secs(8),
+            None,
+            created,
+        );
+        let second = expiry.expire_after_read(
+            &"hydrofin",
+            &entry,
+            created + Duration::from_secs(20),
+            None,
+            created,
+        );
+
+        // then
+        assert_eq!(Some(Duration::from_secs(7)), first);
+        assert_eq!(Some(Duration::ZERO), second);
+    }
+
+    #[test]
+    fn expire_reset_on_update() {
+        // given
+        let expiry = new_expiry(15, 5, 10, 10);
+        let entry: Shared<UuidData> = Arc::new(Dated::from(None));
+        let updated = Instant::now() + Duration::from_secs(20);
+
+        // when
+        let remaining = expiry.expire_after_update(&"hydrofin", &entry, updated, None);
+
+        // then
+        assert_eq!(Some(Duration::from_secs(5)), remaining);
+    }
+
+    #[tokio::test]
+    async fn max_bytes_weighted() {
+        // given
+        let cache = MokaCache::new(new_moka_settings(MokaCacheEntry {
+            cap: 10,
+            max_bytes: Some(3 * 1024),
+            initial_capacity: None,
+            ttl: Duration::from_secs(100),
+            ttl_empty: Duration::from_secs(100),
+            tti: Duration::from_secs(100),
+            tti_empty: Duration::from_secs(100),
+        }));
+        let skin = SkinData {
+            bytes: vec![0; 1024].into(),
+            model: "classic".to_string(),
+            default: false,
+            suppressed: false,
+        };
+
+        // when
+        for id in 0..10 {
+            let uuid = Uuid::from_u128(id);
+            cache.set_skin(&uuid, Dated::from(Some(skin.clone()))).await;
+            cache.set_uuid(&id.to_string(), Dated::from(None)).await;
+        }
+        cache.skins.run_pending_tasks().await;
+        cache.uuids.run_pending_tasks().await;
+
+        // then
+        assert!(cache.skins.weighted_size() <= 3 * 1024);
+        assert!(cache.skins.entry_count() < 3);
+        assert_eq!(cache.uuids.entry_count(), 10);
+    }
+
+    #[test]
+    fn eviction_causes() {
+        // given
+        let expiry = new_expiry(100, 100, 10, 10);
+        let fresh: Entry<UuidData> = Dated::from(None);
+        let aged: Shared<UuidData> = Arc::new(Dated {
+            timestamp: fresh.timestamp - 100,
+            ..fresh
+        });
+
+        // when
+        let reinserted = expiry.expire_after_create(&"hydrofin", &aged, Instant::now());
+        let capacity = eviction_cause(RemovalCause::Size);
+        let explicit = eviction_cause(RemovalCause::Explicit);
+        let replaced = eviction_cause(RemovalCause::Replaced);
+        let expired = eviction_cause(RemovalCause::Expired);
+
+        // then
+        assert_eq!(Some(Duration::from_secs(10)), reinserted);
+        assert_eq!(Some("capacity"), capacity);
+        assert_eq!(Some("explicit"), explicit);
+        assert_eq!(None, replaced);
+        assert_eq!(Some("expired"), expired);
     }
 }
@@ -1,5 +1,10 @@
-use crate::cache::entry::{CapeData, Entry, HeadData, ProfileData, SkinData, UuidData};
-use crate::cache::level::CacheLevel;
+use crate::cache::entry::{
+    BlockedServersData, CapeData, Entry, HeadData, HeadKey, ProfileData, SkinData, TextureData,
+    UuidData,
+};
+use crate::cache::level::{CacheLevel, KeyPattern, PinnedError};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 use uuid::Uuid;
 
 /// [No Cache](NoCache) is a [CacheLevel] implementation that does nothing. It can be used to disable
@@ -20,6 +25,12 @@ impl CacheLevel for NoCache {
 
     async fn set_profile(&self, _: &Uuid, _: Entry<ProfileData>) {}
 
+    async fn get_uuids_batch(&self, keys: &[String]) -> Vec<Option<Entry<UuidData>>> {
+        vec![None; keys.len()]
+    }
+
+    async fn set_uuids_batch(&self, _: Vec<(String, Entry<UuidData>)>) {}
+
     async fn get_skin(&self, _: &Uuid) -> Option<Entry<SkinData>> {
         None
     }
@@ -32,9 +43,56 @@ impl CacheLevel for NoCache {
 
     async fn set_cape(&self, _: &Uuid, _: Entry<CapeData>) {}
 
-    async fn get_head(&self, _: &(Uuid, bool)) -> Option<Entry<HeadData>> {
+    async fn get_head(&self, _: &HeadKey) -> Option<Entry<HeadData>> {
+        None
+    }
+
+    async fn set_head(&self, _: &HeadKey, _: Entry<HeadData>) {}
+
+    async fn get_texture(&self, _: &str) -> Option<Entry<TextureData>> {
+        None
+    }
+
+    async fn set_texture(&self, _: &str, _: Entry<TextureData>) {}
+
+    async fn get_blocked_servers(&self) -> Option<Entry<BlockedServersData>> {
+        None
+    }
+
+    async fn set_blocked_servers(&self, _: Entry<BlockedServersData>) {}
+
+    async fn try_lock(&self, _: &str) -> bool {
+        true
+    }
+
+    async fn try_lock_batch(&self, keys: &[String]) -> Vec<bool> {
+        vec![true; keys.len()]
+    }
+
+    async fn unlock(&self, _: &[String]) {}
+
+    async fn incr_usage(&self, _: &str, _: &str, _: Duration) -> Option<u64> {
+        None
+    }
+
+    async fn get_usage(&self, _: &str) -> Option<HashMap<String, u64>> {
+        None
+    }
+
+    async fn set_pinned(&self, _: &Uuid, _: bool, _: usize) -> Option<Result<(), PinnedError>> {
+        None
+    }
+
+    async fn get_pinned(&self) -> Option<HashSet<Uuid>> {
         None
     }
 
-    async fn set_head(&self, _: &(Uuid, bool), _: Entry<HeadData>) {}
+    async fn purge(&self, _: &KeyPattern) -> u64 {
+        0
+    }
+
+    async fn ping(&self) -> bool {
+        // there is nothing to reach, so it never makes the service unhealthy
+        true
+    }
 }
@@ -1,34 +1,125 @@
-use crate::cache::entry::{CapeData, Entry, HeadData, ProfileData, SkinData, UuidData};
-use crate::cache::level::{metrics_get_handler, metrics_set_handler, CacheLevel};
+use crate::cache::entry::{
+    BlockedServersData, CapeData, Entry, HeadData, HeadKey, ProfileData, SkinData, TextureData,
+    UuidData,
+};
+use crate::cache::level::{
+    head_key, metrics_get_batch_handler, metrics_get_handler, metrics_set_handler, CacheLevel,
+    KeyPattern, PinnedError,
+};
 use crate::settings;
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter_vec, IntCounterVec};
 use redis::aio::ConnectionManager;
 use redis::{
-    from_redis_value, AsyncCommands, FromRedisValue, RedisResult, RedisWrite, SetExpiry,
-    SetOptions, ToRedisArgs, Value,
+    AsyncCommands, ExistenceCheck, RedisResult, RedisWrite, Script, SetExpiry, SetOptions,
+    ToRedisArgs,
 };
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fmt::Debug;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::Mutex;
-use tracing::error;
+use tracing::{error, warn};
 use uuid::Uuid;
 
-/// Builds a sting key for the redis cache. The key is prefixed with "xenos".
+lazy_static! {
+    /// A counter for the redis entries that could not be decoded by their entry version.
+    static ref CACHE_DECODE_FAILURE_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "xenos_cache_decode_failures_total",
+        "The redis cache entries that could not be decoded by their entry version.",
+        &["request_type", "version"]
+    )
+    .unwrap();
+}
+
+/// The version of the serialized redis entries. It has to be increased (and a migration added to
+/// [migrate_entry]) whenever the serialized format of an entry changes incompatibly. Entries of
+/// previous versions (without version field) have version `0`.
+const ENTRY_VERSION: u64 = 1;
+
+/// The field of the serialized redis entries that holds their version.
+const VERSION_FIELD: &str = "v";
+
+/// Builds a sting key for the redis cache. The key is prefixed with the provided key prefix (e.g. "xenos").
 macro_rules! key {
-    ($x1:expr) => {
-        format!("xenos.{}", $x1)
+    ($prefix:expr, $x1:expr) => {
+        format!("{}.{}", $prefix, $x1)
+    };
+    ($prefix:expr, $x1:expr, $x2:expr) => {
+        format!("{}.{}.{}", $prefix, $x1, $x2)
     };
-    ($x1:expr, $x2:expr) => {
-        format!("xenos.{}.{}", $x1, $x2)
+    ($prefix:expr, $x1:expr, $x2:expr, $x3:expr) => {
+        format!("{}.{}.{}.{}", $prefix, $x1, $x2, $x3)
     };
-    ($x1:expr, $x2:expr, $x3:expr) => {
-        format!("xenos.{}.{}.{}", $x1, $x2, $x3)
+    ($prefix:expr, $x1:expr, $x2:expr, $x3:expr, $x4:expr, $x5:expr) => {
+        format!("{}.{}.{}.{}.{}.{}", $prefix, $x1, $x2, $x3, $x4, $x5)
     };
 }
 
+lazy_static! {
+    /// The script that adds a profile to (or removes it from) the pinned profiles. A profile is only
+    /// added if less than the maximum number of profiles are pinned. It returns whether it succeeded.
+    static ref PINNED_SCRIPT: Script = Script::new(
+        r"
+        if ARGV[2] ~= '1' then
+            redis.call('SREM', KEYS[1], ARGV[1])
+            return 1
+        end
+        if redis.call('SISMEMBER', KEYS[1], ARGV[1]) == 0
+            and redis.call('SCARD', KEYS[1]) >= tonumber(ARGV[3]) then
+            return 0
+        end
+        redis.call('SADD', KEYS[1], ARGV[1])
+        return 1
+        "
+    );
+
+    /// The script that increments the usage counter of a client in a window and returns it. The
+    /// expiry of the window is only set if it has none yet, so that it is not extended by every
+    /// request of the window (like `EXPIRE NX`, but also supported before redis 7).
+    static ref USAGE_SCRIPT: Script = Script::new(
+        r"
+        local count = redis.call('HINCRBY', KEYS[1], ARGV[1], 1)
+        if redis.call('TTL', KEYS[1]) == -1 then
+            redis.call('EXPIRE', KEYS[1], ARGV[2])
+        end
+        return count
+        "
+    );
+}
+
+/// The number of keys that are scanned (and deleted) at once while purging.
+const PURGE_BATCH_SIZE: usize = 500;
+
+/// Checks whether a key (without the key prefix) may be purged. Only the keys of cache entries (by
+/// their type and number of segments) are purged. Locks, usage counters and pinned profiles are not
+/// cache entries, and neither are the keys of other namespaces that share the key prefix (e.g.
+/// `staging.uuid.hydrofin` of a deployment without namespace).
+fn purgeable(key: &str) -> bool {
+    let segments: Vec<&str> = key.split('.').collect();
+    let is_uuid = |segment: &str| segment.len() == 32 && Uuid::try_parse(segment).is_ok();
+    match segments.as_slice() {
+        ["uuid" | "texture", _] => true,
+        ["profile" | "skin" | "cape", uuid] => is_uuid(uuid),
+        ["head", uuid, "true" | "false"] | ["head", uuid, "true" | "false", _, _] => is_uuid(uuid),
+        ["blocked_servers"] => true,
+        _ => false,
+    }
+}
+
+/// Gets the prefix of all keys of a [Redis Cache](RedisCache). It is the configured prefix followed by
+/// the optional namespace (e.g. `xenos.production`).
+fn key_prefix(settings: &settings::RedisCache) -> String {
+    match &settings.namespace {
+        Some(namespace) => key!(settings.prefix, namespace),
+        None => settings.prefix.clone(),
+    }
+}
+
 /// [Redis Cache](RedisCache) is a [CacheLevel] implementation using redis. The cache has an
 /// additional expiration (delete) policies with time-to-live.
 ///
@@ -37,25 +128,35 @@ macro_rules! key {
 /// for example, temporarily unavailable.
 pub struct RedisCache {
     settings: settings::RedisCache,
+    key_prefix: String,
     redis_manager: Arc<Mutex<ConnectionManager>>,
 }
 
 impl RedisCache {
-    /// Created a new [Redis Cache](RedisCache).
+    /// Created a new [Redis Cache](RedisCache). All keys are prefixed with the configured prefix and
+    /// optional namespace (e.g. `xenos.production.uuid.hydrofin`).
     pub fn new(con: ConnectionManager, settings: &settings::RedisCache) -> Self {
         Self {
             settings: settings.clone(),
+            key_prefix: key_prefix(settings),
             redis_manager: Arc::new(Mutex::new(con)),
         }
     }
 
     /// Utility for getting some [Entry] from redis. Handles errors by logging them and returning `None`.
+    /// The entry is migrated from its [version](ENTRY_VERSION), if necessary.
     #[tracing::instrument(skip(self))]
-    async fn get<D>(&self, key: String) -> Option<Entry<D>>
+    #[metrics::metrics(
+        metric = "cache_get",
+        labels(cache_variant = "redis", request_type = %request_type),
+        handler = metrics_get_handler
+    )]
+    async fn get<D>(&self, request_type: &str, key: String) -> Option<Entry<D>>
     where
         D: Clone + Debug + Eq + PartialEq + DeserializeOwned,
     {
-        self.redis_manager
+        let value: Option<String> = self
+            .redis_manager
             .lock()
             .await
             .get(key)
@@ -63,12 +164,102 @@ impl RedisCache {
             .unwrap_or_else(|err| {
                 error!("Failed to get value from redis: {:?}", err);
                 None
+            });
+        self.decode(request_type, &value?)
+    }
+
+    /// Utility for getting multiple [Entries](Entry) from redis at once (MGET). Handles errors by
+    /// logging them and returning `None` for all keys. The entries are returned in the order of the keys.
+    #[tracing::instrument(skip(self))]
+    #[metrics::metrics(
+        metric = "cache_get",
+        labels(cache_variant = "redis", request_type = %request_type),
+        handler = metrics_get_batch_handler
+    )]
+    async fn get_batch<D>(&self, request_type: &str, keys: Vec<String>) -> Vec<Option<Entry<D>>>
+    where
+        D: Clone + Debug + Eq + PartialEq + DeserializeOwned,
+    {
+        if keys.is_empty() {
+            return vec![];
+        }
+        // MGET is used explicitly, as `mget` falls back to GET for a single key
+        let values: Vec<Option<String>> = redis::cmd("MGET")
+            .arg(&keys)
+            .query_async(&mut *self.redis_manager.lock().await)
+            .await
+            .unwrap_or_else(|err| {
+                error!("Failed to get values from redis: {:?}", err);
+                vec![None; keys.len()]
+            });
+        values
+            .into_iter()
+            .map(|value| self.decode(request_type, &value?))
+            .collect()
+    }
+
+    /// Utility for setting multiple [Entries](Entry) to redis at once (pipeline). Handles errors by
+    /// logging them.
+    #[tracing::instrument(skip(self))]
+    #[metrics::metrics(
+        metric = "cache_set",
+        labels(cache_variant = "redis", request_type = %request_type),
+        handler = metrics_set_handler
+    )]
+    async fn set_batch<D>(
+        &self,
+        request_type: &str,
+        entries: Vec<(String, Entry<D>)>,
+        ttl: &Duration,
+    ) where
+        D: Clone + Debug + Eq + PartialEq + Send + Sync + Serialize,
+    {
+        if entries.is_empty() {
+            return;
+        }
+        let mut pipeline = redis::pipe();
+        for (key, entry) in entries {
+            pipeline
+                .set_options(
+                    key,
+                    entry,
+                    SetOptions::default().with_expiration(SetExpiry::EX(ttl.as_secs())),
+                )
+                .ignore();
+        }
+        pipeline
+            .query_async::<()>(&mut *self.redis_manager.lock().await)
+            .await
+            .unwrap_or_else(|err| {
+                error!("Failed to set values to redis: {:?}", err);
+            });
+    }
+
+    /// Utility for decoding a serialized [Entry]. Undecodable entries are counted, logged and treated
+    /// as misses (they are overwritten with the next fetch).
+    fn decode<D>(&self, request_type: &str, value: &str) -> Option<Entry<D>>
+    where
+        D: Clone + Debug + Eq + PartialEq + DeserializeOwned,
+    {
+        decode_entry(value)
+            .map_err(|(version, err)| {
+                let version = version.map_or("unknown".to_string(), |version| version.to_string());
+                CACHE_DECODE_FAILURE_COUNTER
+                    .with_label_values(&[request_type, &version])
+                    .inc();
+                warn!(version, error = %err, "failed to decode redis entry");
             })
+            .ok()
     }
 
     /// Utility for setting some [Entry] to redis. Handles errors by logging them.
     #[tracing::instrument(skip(self))]
-    async fn set<D>(&self, key: String, entry: Entry<D>, ttl: &Duration)
+    #[metrics::metrics(
+        metric = "cache_set",
+        labels(cache_variant = "redis", request_type = %request_type),
+        handler = metrics_set_handler
+    )]
+    async fn set<D>(&self, request_type: &str, key: String, entry: Entry<D>, ttl: &Duration)
     where
         D: Clone + Debug + Eq + PartialEq + Send + Sync + Serialize,
     {
@@ -92,131 +283,360 @@ impl Debug for RedisCache {
         // prints all fields except the redis connection
         f.debug_struct("RedisCache")
             .field("settings", &self.settings)
+            .field("key_prefix", &self.key_prefix)
             .finish()
     }
 }
 
 impl CacheLevel for RedisCache {
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_get",
-        labels(cache_variant = "redis", request_type = "uuid"),
-        handler = metrics_get_handler
-    )]
     async fn get_uuid(&self, key: &str) -> Option<Entry<UuidData>> {
-        let key = key!("uuid", key.to_lowercase());
-        self.get(key).await
+        let key = key!(self.key_prefix, "uuid", key.to_lowercase());
+        self.get("uuid", key).await
     }
 
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_set",
-        labels(cache_variant = "redis", request_type = "uuid"),
-        handler = metrics_set_handler
-    )]
     async fn set_uuid(&self, key: &str, entry: Entry<UuidData>) {
-        let key = key!("uuid", key.to_lowercase());
-        self.set(key, entry, &self.settings.entries.uuid.ttl).await
+        let key = key!(self.key_prefix, "uuid", key.to_lowercase());
+        self.set("uuid", key, entry, &self.settings.entries.uuid.ttl)
+            .await
     }
 
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_get",
-        labels(cache_variant = "redis", request_type = "profile"),
-        handler = metrics_get_handler
-    )]
     async fn get_profile(&self, key: &Uuid) -> Option<Entry<ProfileData>> {
-        let key = key!("profile", key.simple());
-        self.get(key).await
+        let key = key!(self.key_prefix, "profile", key.simple());
+        self.get("profile", key).await
     }
 
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_set",
-        labels(cache_variant = "redis", request_type = "profile"),
-        handler = metrics_set_handler
-    )]
     async fn set_profile(&self, key: &Uuid, entry: Entry<ProfileData>) {
-        let key = key!("profile", key.simple());
-        self.set(key, entry, &self.settings.entries.profile.ttl)
+        let key = key!(self.key_prefix, "profile", key.simple());
+        self.set("profile", key, entry, &self.settings.entries.profile.ttl)
+            .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_uuids_batch(&self, keys: &[String]) -> Vec<Option<Entry<UuidData>>> {
+        let prefix = &self.key_prefix;
+        let keys = keys
+            .iter()
+            .map(|key| key!(prefix, "uuid", key.to_lowercase()))
+            .collect();
+        self.get_batch("uuid", keys).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn set_uuids_batch(&self, entries: Vec<(String, Entry<UuidData>)>) {
+        let prefix = &self.key_prefix;
+        let entries = entries
+            .into_iter()
+            .map(|(key, entry)| (key!(prefix, "uuid", key.to_lowercase()), entry))
+            .collect();
+        self.set_batch("uuid", entries, &self.settings.entries.uuid.ttl)
             .await
     }
 
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_get",
-        labels(cache_variant = "redis", request_type = "skin"),
-        handler = metrics_get_handler
-    )]
     async fn get_skin(&self, key: &Uuid) -> Option<Entry<SkinData>> {
-        let key = key!("skin", key.simple());
-        self.get(key).await
+        let key = key!(self.key_prefix, "skin", key.simple());
+        self.get("skin", key).await
     }
 
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_set",
-        labels(cache_variant = "redis", request_type = "skin"),
-        handler = metrics_set_handler
-    )]
     async fn set_skin(&self, key: &Uuid, entry: Entry<SkinData>) {
-        let key = key!("skin", key.simple());
-        self.set(key, entry, &self.settings.entries.skin.ttl).await
+        let key = key!(self.key_prefix, "skin", key.simple());
+        self.set("skin", key, entry, &self.settings.entries.skin.ttl)
+            .await
     }
 
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_get",
-        labels(cache_variant = "redis", request_type = "cape"),
-        handler = metrics_get_handler
-    )]
     async fn get_cape(&self, key: &Uuid) -> Option<Entry<CapeData>> {
-        let key = key!("cape", key.simple());
-        self.get(key).await
+        let key = key!(self.key_prefix, "cape", key.simple());
+        self.get("cape", key).await
     }
 
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_set",
-        labels(cache_variant = "redis", request_type = "cape"),
-        handler = metrics_set_handler
-    )]
     async fn set_cape(&self, key: &Uuid, entry: Entry<CapeData>) {
-        let key = key!("cape", key.simple());
-        self.set(key, entry, &self.settings.entries.cape.ttl).await
+        let key = key!(self.key_prefix, "cape", key.simple());
+        self.set("cape", key, entry, &self.settings.entries.cape.ttl)
+            .await
     }
 
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_get",
-        labels(cache_variant = "redis", request_type = "head"),
-        handler = metrics_get_handler
-    )]
-    async fn get_head(&self, key: &(Uuid, bool)) -> Option<Entry<HeadData>> {
-        let key = key!("head", key.0.simple(), key.1);
-        self.get(key).await
+    async fn get_head(&self, key: &HeadKey) -> Option<Entry<HeadData>> {
+        let key = key!(self.key_prefix, head_key(key));
+        self.get("head", key).await
     }
 
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_set",
-        labels(cache_variant = "redis", request_type = "head"),
-        handler = metrics_set_handler
-    )]
-    async fn set_head(&self, key: &(Uuid, bool), entry: Entry<HeadData>) {
-        let key = key!("head", key.0.simple(), key.1);
-        self.set(key, entry, &self.settings.entries.head.ttl).await
+    async fn set_head(&self, key: &HeadKey, entry: Entry<HeadData>) {
+        let key = key!(self.key_prefix, head_key(key));
+        self.set("head", key, entry, &self.settings.entries.head.ttl)
+            .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_texture(&self, key: &str) -> Option<Entry<TextureData>> {
+        let key = key!(self.key_prefix, "texture", key);
+        self.get("texture", key).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn set_texture(&self, key: &str, entry: Entry<TextureData>) {
+        let key = key!(self.key_prefix, "texture", key);
+        self.set("texture", key, entry, &self.settings.entries.texture.ttl)
+            .await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_blocked_servers(&self) -> Option<Entry<BlockedServersData>> {
+        let key = key!(self.key_prefix, "blocked_servers");
+        self.get("blocked_servers", key).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn set_blocked_servers(&self, entry: Entry<BlockedServersData>) {
+        let key = key!(self.key_prefix, "blocked_servers");
+        let ttl = &self.settings.entries.blocked_servers.ttl;
+        self.set("blocked_servers", key, entry, ttl).await
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn try_lock(&self, key: &str) -> bool {
+        if !self.settings.lock_enabled {
+            return true;
+        }
+        // the lock is released after the fetch, it only expires after the lock ttl if that fails
+        let key = key!(self.key_prefix, "lock", key);
+        let options = SetOptions::default()
+            .conditional_set(ExistenceCheck::NX)
+            .with_expiration(SetExpiry::PX(self.settings.lock_ttl.as_millis() as u64));
+        let acquired: RedisResult<Option<String>> = self
+            .redis_manager
+            .lock()
+            .await
+            .set_options(key, "locked", options)
+            .await;
+        acquired.map(|res| res.is_some()).unwrap_or_else(|err| {
+            // if the lock cannot be acquired because of an error, the fetch should be performed anyway
+            error!("Failed to acquire lock from redis: {:?}", err);
+            true
+        })
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn try_lock_batch(&self, keys: &[String]) -> Vec<bool> {
+        if !self.settings.lock_enabled || keys.is_empty() {
+            return vec![true; keys.len()];
+        }
+        // all locks are acquired in a single pipelined round trip
+        let prefix = &self.key_prefix;
+        let mut pipeline = redis::pipe();
+        for key in keys {
+            let options = SetOptions::default()
+                .conditional_set(ExistenceCheck::NX)
+                .with_expiration(SetExpiry::PX(self.settings.lock_ttl.as_millis() as u64));
+            pipeline.set_options(key!(prefix, "lock", key), "locked", options);
+        }
+        let acquired: RedisResult<Vec<Option<String>>> = pipeline
+            .query_async(&mut *self.redis_manager.lock().await)
+            .await;
+        match acquired {
+            Ok(acquired) => acquired.iter().map(Option::is_some).collect(),
+            Err(err) => {
+                // if the locks cannot be acquired because of an error, the fetch should be performed anyway
+                error!("Failed to acquire locks from redis: {:?}", err);
+                vec![true; keys.len()]
+            }
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn unlock(&self, keys: &[String]) {
+        if !self.settings.lock_enabled || keys.is_empty() {
+            return;
+        }
+        let prefix = &self.key_prefix;
+        let keys: Vec<String> = keys.iter().map(|key| key!(prefix, "lock", key)).collect();
+        let deleted: RedisResult<()> = self.redis_manager.lock().await.del(keys).await;
+        if let Err(err) = deleted {
+            // the locks expire after the lock ttl anyway
+            error!("Failed to release locks from redis: {:?}", err);
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn incr_usage(&self, window: &str, client: &str, ttl: Duration) -> Option<u64> {
+        // the counters of a window are stored in a single hash that expires as a whole
+        let key = key!(self.key_prefix, "usage", window);
+        let mut con = self.redis_manager.lock().await;
+        let count: RedisResult<u64> = USAGE_SCRIPT
+            .key(&key)
+            .arg(client)
+            .arg(ttl.as_secs())
+            .invoke_async(&mut *con)
+            .await;
+        match count {
+            Ok(count) => Some(count),
+            Err(err) => {
+                error!("Failed to increment usage in redis: {:?}", err);
+                None
+            }
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_usage(&self, window: &str) -> Option<HashMap<String, u64>> {
+        let key = key!(self.key_prefix, "usage", window);
+        self.redis_manager
+            .lock()
+            .await
+            .hgetall(key)
+            .await
+            .map_err(|err| error!("Failed to get usage from redis: {:?}", err))
+            .ok()
     }
+
+    #[tracing::instrument(skip(self))]
+    async fn set_pinned(
+        &self,
+        key: &Uuid,
+        pinned: bool,
+        max: usize,
+    ) -> Option<Result<(), PinnedError>> {
+        // the pinned profiles are stored in a single set without expiry, the script checks the
+        // maximum and adds the profile atomically, so that concurrent pins cannot exceed it
+        let pinned_key = key!(self.key_prefix, "pinned");
+        let changed: RedisResult<bool> = PINNED_SCRIPT
+            .key(pinned_key)
+            .arg(key.simple().to_string())
+            .arg(pinned)
+            .arg(max)
+            .invoke_async(&mut *self.redis_manager.lock().await)
+            .await;
+        let result = match changed {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(PinnedError::Exceeded),
+            Err(err) => {
+                error!("Failed to set pinned profile in redis: {:?}", err);
+                Err(PinnedError::Unavailable)
+            }
+        };
+        Some(result)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_pinned(&self) -> Option<HashSet<Uuid>> {
+        let pinned_key = key!(self.key_prefix, "pinned");
+        let members: RedisResult<Vec<String>> =
+            self.redis_manager.lock().await.smembers(pinned_key).await;
+        let members = match members {
+            Ok(members) => members,
+            Err(err) => {
+                error!("Failed to get pinned profiles from redis: {:?}", err);
+                return None;
+            }
+        };
+        Some(
+            members
+                .iter()
+                .filter_map(|member| Uuid::try_parse(member).ok())
+                .collect(),
+        )
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn purge(&self, pattern: &KeyPattern) -> u64 {
+        // the keys are scanned and deleted in batches (SCAN instead of KEYS), so that redis is not
+        // blocked; the connection is only locked per command, so that requests are served meanwhile
+        let glob = key!(self.key_prefix, pattern.glob());
+        let prefix = key!(self.key_prefix, "");
+        let mut cursor = 0u64;
+        let mut purged = 0;
+        loop {
+            let scanned: RedisResult<(u64, Vec<String>)> = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&glob)
+                .arg("COUNT")
+                .arg(PURGE_BATCH_SIZE)
+                .query_async(&mut *self.redis_manager.lock().await)
+                .await;
+            let (next, keys) = match scanned {
+                Ok(scanned) => scanned,
+                Err(err) => {
+                    error!("Failed to scan keys from redis: {:?}", err);
+                    return purged;
+                }
+            };
+            let keys: Vec<String> = keys
+                .into_iter()
+                .filter(|key| key.strip_prefix(&prefix).is_some_and(purgeable))
+                .collect();
+            if !keys.is_empty() {
+                let deleted: RedisResult<u64> = self.redis_manager.lock().await.del(&keys).await;
+                match deleted {
+                    Ok(deleted) => purged += deleted,
+                    Err(err) => error!("Failed to delete keys from redis: {:?}", err),
+                }
+            }
+            if next == 0 {
+                return purged;
+            }
+            cursor = next;
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn ping(&self) -> bool {
+        let pong: RedisResult<String> = redis::cmd("PING")
+            .query_async(&mut *self.redis_manager.lock().await)
+            .await;
+        pong.map_err(|err| error!("Failed to ping redis: {:?}", err))
+            .is_ok()
+    }
+}
+
+/// Migrates a serialized entry of some older version to the current [ENTRY_VERSION]. Entries of newer
+/// versions are rejected by [decode_entry] beforehand.
+fn migrate_entry(version: u64, value: Value) -> Value {
+    debug_assert!(version <= ENTRY_VERSION);
+    // version 1 only added the version field, so entries of version 0 need no migration
+    value
 }
 
-impl<D> FromRedisValue for Entry<D>
+/// Decodes a serialized (versioned) entry. The entry is migrated from its version, if necessary.
+/// Entries of versions newer than [ENTRY_VERSION] (e.g. during a rollback) are rejected, as their
+/// format is unknown. Returns the version of the entry alongside the error, if it cannot be decoded.
+fn decode_entry<D>(str: &str) -> Result<Entry<D>, (Option<u64>, serde_json::Error)>
 where
     D: Clone + Debug + Eq + PartialEq + DeserializeOwned,
 {
-    fn from_redis_value(v: &Value) -> RedisResult<Self> {
-        let v: String = from_redis_value(v)?;
-        Ok(serde_json::from_str(&v)?)
+    let mut value: Value = serde_json::from_str(str).map_err(|err| (None, err))?;
+    let version = match value.as_object_mut() {
+        Some(object) => object
+            .remove(VERSION_FIELD)
+            .and_then(|version| version.as_u64())
+            .unwrap_or(0),
+        None => 0,
+    };
+    if version > ENTRY_VERSION {
+        let err = serde::de::Error::custom(format!("unknown entry version {version}"));
+        return Err((Some(version), err));
     }
+    serde_json::from_value(migrate_entry(version, value)).map_err(|err| (Some(version), err))
+}
+
+/// Encodes an entry with the current [ENTRY_VERSION].
+fn encode_entry<D>(entry: &Entry<D>) -> serde_json::Result<String>
+where
+    D: Clone + Debug + Eq + PartialEq + Serialize,
+{
+    let mut value = serde_json::to_value(entry)?;
+    if let Some(object) = value.as_object_mut() {
+        object.insert(VERSION_FIELD.to_string(), ENTRY_VERSION.into());
+    }
+    serde_json::to_string(&value)
 }
 
 impl<D> ToRedisArgs for Entry<D>
@@ -227,7 +647,132 @@ where
     where
         W: ?Sized + RedisWrite,
     {
-        let str = serde_json::to_string(self).unwrap_or("".to_string());
+        let str = encode_entry(self).unwrap_or("".to_string());
         out.write_arg(str.as_ref())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::entry::HeadStyle;
+    use crate::settings::Settings;
+    use bytes::Bytes;
+
+    #[test]
+    fn head_key_legacy_format() {
+        // given
+        let uuid = Uuid::parse_str("09879557e47945a9b434a56377674627").unwrap();
+        let native = HeadKey::new(uuid, true);
+        let rendered = HeadKey {
+            size: 64,
+            style: HeadStyle::Rgba,
+            ..native
+        };
+
+        // when
+        let native = key!("xenos", head_key(&native));
+        let rendered = key!("xenos", head_key(&rendered));
+
+        // then
+        assert_eq!(native, "xenos.head.09879557e47945a9b434a56377674627.true");
+        assert_eq!(
+            rendered,
+            "xenos.head.09879557e47945a9b434a56377674627.true.64.rgba"
+        );
+    }
+
+    #[test]
+    fn key_prefix_namespace() {
+        // given
+        let mut settings = Settings::default().cache.redis;
+        settings.prefix = "xenos".to_string();
+        let global = settings.clone();
+        settings.namespace = Some("production".to_string());
+
+        // when
+        let global = key_prefix(&global);
+        let namespaced = key_prefix(&settings);
+
+        // then
+        assert_eq!("xenos", global);
+        assert_eq!("xenos.production", namespaced);
+        assert_eq!(
+            "xenos.production.uuid.hydrofin",
+            key!(namespaced, "uuid", "hydrofin")
+        );
+    }
+
+    #[test]
+    fn purgeable_entries() {
+        // given
+        let entries = [
+            "uuid.hydrofin",
+            "profile.09879557e47945a9b434a56377674627",
+            "head.09879557e47945a9b434a56377674627.true",
+            "head.09879557e47945a9b434a56377674627.true.64.rgba",
+            "blocked_servers",
+        ];
+        let internal = ["lock.uuid.hydrofin", "usage.2024-01-01T10:00", "pinned"];
+        let namespaced = [
+            "staging.uuid.hydrofin",
+            "staging.profile.09879557e47945a9b434a56377674627",
+            "staging.lock.uuid.hydrofin",
+            "staging.usage.2024-01-01T10:00",
+            "staging.pinned",
+            "uuid.uuid.hydrofin",
+            "head.uuid.hydrofin",
+        ];
+
+        // when
+        let entries = entries.iter().all(|key| purgeable(key));
+        let internal = internal.iter().any(|key| purgeable(key));
+        let namespaced = namespaced.iter().any(|key| purgeable(key));
+
+        // then
+        assert!(entries);
+        assert!(!internal);
+        assert!(!namespaced);
+    }
+
+    #[test]
+    fn decode_entry_versions() {
+        // given
+        let legacy = r#"{"timestamp":10,"data":{"username":"Hydrofin","uuid":"09879557-e479-45a9-b434-a56377674627"}}"#;
+        let future = r#"{"v":7,"timestamp":10,"data":null,"unknown":true}"#;
+        let invalid = r#"{"v":1,"timestamp":"ten","data":null}"#;
+        let entry: Entry<UuidData> = Entry::at(None, 10);
+
+        // when
+        let legacy = decode_entry::<UuidData>(legacy);
+        let future = decode_entry::<UuidData>(future);
+        let invalid = decode_entry::<UuidData>(invalid);
+        let current = decode_entry::<UuidData>(&encode_entry(&entry).unwrap());
+
+        // then
+        assert!(legacy.is_ok_and(|legacy| legacy.has_some()));
+        assert!(future.is_err_and(|(version, _)| version == Some(7)));
+        assert!(invalid.is_err_and(|(version, _)| version == Some(1)));
+        assert_eq!(current.unwrap(), entry);
+    }
+
+    #[test]
+    fn encode_bytes_as_array() {
+        // given
+        let entry = Entry::at(
+            Some(CapeData {
+                bytes: Bytes::from_static(&[1, 2, 3]),
+                texture_id: None,
+            }),
+            10,
+        );
+
+        // when
+        let encoded = encode_entry(&entry).unwrap();
+        let decoded = decode_entry::<CapeData>(&encoded);
+
+        // then
+        assert!(encoded.contains(r#""bytes":[1,2,3]"#));
+        assert_eq!(decoded.unwrap(), entry);
+    }
+}
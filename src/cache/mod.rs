@@ -1,14 +1,28 @@
+pub mod clock;
 pub mod entry;
 pub mod level;
 
-use crate::cache::entry::{Cached, CapeData, Entry, HeadData, ProfileData, SkinData, UuidData};
-use crate::cache::level::CacheLevel;
+use crate::access_log;
+use crate::cache::clock::{Clock, SystemClock};
+use crate::cache::entry::{
+    BlockedServersData, Cached, CapeData, Dated, Entry, HeadData, HeadKey, ProfileData, SkinData,
+    TextureData, UuidData,
+};
+use crate::cache::level::{CacheLevel, KeyPattern, PinnedError};
 use crate::settings;
-use crate::settings::CacheEntry;
+use crate::settings::{CacheEntry, Lookup};
+use crate::statsd;
+use crate::tenant;
+use futures::future::{select, Either};
 use lazy_static::lazy_static;
 use metrics::MetricsEvent;
-use prometheus::{register_histogram_vec, HistogramVec};
+use prometheus::{register_histogram_vec, register_int_counter_vec, HistogramVec, IntCounterVec};
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::warn;
 use uuid::Uuid;
 
@@ -42,14 +56,34 @@ lazy_static! {
         vec![0.005, 0.01, 0.025, 0.05, 0.075, 0.1, 0.175, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0]
     )
     .unwrap();
+
+    /// A counter for the results of the entries of batch cache get requests. The latency of a batch is
+    /// observed once (with the `batch` cache result), so the results of its entries are counted here.
+    pub(crate) static ref CACHE_BATCH_RESULT_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "xenos_cache_get_batch_results_total",
+        "The results of the entries of batch cache get requests.",
+        &["cache_variant", "request_type", "cache_result"]
+    )
+    .unwrap();
 }
 
-fn metrics_get_handler<T: Clone + Debug + Eq>(event: MetricsEvent<Cached<T>>) {
-    let cache_result = match event.result {
+/// Returns the request type label of a batch request, so that the latencies of batches are not mixed
+/// with the latencies of single requests.
+pub(crate) fn batch_request_type(request_type: &str) -> String {
+    format!("{request_type}_batch")
+}
+
+fn cache_result<T: Clone + Debug + Eq>(result: &Cached<T>) -> &'static str {
+    match result {
         Cached::Hit(_) => "hit",
         Cached::Expired(_) => "expired",
         Cached::Miss => "miss",
-    };
+    }
+}
+
+fn metrics_get_handler<T: Clone + Debug + Eq>(event: MetricsEvent<Cached<T>>) {
+    access_log::record_cache(event.result);
+    let cache_result = cache_result(event.result);
     let Some(request_type) = event.labels.get("request_type") else {
         warn!("Failed to retrieve label 'request_type' for metric!");
         return;
@@ -58,12 +92,32 @@ fn metrics_get_handler<T: Clone + Debug + Eq>(event: MetricsEvent<Cached<T>>) {
     CACHE_GET_HISTOGRAM
         .with_label_values(&[cache_variant, request_type, cache_result])
         .observe(event.time);
+    statsd::timing(
+        "cache.get",
+        &[
+            ("cache_variant", cache_variant),
+            ("request_type", request_type),
+            ("cache_result", cache_result),
+        ],
+        event.time,
+    );
+    observe_age(cache_variant, request_type, event.result);
+}
 
-    match event.result {
+fn observe_age<T: Clone + Debug + Eq>(cache_variant: &str, request_type: &str, result: &Cached<T>) {
+    match result {
         Cached::Hit(entry) | Cached::Expired(entry) => {
             CACHE_AGE_HISTOGRAM
                 .with_label_values(&[cache_variant, request_type])
                 .observe(entry.current_age() as f64);
+            statsd::timing(
+                "cache.age",
+                &[
+                    ("cache_variant", cache_variant),
+                    ("request_type", request_type),
+                ],
+                entry.current_age() as f64,
+            );
         }
         _ => {}
     };
@@ -78,6 +132,73 @@ fn metrics_set_handler<T: Clone + Debug + Eq>(event: MetricsEvent<Entry<T>>) {
     CACHE_SET_HISTOGRAM
         .with_label_values(&[cache_variant, request_type])
         .observe(event.time);
+    statsd::timing(
+        "cache.set",
+        &[
+            ("cache_variant", cache_variant),
+            ("request_type", request_type),
+        ],
+        event.time,
+    );
+}
+
+fn metrics_get_batch_handler<T: Clone + Debug + Eq>(event: MetricsEvent<Vec<Cached<T>>>) {
+    let Some(request_type) = event.labels.get("request_type") else {
+        warn!("Failed to retrieve label 'request_type' for metric!");
+        return;
+    };
+    let cache_variant = "cache";
+    // the results are counted per entry, but the latency is only observed once per batch
+    for result in event.result {
+        access_log::record_cache(result);
+        CACHE_BATCH_RESULT_COUNTER
+            .with_label_values(&[cache_variant, request_type, cache_result(result)])
+            .inc();
+        observe_age(cache_variant, request_type, result);
+    }
+    let batch_request_type = batch_request_type(request_type);
+    CACHE_GET_HISTOGRAM
+        .with_label_values(&[cache_variant, &batch_request_type, "batch"])
+        .observe(event.time);
+    statsd::timing(
+        "cache.get",
+        &[
+            ("cache_variant", cache_variant),
+            ("request_type", &batch_request_type),
+            ("cache_result", "batch"),
+        ],
+        event.time,
+    );
+}
+
+fn metrics_set_batch_handler<T: Clone + Debug + Eq>(event: MetricsEvent<Vec<Entry<T>>>) {
+    let Some(request_type) = event.labels.get("request_type") else {
+        warn!("Failed to retrieve label 'request_type' for metric!");
+        return;
+    };
+    let cache_variant = "cache";
+    let batch_request_type = batch_request_type(request_type);
+    CACHE_SET_HISTOGRAM
+        .with_label_values(&[cache_variant, &batch_request_type])
+        .observe(event.time);
+    statsd::timing(
+        "cache.set",
+        &[
+            ("cache_variant", cache_variant),
+            ("request_type", &batch_request_type),
+        ],
+        event.time,
+    );
+}
+
+/// Checks whether some optional [Entry] exists and is not expired.
+fn is_fresh<D>(entry: &Option<Entry<D>>, expiry: &CacheEntry, now: u64) -> bool
+where
+    D: Clone + Debug + Eq,
+{
+    entry
+        .as_ref()
+        .is_some_and(|entry| !entry.is_expired_at(expiry, now))
 }
 
 /// A [Cache] is a thread-safe multi-level cache. [Levels](CacheLevel) are added to the end of the stack.
@@ -88,6 +209,7 @@ fn metrics_set_handler<T: Clone + Debug + Eq>(event: MetricsEvent<Entry<T>>) {
 /// - **Get operations** find the first [CacheLevel] that contains a some [Entry].
 ///   When a [Hit] is found, all previous levels are updated with that [Entry]. Otherwise, it uses the
 ///   last found [Expired] entry. If no [Entry] could be found. Nothing is updated.
+///   Depending on the [Lookup] of the entry type, the levels are queried sequentially or at once.
 /// - **Set operations** update all levels, starting with the lowest level.
 ///
 /// ```rs
@@ -105,8 +227,11 @@ where
     R: CacheLevel,
 {
     expiry: settings::CacheEntries<CacheEntry>,
+    tenant_expiry: HashMap<String, settings::CacheEntries<CacheEntry>>,
     local_cache: L,
     remote_cache: R,
+    clock: Arc<dyn Clock>,
+    capabilities: settings::Capabilities,
 }
 
 impl<L, R> Cache<L, R>
@@ -122,232 +247,493 @@ where
     ) -> Self {
         Cache {
             expiry,
+            tenant_expiry: HashMap::new(),
             local_cache,
             remote_cache,
+            clock: Arc::new(SystemClock),
+            capabilities: settings::Capabilities {
+                skins: true,
+                capes: true,
+                heads: true,
+            },
         }
     }
 
+    /// Replaces the [Clock] of the [Cache]. The clock is used to date new entries and to check whether
+    /// entries have expired. By default, the [SystemClock] is used.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Replaces the [Capabilities](settings::Capabilities) of the [Cache]. The entries of disabled
+    /// capabilities are neither stored in the local nor in the remote cache. By default, all
+    /// capabilities are enabled.
+    pub fn with_capabilities(mut self, capabilities: settings::Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Replaces the [CacheEntry] configurations of the tenants (by their name). Requests of a tenant
+    /// use its configurations to check whether entries have expired, others use the global ones.
+    pub fn with_tenant_expiry(
+        mut self,
+        tenant_expiry: HashMap<String, settings::CacheEntries<CacheEntry>>,
+    ) -> Self {
+        self.tenant_expiry = tenant_expiry;
+        self
+    }
+
+    /// Gets the [CacheEntry] configurations of the tenant of the current task (see [tenant]). Without
+    /// tenant (or configurations of the tenant), the global configurations are used.
+    pub fn expiry(&self) -> &settings::CacheEntries<CacheEntry> {
+        tenant::current()
+            .and_then(|tenant| self.tenant_expiry.get(&*tenant))
+            .unwrap_or(&self.expiry)
+    }
+
+    /// Gets the current unix time in seconds from the [Clock] of the [Cache].
+    pub fn now_seconds(&self) -> u64 {
+        self.clock.now_seconds()
+    }
+
     /// Gets some [UuidData] from the [Cache] for a case-insensitive username.
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_get",
-        labels(request_type = "uuid"),
-        handler = metrics_get_handler,
-    )]
     pub async fn get_uuid(&self, key: &str) -> Cached<UuidData> {
-        let local = self.local_cache.get_uuid(key).await;
-        if let Some(entry) = &local {
-            if !entry.is_expired(&self.expiry.uuid) {
-                return Cached::with_expiry(local, &self.expiry.uuid);
-            }
-        }
-
-        let remote = self.remote_cache.get_uuid(key).await;
-        match &remote {
-            None => {
-                // if remote cache has no value, use local result
-                Cached::with_expiry(local, &self.expiry.uuid)
-            }
-            Some(entry) => {
-                // if remote cache has a value, sync with local cache
-                self.local_cache.set_uuid(key, entry.clone()).await;
-                Cached::with_expiry(remote, &self.expiry.uuid)
-            }
-        }
+        let expiry = &self.expiry().uuid.jittered(key);
+        self.lookup(
+            "uuid",
+            expiry,
+            self.local_cache.get_uuid(key),
+            self.remote_cache.get_uuid(key),
+            |entry| self.local_cache.set_uuid(key, entry),
+        )
+        .await
     }
 
     /// Sets some optional [UuidData] to the [Cache] for a case-insensitive username.
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_set",
-        labels(request_type = "uuid"),
-        handler = metrics_set_handler,
-    )]
     pub async fn set_uuid(&self, key: &str, data: Option<UuidData>) -> Entry<UuidData> {
-        let entry = Entry::from(data);
-        self.local_cache.set_uuid(key, entry.clone()).await;
-        self.remote_cache.set_uuid(key, entry.clone()).await;
-        entry
+        self.store(
+            "uuid",
+            data,
+            |entry| self.local_cache.set_uuid(key, entry),
+            |entry| self.remote_cache.set_uuid(key, entry),
+        )
+        .await
     }
 
     /// Gets some [ProfileData] from the [Cache] for a profile [Uuid].
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_get",
-        labels(request_type = "profile"),
-        handler = metrics_get_handler,
-    )]
     pub async fn get_profile(&self, uuid: &Uuid) -> Cached<ProfileData> {
-        let local = self.local_cache.get_profile(uuid).await;
-        if let Some(entry) = &local {
-            if !entry.is_expired(&self.expiry.profile) {
-                return Cached::with_expiry(local, &self.expiry.profile);
-            }
-        }
-
-        let remote = self.remote_cache.get_profile(uuid).await;
-        match &remote {
-            None => {
-                // if remote cache has no value, use local result
-                Cached::with_expiry(local, &self.expiry.profile)
-            }
-            Some(entry) => {
-                // if remote cache has a value, sync with local cache
-                self.local_cache.set_profile(uuid, entry.clone()).await;
-                Cached::with_expiry(remote, &self.expiry.profile)
-            }
-        }
+        let expiry = &self.expiry().profile.jittered(uuid);
+        self.lookup(
+            "profile",
+            expiry,
+            self.local_cache.get_profile(uuid),
+            self.remote_cache.get_profile(uuid),
+            |entry| self.local_cache.set_profile(uuid, entry),
+        )
+        .await
     }
 
     /// Sets some optional [ProfileData] to the [Cache] for a profile [Uuid].
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_set",
-        labels(request_type = "profile"),
-        handler = metrics_set_handler,
-    )]
     pub async fn set_profile(&self, key: &Uuid, data: Option<ProfileData>) -> Entry<ProfileData> {
-        let entry = Entry::from(data);
-        self.local_cache.set_profile(key, entry.clone()).await;
-        self.remote_cache.set_profile(key, entry.clone()).await;
-        entry
+        self.store(
+            "profile",
+            data,
+            |entry| self.local_cache.set_profile(key, entry),
+            |entry| self.remote_cache.set_profile(key, entry),
+        )
+        .await
     }
 
-    /// Gets some [SkinData] from the [Cache] for a profile [Uuid].
+    /// Gets some [UuidData] from the [Cache] for multiple case-insensitive usernames at once. Only the
+    /// usernames without fresh local entry are looked up in the remote cache (in one batch). The results
+    /// are returned in the order of the usernames.
     #[tracing::instrument(skip(self))]
     #[metrics::metrics(
         metric = "cache_get",
-        labels(request_type = "skin"),
-        handler = metrics_get_handler,
+        labels(request_type = "uuid"),
+        handler = metrics_get_batch_handler,
     )]
-    pub async fn get_skin(&self, uuid: &Uuid) -> Cached<SkinData> {
-        let local = self.local_cache.get_skin(uuid).await;
-        if let Some(entry) = &local {
-            if !entry.is_expired(&self.expiry.skin) {
-                return Cached::with_expiry(local, &self.expiry.skin);
-            }
-        }
+    pub async fn get_uuids_batch(&self, keys: &[String]) -> Vec<Cached<UuidData>> {
+        let now = self.now_seconds();
+        let expiries: Vec<_> = keys
+            .iter()
+            .map(|key| self.expiry().uuid.jittered(key.as_str()))
+            .collect();
+        let mut entries = self.local_cache.get_uuids_batch(keys).await;
 
-        let remote = self.remote_cache.get_skin(uuid).await;
-        match &remote {
-            None => {
-                // if remote cache has no value, use local result
-                Cached::with_expiry(local, &self.expiry.skin)
+        let stale: Vec<usize> = (0..keys.len())
+            .filter(|&i| !is_fresh(&entries[i], &expiries[i], now))
+            .collect();
+        if !stale.is_empty() {
+            let stale_keys: Vec<String> = stale.iter().map(|&i| keys[i].clone()).collect();
+            let remote = self.remote_cache.get_uuids_batch(&stale_keys).await;
+            // if remote cache has a value, sync with local cache
+            let mut sync = vec![];
+            for (i, entry) in stale.into_iter().zip(remote) {
+                if let Some(entry) = entry {
+                    sync.push((keys[i].clone(), entry.clone()));
+                    entries[i] = Some(entry);
+                }
             }
-            Some(entry) => {
-                // if remote cache has a value, sync with local cache
-                self.local_cache.set_skin(uuid, entry.clone()).await;
-                Cached::with_expiry(remote, &self.expiry.skin)
+            if !sync.is_empty() {
+                self.local_cache.set_uuids_batch(sync).await;
             }
         }
+
+        entries
+            .into_iter()
+            .zip(&expiries)
+            .map(|(entry, expiry)| Cached::with_expiry_at(entry, expiry, now))
+            .collect()
     }
 
-    /// Sets some optional [SkinData] to the [Cache] for a profile [Uuid].
+    /// Sets some optional [UuidData] to the [Cache] for multiple case-insensitive usernames at once.
     #[tracing::instrument(skip(self))]
     #[metrics::metrics(
         metric = "cache_set",
-        labels(request_type = "profile"),
-        handler = metrics_set_handler,
+        labels(request_type = "uuid"),
+        handler = metrics_set_batch_handler,
     )]
+    pub async fn set_uuids_batch(
+        &self,
+        data: Vec<(String, Option<UuidData>)>,
+    ) -> Vec<Entry<UuidData>> {
+        let now = self.now_seconds();
+        let entries: Vec<_> = data
+            .into_iter()
+            .map(|(key, data)| (key, Dated::at(data, now)))
+            .collect();
+        self.local_cache.set_uuids_batch(entries.clone()).await;
+        self.remote_cache.set_uuids_batch(entries.clone()).await;
+        entries.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    /// Gets some [SkinData] from the [Cache] for a profile [Uuid].
+    #[tracing::instrument(skip(self))]
+    pub async fn get_skin(&self, uuid: &Uuid) -> Cached<SkinData> {
+        let expiry = &self.expiry().skin.jittered(uuid);
+        self.lookup(
+            "skin",
+            expiry,
+            self.local_cache.get_skin(uuid),
+            self.remote_cache.get_skin(uuid),
+            |entry| self.local_cache.set_skin(uuid, entry),
+        )
+        .await
+    }
+
+    /// Sets some optional [SkinData] to the [Cache] for a profile [Uuid].
+    #[tracing::instrument(skip(self))]
     pub async fn set_skin(&self, key: &Uuid, data: Option<SkinData>) -> Entry<SkinData> {
-        let entry = Entry::from(data);
-        self.local_cache.set_skin(key, entry.clone()).await;
-        self.remote_cache.set_skin(key, entry.clone()).await;
-        entry
+        if !self.capabilities.skins {
+            return Dated::at(data, self.now_seconds());
+        }
+        self.store(
+            "skin",
+            data,
+            |entry| self.local_cache.set_skin(key, entry),
+            |entry| self.remote_cache.set_skin(key, entry),
+        )
+        .await
     }
 
     /// Gets some [CapeData] from the [Cache] for a profile [Uuid].
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_get",
-        labels(request_type = "cape"),
-        handler = metrics_get_handler,
-    )]
     pub async fn get_cape(&self, uuid: &Uuid) -> Cached<CapeData> {
-        let local = self.local_cache.get_cape(uuid).await;
-        if let Some(entry) = &local {
-            if !entry.is_expired(&self.expiry.cape) {
-                return Cached::with_expiry(local, &self.expiry.cape);
-            }
+        let expiry = &self.expiry().cape.jittered(uuid);
+        self.lookup(
+            "cape",
+            expiry,
+            self.local_cache.get_cape(uuid),
+            self.remote_cache.get_cape(uuid),
+            |entry| self.local_cache.set_cape(uuid, entry),
+        )
+        .await
+    }
+
+    /// Sets some optional [CapeData] to the [Cache] for a profile [Uuid].
+    #[tracing::instrument(skip(self))]
+    pub async fn set_cape(&self, key: &Uuid, data: Option<CapeData>) -> Entry<CapeData> {
+        if !self.capabilities.capes {
+            return Dated::at(data, self.now_seconds());
         }
+        self.store(
+            "cape",
+            data,
+            |entry| self.local_cache.set_cape(key, entry),
+            |entry| self.remote_cache.set_cape(key, entry),
+        )
+        .await
+    }
 
-        let remote = self.remote_cache.get_cape(uuid).await;
-        match &remote {
-            None => {
-                // if remote cache has no value, use local result
-                Cached::with_expiry(local, &self.expiry.cape)
-            }
-            Some(entry) => {
-                // if remote cache has a value, sync with local cache
-                self.local_cache.set_cape(uuid, entry.clone()).await;
-                Cached::with_expiry(remote, &self.expiry.cape)
-            }
+    /// Gets some [HeadData] from the [Cache] for a [HeadKey].
+    #[tracing::instrument(skip(self))]
+    pub async fn get_head(&self, key: &HeadKey) -> Cached<HeadData> {
+        let expiry = &self.expiry().head.jittered(key);
+        self.lookup(
+            "head",
+            expiry,
+            self.local_cache.get_head(key),
+            self.remote_cache.get_head(key),
+            |entry| self.local_cache.set_head(key, entry),
+        )
+        .await
+    }
+
+    /// Sets some optional [HeadData] to the [Cache] for a [HeadKey].
+    #[tracing::instrument(skip(self))]
+    pub async fn set_head(&self, key: &HeadKey, data: Option<HeadData>) -> Entry<HeadData> {
+        if !self.capabilities.heads {
+            return Dated::at(data, self.now_seconds());
         }
+        self.store(
+            "head",
+            data,
+            |entry| self.local_cache.set_head(key, entry),
+            |entry| self.remote_cache.set_head(key, entry),
+        )
+        .await
     }
 
-    /// Sets some optional [CapeData] to the [Cache] for a profile [Uuid].
+    /// Gets some [TextureData] from the [Cache] for a (lowercase) texture id.
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(
-        metric = "cache_set",
-        labels(request_type = "cape"),
-        handler = metrics_set_handler,
-    )]
-    pub async fn set_cape(&self, key: &Uuid, data: Option<CapeData>) -> Entry<CapeData> {
-        let entry = Entry::from(data);
-        self.local_cache.set_cape(key, entry.clone()).await;
-        self.remote_cache.set_cape(key, entry.clone()).await;
-        entry
+    pub async fn get_texture(&self, texture_id: &str) -> Cached<TextureData> {
+        let expiry = &self.expiry().texture.jittered(texture_id);
+        self.lookup(
+            "texture",
+            expiry,
+            self.local_cache.get_texture(texture_id),
+            self.remote_cache.get_texture(texture_id),
+            |entry| self.local_cache.set_texture(texture_id, entry),
+        )
+        .await
     }
 
-    /// Gets some [HeadData] from the [Cache] for a profile [Uuid] with or without its overlay.
+    /// Sets some optional [TextureData] to the [Cache] for a (lowercase) texture id.
     #[tracing::instrument(skip(self))]
+    pub async fn set_texture(&self, key: &str, data: Option<TextureData>) -> Entry<TextureData> {
+        self.store(
+            "texture",
+            data,
+            |entry| self.local_cache.set_texture(key, entry),
+            |entry| self.remote_cache.set_texture(key, entry),
+        )
+        .await
+    }
+
+    /// Gets the [BlockedServersData] from the [Cache].
+    #[tracing::instrument(skip(self))]
+    pub async fn get_blocked_servers(&self) -> Cached<BlockedServersData> {
+        let expiry = &self.expiry().blocked_servers;
+        self.lookup(
+            "blocked_servers",
+            expiry,
+            self.local_cache.get_blocked_servers(),
+            self.remote_cache.get_blocked_servers(),
+            |entry| self.local_cache.set_blocked_servers(entry),
+        )
+        .await
+    }
+
+    /// Sets some optional [BlockedServersData] to the [Cache].
+    #[tracing::instrument(skip(self))]
+    pub async fn set_blocked_servers(
+        &self,
+        data: Option<BlockedServersData>,
+    ) -> Entry<BlockedServersData> {
+        self.store(
+            "blocked_servers",
+            data,
+            |entry| self.local_cache.set_blocked_servers(entry),
+            |entry| self.remote_cache.set_blocked_servers(entry),
+        )
+        .await
+    }
+
+    /// Looks up an [Entry] in the local and remote cache according to the [Lookup] of the entry type.
+    /// Fresh local entries are preferred, otherwise remote entries are preferred and synced with the
+    /// local cache. Sequential lookups only query the remote cache if the local cache has no fresh
+    /// entry, racing lookups query both at once and use the first fresh entry.
     #[metrics::metrics(
         metric = "cache_get",
-        labels(request_type = "head"),
+        labels(request_type = %request_type),
         handler = metrics_get_handler,
     )]
-    pub async fn get_head(&self, uuid: &(Uuid, bool)) -> Cached<HeadData> {
-        let local = self.local_cache.get_head(uuid).await;
-        if let Some(entry) = &local {
-            if !entry.is_expired(&self.expiry.head) {
-                return Cached::with_expiry(local, &self.expiry.head);
+    async fn lookup<D, LF, RF, SF>(
+        &self,
+        request_type: &str,
+        expiry: &CacheEntry,
+        local: LF,
+        remote: RF,
+        sync: impl FnOnce(Entry<D>) -> SF,
+    ) -> Cached<D>
+    where
+        D: Clone + Debug + Eq,
+        LF: Future<Output = Option<Entry<D>>>,
+        RF: Future<Output = Option<Entry<D>>>,
+        SF: Future<Output = ()>,
+    {
+        let now = self.now_seconds();
+        let is_fresh = |entry: &Option<Entry<D>>| is_fresh(entry, expiry, now);
+        let (local, remote) = match expiry.lookup {
+            Lookup::Sequential => {
+                let local = local.await;
+                if is_fresh(&local) {
+                    return Cached::with_expiry_at(local, expiry, now);
+                }
+                (local, remote.await)
             }
+            Lookup::Race => match select(pin!(local), pin!(remote)).await {
+                Either::Left((local, _)) if is_fresh(&local) => {
+                    return Cached::with_expiry_at(local, expiry, now);
+                }
+                Either::Left((local, remote)) => (local, remote.await),
+                // the local entry is replaced by the fresh remote entry anyway
+                Either::Right((remote, _)) if is_fresh(&remote) => (None, remote),
+                Either::Right((remote, local)) => (local.await, remote),
+            },
+        };
+        if is_fresh(&local) {
+            return Cached::with_expiry_at(local, expiry, now);
         }
-
-        let remote = self.remote_cache.get_head(uuid).await;
         match &remote {
             None => {
                 // if remote cache has no value, use local result
-                Cached::with_expiry(local, &self.expiry.head)
+                Cached::with_expiry_at(local, expiry, now)
             }
             Some(entry) => {
                 // if remote cache has a value, sync with local cache
-                self.local_cache.set_head(uuid, entry.clone()).await;
-                Cached::with_expiry(remote, &self.expiry.head)
+                sync(entry.clone()).await;
+                Cached::with_expiry_at(remote, expiry, now)
             }
         }
     }
 
-    /// Sets some optional [HeadData] to the [Cache] for a profile [Uuid] with or without its overlay.
-    #[tracing::instrument(skip(self))]
+    /// Stores some optional data as new [Entry] in the local and remote cache and returns the [Entry].
     #[metrics::metrics(
         metric = "cache_set",
-        labels(request_type = "head"),
+        labels(request_type = %request_type),
         handler = metrics_set_handler,
     )]
-    pub async fn set_head(&self, key: &(Uuid, bool), data: Option<HeadData>) -> Entry<HeadData> {
-        let entry = Entry::from(data);
-        self.local_cache.set_head(key, entry.clone()).await;
-        self.remote_cache.set_head(key, entry.clone()).await;
+    async fn store<D, LF, RF>(
+        &self,
+        request_type: &str,
+        data: Option<D>,
+        local: impl FnOnce(Entry<D>) -> LF,
+        remote: impl FnOnce(Entry<D>) -> RF,
+    ) -> Entry<D>
+    where
+        D: Clone + Debug + Eq,
+        LF: Future<Output = ()>,
+        RF: Future<Output = ()>,
+    {
+        let entry = Dated::at(data, self.now_seconds());
+        local(entry.clone()).await;
+        remote(entry.clone()).await;
         entry
     }
+
+    /// Tries to acquire a short-lived fetch lock for a key (e.g. `profile.<uuid>`) from the remote
+    /// cache. It is used to prevent multiple instances from refreshing the same expired entry at once.
+    #[tracing::instrument(skip(self))]
+    pub async fn try_lock(&self, key: &str) -> bool {
+        self.remote_cache.try_lock(key).await
+    }
+
+    /// Tries to acquire the short-lived fetch locks for multiple keys at once from the remote cache (in
+    /// one batch). Returns whether each lock was acquired (in the order of the keys).
+    #[tracing::instrument(skip(self))]
+    pub async fn try_lock_batch(&self, keys: &[String]) -> Vec<bool> {
+        self.remote_cache.try_lock_batch(keys).await
+    }
+
+    /// Releases the fetch locks for multiple keys at once in the remote cache, so that the next
+    /// refresh of the entries does not wait for the locks to expire.
+    #[tracing::instrument(skip(self))]
+    pub async fn unlock(&self, keys: &[String]) {
+        self.remote_cache.unlock(keys).await
+    }
+
+    /// Increments the usage counter of a client in a quota window and returns the new count. The
+    /// counters are shared using the remote cache. If the remote cache does not support usage counters,
+    /// the local cache is used instead.
+    #[tracing::instrument(skip(self))]
+    pub async fn incr_usage(&self, window: &str, client: &str, ttl: Duration) -> u64 {
+        if let Some(count) = self.remote_cache.incr_usage(window, client, ttl).await {
+            return count;
+        }
+        self.local_cache
+            .incr_usage(window, client, ttl)
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Gets the usage counters of all clients in a quota window. The counters are read from the remote
+    /// cache. If the remote cache does not support usage counters, the local cache is used instead.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_usage(&self, window: &str) -> HashMap<String, u64> {
+        if let Some(usage) = self.remote_cache.get_usage(window).await {
+            return usage;
+        }
+        self.local_cache.get_usage(window).await.unwrap_or_default()
+    }
+
+    /// Adds a profile uuid to (or removes it from) the pinned profiles, if less than `max` profiles
+    /// are pinned. The pinned profiles are persisted in the remote cache, so that they survive
+    /// restarts. If the remote cache does not support pinned profiles, the local cache is used instead.
+    #[tracing::instrument(skip(self))]
+    pub async fn set_pinned(
+        &self,
+        key: &Uuid,
+        pinned: bool,
+        max: usize,
+    ) -> Result<(), PinnedError> {
+        if let Some(result) = self.remote_cache.set_pinned(key, pinned, max).await {
+            return result;
+        }
+        self.local_cache
+            .set_pinned(key, pinned, max)
+            .await
+            .unwrap_or(Ok(()))
+    }
+
+    /// Gets all pinned profile uuids. They are read from the remote cache. If the remote cache does
+    /// not support pinned profiles, the local cache is used instead.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_pinned(&self) -> HashSet<Uuid> {
+        if let Some(pinned) = self.remote_cache.get_pinned().await {
+            return pinned;
+        }
+        self.local_cache.get_pinned().await.unwrap_or_default()
+    }
+
+    /// Deletes all entries whose key matches the [KeyPattern] from the local and remote cache and
+    /// returns the number of deleted entries (of both caches).
+    #[tracing::instrument(skip(self))]
+    pub async fn purge(&self, pattern: &KeyPattern) -> u64 {
+        let local = self.local_cache.purge(pattern).await;
+        let remote = self.remote_cache.purge(pattern).await;
+        local + remote
+    }
+
+    /// Checks whether the remote cache is reachable.
+    #[tracing::instrument(skip(self))]
+    pub async fn ping_remote(&self) -> bool {
+        self.remote_cache.ping().await
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::cache::clock::ManualClock;
     use crate::cache::level::moka::MokaCache;
-    use crate::settings::{CacheEntries, MokaCacheEntry};
+    use crate::settings::{CacheEntries, Jitter, MokaCacheEntry};
     use std::time::Duration;
     use uuid::uuid;
     use Cached::*;
@@ -355,6 +741,8 @@ mod test {
     fn new_moka_settings() -> settings::MokaCache {
         let entry = MokaCacheEntry {
             cap: 10,
+            max_bytes: None,
+            initial_capacity: None,
             ttl: Duration::from_secs(100),
             ttl_empty: Duration::from_secs(100),
             tti: Duration::from_secs(100),
@@ -367,6 +755,8 @@ mod test {
                 skin: entry.clone(),
                 cape: entry.clone(),
                 head: entry.clone(),
+                texture: entry.clone(),
+                blocked_servers: entry.clone(),
             },
         }
     }
@@ -375,6 +765,9 @@ mod test {
         let expiry = CacheEntry {
             exp: dur,
             exp_empty: dur,
+            jitter: Jitter::None,
+            lookup: Lookup::Sequential,
+            pre_render: false,
         };
         CacheEntries {
             uuid: expiry.clone(),
@@ -382,6 +775,8 @@ mod test {
             skin: expiry.clone(),
             cape: expiry.clone(),
             head: expiry.clone(),
+            texture: expiry.clone(),
+            blocked_servers: expiry.clone(),
         }
     }
 
@@ -430,6 +825,57 @@ mod test {
         assert!(matches!(cached2, Some(entry) if entry.data.is_none()));
     }
 
+    #[tokio::test]
+    async fn set_disabled_capability() {
+        // given
+        let cache = new_cache_2l(Duration::from_secs(10))
+            .await
+            .with_capabilities(settings::Capabilities {
+                skins: false,
+                capes: true,
+                heads: true,
+            });
+        let uuid = uuid!("09879557e47945a9b434a56377674627");
+
+        // when
+        cache.set_skin(&uuid, None).await;
+        cache.set_cape(&uuid, None).await;
+
+        // then
+        assert!(cache.local_cache.get_skin(&uuid).await.is_none());
+        assert!(cache.remote_cache.get_skin(&uuid).await.is_none());
+        assert!(cache.remote_cache.get_cape(&uuid).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn get_tenant_expiry() {
+        // given
+        let tenant_expiry = new_expiry(Duration::from_secs(10)).with_overrides(&CacheEntries {
+            uuid: Some(new_expiry(Duration::ZERO).uuid),
+            ..Default::default()
+        });
+        let cache = new_cache_2l(Duration::from_secs(10))
+            .await
+            .with_tenant_expiry(HashMap::from([("moderation".to_string(), tenant_expiry)]));
+        let moderation = Some(Arc::from("moderation"));
+
+        // when
+        let global = async {
+            cache.set_uuid("hydrofin", None).await;
+            cache.get_uuid("hydrofin").await
+        }
+        .await;
+        let tenant = tenant::scope(moderation, async {
+            cache.set_uuid("hydrofin", None).await;
+            cache.get_uuid("hydrofin").await
+        })
+        .await;
+
+        // then
+        assert!(matches!(global, Hit(_)));
+        assert!(matches!(tenant, Expired(_)));
+    }
+
     #[tokio::test]
     async fn get_hit() {
         // given
@@ -467,4 +913,166 @@ mod test {
         // then
         assert!(matches!(cached, Miss));
     }
+
+    #[tokio::test]
+    async fn get_expired_with_clock() {
+        // given
+        let clock = Arc::new(ManualClock::new(1000));
+        let cache = new_cache_2l(Duration::from_secs(10))
+            .await
+            .with_clock(clock.clone());
+        cache.set_uuid("hydrofin", None).await;
+
+        // when
+        let before = cache.get_uuid("hydrofin").await;
+        clock.advance(Duration::from_secs(10));
+        let after = cache.get_uuid("hydrofin").await;
+
+        // then
+        assert!(matches!(before, Hit(entry) if entry.timestamp == 1000));
+        assert!(matches!(after, Expired(entry) if entry.timestamp == 1000));
+    }
+
+    #[tokio::test]
+    async fn get_uuids_batch_in_order() {
+        // given
+        let cache = new_cache_2l(Duration::from_secs(10)).await;
+        let data = UuidData {
+            username: "Hydrofin".to_string(),
+            uuid: uuid!("09879557e47945a9b434a56377674627"),
+        };
+        let entry = Dated::at(Some(data.clone()), cache.now_seconds());
+        cache.remote_cache.set_uuid("hydrofin", entry).await;
+        cache.set_uuid("scrayos", None).await;
+        let keys = vec![
+            "scrayos".to_string(),
+            "unknown".to_string(),
+            "hydrofin".to_string(),
+        ];
+
+        // when
+        let cached = cache.get_uuids_batch(&keys).await;
+
+        // then
+        let synced = cache.local_cache.get_uuid("hydrofin").await;
+        assert!(matches!(&cached[0], Hit(entry) if entry.data.is_none()));
+        assert!(matches!(&cached[1], Miss));
+        assert!(matches!(&cached[2], Hit(entry) if entry.data == Some(data.clone())));
+        assert!(matches!(synced, Some(entry) if entry.data == Some(data.clone())));
+    }
+
+    #[test]
+    fn batch_metrics_per_entry() {
+        // given
+        let entry = Dated::at(None::<UuidData>, 1000);
+        let result = vec![Hit(entry.clone()), Expired(entry), Miss, Miss];
+        let labels = HashMap::from([("request_type", "batch_metrics_test")]);
+        let counter = |cache_result| {
+            CACHE_BATCH_RESULT_COUNTER
+                .with_label_values(&["cache", "batch_metrics_test", cache_result])
+                .get()
+        };
+
+        // when
+        metrics_get_batch_handler(MetricsEvent {
+            metric: "cache_get",
+            labels,
+            time: 0.5,
+            result: &result,
+        });
+
+        // then
+        let batches = CACHE_GET_HISTOGRAM
+            .with_label_values(&["cache", "batch_metrics_test_batch", "batch"])
+            .get_sample_count();
+        let singles = CACHE_GET_HISTOGRAM
+            .with_label_values(&["cache", "batch_metrics_test", "miss"])
+            .get_sample_count();
+        assert_eq!(1, counter("hit"));
+        assert_eq!(1, counter("expired"));
+        assert_eq!(2, counter("miss"));
+        assert_eq!(1, batches);
+        assert_eq!(0, singles);
+    }
+
+    #[tokio::test]
+    async fn race_lookup_syncs_remote() {
+        // given
+        let mut expiry = new_expiry(Duration::from_secs(10));
+        expiry.uuid.lookup = Lookup::Race;
+        let cache = Cache::new(
+            expiry,
+            MokaCache::new(new_moka_settings()),
+            MokaCache::new(new_moka_settings()),
+        );
+        let entry = Dated::at(None, cache.now_seconds());
+        cache.remote_cache.set_uuid("hydrofin", entry).await;
+
+        // when
+        let cached = cache.get_uuid("hydrofin").await;
+
+        // then
+        let synced = cache.local_cache.get_uuid("hydrofin").await;
+        assert!(matches!(cached, Hit(entry) if entry.data.is_none()));
+        assert!(matches!(synced, Some(entry) if entry.data.is_none()));
+    }
+
+    #[tokio::test]
+    async fn jitter_spreads_expiry() {
+        // given
+        let clock = Arc::new(ManualClock::new(1000));
+        let mut expiry = new_expiry(Duration::from_secs(100));
+        expiry.uuid.jitter = Jitter::Percentage { percent: 50 };
+        let cache = Cache::new(
+            expiry,
+            MokaCache::new(new_moka_settings()),
+            MokaCache::new(new_moka_settings()),
+        )
+        .with_clock(clock.clone());
+        let usernames: Vec<String> = (0..10).map(|i| format!("user{}", i)).collect();
+        for username in &usernames {
+            cache.set_uuid(username, None).await;
+        }
+
+        // when
+        let mut expired = vec![];
+        for advance in [49, 26, 25] {
+            clock.advance(Duration::from_secs(advance));
+            let mut count = 0;
+            for username in &usernames {
+                if matches!(cache.get_uuid(username).await, Expired(_)) {
+                    count += 1;
+                }
+            }
+            expired.push(count);
+        }
+
+        // then
+        assert_eq!(0, expired[0]);
+        assert!(expired[1] > 0 && expired[1] < 10);
+        assert_eq!(10, expired[2]);
+    }
+
+    #[test]
+    fn jitter_is_stable_per_key() {
+        // given
+        let expiry = CacheEntry {
+            exp: Duration::from_secs(100),
+            exp_empty: Duration::from_secs(10),
+            jitter: Jitter::Uniform {
+                max: Duration::from_secs(30),
+            },
+            lookup: Lookup::Sequential,
+            pre_render: false,
+        };
+
+        // when
+        let first = expiry.jittered("hydrofin");
+        let second = expiry.jittered("hydrofin");
+
+        // then
+        assert_eq!(first.exp, second.exp);
+        assert_eq!(76, first.exp.as_secs());
+        assert!(first.exp_empty <= Duration::from_secs(10));
+    }
 }
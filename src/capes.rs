@@ -0,0 +1,92 @@
+//! The capes module provides the identification of capes by their texture id (hash). The names of
+//! known capes (e.g. `Migrator` or `Vanilla`) are shipped with Xenos and can be extended or
+//! overridden by the configuration, so that new capes can be identified without a release.
+
+use crate::settings;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+
+lazy_static! {
+    /// The shipped names of known capes by their (lowercase) texture id.
+    static ref EMBEDDED_NAMES: HashMap<String, String> =
+        serde_json::from_str(include_str!("../resources/capes.json"))
+            .expect("failed to parse embedded cape names");
+}
+
+/// The [CapeNames] identify capes by their texture id.
+#[derive(Debug, Default)]
+pub struct CapeNames {
+    names: HashMap<String, String>,
+}
+
+impl CapeNames {
+    /// Builds the [CapeNames] from the shipped names (if enabled) and the configured names. The
+    /// configured names take precedence.
+    pub fn new(settings: &settings::CapeNames) -> Self {
+        let embedded = match settings.embedded {
+            true => EMBEDDED_NAMES.clone(),
+            false => HashMap::new(),
+        };
+        let configured = settings
+            .names
+            .iter()
+            .map(|(texture_id, name)| (texture_id.to_lowercase(), name.clone()));
+        Self {
+            names: embedded.into_iter().chain(configured).collect(),
+        }
+    }
+
+    /// Identifies the name of a cape by its (case-insensitive) texture id, if it is known.
+    pub fn identify(&self, texture_id: &str) -> Option<&str> {
+        self.names
+            .get(&texture_id.to_lowercase())
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MIGRATOR: &str = "2340c0e03dd24a11b15a8b33c2a7e9e32abb2051b2481d0ba7defd635ca7a933";
+
+    #[test]
+    fn identify_embedded_and_configured() {
+        // given
+        let settings = settings::CapeNames {
+            embedded: true,
+            names: HashMap::from([
+                ("DEADBEEF".to_string(), "Custom".to_string()),
+                (MIGRATOR.to_string(), "Migration".to_string()),
+            ]),
+        };
+        let names = CapeNames::new(&settings);
+
+        // when
+        let custom = names.identify("deadbeef");
+        let migrator = names.identify(MIGRATOR);
+        let unknown = names.identify("cafebabe");
+
+        // then
+        assert_eq!(Some("Custom"), custom);
+        assert_eq!(Some("Migration"), migrator);
+        assert_eq!(None, unknown);
+    }
+
+    #[test]
+    fn identify_without_embedded() {
+        // given
+        let settings = settings::CapeNames {
+            embedded: false,
+            names: HashMap::new(),
+        };
+        let names = CapeNames::new(&settings);
+
+        // when
+        let migrator = names.identify(MIGRATOR);
+
+        // then
+        assert_eq!(None, migrator);
+        assert!(!EMBEDDED_NAMES.is_empty());
+    }
+}
@@ -0,0 +1,212 @@
+//! The client module provides a typed gRPC client for consuming Xenos from other rust applications.
+//! It requires the `client` feature. The [XenosClient] wraps the generated [ProfileClient] with
+//! convenience methods for the most common requests and an optional local cache. All other requests
+//! are available through the generated client (see [XenosClient::inner]).
+//!
+//! ```rs
+//! let client = XenosClient::connect("http://localhost:50051").await?
+//!     .with_cache(10_000, Duration::from_secs(60));
+//! let uuid = client.uuid("Hydrofin").await?;
+//! let head = client.head(&uuid.uuid.parse()?, true).await?;
+//! ```
+
+pub use crate::proto::profile_client::ProfileClient;
+use crate::proto::{
+    HeadRequest, HeadResponse, ProfileRequest, ProfileResponse, UuidRequest, UuidResponse,
+};
+use moka::future::Cache;
+use std::time::Duration;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Status};
+use uuid::Uuid;
+
+/// [ClientError] is the error type of the [XenosClient].
+#[derive(thiserror::Error, Debug)]
+pub enum ClientError {
+    /// A [Transport] error wraps a [tonic::transport::Error] (e.g. failed to connect to Xenos).
+    #[error(transparent)]
+    Transport(#[from] tonic::transport::Error),
+
+    /// A [NotFound] error indicates that the requested resource does not exist.
+    #[error("resource not found")]
+    NotFound,
+
+    /// A [Status] error wraps any other (non-ok) gRPC [Status] of Xenos.
+    #[error("request failed: {0}")]
+    Status(Box<Status>),
+}
+
+impl From<Status> for ClientError {
+    fn from(status: Status) -> Self {
+        match status.code() {
+            Code::NotFound => ClientError::NotFound,
+            _ => ClientError::Status(Box::new(status)),
+        }
+    }
+}
+
+/// The local cache of the [XenosClient]. The entries expire after a fixed time-to-live.
+#[derive(Debug, Clone)]
+struct ClientCache {
+    uuids: Cache<String, UuidResponse>,
+    profiles: Cache<Uuid, ProfileResponse>,
+    heads: Cache<(Uuid, bool), HeadResponse>,
+}
+
+/// The [XenosClient] is a typed gRPC client of Xenos. It is cheap to clone, all clones share the same
+/// connection (and local cache).
+#[derive(Debug, Clone)]
+pub struct XenosClient {
+    inner: ProfileClient<Channel>,
+    cache: Option<ClientCache>,
+}
+
+impl XenosClient {
+    /// Connects a new [XenosClient] to the gRPC server of Xenos (e.g. `http://localhost:50051`).
+    pub async fn connect(address: impl Into<String>) -> Result<Self, ClientError> {
+        let channel = Endpoint::from_shared(address.into())?.connect().await?;
+        Ok(Self::new(channel))
+    }
+
+    /// Creates a new [XenosClient] from an existing [Channel].
+    pub fn new(channel: Channel) -> Self {
+        Self {
+            inner: ProfileClient::new(channel),
+            cache: None,
+        }
+    }
+
+    /// Enables the local cache of the [XenosClient]. Each request type caches up to `capacity`
+    /// responses for the time-to-live.
+    pub fn with_cache(mut self, capacity: u64, ttl: Duration) -> Self {
+        self.cache = Some(ClientCache {
+            uuids: Cache::builder()
+                .max_capacity(capacity)
+                .time_to_live(ttl)
+                .build(),
+            profiles: Cache::builder()
+                .max_capacity(capacity)
+                .time_to_live(ttl)
+                .build(),
+            heads: Cache::builder()
+                .max_capacity(capacity)
+                .time_to_live(ttl)
+                .build(),
+        });
+        self
+    }
+
+    /// Gets the generated [ProfileClient] for all other requests. They are not cached.
+    pub fn inner(&self) -> ProfileClient<Channel> {
+        self.inner.clone()
+    }
+
+    /// Resolves a (case-insensitive) username to its uuid.
+    pub async fn uuid(&self, username: &str) -> Result<UuidResponse, ClientError> {
+        let key = username.to_lowercase();
+        if let Some(cached) = self.cached(|cache| &cache.uuids, &key).await {
+            return Ok(cached);
+        }
+        let request = UuidRequest {
+            username: username.to_string(),
+        };
+        let response = self.inner.clone().get_uuid(request).await?.into_inner();
+        if let Some(cache) = &self.cache {
+            cache.uuids.insert(key, response.clone()).await;
+        }
+        Ok(response)
+    }
+
+    /// Gets the (complete) profile of an uuid.
+    pub async fn profile(&self, uuid: &Uuid) -> Result<ProfileResponse, ClientError> {
+        if let Some(cached) = self.cached(|cache| &cache.profiles, uuid).await {
+            return Ok(cached);
+        }
+        let request = ProfileRequest {
+            uuid: uuid.to_string(),
+            ..Default::default()
+        };
+        let response = self.inner.clone().get_profile(request).await?.into_inner();
+        if let Some(cache) = &self.cache {
+            cache.profiles.insert(*uuid, response.clone()).await;
+        }
+        Ok(response)
+    }
+
+    /// Gets the head (8x8 PNG image) of an uuid with or without overlay.
+    pub async fn head(&self, uuid: &Uuid, overlay: bool) -> Result<HeadResponse, ClientError> {
+        let key = (*uuid, overlay);
+        if let Some(cached) = self.cached(|cache| &cache.heads, &key).await {
+            return Ok(cached);
+        }
+        let request = HeadRequest {
+            uuid: uuid.to_string(),
+            overlay: Some(overlay),
+            ..Default::default()
+        };
+        let response = self.inner.clone().get_head(request).await?.into_inner();
+        if let Some(cache) = &self.cache {
+            cache.heads.insert(key, response.clone()).await;
+        }
+        Ok(response)
+    }
+
+    /// Gets a response from the local cache (if enabled).
+    async fn cached<K, V>(
+        &self,
+        select: impl Fn(&ClientCache) -> &Cache<K, V>,
+        key: &K,
+    ) -> Option<V>
+    where
+        K: std::hash::Hash + Eq + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+    {
+        select(self.cache.as_ref()?).get(key).await
+    }
+}
+
+#[cfg(all(test, feature = "grpc-server"))]
+mod test {
+    use super::*;
+    use crate::cache::level::no::NoCache;
+    use crate::cache::Cache as ServiceCache;
+    use crate::grpc_profile_server;
+    use crate::mojang::testing::{MojangTestingApi, HYDROFIN};
+    use crate::service::Service;
+    use crate::settings::Settings;
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+    use tonic::transport::server::TcpIncoming;
+    use tonic::transport::Server;
+
+    #[tokio::test]
+    async fn uuid_cached() {
+        // given
+        let settings = Settings::default();
+        let cache = ServiceCache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Arc::new(Service::new(Arc::new(settings), cache, mojang));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(grpc_profile_server(Arc::clone(&service)))
+                .serve_with_incoming(incoming),
+        );
+        let client = XenosClient::connect(address)
+            .await
+            .unwrap()
+            .with_cache(10, Duration::from_secs(60));
+
+        // when
+        let first = client.uuid("hydrofin").await.unwrap();
+        let second = client.uuid("HYDROFIN").await.unwrap();
+        let unknown = client.uuid("unknown").await;
+
+        // then
+        assert_eq!(HYDROFIN.profile.id.hyphenated().to_string(), first.uuid);
+        assert_eq!(first, second);
+        assert!(matches!(unknown, Err(ClientError::NotFound)));
+    }
+}
@@ -0,0 +1,89 @@
+//! The compression module provides the response compression of the rest server. The encoding is
+//! negotiated with the `Accept-Encoding` header of the request (`gzip`, `br` or `zstd`). Only responses
+//! of at least the configured threshold are compressed, so that streamed responses (e.g. the profile
+//! change events) and already compressed images (e.g. PNG textures) are sent as-is.
+
+use crate::settings;
+use tower_http::compression::predicate::{NotForContentType, Predicate, SizeAbove};
+use tower_http::compression::{CompressionLayer, CompressionLevel};
+
+/// Builds the [CompressionLayer] for the [settings](settings::Compression). The response bodies of at
+/// least `min_bytes` are compressed, except for images, gRPC and server-sent events.
+pub fn layer(settings: &settings::Compression) -> CompressionLayer<impl Predicate> {
+    let predicate = SizeAbove::new(settings.min_bytes.try_into().unwrap_or(u16::MAX))
+        .and(NotForContentType::GRPC)
+        .and(NotForContentType::IMAGES)
+        .and(NotForContentType::SSE);
+    CompressionLayer::new()
+        .quality(CompressionLevel::Precise(settings.level as i32))
+        .compress_when(predicate)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE};
+    use axum::http::Request;
+    use axum::response::Response;
+    use axum::routing::get;
+    use axum::Router;
+    use tower::Service as _;
+
+    fn router() -> Router {
+        let settings = settings::Compression {
+            enabled: true,
+            min_bytes: 64,
+            level: 6,
+        };
+        Router::new()
+            .route("/small", get(|| async { "small" }))
+            .route("/large", get(|| async { "a".repeat(128) }))
+            .route(
+                "/image",
+                get(|| async { ([(CONTENT_TYPE, "image/png")], vec![0u8; 128]) }),
+            )
+            .layer(layer(&settings))
+    }
+
+    async fn request(path: &str, encoding: &str) -> Response {
+        let request = Request::get(path)
+            .header(ACCEPT_ENCODING, encoding)
+            .body(Body::empty())
+            .unwrap();
+        router().call(request).await.unwrap().map(Body::new)
+    }
+
+    #[tokio::test]
+    async fn layer_negotiate() {
+        // given
+        let encodings = ["gzip", "br", "zstd"];
+
+        // when
+        let mut responses = vec![];
+        for encoding in encodings {
+            responses.push(request("/large", encoding).await);
+        }
+
+        // then
+        for (encoding, response) in encodings.iter().zip(responses) {
+            assert_eq!(*encoding, response.headers()[CONTENT_ENCODING]);
+        }
+    }
+
+    #[tokio::test]
+    async fn layer_skip() {
+        // given
+        let encoding = "gzip, br, zstd";
+
+        // when
+        let small = request("/small", encoding).await;
+        let image = request("/image", encoding).await;
+        let identity = request("/large", "identity").await;
+
+        // then
+        assert!(!small.headers().contains_key(CONTENT_ENCODING));
+        assert!(!image.headers().contains_key(CONTENT_ENCODING));
+        assert!(!identity.headers().contains_key(CONTENT_ENCODING));
+    }
+}
@@ -0,0 +1,180 @@
+//! The deadline module provides the propagation of request deadlines into the
+//! [Service](crate::service::Service). The deadline of a request is bound to the task that handles
+//! the request. Mojang requests that cannot complete before the deadline are not sent, so that doomed
+//! requests do not waste the rate limit budget. Instead, expired cache entries are used (if any).
+//!
+//! The deadline is read from the `grpc-timeout` header (gRPC) or the `X-Request-Timeout` header
+//! (rest, in seconds). Requests are cancelled once their deadline is exceeded.
+
+#[cfg(feature = "grpc-server")]
+use futures::future::BoxFuture;
+use std::future::Future;
+#[cfg(feature = "grpc-server")]
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+#[cfg(feature = "grpc-server")]
+use tonic::codegen::http;
+
+/// The rest header that contains the timeout of the request in seconds (e.g. `2.5`).
+pub const REQUEST_TIMEOUT_HEADER: &str = "x-request-timeout";
+
+/// The gRPC header that contains the timeout of the request (e.g. `2500m`).
+pub const GRPC_TIMEOUT_HEADER: &str = "grpc-timeout";
+
+tokio::task_local! {
+    /// The deadline of the request that is handled by the current task.
+    static DEADLINE: Instant;
+}
+
+/// Runs a future (e.g. a request handler) with an (optional) deadline. The future is not cancelled
+/// at the deadline, use a [timeout](tokio::time::timeout) for that.
+pub async fn scope<F: Future>(deadline: Option<Instant>, future: F) -> F::Output {
+    match deadline {
+        Some(deadline) => DEADLINE.scope(deadline, future).await,
+        None => future.await,
+    }
+}
+
+/// Gets the remaining time until the deadline of the request of the current task. It returns `None`
+/// if the request has no deadline.
+pub fn remaining() -> Option<Duration> {
+    DEADLINE
+        .try_with(|deadline| deadline.saturating_duration_since(Instant::now()))
+        .ok()
+}
+
+/// Parses the value of the rest `X-Request-Timeout` header (in seconds).
+pub fn parse_request_timeout(value: &str) -> Option<Duration> {
+    value
+        .trim()
+        .parse()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+}
+
+/// Parses the value of the `grpc-timeout` header. The value consists of at most eight digits and a
+/// unit (`H`, `M`, `S`, `m`, `u` or `n`).
+pub fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    let (digits, unit) = value.split_at_checked(value.len().checked_sub(1)?)?;
+    if digits.is_empty() || digits.len() > 8 {
+        return None;
+    }
+    let amount: u64 = digits.parse().ok()?;
+    let timeout = match unit {
+        "H" => Duration::from_secs(amount * 60 * 60),
+        "M" => Duration::from_secs(amount * 60),
+        "S" => Duration::from_secs(amount),
+        "m" => Duration::from_millis(amount),
+        "u" => Duration::from_micros(amount),
+        "n" => Duration::from_nanos(amount),
+        _ => return None,
+    };
+    Some(timeout)
+}
+
+/// The [DeadlineLayer] is a tower layer for the gRPC server that binds the deadline of the
+/// `grpc-timeout` header to the request. The request itself is cancelled by tonic at the deadline.
+/// It does nothing if the deadline propagation is disabled.
+#[cfg(feature = "grpc-server")]
+#[derive(Debug, Clone)]
+pub struct DeadlineLayer {
+    enabled: bool,
+}
+
+#[cfg(feature = "grpc-server")]
+impl DeadlineLayer {
+    /// Creates a new [DeadlineLayer].
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+}
+
+#[cfg(feature = "grpc-server")]
+impl<S> tower::Layer<S> for DeadlineLayer {
+    type Service = DeadlineService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DeadlineService {
+            enabled: self.enabled,
+            inner,
+        }
+    }
+}
+
+/// The [DeadlineService] is the tower service of the [DeadlineLayer].
+#[cfg(feature = "grpc-server")]
+#[derive(Debug, Clone)]
+pub struct DeadlineService<S> {
+    enabled: bool,
+    inner: S,
+}
+
+#[cfg(feature = "grpc-server")]
+impl<S, B> tower::Service<http::Request<B>> for DeadlineService<S>
+where
+    S: tower::Service<http::Request<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let deadline = request
+            .headers()
+            .get(GRPC_TIMEOUT_HEADER)
+            .filter(|_| self.enabled)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_grpc_timeout)
+            .map(|timeout| Instant::now() + timeout);
+        Box::pin(scope(deadline, self.inner.call(request)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_grpc_timeout_units() {
+        // given
+        let values = ["2S", "250m", "1M", "12345678n", "123456789S", "5x", "S", ""];
+
+        // when
+        let timeouts: Vec<_> = values.into_iter().map(parse_grpc_timeout).collect();
+
+        // then
+        assert_eq!(
+            vec![
+                Some(Duration::from_secs(2)),
+                Some(Duration::from_millis(250)),
+                Some(Duration::from_secs(60)),
+                Some(Duration::from_nanos(12345678)),
+                None,
+                None,
+                None,
+                None,
+            ],
+            timeouts
+        );
+    }
+
+    #[tokio::test]
+    async fn scope_remaining() {
+        // given
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        // when
+        let inside = scope(Some(deadline), async { remaining() }).await;
+        let outside = remaining();
+
+        // then
+        assert!(inside.is_some_and(|remaining| remaining > Duration::from_secs(5)));
+        assert_eq!(None, outside);
+    }
+}
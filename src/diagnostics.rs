@@ -0,0 +1,118 @@
+//! The diagnostics module provides the runtime diagnostics of Xenos. It is used to diagnose stuck
+//! requests in production and requires the `diagnostics` feature. The diagnostics are exposed at the
+//! rest server at `/debug/runtime`.
+//!
+//! Some diagnostics depend on unstable tokio features and are only collected if Xenos is built with
+//! `RUSTFLAGS="--cfg tokio_unstable"`:
+//! - The blocking pool statistics require `--cfg tokio_unstable`.
+//! - The task dump additionally requires `--cfg tokio_taskdump` (only on linux).
+
+use serde::Serialize;
+use tokio::runtime::{Handle, RuntimeMetrics};
+
+/// The [RuntimeDiagnostics] are a snapshot of the state of the tokio runtime.
+#[derive(Debug, Serialize)]
+pub struct RuntimeDiagnostics {
+    /// The number of worker threads of the runtime.
+    pub workers: usize,
+
+    /// The number of tasks that are currently alive.
+    pub alive_tasks: usize,
+
+    /// The number of tasks that are scheduled in the global queue.
+    pub global_queue_depth: usize,
+
+    /// The statistics of the blocking pool. Requires `--cfg tokio_unstable`.
+    pub blocking_pool: Option<BlockingPool>,
+
+    /// The traces of all tasks, if requested. Requires `--cfg tokio_unstable --cfg tokio_taskdump`.
+    pub tasks: Option<Vec<String>>,
+}
+
+/// The [BlockingPool] holds the statistics of the blocking pool of the tokio runtime.
+#[derive(Debug, Serialize)]
+pub struct BlockingPool {
+    /// The number of threads of the blocking pool.
+    pub threads: usize,
+
+    /// The number of idle threads of the blocking pool.
+    pub idle_threads: usize,
+
+    /// The number of tasks that are waiting for a thread of the blocking pool.
+    pub queue_depth: usize,
+}
+
+/// Collects the [RuntimeDiagnostics] of the current tokio runtime. The traces of all tasks are only
+/// dumped if requested, as the dump pauses the runtime.
+pub async fn collect(task_dump: bool) -> RuntimeDiagnostics {
+    let handle = Handle::current();
+    let metrics = handle.metrics();
+    RuntimeDiagnostics {
+        workers: metrics.num_workers(),
+        alive_tasks: metrics.num_alive_tasks(),
+        global_queue_depth: metrics.global_queue_depth(),
+        blocking_pool: blocking_pool(&metrics),
+        tasks: match task_dump {
+            true => dump_tasks(&handle).await,
+            false => None,
+        },
+    }
+}
+
+#[cfg(tokio_unstable)]
+fn blocking_pool(metrics: &RuntimeMetrics) -> Option<BlockingPool> {
+    Some(BlockingPool {
+        threads: metrics.num_blocking_threads(),
+        idle_threads: metrics.num_idle_blocking_threads(),
+        queue_depth: metrics.blocking_queue_depth(),
+    })
+}
+
+#[cfg(not(tokio_unstable))]
+fn blocking_pool(_: &RuntimeMetrics) -> Option<BlockingPool> {
+    None
+}
+
+#[cfg(all(
+    tokio_unstable,
+    tokio_taskdump,
+    target_os = "linux",
+    any(target_arch = "aarch64", target_arch = "x86", target_arch = "x86_64")
+))]
+async fn dump_tasks(handle: &Handle) -> Option<Vec<String>> {
+    let dump = handle.dump().await;
+    let traces = dump
+        .tasks()
+        .iter()
+        .map(|task| task.trace().to_string())
+        .collect();
+    Some(traces)
+}
+
+#[cfg(not(all(
+    tokio_unstable,
+    tokio_taskdump,
+    target_os = "linux",
+    any(target_arch = "aarch64", target_arch = "x86", target_arch = "x86_64")
+)))]
+async fn dump_tasks(_: &Handle) -> Option<Vec<String>> {
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn collect_without_tasks() {
+        // given
+        let task_dump = false;
+
+        // when
+        let diagnostics = collect(task_dump).await;
+
+        // then
+        assert_eq!(1, diagnostics.workers);
+        assert!(diagnostics.tasks.is_none());
+    }
+}
@@ -1,5 +1,10 @@
 use crate::error::ServiceError::{NotFound, Unavailable};
+#[cfg(feature = "history")]
+use crate::history;
 use crate::mojang;
+use crate::settings::Capability;
+use crate::usage::UsagePeriod;
+use std::time::Duration;
 
 /// [ServiceError] is the internal error type for xenos. Other crates might implement conversion traits
 /// for these errors.
@@ -17,22 +22,89 @@ pub enum ServiceError {
     #[error(transparent)]
     TextureError(#[from] mojang::TextureError),
 
+    /// A [InvalidTexture] wraps a [mojang::InvalidTexture] (e.g. a fetched skin is not a png image).
+    #[error(transparent)]
+    InvalidTexture(#[from] mojang::InvalidTexture),
+
+    /// A [HistoryError] wraps a [history::HistoryError] (e.g. failed to read from postgres).
+    #[cfg(feature = "history")]
+    #[error(transparent)]
+    HistoryError(#[from] history::HistoryError),
+
+    /// A [InvalidArgument] error indicates that a request contained an invalid argument (e.g. an
+    /// unknown skin model).
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+
     /// A [Unavailable] error indicates that a requested resource that was not cached and could not
     /// be retrieved from mojang because of rate limiting or (mojang) fault. It is not clear, if the
     /// requested resource exists or not.
     #[error("unable to request resource from mojang api")]
     Unavailable,
 
+    /// A [RateLimited] error indicates that a requested resource that was not cached and could not
+    /// be retrieved from mojang because of rate limiting. The client may retry after the (optional)
+    /// retry hint.
+    #[error("mojang api rate limit exceeded")]
+    RateLimited { retry_after: Option<Duration> },
+
+    /// A [QuotaExceeded] error indicates that the client exceeded its request quota for a
+    /// [UsagePeriod]. The client has to wait for the next quota window.
+    #[error("{period} request quota of {limit} requests exceeded")]
+    QuotaExceeded {
+        period: UsagePeriod,
+        limit: u64,
+        retry_after: Duration,
+    },
+
+    /// A [Disabled] error indicates that a requested [Capability] is disabled by the configuration.
+    #[error("{0} are disabled")]
+    Disabled(Capability),
+
     /// A [NotFound] error indicates that a requested resource does not exist. Either marked in cache
     /// or from a mojang response.
     #[error("resource not found")]
     NotFound,
 }
 
+impl ServiceError {
+    /// Gets the machine-readable reason of the [ServiceError]. It is exposed to clients as part of
+    /// the structured error details (grpc and rest), so that they do not have to match error messages.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            ServiceError::UuidError(_) => "INVALID_UUID",
+            ServiceError::ImageError(_) | ServiceError::TextureError(_) => "INTERNAL",
+            ServiceError::InvalidTexture(_) => "INVALID_TEXTURE",
+            #[cfg(feature = "history")]
+            ServiceError::HistoryError(history::HistoryError::Disabled) => "HISTORY_DISABLED",
+            #[cfg(feature = "history")]
+            ServiceError::HistoryError(_) => "INTERNAL",
+            ServiceError::InvalidArgument(_) => "INVALID_ARGUMENT",
+            ServiceError::Unavailable => "UPSTREAM_UNAVAILABLE",
+            ServiceError::RateLimited { .. } => "UPSTREAM_RATE_LIMITED",
+            ServiceError::QuotaExceeded { .. } => "QUOTA_EXCEEDED",
+            ServiceError::Disabled(_) => "CAPABILITY_DISABLED",
+            ServiceError::NotFound => "NOT_FOUND",
+        }
+    }
+
+    /// Gets the (optional) duration after which the client may retry the request.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ServiceError::QuotaExceeded { retry_after, .. } => Some(*retry_after),
+            ServiceError::RateLimited { retry_after } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
 impl From<mojang::ApiError> for ServiceError {
     fn from(value: mojang::ApiError) -> Self {
         match value {
             mojang::ApiError::Unavailable => Unavailable,
+            mojang::ApiError::RateLimited { retry_after } => {
+                ServiceError::RateLimited { retry_after }
+            }
             mojang::ApiError::NotFound => NotFound,
         }
     }
@@ -0,0 +1,152 @@
+//! The events module provides the change detection of profiles. Whenever an expired profile is
+//! refreshed from mojang, it is compared with the previously cached profile. Changed usernames and
+//! skins are published as [ProfileEvent] to all subscribers of the [Service](crate::service::Service)
+//! (e.g. the server-sent events stream at `/events`).
+//!
+//! Changes are only observed by the instance that refreshes the profile. Profiles that are not
+//! requested are never refreshed and therefore never produce events.
+
+use crate::cache::entry::ProfileData;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// A [ProfileEvent] is an observed change of a profile.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProfileEvent {
+    /// The username of the profile changed.
+    NameChanged {
+        uuid: Uuid,
+        timestamp: u64,
+        previous: String,
+        current: String,
+    },
+
+    /// The skin texture of the profile changed. The skins are identified by their texture id and are
+    /// `None` if the profile has no (valid) skin texture.
+    SkinChanged {
+        uuid: Uuid,
+        timestamp: u64,
+        previous: Option<String>,
+        current: Option<String>,
+    },
+}
+
+impl ProfileEvent {
+    /// Gets the uuid of the changed profile.
+    pub fn uuid(&self) -> &Uuid {
+        match self {
+            ProfileEvent::NameChanged { uuid, .. } => uuid,
+            ProfileEvent::SkinChanged { uuid, .. } => uuid,
+        }
+    }
+
+    /// Gets the name of the event type (e.g. `name_changed`).
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            ProfileEvent::NameChanged { .. } => "name_changed",
+            ProfileEvent::SkinChanged { .. } => "skin_changed",
+        }
+    }
+}
+
+/// Gets the texture id of the skin of a profile.
+fn skin_id(profile: &ProfileData) -> Option<String> {
+    let skin = profile.get_textures().ok()?.textures.skin?;
+    Some(skin.texture_id().to_string())
+}
+
+/// Detects the changes between a previous and the current version of a profile. The events have
+/// the provided unix timestamp in seconds.
+pub fn detect_changes(
+    previous: &ProfileData,
+    current: &ProfileData,
+    timestamp: u64,
+) -> Vec<ProfileEvent> {
+    let mut events = vec![];
+    if previous.name != current.name {
+        events.push(ProfileEvent::NameChanged {
+            uuid: current.id,
+            timestamp,
+            previous: previous.name.clone(),
+            current: current.name.clone(),
+        });
+    }
+    let previous_skin = skin_id(previous);
+    let current_skin = skin_id(current);
+    if previous_skin != current_skin {
+        events.push(ProfileEvent::SkinChanged {
+            uuid: current.id,
+            timestamp,
+            previous: previous_skin,
+            current: current_skin,
+        });
+    }
+    events
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mojang::{encode_texture_prop, ProfileProperty, TexturesProperty, CLASSIC_MODEL};
+    use uuid::uuid;
+
+    fn profile(name: &str, skin_id: &str) -> ProfileData {
+        let id = uuid!("09879557e47945a9b434a56377674627");
+        let textures = TexturesProperty::new(
+            id,
+            name,
+            format!("http://textures.minecraft.net/texture/{skin_id}"),
+            CLASSIC_MODEL,
+            None,
+            0,
+        );
+        ProfileData {
+            id,
+            name: name.to_string(),
+            properties: vec![ProfileProperty {
+                name: "textures".to_string(),
+                value: encode_texture_prop(&textures),
+                signature: None,
+            }]
+            .into(),
+            profile_actions: vec![],
+        }
+    }
+
+    #[test]
+    fn detect_changes_unchanged() {
+        // given
+        let previous = profile("Hydrofin", "abc");
+        let current = profile("Hydrofin", "abc");
+
+        // when
+        let events = detect_changes(&previous, &current, 42);
+
+        // then
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn detect_changes_name_and_skin() {
+        // given
+        let previous = profile("Hydrofin", "abc");
+        let current = profile("Scrayos", "def");
+
+        // when
+        let events = detect_changes(&previous, &current, 42);
+
+        // then
+        assert_eq!(2, events.len());
+        assert_eq!("name_changed", events[0].event_type());
+        assert_eq!(
+            ProfileEvent::SkinChanged {
+                uuid: current.id,
+                timestamp: 42,
+                previous: Some("abc".to_string()),
+                current: Some("def".to_string()),
+            },
+            events[1]
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! The faults module provides the fault injection of the rest gateway and grpc profile service. If
+//! enabled, random latency is injected into each request and a percentage of the requests fails with
+//! an injected error. It is used to validate the retry behavior of clients against a real deployment
+//! (e.g. in staging). The faults can be changed at runtime (rest `/admin/faults`).
+
+use crate::settings;
+#[cfg(feature = "grpc-server")]
+use futures::future::BoxFuture;
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "grpc-server")]
+use std::sync::Arc;
+use std::sync::RwLock;
+#[cfg(feature = "grpc-server")]
+use std::task::{Context, Poll};
+use std::time::Duration;
+#[cfg(feature = "grpc-server")]
+use tonic::body::BoxBody;
+#[cfg(feature = "grpc-server")]
+use tonic::codegen::http;
+#[cfg(feature = "grpc-server")]
+use tonic::Status;
+
+lazy_static! {
+    /// A counter for the injected faults by their kind (latency or error).
+    static ref FAULTS_INJECTED: IntCounterVec = register_int_counter_vec!(
+        "xenos_faults_injected_total",
+        "The number of injected faults.",
+        &["fault"]
+    )
+    .unwrap();
+}
+
+/// The prefix of the grpc paths of the profile service. Other grpc services (e.g. health) are not
+/// affected by the fault injection.
+#[cfg(feature = "grpc-server")]
+const GRPC_PROFILE_PATH: &str = "/scrayosnet.xenos.";
+
+/// A [FaultError] indicates that the faults could not be changed.
+#[derive(thiserror::Error, Debug)]
+pub enum FaultError {
+    /// The error percentage is not between `0` and `100`.
+    #[error("invalid error percentage: {0}")]
+    ErrorPercent(f64),
+
+    /// The minimum latency is larger than the maximum latency.
+    #[error("invalid latency range: {0}ms to {1}ms")]
+    LatencyRange(u64, u64),
+}
+
+/// The [FaultState] are the current faults. It is used as request and response of the fault injection
+/// admin endpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FaultState {
+    /// The minimum latency that is injected into each request in milliseconds.
+    pub latency_min_ms: u64,
+
+    /// The maximum latency that is injected into each request in milliseconds.
+    pub latency_max_ms: u64,
+
+    /// The percentage of requests (`0` to `100`) that fail with an injected error.
+    pub error_percent: f64,
+}
+
+impl FaultState {
+    /// Validates the [FaultState].
+    fn validate(&self) -> Result<(), FaultError> {
+        if !(0.0..=100.0).contains(&self.error_percent) {
+            return Err(FaultError::ErrorPercent(self.error_percent));
+        }
+        if self.latency_min_ms > self.latency_max_ms {
+            return Err(FaultError::LatencyRange(
+                self.latency_min_ms,
+                self.latency_max_ms,
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// [Faults] holds the current [FaultState]. It is initialized from the [settings](settings::Faults)
+/// and can be changed at runtime until the next restart.
+#[derive(Debug)]
+pub struct Faults {
+    state: RwLock<FaultState>,
+}
+
+impl Faults {
+    /// Creates new [Faults] from the [settings](settings::Faults).
+    pub fn new(settings: &settings::Faults) -> Self {
+        let min = settings.latency_min.as_millis() as u64;
+        let max = settings.latency_max.as_millis() as u64;
+        Self {
+            state: RwLock::new(FaultState {
+                latency_min_ms: min,
+                latency_max_ms: max.max(min),
+                error_percent: settings.error_percent.clamp(0.0, 100.0),
+            }),
+        }
+    }
+
+    /// Gets the current [FaultState].
+    pub fn state(&self) -> FaultState {
+        self.state.read().expect("fault state poisoned").clone()
+    }
+
+    /// Changes the [FaultState] until the next restart. Returns the new [FaultState].
+    pub fn set_state(&self, state: FaultState) -> Result<FaultState, FaultError> {
+        state.validate()?;
+        *self.state.write().expect("fault state poisoned") = state.clone();
+        tracing::info!(faults = ?state, "changed injected faults");
+        Ok(state)
+    }
+
+    /// Injects the current latency into a request and returns whether the request should fail with
+    /// an injected error.
+    pub async fn inject(&self) -> bool {
+        let state = self.state();
+        let (latency, fail) = {
+            let mut rng = rand::thread_rng();
+            let latency = rng.gen_range(state.latency_min_ms..=state.latency_max_ms);
+            let fail = rng.gen_bool(state.error_percent / 100.0);
+            (latency, fail)
+        };
+        if latency > 0 {
+            FAULTS_INJECTED.with_label_values(&["latency"]).inc();
+            tokio::time::sleep(Duration::from_millis(latency)).await;
+        }
+        if fail {
+            FAULTS_INJECTED.with_label_values(&["error"]).inc();
+        }
+        fail
+    }
+}
+
+/// The [FaultLayer] is a tower layer for the gRPC server that injects the [Faults] into the requests
+/// of the profile service. Failed requests are answered with the status `UNAVAILABLE`.
+#[cfg(feature = "grpc-server")]
+#[derive(Debug, Clone)]
+pub struct FaultLayer {
+    faults: Option<Arc<Faults>>,
+}
+
+#[cfg(feature = "grpc-server")]
+impl FaultLayer {
+    /// Creates a new [FaultLayer]. No faults are injected if the fault injection is disabled (`None`).
+    pub fn new(faults: Option<Arc<Faults>>) -> Self {
+        Self { faults }
+    }
+}
+
+#[cfg(feature = "grpc-server")]
+impl<S> tower::Layer<S> for FaultLayer {
+    type Service = FaultService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        FaultService {
+            faults: self.faults.clone(),
+            inner,
+        }
+    }
+}
+
+/// The [FaultService] is the tower service of the [FaultLayer].
+#[cfg(feature = "grpc-server")]
+#[derive(Debug, Clone)]
+pub struct FaultService<S> {
+    faults: Option<Arc<Faults>>,
+    inner: S,
+}
+
+#[cfg(feature = "grpc-server")]
+impl<S, B> tower::Service<http::Request<B>> for FaultService<S>
+where
+    S: tower::Service<http::Request<B>, Response = http::Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let faults = match &self.faults {
+            Some(faults) if request.uri().path().starts_with(GRPC_PROFILE_PATH) => {
+                Arc::clone(faults)
+            }
+            _ => return Box::pin(self.inner.call(request)),
+        };
+        // the inner service is called while it is ready, its response is dropped if the request fails
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            if faults.inject().await {
+                return Ok(Status::unavailable("injected fault").into_http());
+            }
+            response.await
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn inject_error_percent() {
+        // given
+        let faults = Faults {
+            state: RwLock::new(FaultState {
+                latency_min_ms: 0,
+                latency_max_ms: 0,
+                error_percent: 100.0,
+            }),
+        };
+
+        // when
+        let failed = faults.inject().await;
+        let invalid = faults.set_state(FaultState {
+            latency_min_ms: 10,
+            latency_max_ms: 5,
+            error_percent: 0.0,
+        });
+        faults
+            .set_state(FaultState {
+                latency_min_ms: 0,
+                latency_max_ms: 0,
+                error_percent: 0.0,
+            })
+            .unwrap();
+        let passed = faults.inject().await;
+
+        // then
+        assert!(failed);
+        assert!(matches!(invalid, Err(FaultError::LatencyRange(10, 5))));
+        assert!(!passed);
+    }
+}
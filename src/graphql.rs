@@ -0,0 +1,590 @@
+//! The graphql module provides the optional GraphQL API of Xenos. It is exposed at the rest server at
+//! `/graphql` and maps to the same [Service] (and therefore the same caches and metrics) as the rest
+//! gateway and the gRPC server. It is intended for dashboard frontends that fetch a profile with its
+//! skin, cape and head in a single query. The skin, cape and head are only fetched if selected.
+//!
+//! ```graphql
+//! {
+//!   profile(uuid: "09879557-e479-45a9-b434-a56377674627") {
+//!     name
+//!     skin { model bytes }
+//!     head(overlay: true) { bytes }
+//!   }
+//! }
+//! ```
+//!
+//! All binary image data is encoded in base64.
+
+use crate::cache::entry::HeadKey;
+use crate::cache::level::CacheLevel;
+use crate::error::ServiceError;
+use crate::mojang::Mojang;
+use crate::proto::{
+    parse_uuid, CapeResponse, HeadResponse, ProfileProperty, ProfileResponse, SkinResponse,
+    UuidResponse, UuidsResponse,
+};
+use crate::service::Service;
+use crate::settings::{Capability, UuidFormat};
+use async_graphql::{EmptyMutation, EmptySubscription, Error, ErrorExtensions, Object, Schema};
+use axum::{Extension, Json};
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// [XenosSchema] is the GraphQL schema of Xenos. It has no mutations and subscriptions.
+pub type XenosSchema<L, R, M> = Schema<Query<L, R, M>, EmptyMutation, EmptySubscription>;
+
+/// [GraphqlResult] is an alias for a GraphQL resolver result.
+type GraphqlResult<T> = Result<T, Error>;
+
+/// Converts a [ServiceError] into a GraphQL [Error]. The reason of the error is added as `reason`
+/// extension, so that clients can handle errors the same way as with the rest gateway.
+fn graphql_error(err: ServiceError) -> Error {
+    let reason = err.reason();
+    Error::new(err.to_string()).extend_with(|_, ext| ext.set("reason", reason.to_string()))
+}
+
+/// Converts a [ServiceError] of a nullable resolver. Resources that weren't found resolve to `null`.
+fn not_found_as_none<T>(result: Result<T, ServiceError>) -> GraphqlResult<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(ServiceError::NotFound) => Ok(None),
+        Err(err) => Err(graphql_error(err)),
+    }
+}
+
+/// Builds the [XenosSchema] for a [Service].
+pub fn schema<L, R, M>(service: Arc<Service<L, R, M>>) -> XenosSchema<L, R, M>
+where
+    L: CacheLevel + Sync + 'static,
+    R: CacheLevel + Sync + 'static,
+    M: Mojang + Sync + 'static,
+{
+    Schema::build(Query { service }, EmptyMutation, EmptySubscription).finish()
+}
+
+/// An [axum] handler for the GraphQL API.
+pub async fn graphql<L, R, M>(
+    Extension(schema): Extension<XenosSchema<L, R, M>>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response>
+where
+    L: CacheLevel + Sync + 'static,
+    R: CacheLevel + Sync + 'static,
+    M: Mojang + Sync + 'static,
+{
+    Json(schema.execute(request).await)
+}
+
+/// The [Query] is the GraphQL query root.
+pub struct Query<L, R, M>
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    service: Arc<Service<L, R, M>>,
+}
+
+#[Object]
+impl<L, R, M> Query<L, R, M>
+where
+    L: CacheLevel + Sync + 'static,
+    R: CacheLevel + Sync + 'static,
+    M: Mojang + Sync + 'static,
+{
+    /// Gets the uuid of a username. It resolves to `null` if the username doesn't exist. The format
+    /// of the uuid overrides the configured format.
+    async fn uuid(
+        &self,
+        username: String,
+        uuid_format: Option<UuidFormat>,
+    ) -> GraphqlResult<Option<UuidObject>> {
+        let format = self.uuid_format(uuid_format);
+        let uuid = not_found_as_none(self.service.get_uuid(&username).await)?;
+        let (expiry, now) = (
+            &self.service.cache_entries().uuid,
+            self.service.now_seconds(),
+        );
+        Ok(uuid.map(|uuid| {
+            UuidObject(
+                UuidResponse::from(uuid)
+                    .with_uuid_format(format)
+                    .with_staleness(expiry, now),
+            )
+        }))
+    }
+
+    /// Gets the uuids of multiple usernames. Usernames that don't exist are omitted. The format of
+    /// the uuids overrides the configured format.
+    async fn uuids(
+        &self,
+        usernames: Vec<String>,
+        uuid_format: Option<UuidFormat>,
+    ) -> GraphqlResult<Vec<UuidObject>> {
+        let format = self.uuid_format(uuid_format);
+        let uuids = self
+            .service
+            .get_uuids(&usernames)
+            .await
+            .map_err(graphql_error)?;
+        Ok(UuidsResponse::from(uuids)
+            .with_staleness(
+                &self.service.cache_entries().uuid,
+                self.service.now_seconds(),
+            )
+            .resolved
+            .into_values()
+            .map(|uuid| UuidObject(uuid.with_uuid_format(format)))
+            .collect())
+    }
+
+    /// Gets the profile of a uuid. It resolves to `null` if the profile doesn't exist. The format of
+    /// the uuid overrides the configured format.
+    async fn profile(
+        &self,
+        uuid: String,
+        uuid_format: Option<UuidFormat>,
+    ) -> GraphqlResult<Option<ProfileObject<L, R, M>>> {
+        let format = self.uuid_format(uuid_format);
+        let uuid = parse_uuid(&uuid).map_err(|err| graphql_error(err.into()))?;
+        let profile = not_found_as_none(self.service.get_profile(&uuid).await)?;
+        Ok(profile.map(|profile| ProfileObject {
+            service: Arc::clone(&self.service),
+            uuid,
+            response: ProfileResponse::from(profile)
+                .with_uuid_format(format)
+                .with_staleness(
+                    &self.service.cache_entries().profile,
+                    self.service.now_seconds(),
+                ),
+        }))
+    }
+}
+
+impl<L, R, M> Query<L, R, M>
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    /// Gets the requested [UuidFormat] or the configured format if none is requested.
+    fn uuid_format(&self, requested: Option<UuidFormat>) -> UuidFormat {
+        requested.unwrap_or(self.service.settings().uuid_format)
+    }
+}
+
+/// The [UuidObject] is the GraphQL representation of a [UuidResponse].
+pub struct UuidObject(UuidResponse);
+
+#[Object(name = "Uuid")]
+impl UuidObject {
+    /// The unix timestamp (in seconds) at which the data was last updated.
+    async fn timestamp(&self) -> u64 {
+        self.0.timestamp
+    }
+
+    /// The age (in seconds) of the data at the time of the request.
+    async fn age_seconds(&self) -> u64 {
+        self.0.age_seconds
+    }
+
+    /// Whether the data is older than the expiry of its cache entry.
+    async fn stale(&self) -> bool {
+        self.0.stale
+    }
+
+    /// The username with correct capitalization.
+    async fn username(&self) -> &str {
+        &self.0.username
+    }
+
+    /// The uuid in the requested (or configured) format.
+    async fn uuid(&self) -> &str {
+        &self.0.uuid
+    }
+
+    /// The name of the fallback provider that served the uuid. It is `null` if the uuid was served
+    /// by mojang.
+    async fn provider(&self) -> Option<&str> {
+        self.0.provider.as_deref()
+    }
+}
+
+/// The [ProfileObject] is the GraphQL representation of a [ProfileResponse]. Its skin, cape and head
+/// are resolved lazily with the [Service].
+pub struct ProfileObject<L, R, M>
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    service: Arc<Service<L, R, M>>,
+    uuid: Uuid,
+    response: ProfileResponse,
+}
+
+#[Object(name = "Profile")]
+impl<L, R, M> ProfileObject<L, R, M>
+where
+    L: CacheLevel + Sync + 'static,
+    R: CacheLevel + Sync + 'static,
+    M: Mojang + Sync + 'static,
+{
+    /// The unix timestamp (in seconds) at which the data was last updated.
+    async fn timestamp(&self) -> u64 {
+        self.response.timestamp
+    }
+
+    /// The age (in seconds) of the data at the time of the request.
+    async fn age_seconds(&self) -> u64 {
+        self.response.age_seconds
+    }
+
+    /// Whether the data is older than the expiry of its cache entry.
+    async fn stale(&self) -> bool {
+        self.response.stale
+    }
+
+    /// The uuid in the requested (or configured) format.
+    async fn uuid(&self) -> &str {
+        &self.response.uuid
+    }
+
+    /// The username with correct capitalization.
+    async fn name(&self) -> &str {
+        &self.response.name
+    }
+
+    /// The properties of attached information for this profile.
+    async fn properties(&self) -> Vec<PropertyObject> {
+        self.response
+            .properties
+            .iter()
+            .cloned()
+            .map(PropertyObject)
+            .collect()
+    }
+
+    /// The moderative actions/sanctions that have been imposed on this profile.
+    async fn profile_actions(&self) -> &[String] {
+        &self.response.profile_actions
+    }
+
+    /// The name of the fallback provider that served the profile. It is `null` if the profile was
+    /// served by mojang.
+    async fn provider(&self) -> Option<&str> {
+        self.response.provider.as_deref()
+    }
+
+    /// The skin of the profile.
+    async fn skin(&self) -> GraphqlResult<Option<SkinObject>> {
+        self.service
+            .ensure_enabled(&[Capability::Skins])
+            .map_err(graphql_error)?;
+        let skin = not_found_as_none(self.service.get_skin(&self.uuid).await)?;
+        let (expiry, now) = (
+            &self.service.cache_entries().skin,
+            self.service.now_seconds(),
+        );
+        Ok(skin.map(|skin| SkinObject(SkinResponse::from(skin).with_staleness(expiry, now))))
+    }
+
+    /// The cape of the profile. It resolves to `null` if the profile has no cape.
+    async fn cape(&self) -> GraphqlResult<Option<CapeObject>> {
+        self.service
+            .ensure_enabled(&[Capability::Capes])
+            .map_err(graphql_error)?;
+        let cape = not_found_as_none(self.service.get_cape(&self.uuid).await)?;
+        let names = self.service.cape_names();
+        let (expiry, now) = (
+            &self.service.cache_entries().cape,
+            self.service.now_seconds(),
+        );
+        Ok(cape.map(|cape| {
+            CapeObject(
+                CapeResponse::from(cape)
+                    .with_name(names)
+                    .with_staleness(expiry, now),
+            )
+        }))
+    }
+
+    /// The head of the profile, optionally with its overlay layer.
+    async fn head(
+        &self,
+        #[graphql(default = true)] overlay: bool,
+    ) -> GraphqlResult<Option<HeadObject>> {
+        self.service
+            .ensure_enabled(&[Capability::Heads])
+            .map_err(graphql_error)?;
+        let head = not_found_as_none(
+            self.service
+                .get_head(&HeadKey::new(self.uuid, overlay))
+                .await,
+        )?;
+        let (expiry, now) = (
+            &self.service.cache_entries().head,
+            self.service.now_seconds(),
+        );
+        Ok(head.map(|head| HeadObject(HeadResponse::from(head).with_staleness(expiry, now))))
+    }
+}
+
+/// The [PropertyObject] is the GraphQL representation of a [ProfileProperty].
+pub struct PropertyObject(ProfileProperty);
+
+#[Object(name = "ProfileProperty")]
+impl PropertyObject {
+    /// The name of the property (e.g. `textures`).
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// The base64 encoded value of the property.
+    async fn value(&self) -> &str {
+        &self.0.value
+    }
+
+    /// The signature of the value, if signed profiles are enabled.
+    async fn signature(&self) -> Option<&str> {
+        self.0.signature.as_deref()
+    }
+}
+
+/// The [SkinObject] is the GraphQL representation of a [SkinResponse].
+pub struct SkinObject(SkinResponse);
+
+#[Object(name = "Skin")]
+impl SkinObject {
+    /// The unix timestamp (in seconds) at which the data was last updated.
+    async fn timestamp(&self) -> u64 {
+        self.0.timestamp
+    }
+
+    /// The age (in seconds) of the data at the time of the request.
+    async fn age_seconds(&self) -> u64 {
+        self.0.age_seconds
+    }
+
+    /// Whether the data is older than the expiry of its cache entry.
+    async fn stale(&self) -> bool {
+        self.0.stale
+    }
+
+    /// The base64 encoded 64x64 PNG image of the skin.
+    async fn bytes(&self) -> String {
+        BASE64_STANDARD.encode(&self.0.bytes)
+    }
+
+    /// The model of the skin (e.g. `slim`).
+    async fn model(&self) -> &str {
+        &self.0.model
+    }
+
+    /// Whether the skin is the player default skin.
+    #[graphql(name = "default")]
+    async fn is_default(&self) -> bool {
+        self.0.default
+    }
+
+    /// Whether the (banned) skin was suppressed and replaced with the player default skin.
+    async fn suppressed(&self) -> bool {
+        self.0.suppressed
+    }
+}
+
+/// The [CapeObject] is the GraphQL representation of a [CapeResponse].
+pub struct CapeObject(CapeResponse);
+
+#[Object(name = "Cape")]
+impl CapeObject {
+    /// The unix timestamp (in seconds) at which the data was last updated.
+    async fn timestamp(&self) -> u64 {
+        self.0.timestamp
+    }
+
+    /// The age (in seconds) of the data at the time of the request.
+    async fn age_seconds(&self) -> u64 {
+        self.0.age_seconds
+    }
+
+    /// Whether the data is older than the expiry of its cache entry.
+    async fn stale(&self) -> bool {
+        self.0.stale
+    }
+
+    /// The base64 encoded PNG image of the cape.
+    async fn bytes(&self) -> String {
+        BASE64_STANDARD.encode(&self.0.bytes)
+    }
+
+    /// The name of the cape (e.g. `Migrator`), if it is identified by its texture id.
+    async fn name(&self) -> Option<&str> {
+        self.0.name.as_deref()
+    }
+}
+
+/// The [HeadObject] is the GraphQL representation of a [HeadResponse].
+pub struct HeadObject(HeadResponse);
+
+#[Object(name = "Head")]
+impl HeadObject {
+    /// The unix timestamp (in seconds) at which the data was last updated.
+    async fn timestamp(&self) -> u64 {
+        self.0.timestamp
+    }
+
+    /// The age (in seconds) of the data at the time of the request.
+    async fn age_seconds(&self) -> u64 {
+        self.0.age_seconds
+    }
+
+    /// Whether the data is older than the expiry of its cache entry.
+    async fn stale(&self) -> bool {
+        self.0.stale
+    }
+
+    /// The base64 encoded 8x8 PNG image of the head.
+    async fn bytes(&self) -> String {
+        BASE64_STANDARD.encode(&self.0.bytes)
+    }
+
+    /// Whether the head was generated from the player default skin.
+    #[graphql(name = "default")]
+    async fn is_default(&self) -> bool {
+        self.0.default
+    }
+
+    /// Whether the head of the (banned) skin was suppressed and replaced with the default head.
+    async fn suppressed(&self) -> bool {
+        self.0.suppressed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::level::no::NoCache;
+    use crate::cache::Cache;
+    use crate::mojang::testing::{MojangTestingApi, HYDROFIN};
+    use crate::settings::Settings;
+    use serde_json::json;
+
+    fn test_schema(
+        uuid_format: UuidFormat,
+    ) -> XenosSchema<NoCache, NoCache, MojangTestingApi<'static>> {
+        let settings = Settings {
+            uuid_format,
+            ..Default::default()
+        };
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        schema(Arc::new(Service::new(Arc::new(settings), cache, mojang)))
+    }
+
+    #[tokio::test]
+    async fn uuid_found() {
+        // given
+        let schema = test_schema(UuidFormat::Hyphenated);
+
+        // when
+        let response = schema
+            .execute(r#"{ uuid(username: "hydrofin") { username uuid provider } }"#)
+            .await;
+
+        // then
+        assert!(response.errors.is_empty());
+        assert_eq!(
+            json!({ "uuid": {
+                "username": HYDROFIN.profile.name,
+                "uuid": HYDROFIN.profile.id.hyphenated().to_string(),
+                "provider": null,
+            }}),
+            response.data.into_json().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn uuid_not_found() {
+        // given
+        let schema = test_schema(UuidFormat::Hyphenated);
+
+        // when
+        let response = schema
+            .execute(r#"{ uuid(username: "unknown") { uuid } }"#)
+            .await;
+
+        // then
+        assert!(response.errors.is_empty());
+        assert_eq!(json!({ "uuid": null }), response.data.into_json().unwrap());
+    }
+
+    #[tokio::test]
+    async fn uuid_format() {
+        // given
+        let schema = test_schema(UuidFormat::Simple);
+
+        // when
+        let configured = schema
+            .execute(r#"{ uuids(usernames: ["hydrofin"]) { uuid } }"#)
+            .await;
+        let requested = schema
+            .execute(r#"{ uuid(username: "hydrofin", uuidFormat: HYPHENATED) { uuid } }"#)
+            .await;
+
+        // then
+        assert_eq!(
+            json!({ "uuids": [{ "uuid": HYDROFIN.profile.id.simple().to_string() }] }),
+            configured.data.into_json().unwrap()
+        );
+        assert_eq!(
+            json!({ "uuid": { "uuid": HYDROFIN.profile.id.hyphenated().to_string() } }),
+            requested.data.into_json().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn profile_with_head() {
+        // given
+        let schema = test_schema(UuidFormat::Hyphenated);
+        let query = format!(
+            r#"{{ profile(uuid: "{}") {{ name head(overlay: false) {{ default }} }} }}"#,
+            HYDROFIN.profile.id.simple()
+        );
+
+        // when
+        let response = schema.execute(query).await;
+
+        // then
+        assert!(response.errors.is_empty());
+        assert_eq!(
+            json!({ "profile": {
+                "name": HYDROFIN.profile.name,
+                "head": { "default": false },
+            }}),
+            response.data.into_json().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn profile_invalid_uuid() {
+        // given
+        let schema = test_schema(UuidFormat::Hyphenated);
+
+        // when
+        let response = schema
+            .execute(r#"{ profile(uuid: "invalid") { name } }"#)
+            .await;
+
+        // then
+        assert_eq!(1, response.errors.len());
+        let reason = response.errors[0]
+            .extensions
+            .as_ref()
+            .and_then(|extensions| extensions.get("reason"))
+            .cloned();
+        assert_eq!(Some(async_graphql::Value::from("INVALID_UUID")), reason);
+    }
+}
@@ -1,32 +1,111 @@
 use crate::cache::level::CacheLevel;
 use crate::error::ServiceError;
-use crate::error::ServiceError::{NotFound, Unavailable, UuidError};
+use crate::error::ServiceError::{InvalidArgument, NotFound, Unavailable, UuidError};
+use crate::logging::{self, LogLevelError};
 use crate::mojang::Mojang;
 use crate::proto::{
-    profile_server::Profile, CapeRequest, CapeResponse, HeadRequest, HeadResponse, ProfileRequest,
-    ProfileResponse, SkinRequest, SkinResponse, UuidRequest, UuidResponse, UuidsRequest,
-    UuidsResponse,
+    parse_uuid, profile_server::Profile, BlockedServerRequest, BlockedServerResponse,
+    BlockedServersRequest, BlockedServersResponse, BuildTexturesRequest, BuildTexturesResponse,
+    CapeRequest, CapeResponse, ChecksumRequest, ChecksumResponse, ColorsRequest, ColorsResponse,
+    HeadRequest, HeadResponse, LogLevelRequest, LogLevelResponse, NameHistoryRequest,
+    NameHistoryResponse, ProfileBundleRequest, ProfileBundleResponse, ProfileRequest,
+    ProfileResponse, SkinHistoryRequest, SkinHistoryResponse, SkinRequest, SkinResponse,
+    StatusRequest, StatusResponse, TextureRequest, TextureResponse, UuidRequest, UuidResponse,
+    UuidsRequest, UuidsResponse, ValidateUuidRequest, ValidateUuidResponse,
 };
-use crate::service::Service;
+use crate::proxy::FORWARDED_FOR_HEADER;
+use crate::service::{record_image_source, Service};
+use crate::settings::{Admin, Capability, UuidFormat};
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tonic::{Request, Response, Status};
-use uuid::Uuid;
+use std::time::Duration;
+use tonic::{Request, Response, Status, Streaming};
+use tonic_types::{ErrorDetails, StatusExt};
 
 /// [GrpcResult] is an alias for grpc result [Response] and [Status].
 type GrpcResult<T> = Result<Response<T>, Status>;
 
+/// The metadata key to override the configured [UuidFormat] of the response.
+const UUID_FORMAT_KEY: &str = "x-uuid-format";
+
+/// The number of (distinct) streamed usernames that are resolved together. The cache misses of a batch
+/// are fetched from mojang in chunks of ten usernames, so only the last chunk of a batch may be partial.
+const RESOLVE_BATCH_SIZE: usize = 100;
+
+/// The maximum number of distinct usernames that are resolved in one stream. The resolved usernames
+/// are held until the stream completes, so the stream is rejected once it exceeds the limit.
+const RESOLVE_MAX_USERNAMES: usize = 10_000;
+
+/// The error domain of the structured error details.
+const ERROR_DOMAIN: &str = "xenos.scrayos.net";
+
+/// Adds the structured error details (`google.rpc.ErrorInfo` and `google.rpc.RetryInfo`) of a
+/// [ServiceError] to a [Status].
+fn with_details(status: Status, reason: &str, retry_after: Option<Duration>) -> Status {
+    let mut details = ErrorDetails::with_error_info(reason, ERROR_DOMAIN, HashMap::new());
+    if let Some(retry_after) = retry_after {
+        details.set_retry_info(Some(retry_after));
+    }
+    Status::with_error_details(status.code(), status.message(), details)
+}
+
 // utility that allows the usage of ServiceError in result with auto conversion to (tonic) response status
 impl From<ServiceError> for Status {
     fn from(value: ServiceError) -> Self {
-        match value {
+        let reason = value.reason();
+        let retry_after = value.retry_after();
+        let status = match value {
             UuidError(_) => Status::invalid_argument("invalid uuid"),
+            InvalidArgument(msg) => Status::invalid_argument(msg),
             Unavailable => Status::unavailable("unable to request resource from mojang api"),
             NotFound => Status::not_found("resource not found"),
+            err @ ServiceError::Disabled(_) => Status::unimplemented(err.to_string()),
+            err @ ServiceError::InvalidTexture(_) => Status::data_loss(err.to_string()),
+            err @ ServiceError::RateLimited { .. } => Status::resource_exhausted(err.to_string()),
+            err @ ServiceError::QuotaExceeded { .. } => Status::resource_exhausted(err.to_string()),
+            #[cfg(feature = "history")]
+            ServiceError::HistoryError(crate::history::HistoryError::Disabled) => {
+                Status::unimplemented("profile history is disabled")
+            }
             err => Status::internal(err.to_string()),
+        };
+        with_details(status, reason, retry_after)
+    }
+}
+
+// utility that allows the usage of LogLevelError in result with auto conversion to (tonic) response status
+impl From<LogLevelError> for Status {
+    fn from(value: LogLevelError) -> Self {
+        match value {
+            err @ LogLevelError::Invalid(_) => Status::invalid_argument(err.to_string()),
+            err @ LogLevelError::Unavailable => Status::unavailable(err.to_string()),
         }
     }
 }
 
+/// Validates the basic auth (`authorization` metadata) of an admin request with the shared [Admin]
+/// credentials if enabled. It returns the reason if the request is unauthorized.
+fn check_admin_auth<T>(request: &Request<T>, settings: &Admin) -> Result<(), &'static str> {
+    if !settings.auth_enabled {
+        return Ok(());
+    }
+    let credentials = request
+        .metadata()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|value| BASE64_STANDARD.decode(value.trim()).ok())
+        .and_then(|value| String::from_utf8(value).ok())
+        .ok_or("missing basic auth")?;
+    let (username, password) = credentials.split_once(':').ok_or("invalid auth")?;
+    if !settings.is_authorized(username, Some(password)) {
+        return Err("invalid auth");
+    }
+    Ok(())
+}
+
 /// A [GrpcProfileService] wraps [Service] and implements the grpc [Profile] service.
 pub struct GrpcProfileService<L, R, M>
 where
@@ -47,6 +126,37 @@ where
     pub fn new(service: Arc<Service<L, R, M>>) -> Self {
         Self { service }
     }
+
+    /// Counts the request for the client (see [IdentityResolver](crate::identity::IdentityResolver))
+    /// and rejects it if the client exceeded its quota.
+    async fn record_usage<T>(&self, request: &Request<T>) -> Result<(), Status> {
+        let identity = self.service.identity();
+        let metadata = |key: &str| {
+            request
+                .metadata()
+                .get(key)
+                .and_then(|value| value.to_str().ok())
+        };
+        let client = identity.identify(
+            metadata(identity.header()),
+            metadata(FORWARDED_FOR_HEADER),
+            request.remote_addr(),
+        );
+        Ok(self.service.record_usage(&client).await?)
+    }
+
+    /// Gets the [UuidFormat] of the response. It can be overridden per request with the
+    /// `x-uuid-format` metadata key and defaults to the configured format.
+    fn uuid_format<T>(&self, request: &Request<T>) -> Result<UuidFormat, ServiceError> {
+        match request.metadata().get(UUID_FORMAT_KEY) {
+            Some(value) => value
+                .to_str()
+                .map_err(|_| InvalidArgument("invalid uuid format".to_string()))?
+                .parse()
+                .map_err(InvalidArgument),
+            None => Ok(self.service.settings().uuid_format),
+        }
+    }
 }
 
 #[tonic::async_trait]
@@ -57,41 +167,593 @@ where
     M: Mojang + Sync + 'static,
 {
     async fn get_uuid(&self, request: Request<UuidRequest>) -> GrpcResult<UuidResponse> {
+        self.record_usage(&request).await?;
+        let format = self.uuid_format(&request)?;
         let username = request.into_inner().username;
         let uuid = self.service.get_uuid(&username).await?;
-        Ok(Response::new(uuid.into()))
+        let expiry = &self.service.cache_entries().uuid;
+        Ok(Response::new(
+            UuidResponse::from(uuid)
+                .with_uuid_format(format)
+                .with_staleness(expiry, self.service.now_seconds()),
+        ))
     }
 
     async fn get_uuids(&self, request: Request<UuidsRequest>) -> GrpcResult<UuidsResponse> {
-        let usernames = request.into_inner().usernames;
-        let uuids = self.service.get_uuids(&usernames).await?;
-        Ok(Response::new(uuids.into()))
+        self.record_usage(&request).await?;
+        let format = self.uuid_format(&request)?;
+        let req = request.into_inner();
+        // partial failures are only reported per username if the statuses are requested
+        let mut uuids = match req.include_status {
+            true => UuidsResponse::from(self.service.get_uuids_partial(&req.usernames).await?),
+            false => UuidsResponse::from(self.service.get_uuids(&req.usernames).await?),
+        };
+        if req.exclude_sanctioned {
+            let sanctioned = self.service.get_sanctioned(&uuids.resolved_uuids()).await;
+            uuids = uuids.without_sanctioned(&sanctioned);
+        }
+        if req.preserve_case {
+            uuids = uuids.with_requested_usernames(&req.usernames);
+        }
+        let expiry = &self.service.cache_entries().uuid;
+        Ok(Response::new(
+            uuids
+                .with_not_found(req.include_not_found)
+                .with_uuid_format(format)
+                .with_staleness(expiry, self.service.now_seconds()),
+        ))
+    }
+
+    async fn resolve_uuids(
+        &self,
+        request: Request<Streaming<UuidRequest>>,
+    ) -> GrpcResult<UuidsResponse> {
+        // the stream is not shared with the (awaited) usage accounting, as it is not Sync
+        let (metadata, extensions, mut stream) = request.into_parts();
+        let request = Request::from_parts(metadata, extensions, ());
+        let format = self.uuid_format(&request)?;
+        let mut seen = HashSet::new();
+        let mut batch = Vec::with_capacity(RESOLVE_BATCH_SIZE);
+        let mut uuids = HashMap::new();
+        loop {
+            let username = stream.message().await?;
+            if let Some(req) = &username {
+                // every streamed username is counted like a single request
+                self.record_usage(&request).await?;
+                if seen.insert(req.username.to_lowercase()) {
+                    if seen.len() > RESOLVE_MAX_USERNAMES {
+                        return Err(InvalidArgument(format!(
+                            "stream exceeds {} usernames",
+                            RESOLVE_MAX_USERNAMES
+                        ))
+                        .into());
+                    }
+                    batch.push(req.username.clone());
+                }
+            }
+            if batch.len() >= RESOLVE_BATCH_SIZE || (username.is_none() && !batch.is_empty()) {
+                uuids.extend(self.service.get_uuids(&batch).await?);
+                batch.clear();
+            }
+            if username.is_none() {
+                break;
+            }
+        }
+        let expiry = &self.service.cache_entries().uuid;
+        Ok(Response::new(
+            UuidsResponse::from(uuids)
+                .with_not_found(false)
+                .with_uuid_format(format)
+                .with_staleness(expiry, self.service.now_seconds()),
+        ))
     }
 
     async fn get_profile(&self, request: Request<ProfileRequest>) -> GrpcResult<ProfileResponse> {
-        let uuid = Uuid::try_parse(&request.into_inner().uuid).map_err(UuidError)?;
+        self.record_usage(&request).await?;
+        let format = self.uuid_format(&request)?;
+        let req = request.into_inner();
+        let uuid = req.parse_uuid().map_err(UuidError)?;
         let profile = self.service.get_profile(&uuid).await?;
-        Ok(Response::new(profile.into()))
+        let expiry = &self.service.cache_entries().profile;
+        let response = ProfileResponse::from(profile)
+            .with_uuid_format(format)
+            .with_staleness(expiry, self.service.now_seconds())
+            .with_fields(&req.fields)?;
+        Ok(Response::new(response))
+    }
+
+    async fn get_profile_bundle(
+        &self,
+        request: Request<ProfileBundleRequest>,
+    ) -> GrpcResult<ProfileBundleResponse> {
+        self.service
+            .ensure_enabled(&[Capability::Skins, Capability::Capes, Capability::Heads])?;
+        self.record_usage(&request).await?;
+        let format = self.uuid_format(&request)?;
+        let req = request.into_inner();
+        let uuid = req.parse_uuid().map_err(UuidError)?;
+        let overlay = req
+            .overlay
+            .unwrap_or(self.service.settings().head_overlay.default);
+        let bundle = self.service.get_profile_bundle(&uuid, overlay).await?;
+        let entries = &self.service.cache_entries();
+        Ok(Response::new(
+            ProfileBundleResponse::from(bundle)
+                .with_uuid_format(format)
+                .with_staleness(entries, self.service.now_seconds()),
+        ))
     }
 
     async fn get_skin(&self, request: Request<SkinRequest>) -> GrpcResult<SkinResponse> {
+        self.service.ensure_enabled(&[Capability::Skins])?;
+        self.record_usage(&request).await?;
         let req = request.into_inner();
-        let uuid = Uuid::try_parse(&req.uuid).map_err(UuidError)?;
-        let skin = self.service.get_skin(&uuid).await?;
-        Ok(Response::new(skin.into()))
+        let uuid = req.parse_uuid().map_err(UuidError)?;
+        let entries = &self.service.cache_entries();
+        let skin = match req.url_only {
+            true => SkinResponse::from(self.service.get_skin_url(&uuid).await?)
+                .with_staleness(&entries.profile, self.service.now_seconds()),
+            false => {
+                let model = req.model.as_deref();
+                let skin = match self
+                    .service
+                    .get_skin_styled(&uuid, req.force_default, model)
+                    .await
+                {
+                    Err(err) if self.service.serves_placeholder(&err) => SkinResponse {
+                        placeholder: true,
+                        ..SkinResponse::from(self.service.get_placeholder_skin(&uuid))
+                    },
+                    skin => SkinResponse::from(skin?)
+                        .with_staleness(&entries.skin, self.service.now_seconds()),
+                };
+                record_image_source("grpc", "skin", skin.default, skin.placeholder);
+                skin
+            }
+        };
+        Ok(Response::new(skin))
     }
 
     async fn get_cape(&self, request: Request<CapeRequest>) -> GrpcResult<CapeResponse> {
-        let uuid = Uuid::try_parse(&request.into_inner().uuid).map_err(UuidError)?;
-        let cape = self.service.get_cape(&uuid).await?;
-        Ok(Response::new(cape.into()))
+        self.service.ensure_enabled(&[Capability::Capes])?;
+        self.record_usage(&request).await?;
+        let req = request.into_inner();
+        let uuid = req.parse_uuid().map_err(UuidError)?;
+        let entries = &self.service.cache_entries();
+        let cape = match req.url_only {
+            true => CapeResponse::from(self.service.get_cape_url(&uuid).await?)
+                .with_staleness(&entries.profile, self.service.now_seconds()),
+            false => CapeResponse::from(self.service.get_cape(&uuid).await?)
+                .with_staleness(&entries.cape, self.service.now_seconds()),
+        }
+        .with_name(self.service.cape_names());
+        let cape = self
+            .service
+            .render("cape", move || {
+                cape.with_animation(req.animated, req.frames)
+            })
+            .await?;
+        Ok(Response::new(cape))
     }
 
     async fn get_head(&self, request: Request<HeadRequest>) -> GrpcResult<HeadResponse> {
+        self.service.ensure_enabled(&[Capability::Heads])?;
+        self.record_usage(&request).await?;
+        let req = request.into_inner();
+        let overlay = req
+            .overlay
+            .unwrap_or(self.service.settings().head_overlay.default);
+        let uuid = req.parse_uuid().map_err(UuidError)?;
+        let key = req.head_key(uuid, overlay)?;
+        let model = req.model.as_deref();
+        let expiry = &self.service.cache_entries().head;
+        let head = match self
+            .service
+            .get_head_styled(&key, req.force_default, model)
+            .await
+        {
+            Err(err) if self.service.serves_placeholder(&err) => {
+                let placeholder = self.service.get_placeholder_head(&uuid);
+                HeadResponse {
+                    placeholder: true,
+                    ..HeadResponse::from(self.service.format_head(placeholder, &key).await?)
+                }
+            }
+            head => HeadResponse::from(head?).with_staleness(expiry, self.service.now_seconds()),
+        };
+        record_image_source("grpc", "head", head.default, head.placeholder);
+        Ok(Response::new(head.with_size(&key)))
+    }
+
+    async fn get_checksum(
+        &self,
+        request: Request<ChecksumRequest>,
+    ) -> GrpcResult<ChecksumResponse> {
+        self.service
+            .ensure_enabled(&[Capability::Skins, Capability::Heads])?;
+        self.record_usage(&request).await?;
+        let req = request.into_inner();
+        let uuid = req.parse_uuid().map_err(UuidError)?;
+        let overlay = req
+            .overlay
+            .unwrap_or(self.service.settings().head_overlay.default);
+        let checksum = self.service.get_checksum(&uuid, overlay).await?;
+        let expiry = &self.service.cache_entries().skin;
+        Ok(Response::new(
+            ChecksumResponse::from(checksum).with_staleness(expiry, self.service.now_seconds()),
+        ))
+    }
+
+    async fn get_colors(&self, request: Request<ColorsRequest>) -> GrpcResult<ColorsResponse> {
+        self.service.ensure_enabled(&[Capability::Skins])?;
+        self.record_usage(&request).await?;
+        let req = request.into_inner();
+        if req.head {
+            self.service.ensure_enabled(&[Capability::Heads])?;
+        }
+        let uuid = req.parse_uuid().map_err(UuidError)?;
+        let colors = self
+            .service
+            .get_colors(&uuid, req.head, req.count as usize)
+            .await?;
+        let expiry = match req.head {
+            true => &self.service.cache_entries().head,
+            false => &self.service.cache_entries().skin,
+        };
+        Ok(Response::new(
+            ColorsResponse::from(colors).with_staleness(expiry, self.service.now_seconds()),
+        ))
+    }
+
+    async fn get_texture(&self, request: Request<TextureRequest>) -> GrpcResult<TextureResponse> {
+        self.record_usage(&request).await?;
+        let texture_id = request.into_inner().texture_id;
+        let texture = self.service.get_texture(&texture_id).await?;
+        let expiry = &self.service.cache_entries().texture;
+        Ok(Response::new(
+            TextureResponse::from(texture).with_staleness(expiry, self.service.now_seconds()),
+        ))
+    }
+
+    async fn build_textures(
+        &self,
+        request: Request<BuildTexturesRequest>,
+    ) -> GrpcResult<BuildTexturesResponse> {
+        self.record_usage(&request).await?;
+        let format = self.uuid_format(&request)?;
         let req = request.into_inner();
-        let overlay = req.overlay;
-        let uuid = Uuid::try_parse(&req.uuid).map_err(UuidError)?;
-        let head = self.service.get_head(&uuid, overlay).await?;
-        Ok(Response::new(head.into()))
+        let uuid = req.uuid.map(|uuid| parse_uuid(&uuid)).transpose();
+        let profile_id = req.profile_uuid.map(|id| parse_uuid(&id)).transpose();
+        let textures = self
+            .service
+            .build_textures(
+                uuid.map_err(UuidError)?.as_ref(),
+                req.skin.as_deref(),
+                req.model.as_deref(),
+                req.cape.as_deref(),
+                profile_id.map_err(UuidError)?,
+                req.profile_name.as_deref(),
+            )
+            .await?;
+        Ok(Response::new(
+            BuildTexturesResponse::from(textures)
+                .with_uuid_format(format)
+                .with_staleness(
+                    &self.service.cache_entries().profile,
+                    self.service.now_seconds(),
+                ),
+        ))
+    }
+
+    async fn validate_uuid(
+        &self,
+        request: Request<ValidateUuidRequest>,
+    ) -> GrpcResult<ValidateUuidResponse> {
+        self.record_usage(&request).await?;
+        let uuid = request.into_inner().uuid;
+        Ok(Response::new(ValidateUuidResponse::validate(&uuid)))
+    }
+
+    async fn get_blocked_servers(
+        &self,
+        request: Request<BlockedServersRequest>,
+    ) -> GrpcResult<BlockedServersResponse> {
+        self.record_usage(&request).await?;
+        let blocked = self.service.get_blocked_servers().await?;
+        let expiry = &self.service.cache_entries().blocked_servers;
+        Ok(Response::new(
+            BlockedServersResponse::from(blocked)
+                .with_staleness(expiry, self.service.now_seconds()),
+        ))
+    }
+
+    async fn check_blocked_server(
+        &self,
+        request: Request<BlockedServerRequest>,
+    ) -> GrpcResult<BlockedServerResponse> {
+        self.record_usage(&request).await?;
+        let hostname = request.into_inner().hostname;
+        let pattern = self.service.is_server_blocked(&hostname).await?;
+        let expiry = &self.service.cache_entries().blocked_servers;
+        Ok(Response::new(
+            BlockedServerResponse::new(hostname, pattern)
+                .with_staleness(expiry, self.service.now_seconds()),
+        ))
+    }
+
+    async fn get_status(&self, _: Request<StatusRequest>) -> GrpcResult<StatusResponse> {
+        Ok(Response::new(self.service.get_status().into()))
+    }
+
+    async fn set_log_level(
+        &self,
+        request: Request<LogLevelRequest>,
+    ) -> GrpcResult<LogLevelResponse> {
+        let settings = &self.service.settings().logging;
+        if !settings.toggle_enabled {
+            return Err(Status::unimplemented("log level toggle is disabled"));
+        }
+        check_admin_auth(&request, &self.service.settings().admin)
+            .map_err(Status::unauthenticated)?;
+        let level = match request.into_inner().level {
+            Some(level) => logging::set_level(&level)?,
+            None => logging::level()?,
+        };
+        Ok(Response::new(LogLevelResponse {
+            level: level.to_string().to_lowercase(),
+        }))
+    }
+
+    #[cfg(feature = "history")]
+    async fn get_name_history(
+        &self,
+        request: Request<NameHistoryRequest>,
+    ) -> GrpcResult<NameHistoryResponse> {
+        self.record_usage(&request).await?;
+        let uuid = request.into_inner().parse_uuid().map_err(UuidError)?;
+        let names = self.service.get_name_history(&uuid).await?;
+        Ok(Response::new(
+            NameHistoryResponse::from(names).with_staleness(
+                &self.service.cache_entries().profile,
+                self.service.now_seconds(),
+            ),
+        ))
+    }
+
+    #[cfg(not(feature = "history"))]
+    async fn get_name_history(
+        &self,
+        _: Request<NameHistoryRequest>,
+    ) -> GrpcResult<NameHistoryResponse> {
+        Err(Status::unimplemented("profile history is not supported"))
+    }
+
+    #[cfg(feature = "history")]
+    async fn get_skin_history(
+        &self,
+        request: Request<SkinHistoryRequest>,
+    ) -> GrpcResult<SkinHistoryResponse> {
+        self.record_usage(&request).await?;
+        let uuid = request.into_inner().parse_uuid().map_err(UuidError)?;
+        let skins = self.service.get_skin_history(&uuid).await?;
+        Ok(Response::new(
+            SkinHistoryResponse::from(skins).with_staleness(
+                &self.service.cache_entries().profile,
+                self.service.now_seconds(),
+            ),
+        ))
+    }
+
+    #[cfg(not(feature = "history"))]
+    async fn get_skin_history(
+        &self,
+        _: Request<SkinHistoryRequest>,
+    ) -> GrpcResult<SkinHistoryResponse> {
+        Err(Status::unimplemented("profile history is not supported"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::level::moka::MokaCache;
+    use crate::cache::level::no::NoCache;
+    use crate::cache::Cache;
+    use crate::mojang::testing::{MojangTestingApi, HYDROFIN, SCRAYOS};
+    use crate::settings::{Credential, Settings, UsageQuota};
+    use bytes::{BufMut, BytesMut};
+    use http_body_util::Full;
+    use prost::Message;
+    use tonic::codec::{Codec, ProstCodec};
+
+    fn grpc_service(
+        settings: Settings,
+    ) -> GrpcProfileService<MokaCache, NoCache, MojangTestingApi<'static>> {
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        GrpcProfileService::new(Arc::new(Service::new(Arc::new(settings), cache, mojang)))
+    }
+
+    /// Builds a client stream of [UuidRequests](UuidRequest) from the encoded grpc frames.
+    fn username_stream(usernames: &[&str]) -> Request<Streaming<UuidRequest>> {
+        let mut body = BytesMut::new();
+        for username in usernames {
+            let message = UuidRequest {
+                username: username.to_string(),
+            }
+            .encode_to_vec();
+            body.put_u8(0);
+            body.put_u32(message.len() as u32);
+            body.put_slice(&message);
+        }
+        let decoder = ProstCodec::<UuidsResponse, UuidRequest>::default().decoder();
+        let stream = Streaming::new_request(decoder, Full::new(body.freeze()), None, None);
+        Request::new(stream)
+    }
+
+    #[tokio::test]
+    async fn resolve_uuids_found() {
+        // given
+        let service = grpc_service(Settings::default());
+        let request = username_stream(&["Hydrofin", "hydrofin", "scrayos", "unknown"]);
+
+        // when
+        let response = service.resolve_uuids(request).await.unwrap().into_inner();
+
+        // then
+        assert_eq!(2, response.resolved.len());
+        assert_eq!(
+            HYDROFIN.profile.id.hyphenated().to_string(),
+            response.resolved["hydrofin"].uuid
+        );
+        assert_eq!(
+            SCRAYOS.profile.id.hyphenated().to_string(),
+            response.resolved["scrayos"].uuid
+        );
+    }
+
+    #[tokio::test]
+    async fn resolve_uuids_usage_per_username() {
+        // given
+        let mut settings = Settings::default();
+        settings.usage.enabled = true;
+        settings.usage.default = UsageQuota {
+            daily: Some(2),
+            monthly: None,
+        };
+        let service = grpc_service(settings);
+        let request = username_stream(&["hydrofin", "scrayos", "herbert"]);
+
+        // when
+        let status = service.resolve_uuids(request).await.unwrap_err();
+
+        // then
+        assert_eq!(tonic::Code::ResourceExhausted, status.code());
+    }
+
+    #[tokio::test]
+    async fn resolve_uuids_too_many_usernames() {
+        // given
+        let service = grpc_service(Settings::default());
+        let usernames: Vec<String> = (0..=RESOLVE_MAX_USERNAMES)
+            .map(|index| format!("user{}", index))
+            .collect();
+        let usernames: Vec<&str> = usernames.iter().map(String::as_str).collect();
+        let request = username_stream(&usernames);
+
+        // when
+        let status = service.resolve_uuids(request).await.unwrap_err();
+
+        // then
+        assert_eq!(tonic::Code::InvalidArgument, status.code());
+    }
+
+    #[test]
+    fn status_invalid_uuid() {
+        // given
+        let error = UuidError(uuid::Uuid::parse_str("invalid").unwrap_err());
+
+        // when
+        let status = Status::from(error);
+
+        // then
+        assert_eq!(tonic::Code::InvalidArgument, status.code());
+        let error_info = status.get_details_error_info().unwrap();
+        assert_eq!("INVALID_UUID", error_info.reason);
+        assert_eq!(ERROR_DOMAIN, error_info.domain);
+        assert!(status.get_details_retry_info().is_none());
+    }
+
+    #[test]
+    fn status_rate_limited() {
+        // given
+        let error = ServiceError::RateLimited {
+            retry_after: Some(Duration::from_secs(30)),
+        };
+
+        // when
+        let status = Status::from(error);
+
+        // then
+        assert_eq!(tonic::Code::ResourceExhausted, status.code());
+        let error_info = status.get_details_error_info().unwrap();
+        assert_eq!("UPSTREAM_RATE_LIMITED", error_info.reason);
+        let retry_info = status.get_details_retry_info().unwrap();
+        assert_eq!(Some(Duration::from_secs(30)), retry_info.retry_delay);
+    }
+
+    fn admin_settings() -> Admin {
+        let mut settings = Settings::default().admin;
+        settings.auth_enabled = true;
+        settings.username = "admin".to_string();
+        settings.password = "secret".to_string();
+        settings.credentials = vec![Credential {
+            username: "rotated".to_string(),
+            password: "new-secret".to_string(),
+        }];
+        settings
+    }
+
+    fn admin_request(credentials: &str) -> Request<()> {
+        let mut request = Request::new(());
+        let value = format!("Basic {}", BASE64_STANDARD.encode(credentials));
+        request
+            .metadata_mut()
+            .insert("authorization", value.parse().unwrap());
+        request
+    }
+
+    #[test]
+    fn admin_auth_authorized() {
+        // given
+        let settings = admin_settings();
+
+        // when
+        let configured = check_admin_auth(&admin_request("admin:secret"), &settings);
+        let rotated = check_admin_auth(&admin_request("rotated:new-secret"), &settings);
+
+        // then
+        assert_eq!(Ok(()), configured);
+        assert_eq!(Ok(()), rotated);
+    }
+
+    #[test]
+    fn admin_auth_invalid() {
+        // given
+        let settings = admin_settings();
+
+        // when
+        let wrong_password = check_admin_auth(&admin_request("admin:wrong"), &settings);
+        let malformed = check_admin_auth(&admin_request("admin"), &settings);
+
+        // then
+        assert_eq!(Err("invalid auth"), wrong_password);
+        assert_eq!(Err("invalid auth"), malformed);
+    }
+
+    #[test]
+    fn admin_auth_missing() {
+        // given
+        let settings = admin_settings();
+
+        // when
+        let result = check_admin_auth(&Request::new(()), &settings);
+
+        // then
+        assert_eq!(Err("missing basic auth"), result);
+    }
+
+    #[test]
+    fn admin_auth_disabled() {
+        // given
+        let mut settings = admin_settings();
+        settings.auth_enabled = false;
+
+        // when
+        let result = check_admin_auth(&Request::new(()), &settings);
+
+        // then
+        assert_eq!(Ok(()), result);
     }
 }
@@ -0,0 +1,232 @@
+//! The identity module provides the identification of clients for the usage quotas and the access
+//! log. A client is identified by its api key (usage header) or the client of its alias (e.g. while
+//! the api key is rotated). Only configured api keys identify a client, as the header is chosen by the
+//! client and could otherwise be used to evade its quotas. Clients without (known) api key are identified
+//! by their (proxy-aware) ip address, so that they do not share a single quota. IPv6 addresses are
+//! bucketed by their network prefix (default `/64`), as a single client usually controls a whole
+//! subnet and could otherwise bypass the quotas by rotating its addresses.
+
+use crate::proxy::{client_ip, FORWARDED_FOR_HEADER};
+use crate::settings::{self, Settings};
+use crate::usage::ANONYMOUS_CLIENT;
+use ipnet::IpNet;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+/// The prefix of the identities of clients that are identified by their ip address.
+pub const IP_PREFIX: &str = "ip:";
+
+/// Buckets an ip address by the network prefix length of its address family. The prefix lengths are
+/// clamped to the length of the addresses.
+pub fn bucket(ip: IpAddr, ipv4_prefix: u8, ipv6_prefix: u8) -> IpNet {
+    let prefix = match ip {
+        IpAddr::V4(_) => ipv4_prefix.min(32),
+        IpAddr::V6(_) => ipv6_prefix.min(128),
+    };
+    // the prefix length is always valid for the address family
+    IpNet::new(ip, prefix).expect("valid prefix length").trunc()
+}
+
+/// The [IdentityResolver] resolves the identity of the client of a request. A known api key takes
+/// precedence over the forwarded ip address (of trusted proxies), which takes precedence over the
+/// peer ip address. Unknown api keys are ignored. Clients without api key and ip address are
+/// `anonymous`.
+///
+/// ```rs
+/// let resolver = IdentityResolver::new(&settings);
+/// let client = resolver.resolve(request.headers(), peer);
+/// ```
+#[derive(Debug, Clone)]
+pub struct IdentityResolver {
+    header: String,
+    aliases: Arc<HashMap<String, String>>,
+    known: Arc<HashSet<String>>,
+    trusted: Arc<[IpNet]>,
+    settings: settings::ClientIdentity,
+}
+
+impl IdentityResolver {
+    /// Creates a new [IdentityResolver] from the application [Settings]. The api key is read from the
+    /// usage header. The api keys with a dedicated quota, the aliases (and their clients) and the api
+    /// keys of the tenants are known.
+    pub fn new(settings: &Settings) -> Self {
+        let usage = &settings.usage;
+        let known = usage
+            .keys
+            .keys()
+            .chain(usage.aliases.keys())
+            .chain(usage.aliases.values())
+            .chain(
+                settings
+                    .tenancy
+                    .tenants
+                    .values()
+                    .flat_map(|tenant| tenant.keys.iter()),
+            )
+            .cloned()
+            .collect();
+        Self {
+            header: usage.header.clone(),
+            aliases: Arc::new(usage.aliases.clone()),
+            known: Arc::new(known),
+            trusted: Arc::from(settings.proxy.trusted.as_slice()),
+            settings: settings.client_identity.clone(),
+        }
+    }
+
+    /// Gets the header (or metadata key) of the api key.
+    pub fn header(&self) -> &str {
+        &self.header
+    }
+
+    /// Identifies a client by its (optional) api key, the `X-Forwarded-For` header and the peer
+    /// address of the request. Aliased api keys identify the client of their alias, unknown api keys
+    /// are ignored.
+    pub fn identify(
+        &self,
+        api_key: Option<&str>,
+        forwarded_for: Option<&str>,
+        peer: Option<SocketAddr>,
+    ) -> String {
+        if let Some(api_key) = api_key.filter(|api_key| self.known.contains(*api_key)) {
+            return self
+                .aliases
+                .get(api_key)
+                .map_or(api_key, String::as_str)
+                .to_string();
+        }
+        if !self.settings.by_ip {
+            return ANONYMOUS_CLIENT.to_string();
+        }
+        match client_ip(peer.map(|peer| peer.ip()), forwarded_for, &self.trusted) {
+            Some(ip) => {
+                let network = bucket(ip, self.settings.ipv4_prefix, self.settings.ipv6_prefix);
+                format!("{}{}", IP_PREFIX, network)
+            }
+            None => ANONYMOUS_CLIENT.to_string(),
+        }
+    }
+
+    /// Resolves the identity of the client of a request from its headers and peer address.
+    pub fn resolve(&self, headers: &http::HeaderMap, peer: Option<SocketAddr>) -> String {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        self.identify(header(&self.header), header(FORWARDED_FOR_HEADER), peer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::settings::Tenant;
+
+    #[test]
+    fn bucket_ipv6_prefix() {
+        // given
+        let first: IpAddr = "2001:db8:1:2:aaaa::1".parse().unwrap();
+        let second: IpAddr = "2001:db8:1:2:bbbb::2".parse().unwrap();
+        let ipv4: IpAddr = "203.0.113.7".parse().unwrap();
+
+        // when
+        let first = bucket(first, 32, 64);
+        let second = bucket(second, 32, 64);
+        let ipv4 = bucket(ipv4, 32, 64);
+
+        // then
+        assert_eq!(first, second);
+        assert_eq!("2001:db8:1:2::/64", first.to_string());
+        assert_eq!("203.0.113.7/32", ipv4.to_string());
+    }
+
+    #[test]
+    fn identify_precedence() {
+        // given
+        let mut settings = Settings::default();
+        settings.proxy.trusted = vec!["10.0.0.0/8".parse().unwrap()];
+        settings.client_identity.by_ip = true;
+        settings
+            .usage
+            .keys
+            .insert("key".to_string(), Default::default());
+        settings
+            .usage
+            .keys
+            .insert("tenant-key".to_string(), Default::default());
+        let resolver = IdentityResolver::new(&settings);
+        let proxy: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+
+        // when
+        let api_key = resolver.identify(Some("key"), Some("203.0.113.7"), Some(proxy));
+        let forwarded = resolver.identify(None, Some("2001:db8::1"), Some(proxy));
+        let peer = resolver.identify(Some(""), None, Some(proxy));
+        let anonymous = resolver.identify(None, None, None);
+        let known = resolver.identify(Some("tenant-key"), None, Some(proxy));
+
+        // then
+        assert_eq!("key", api_key);
+        assert_eq!("tenant-key", known);
+        assert_eq!("ip:2001:db8::/64", forwarded);
+        assert_eq!("ip:10.0.0.1/32", peer);
+        assert_eq!(ANONYMOUS_CLIENT, anonymous);
+    }
+
+    #[test]
+    fn identify_alias() {
+        // given
+        let mut settings = Settings::default();
+        settings.client_identity.by_ip = true;
+        settings
+            .usage
+            .keys
+            .insert("old-key".to_string(), Default::default());
+        settings
+            .usage
+            .aliases
+            .insert("new-key".to_string(), "old-key".to_string());
+        settings
+            .usage
+            .aliases
+            .insert("other-key".to_string(), "client".to_string());
+        let resolver = IdentityResolver::new(&settings);
+        let peer: SocketAddr = "203.0.113.7:4000".parse().unwrap();
+
+        // when
+        let old = resolver.identify(Some("old-key"), None, Some(peer));
+        let new = resolver.identify(Some("new-key"), None, Some(peer));
+        let other = resolver.identify(Some("other-key"), None, Some(peer));
+        let client = resolver.identify(Some("client"), None, Some(peer));
+
+        // then
+        assert_eq!("old-key", old);
+        assert_eq!("old-key", new);
+        assert_eq!("client", other);
+        assert_eq!("client", client);
+    }
+
+    #[test]
+    fn identify_unknown_api_key() {
+        // given
+        let mut settings = Settings::default();
+        settings.client_identity.by_ip = true;
+        settings.tenancy.tenants.insert(
+            "network".to_string(),
+            Tenant {
+                keys: vec!["tenant-key".to_string()],
+                quota: Default::default(),
+                cache_entries: Default::default(),
+            },
+        );
+        let resolver = IdentityResolver::new(&settings);
+        let peer: SocketAddr = "203.0.113.7:4000".parse().unwrap();
+
+        // when
+        let unknown = resolver.identify(Some("random-key"), None, Some(peer));
+        let unknown_anonymous = resolver.identify(Some("random-key"), None, None);
+        let tenant = resolver.identify(Some("tenant-key"), None, Some(peer));
+
+        // then
+        assert_eq!("ip:203.0.113.7/32", unknown);
+        assert_eq!(ANONYMOUS_CLIENT, unknown_anonymous);
+        assert_eq!("tenant-key", tenant);
+    }
+}
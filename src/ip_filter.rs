@@ -0,0 +1,152 @@
+//! The ip filter module provides the ip allowlist and denylist of the rest and gRPC server. Behind
+//! trusted proxies, the client ip address of a request is resolved with the [proxy](crate::proxy)
+//! module.
+
+#[cfg(feature = "grpc-server")]
+use crate::proxy::request_client_ip;
+use crate::settings::IpFilter;
+#[cfg(feature = "grpc-server")]
+use crate::settings::Settings;
+#[cfg(feature = "grpc-server")]
+use futures::future::BoxFuture;
+use ipnet::IpNet;
+use std::net::IpAddr;
+#[cfg(feature = "grpc-server")]
+use std::sync::Arc;
+#[cfg(feature = "grpc-server")]
+use std::task::{Context, Poll};
+#[cfg(feature = "grpc-server")]
+use tonic::body::BoxBody;
+#[cfg(feature = "grpc-server")]
+use tonic::codegen::http;
+#[cfg(feature = "grpc-server")]
+use tonic::transport::server::TcpConnectInfo;
+#[cfg(feature = "grpc-server")]
+use tonic::Status;
+use tracing::debug;
+
+/// Checks whether an ip address is part of any of the networks.
+fn contains(networks: &[IpNet], ip: &IpAddr) -> bool {
+    networks.iter().any(|network| network.contains(ip))
+}
+
+/// Checks whether requests of a client ip address are allowed. Requests of unknown ip addresses are
+/// only allowed if the allowlist is empty.
+pub fn is_allowed(filter: &IpFilter, ip: Option<IpAddr>) -> bool {
+    if !filter.enabled {
+        return true;
+    }
+    let allowed = match ip {
+        None => filter.allow.is_empty(),
+        Some(ip) => {
+            !contains(&filter.deny, &ip)
+                && (filter.allow.is_empty() || contains(&filter.allow, &ip))
+        }
+    };
+    if !allowed {
+        debug!(ip = ?ip, "rejected request of filtered ip address");
+    }
+    allowed
+}
+
+/// The [IpFilterLayer] is a tower layer for the gRPC server that rejects requests of filtered ip
+/// addresses with the status `PERMISSION_DENIED`.
+#[cfg(feature = "grpc-server")]
+#[derive(Debug, Clone)]
+pub struct IpFilterLayer {
+    filter: Arc<IpFilter>,
+    trusted: Arc<[IpNet]>,
+}
+
+#[cfg(feature = "grpc-server")]
+impl IpFilterLayer {
+    /// Creates a new [IpFilterLayer] from the application [Settings].
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            filter: Arc::new(settings.ip_filter.clone()),
+            trusted: Arc::from(settings.proxy.trusted.as_slice()),
+        }
+    }
+}
+
+#[cfg(feature = "grpc-server")]
+impl<S> tower::Layer<S> for IpFilterLayer {
+    type Service = IpFilterService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        IpFilterService {
+            layer: self.clone(),
+            inner,
+        }
+    }
+}
+
+/// The [IpFilterService] is the tower service of the [IpFilterLayer].
+#[cfg(feature = "grpc-server")]
+#[derive(Debug, Clone)]
+pub struct IpFilterService<S> {
+    layer: IpFilterLayer,
+    inner: S,
+}
+
+#[cfg(feature = "grpc-server")]
+impl<S, B> tower::Service<http::Request<B>> for IpFilterService<S>
+where
+    S: tower::Service<http::Request<B>, Response = http::Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        if !self.layer.filter.enabled {
+            return Box::pin(self.inner.call(request));
+        }
+        let peer = request
+            .extensions()
+            .get::<TcpConnectInfo>()
+            .and_then(|info| info.remote_addr());
+        let ip = request_client_ip(peer, request.headers(), &self.layer.trusted);
+        if !is_allowed(&self.layer.filter, ip) {
+            let status = Status::permission_denied("client ip address is not allowed");
+            return Box::pin(async move { Ok(status.into_http()) });
+        }
+        Box::pin(self.inner.call(request))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn networks(values: &[&str]) -> Vec<IpNet> {
+        values.iter().map(|value| value.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn is_allowed_deny_precedence() {
+        // given
+        let filter = IpFilter {
+            enabled: true,
+            allow: networks(&["192.0.2.0/24"]),
+            deny: networks(&["192.0.2.13/32"]),
+        };
+
+        // when
+        let allowed = is_allowed(&filter, Some("192.0.2.1".parse().unwrap()));
+        let denied = is_allowed(&filter, Some("192.0.2.13".parse().unwrap()));
+        let other = is_allowed(&filter, Some("198.51.100.1".parse().unwrap()));
+        let unknown = is_allowed(&filter, None);
+
+        // then
+        assert!(allowed);
+        assert!(!denied);
+        assert!(!other);
+        assert!(!unknown);
+    }
+}
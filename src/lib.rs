@@ -5,46 +5,155 @@
 //! Start the application by first initializing [sentry] and [tracing] and then calling [start] with
 //! the [application configuration](settings).
 //!
+//! Alternatively, Xenos can be embedded into an existing application. Use the [ServiceBuilder] to
+//! assemble a [Service](service::Service) and build its servers with `rest_router` and `grpc_profile_server`.
+//! To keep the servers of [start] but add cross-cutting middleware (e.g. auth), call [start_with] with a
+//! [ServerCustomizer].
+//!
+//! # Features
+//!
+//! The rest server (`rest-server`) and the gRPC server (`grpc-server`) are enabled by default. Library
+//! consumers that only need the [Service](service::Service) and its caches can disable them. The
+//! optional GraphQL API (`graphql`) is part of the rest server.
+//!
 //! # Configuration
 //!
 //! See [settings] for a description on how to create the application configuration.
 
-use crate::cache::level::moka::MokaCache;
-#[cfg(not(feature = "redis"))]
-use crate::cache::level::no::NoCache;
+#[cfg(feature = "grpc-server")]
+use crate::access_log::AccessLogLayer;
 #[cfg(feature = "redis")]
 use crate::cache::level::redis::RedisCache;
 use crate::cache::level::CacheLevel;
-use crate::cache::Cache;
-use crate::grpc_services::GrpcProfileService;
-#[cfg(not(feature = "static-testing"))]
-use crate::mojang::api::MojangApi;
-#[cfg(feature = "static-testing")]
-use crate::mojang::testing::MojangTestingApi;
+#[cfg(feature = "grpc-server")]
+use crate::deadline::DeadlineLayer;
+#[cfg(feature = "grpc-server")]
+use crate::faults::FaultLayer;
+#[cfg(feature = "history")]
+use crate::history::PostgresHistory;
+#[cfg(feature = "grpc-server")]
+use crate::ip_filter::IpFilterLayer;
 use crate::mojang::Mojang;
+#[cfg(feature = "grpc-server")]
 use crate::proto::profile_server::ProfileServer;
+#[cfg(feature = "rest-server")]
+use crate::proxy::ProxiedStream;
+#[cfg(feature = "rest-server")]
+use crate::response_cache::ResponseCache;
+#[cfg(feature = "grpc-server")]
+use crate::sampling::SamplingLayer;
+#[cfg(any(feature = "rest-server", feature = "grpc-server"))]
+use crate::sensitive::SensitiveHeaderLayer;
 use crate::service::Service;
 use crate::settings::Settings;
+#[cfg(any(feature = "rest-server", feature = "grpc-server"))]
+use crate::shutdown::InFlightLayer;
+#[cfg(feature = "grpc-server")]
+use crate::tenant::TenantLayer;
+#[cfg(feature = "rest-server")]
+use axum::extract::{ConnectInfo, Request};
+#[cfg(feature = "rest-server")]
+use axum::middleware;
+#[cfg(feature = "rest-server")]
 use axum::routing::{post, MethodRouter};
+#[cfg(feature = "rest-server")]
 use axum::{routing::get, Extension, Router};
+#[cfg(any(feature = "rest-server", feature = "grpc-server"))]
 use futures_util::FutureExt;
+#[cfg(feature = "rest-server")]
+use futures_util::{Stream, StreamExt};
+#[cfg(feature = "rest-server")]
+use hyper::body::Incoming;
+#[cfg(feature = "rest-server")]
+use hyper::service::service_fn;
+#[cfg(feature = "rest-server")]
+use hyper_util::rt::{TokioExecutor, TokioIo};
+#[cfg(feature = "rest-server")]
+use hyper_util::server::conn::auto;
+#[cfg(feature = "rest-server")]
+use hyper_util::server::graceful::GracefulShutdown;
+#[cfg(any(feature = "rest-server", feature = "grpc-server"))]
+use ipnet::IpNet;
+#[cfg(any(feature = "rest-server", feature = "grpc-server"))]
+use sentry::integrations::tower::{NewSentryLayer, SentryHttpLayer};
+#[cfg(any(feature = "rest-server", feature = "grpc-server"))]
+use socket2::{Domain, Socket, Type};
+#[cfg(feature = "rest-server")]
+use std::future::Future;
+#[cfg(feature = "rest-server")]
+use std::future::IntoFuture;
+#[cfg(any(feature = "rest-server", feature = "grpc-server"))]
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::try_join;
+#[cfg(feature = "grpc-server")]
+use tonic::transport::server::TcpIncoming;
+#[cfg(feature = "grpc-server")]
 use tonic::transport::Server;
-use tonic_health::server::health_reporter;
+#[cfg(feature = "grpc-server")]
+use tonic_health::server::{health_reporter, HealthReporter};
+#[cfg(feature = "rest-server")]
+use tower::Service as _;
+#[cfg(feature = "rest-server")]
+use tracing::debug;
 use tracing::info;
+use tracing::warn;
 
+pub mod access_log;
+mod builder;
 pub mod cache;
+pub mod capes;
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(feature = "rest-server")]
+pub mod compression;
+pub mod deadline;
+#[cfg(feature = "diagnostics")]
+pub mod diagnostics;
 pub mod error;
+pub mod events;
+pub mod faults;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "grpc-server")]
 mod grpc_services;
+#[cfg(feature = "history")]
+pub mod history;
+pub mod identity;
+pub mod ip_filter;
+pub mod logging;
 pub mod mojang;
+pub mod openmetrics;
+pub mod placeholder;
 pub mod proto;
+pub mod proxy;
+pub mod pushgateway;
+pub mod refresh;
+pub mod render;
+#[cfg(feature = "rest-server")]
+pub mod response_cache;
+#[cfg(feature = "rest-server")]
 mod rest_services;
+pub mod sampling;
+pub mod sanctions;
+pub mod sensitive;
 pub mod service;
 pub mod settings;
+pub mod shutdown;
+pub mod slo;
+pub mod statsd;
+pub mod tenant;
+pub mod usage;
+
+#[cfg(feature = "grpc-server")]
+pub use crate::builder::GrpcInterceptors;
+pub use crate::builder::{DefaultMojang, ServerCustomizer, ServiceBuilder};
+#[cfg(feature = "grpc-server")]
+pub use crate::grpc_services::GrpcProfileService;
 
 /// [OptionalRoute] is a utility used to add optional routers to a [Router]. Routes can be disabled
 /// on construction based on application settings.
+#[cfg(feature = "rest-server")]
 trait OptionalRoute<S>
 where
     S: Clone + Send + Sync + 'static,
@@ -53,6 +162,7 @@ where
     fn optional_route(self, enabled: bool, path: &str, method_router: MethodRouter<S>) -> Self;
 }
 
+#[cfg(feature = "rest-server")]
 impl<S> OptionalRoute<S> for Router<S>
 where
     S: Clone + Send + Sync + 'static,
@@ -70,84 +180,144 @@ where
 /// [tracing] was configured beforehand. It blocks until a shutdown signal is received (graceful shutdown).
 #[tracing::instrument(skip(settings))]
 pub async fn start(settings: Arc<Settings>) -> Result<(), Box<dyn std::error::Error>> {
-    info!("starting xenos …");
-
-    // build cache with selected cache levels
-    info!("building multi-level cache");
-    let cache = Cache::new(
-        settings.cache.entries.clone(),
-        {
-            info!("building moka cache");
-            MokaCache::new(settings.cache.moka.clone())
-        },
-        // the remote cache should be selected using feature flags
-        {
-            #[cfg(feature = "redis")]
-            {
-                info!("building redis cache");
-                let cs = &settings.cache;
-                let redis_client = redis::Client::open(cs.redis.address.clone())?;
-                let redis_manager = redis_client.get_connection_manager().await?;
-                RedisCache::new(redis_manager, &settings.cache.redis)
-            }
-            #[cfg(not(feature = "redis"))]
-            {
-                info!("disabling remote cache");
-                NoCache
-            }
-        },
-    );
+    start_with(settings, ServerCustomizer::default()).await
+}
 
-    // build mojang api
-    // it is either the actual mojang api or a testing api for integration tests
-    info!("building mojang api");
-    #[cfg(not(feature = "static-testing"))]
-    let mojang = MojangApi::new();
-    #[cfg(feature = "static-testing")]
-    let mojang = MojangTestingApi::with_profiles();
+/// Starts Xenos like [start], but customizes its servers with the [ServerCustomizer] (e.g. to add
+/// custom axum layers or tonic interceptors).
+#[tracing::instrument(skip(settings, customizer))]
+pub async fn start_with(
+    settings: Arc<Settings>,
+    customizer: ServerCustomizer,
+) -> Result<(), Box<dyn std::error::Error>> {
+    info!("starting xenos …");
 
     // build xenos service from cache and mojang api
     // the service is then shared by the grpc and rest servers
     info!("building shared xenos service");
-    let service = Arc::new(Service::new(settings.clone(), cache, mojang));
+    let builder = ServiceBuilder::new(Arc::clone(&settings));
+
+    // the remote cache should be selected using feature flags
+    #[cfg(feature = "redis")]
+    let builder = {
+        info!("building redis cache");
+        let cs = &settings.cache;
+        let redis_client = redis::Client::open(cs.redis.address.clone())?;
+        let redis_manager = redis_client.get_connection_manager().await?;
+        builder.remote_cache(RedisCache::new(redis_manager, &settings.cache.redis))
+    };
+    #[cfg(not(feature = "redis"))]
+    info!("disabling remote cache");
+
+    // add the profile history to the service if enabled
+    #[cfg(feature = "history")]
+    let builder = match settings.history.enabled {
+        true => {
+            info!("building postgres profile history");
+            builder.history(PostgresHistory::connect(&settings.history).await?)
+        }
+        false => builder,
+    };
+    let service = Arc::new(builder.build());
+
+    // background tasks are cancelled on shutdown (and reported)
+    let mut tasks = vec![];
+
+    // refresh hot entries in the background if enabled
+    if settings.refresh.enabled {
+        tasks.push(tokio::spawn(Arc::clone(&service).run_refresh()));
+    }
+
+    // keep pinned profiles fresh if enabled
+    if settings.pinned.enabled {
+        tasks.push(tokio::spawn(Arc::clone(&service).run_pinned()));
+    }
+
+    // prefetch skins and heads of fetched profiles if enabled
+    if settings.prefetch.enabled {
+        tasks.push(tokio::spawn(Arc::clone(&service).run_prefetch()));
+    }
+
+    // pre-render the heads of fetched skins if enabled
+    if settings.cache.entries.head.pre_render {
+        tasks.push(tokio::spawn(Arc::clone(&service).run_pre_render()));
+    }
+
+    // send metrics to statsd if enabled
+    if settings.metrics.statsd.enabled {
+        statsd::init(&settings.metrics.statsd)?;
+    }
+
+    // record service level indicators if enabled
+    if settings.metrics.slo.enabled {
+        slo::init(&settings.metrics.slo);
+    }
+
+    // push metrics to the pushgateway if enabled
+    if settings.metrics.push.enabled {
+        tasks.push(tokio::spawn(pushgateway::push_periodically(
+            settings.metrics.push.clone(),
+        )));
+    }
 
     try_join!(
-        serve_rest_server(Arc::clone(&service)),
-        serve_grpc_server(Arc::clone(&service)),
+        serve_rest_server(Arc::clone(&service), &customizer),
+        serve_grpc_server(Arc::clone(&service), &customizer),
     )?;
+
+    // cancel the remaining background tasks and report the shutdown
+    let mut cancelled = 0;
+    for task in tasks.iter().filter(|task| !task.is_finished()) {
+        task.abort();
+        cancelled += 1;
+    }
+    service.shutdown().report(cancelled);
+    if settings.metrics.push.enabled {
+        if let Err(err) = pushgateway::push(&settings.metrics.push).await {
+            warn!(error = %err, "failed to push shutdown metrics");
+        }
+    }
     info!("xenos stopped successfully");
     Ok(())
 }
 
-/// Tries to start the rest server. The rest server is started if either the rest gateway or the
-/// metrics service is enabled. Blocks until shutdown (graceful shutdown).
-#[tracing::instrument(skip_all)]
-async fn serve_rest_server<L, R, M>(
-    service: Arc<Service<L, R, M>>,
-) -> Result<(), Box<dyn std::error::Error>>
+/// Builds the rest [Router] for a [Service]. It contains the rest gateway, the metrics and the usage
+/// routes (if enabled in the [application settings](Settings) of the service). It can be used to
+/// embed Xenos into an existing axum application.
+#[cfg(feature = "rest-server")]
+pub fn rest_router<L, R, M>(service: Arc<Service<L, R, M>>) -> Router
 where
     L: CacheLevel + Sync + 'static,
     R: CacheLevel + Sync + 'static,
     M: Mojang + Sync + 'static,
 {
     let settings = service.settings();
-    let address = settings.rest_server.address;
     let metrics_enabled = settings.metrics.enabled;
     let gateway_enabled = settings.rest_server.rest_gateway;
+    let graphql_enabled = cfg!(feature = "graphql") && settings.rest_server.graphql;
+    let events_enabled = settings.events.enabled;
+    let cache_only_enabled = settings.cache_only.toggle_enabled;
+    let log_level_enabled = settings.logging.toggle_enabled;
+    let purge_enabled = settings.purge.enabled;
+    let pinned_enabled = settings.pinned.enabled;
+    let skins_enabled = settings.capabilities.skins;
+    let capes_enabled = settings.capabilities.capes;
+    let heads_enabled = settings.capabilities.heads;
+    let usage_enabled = settings.usage.enabled;
+    let access_log_enabled = settings.access_log.enabled;
+    let deadline_enabled = settings.deadline.enabled;
+    let tenancy_enabled = settings.tenancy.enabled;
+    let sampling_enabled = settings.logging.sampling.enabled;
+    let ip_filter_enabled = settings.ip_filter.enabled;
+    let response_cache_enabled = settings.response_cache.enabled;
+    let faults_enabled = settings.faults.enabled;
+    let mojang_headers_enabled = settings.mojang_headers.endpoint_enabled;
+    let compression_enabled = settings.rest_server.compression.enabled;
+    let sentry_layer = sentry_http_layer(settings);
+    let sensitive_layer = SensitiveHeaderLayer::new(&settings.usage.header);
 
-    // check if rest server should be started
-    if !metrics_enabled && !gateway_enabled {
-        info!("rest server is disabled (enable either metrics or rest gateway)");
-        return Ok(());
-    }
-
-    // build rest server
-    let rest_app = Router::new()
-        .optional_route(
-            metrics_enabled,
-            "/metrics",
-            get(rest_services::metrics::<L, R, M>),
-        )
+    // build rest gateway
+    let gateway_app = Router::new()
         .optional_route(
             gateway_enabled,
             "/uuid",
@@ -164,47 +334,382 @@ where
             post(rest_services::profile::<L, R, M>),
         )
         .optional_route(
-            gateway_enabled,
+            gateway_enabled && skins_enabled && capes_enabled && heads_enabled,
+            "/profile_bundle",
+            post(rest_services::profile_bundle::<L, R, M>),
+        )
+        .optional_route(
+            gateway_enabled && skins_enabled,
             "/skin",
             post(rest_services::skin::<L, R, M>),
         )
         .optional_route(
-            gateway_enabled,
+            gateway_enabled && capes_enabled,
             "/cape",
             post(rest_services::cape::<L, R, M>),
         )
         .optional_route(
-            gateway_enabled,
+            gateway_enabled && heads_enabled,
             "/head",
             post(rest_services::head::<L, R, M>),
         )
+        .optional_route(
+            gateway_enabled && skins_enabled && heads_enabled,
+            "/checksum",
+            post(rest_services::checksum::<L, R, M>),
+        )
+        .optional_route(
+            gateway_enabled && skins_enabled,
+            "/colors",
+            post(rest_services::colors::<L, R, M>),
+        )
+        .optional_route(
+            gateway_enabled,
+            "/texture/:texture_id",
+            get(rest_services::texture::<L, R, M>),
+        )
+        .optional_route(
+            gateway_enabled,
+            "/textures",
+            post(rest_services::build_textures::<L, R, M>),
+        )
+        .optional_route(
+            gateway_enabled,
+            "/uuid/validate",
+            post(rest_services::validate_uuid),
+        )
+        .optional_route(
+            gateway_enabled,
+            "/blocked_servers",
+            get(rest_services::blocked_servers::<L, R, M>),
+        )
+        .optional_route(
+            gateway_enabled,
+            "/blocked_server",
+            post(rest_services::blocked_server::<L, R, M>),
+        );
+
+    // add profile history routes
+    #[cfg(feature = "history")]
+    let gateway_app = gateway_app
+        .optional_route(
+            gateway_enabled && settings.history.enabled,
+            "/name_history",
+            post(rest_services::name_history::<L, R, M>),
+        )
+        .optional_route(
+            gateway_enabled && settings.history.enabled,
+            "/skin_history",
+            post(rest_services::skin_history::<L, R, M>),
+        );
+
+    // add graphql api
+    #[cfg(feature = "graphql")]
+    let gateway_app = gateway_app
+        .optional_route(
+            graphql_enabled,
+            "/graphql",
+            post(graphql::graphql::<L, R, M>),
+        )
+        .layer(Extension(graphql::schema(Arc::clone(&service))));
+
+    // cache the encoded responses of the image endpoints
+    let gateway_app = match gateway_enabled && response_cache_enabled {
+        true => gateway_app
+            .route_layer(middleware::from_fn(rest_services::response_cache))
+            .layer(Extension(Arc::new(ResponseCache::new(
+                &settings.response_cache,
+            )))),
+        false => gateway_app,
+    };
+
+    // inject the faults into all rest gateway (and graphql) requests
+    let gateway_app = match (gateway_enabled || graphql_enabled) && faults_enabled {
+        true => gateway_app.route_layer(middleware::from_fn(rest_services::faults::<L, R, M>)),
+        false => gateway_app,
+    };
+
+    // count all rest gateway (and graphql) requests for the usage accounting
+    // the route layer can only be added if there are any routes
+    let gateway_app = match (gateway_enabled || graphql_enabled) && usage_enabled {
+        true => gateway_app.route_layer(middleware::from_fn(rest_services::usage::<L, R, M>)),
+        false => gateway_app,
+    };
+
+    // compress large metrics expositions independently of the rest response compression
+    let metrics_route = match settings.metrics.compression.enabled {
+        true => get(rest_services::metrics::<L, R, M>)
+            .layer(compression::layer(&settings.metrics.compression)),
+        false => get(rest_services::metrics::<L, R, M>),
+    };
+
+    // build rest server
+    let rest_app = Router::new()
+        .optional_route(metrics_enabled, "/metrics", metrics_route)
+        .optional_route(
+            usage_enabled,
+            "/admin/usage",
+            get(rest_services::usage_report::<L, R, M>),
+        )
+        .optional_route(
+            gateway_enabled,
+            "/status",
+            get(rest_services::status::<L, R, M>),
+        )
+        .optional_route(
+            cache_only_enabled,
+            "/admin/cache_only",
+            get(rest_services::get_cache_only::<L, R, M>)
+                .put(rest_services::set_cache_only::<L, R, M>),
+        )
+        .optional_route(
+            purge_enabled,
+            "/admin/purge",
+            post(rest_services::purge::<L, R, M>),
+        )
+        .optional_route(
+            pinned_enabled,
+            "/admin/pinned",
+            get(rest_services::get_pinned::<L, R, M>).put(rest_services::set_pinned::<L, R, M>),
+        )
+        .optional_route(
+            log_level_enabled,
+            "/admin/log_level",
+            get(rest_services::get_log_level::<L, R, M>)
+                .put(rest_services::set_log_level::<L, R, M>),
+        )
+        .optional_route(
+            faults_enabled,
+            "/admin/faults",
+            get(rest_services::get_faults::<L, R, M>).put(rest_services::set_faults::<L, R, M>),
+        )
+        .optional_route(
+            mojang_headers_enabled,
+            "/debug/mojang_headers",
+            get(rest_services::mojang_headers::<L, R, M>),
+        )
+        .optional_route(
+            events_enabled,
+            "/events",
+            get(rest_services::events::<L, R, M>),
+        )
+        .merge(gateway_app);
+
+    // add runtime diagnostics
+    #[cfg(feature = "diagnostics")]
+    let rest_app = rest_app.optional_route(
+        settings.diagnostics.endpoint_enabled,
+        "/debug/runtime",
+        get(rest_services::runtime_diagnostics::<L, R, M>),
+    );
+
+    // propagate the deadline of all requests
+    let rest_app = match deadline_enabled {
+        true => rest_app.layer(middleware::from_fn(rest_services::deadline)),
+        false => rest_app,
+    };
+
+    // log all requests to the access log
+    let rest_app = match access_log_enabled {
+        true => rest_app.layer(middleware::from_fn(rest_services::access_log::<L, R, M>)),
+        false => rest_app,
+    };
+
+    // decide whether the tracing spans of all requests are sampled
+    let rest_app = match sampling_enabled {
+        true => rest_app.layer(middleware::from_fn(rest_services::sampling::<L, R, M>)),
+        false => rest_app,
+    };
+
+    // bind the tenant of the api key to all requests
+    let rest_app = match tenancy_enabled {
+        true => rest_app.layer(middleware::from_fn(rest_services::tenant::<L, R, M>)),
+        false => rest_app,
+    };
+
+    // reject all requests of filtered ip addresses
+    let rest_app = match ip_filter_enabled {
+        true => rest_app.layer(middleware::from_fn(rest_services::ip_filter::<L, R, M>)),
+        false => rest_app,
+    };
+
+    // compress the responses of all requests
+    let rest_app = match compression_enabled {
+        true => rest_app.layer(compression::layer(&settings.rest_server.compression)),
+        false => rest_app,
+    };
+
+    // attach the request context to sentry (the api key is not sent to sentry)
+    rest_app
+        .layer(sentry_layer)
+        .layer(NewSentryLayer::<Request>::new_from_top())
+        .layer(sensitive_layer)
+        .layer(InFlightLayer::new(Arc::clone(service.shutdown())))
         .layer(Extension(Arc::clone(&service)))
-        .with_state(());
+        .with_state(())
+}
 
-    // register shutdown signal (as future)
-    let shutdown = tokio::signal::ctrl_c().map(|_| ());
+/// Builds the sentry layer of the rest and gRPC server. It attaches the request context to captured
+/// errors and starts a performance transaction per request if the transactions are sampled.
+#[cfg(any(feature = "rest-server", feature = "grpc-server"))]
+fn sentry_http_layer(settings: &Settings) -> SentryHttpLayer {
+    match settings.sentry.enabled && settings.sentry.traces_sample_rate > 0.0 {
+        true => SentryHttpLayer::with_transaction(),
+        false => SentryHttpLayer::new(),
+    }
+}
+
+/// Builds the grpc [ProfileServer] for a [Service]. It can be used to embed Xenos into an existing
+/// tonic server.
+#[cfg(feature = "grpc-server")]
+pub fn grpc_profile_server<L, R, M>(
+    service: Arc<Service<L, R, M>>,
+) -> ProfileServer<GrpcProfileService<L, R, M>>
+where
+    L: CacheLevel + Sync + 'static,
+    R: CacheLevel + Sync + 'static,
+    M: Mojang + Sync + 'static,
+{
+    ProfileServer::new(GrpcProfileService::new(service))
+}
+
+/// Tries to start the rest server. The rest server is started if either the rest gateway or the
+/// metrics service is enabled. Blocks until shutdown (graceful shutdown).
+#[cfg(feature = "rest-server")]
+#[tracing::instrument(skip_all)]
+async fn serve_rest_server<L, R, M>(
+    service: Arc<Service<L, R, M>>,
+    customizer: &ServerCustomizer,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    L: CacheLevel + Sync + 'static,
+    R: CacheLevel + Sync + 'static,
+    M: Mojang + Sync + 'static,
+{
+    let settings = service.settings();
+    let address = &settings.rest_server.address;
+    let metrics_enabled = settings.metrics.enabled;
+    let gateway_enabled = settings.rest_server.rest_gateway;
+    let graphql_enabled = cfg!(feature = "graphql") && settings.rest_server.graphql;
+    let events_enabled = settings.events.enabled;
+    let cache_only_enabled = settings.cache_only.toggle_enabled;
+    let log_level_enabled = settings.logging.toggle_enabled;
+    let usage_enabled = settings.usage.enabled;
+    let access_log_enabled = settings.access_log.enabled;
+
+    // check if rest server should be started
+    if !metrics_enabled
+        && !gateway_enabled
+        && !graphql_enabled
+        && !events_enabled
+        && !cache_only_enabled
+        && !log_level_enabled
+        && !usage_enabled
+    {
+        info!("rest server is disabled (enable either metrics, rest gateway, graphql, events, cache-only toggle, log level toggle or usage)");
+        return Ok(());
+    }
+
+    let rest_app = customizer.customize_rest(rest_router(Arc::clone(&service)));
 
     info!(
-        address = address.to_string(),
+        address = format_addresses(address),
         metrics = metrics_enabled,
         rest_gateway = gateway_enabled,
+        graphql = graphql_enabled,
+        events = events_enabled,
+        cache_only = cache_only_enabled,
+        log_level = log_level_enabled,
+        usage = usage_enabled,
+        access_log = access_log_enabled,
         "rest server listening on {}",
-        address
+        format_addresses(address)
     );
-    let listener = tokio::net::TcpListener::bind(address).await.unwrap();
-    axum::serve(listener, rest_app)
-        .with_graceful_shutdown(shutdown)
-        .await
-        .unwrap();
+    let listeners = bind_listeners(address).await?;
+    if settings.proxy.protocol {
+        // register shutdown signal (as future)
+        let state = Arc::clone(service.shutdown());
+        let shutdown = shutdown::signal().map(move |_| state.begin());
+        let trusted: Arc<[IpNet]> = Arc::from(settings.proxy.trusted.as_slice());
+        let incoming = futures_util::stream::select_all(
+            listeners
+                .into_iter()
+                .map(|listener| Box::pin(proxy::incoming(listener, Arc::clone(&trusted)))),
+        );
+        serve_rest_proxied(incoming, rest_app, shutdown).await;
+        info!("rest server stopped successfully");
+        return Ok(());
+    }
+    // the peer address is required by the ip filter and the access log
+    let rest_app = rest_app.into_make_service_with_connect_info::<SocketAddr>();
+    let servers = listeners.into_iter().map(|listener| {
+        // register shutdown signal (as future), every listener waits for it on its own
+        let state = Arc::clone(service.shutdown());
+        let shutdown = shutdown::signal().map(move |_| state.begin());
+        axum::serve(listener, rest_app.clone())
+            .with_graceful_shutdown(shutdown)
+            .into_future()
+    });
+    futures_util::future::try_join_all(servers).await?;
     info!("rest server stopped successfully");
     Ok(())
 }
 
+/// Serves the rest server on connections with PROXY protocol header until shutdown. The (proxied)
+/// remote address of each connection is provided as [ConnectInfo] to the requests. Like
+/// [axum::serve], open connections are drained on shutdown. Connections that cannot be accepted are
+/// backed off, so that repeated errors (e.g. exhausted file descriptors) do not spin.
+#[cfg(feature = "rest-server")]
+async fn serve_rest_proxied(
+    incoming: impl Stream<Item = std::io::Result<ProxiedStream>>,
+    rest_app: Router,
+    shutdown: impl Future<Output = ()>,
+) {
+    let mut incoming = Box::pin(incoming);
+    let mut shutdown = std::pin::pin!(shutdown);
+    let graceful = GracefulShutdown::new();
+    let builder = auto::Builder::new(TokioExecutor::new());
+    loop {
+        let stream = tokio::select! {
+            next = incoming.next() => match next {
+                Some(Ok(stream)) => stream,
+                Some(Err(err)) => {
+                    warn!(error = %err, "failed to accept rest connection");
+                    tokio::time::sleep(proxy::ACCEPT_BACKOFF).await;
+                    continue;
+                }
+                None => break,
+            },
+            _ = &mut shutdown => break,
+        };
+        let remote = stream.remote_addr();
+        let rest_app = rest_app.clone();
+        let service = service_fn(move |mut request: hyper::Request<Incoming>| {
+            request.extensions_mut().insert(ConnectInfo(remote));
+            rest_app.clone().call(request)
+        });
+        let connection = builder
+            .serve_connection_with_upgrades(TokioIo::new(stream), service)
+            .into_owned();
+        let connection = graceful.watch(connection);
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                debug!(error = %err, "failed to serve rest connection");
+            }
+        });
+    }
+    // stop accepting and wait for the open connections to complete their requests
+    drop(incoming);
+    graceful.shutdown().await;
+}
+
 /// Tries to start the grpc server. The grpc server is started if it is enabled. It also starts the
 /// health reporter. Blocks until shutdown (graceful shutdown).
+#[cfg(feature = "grpc-server")]
 #[tracing::instrument(skip_all)]
 async fn serve_grpc_server<L, R, M>(
     service: Arc<Service<L, R, M>>,
+    customizer: &ServerCustomizer,
 ) -> Result<(), Box<dyn std::error::Error>>
 where
     L: CacheLevel + Sync + 'static,
@@ -212,7 +717,7 @@ where
     M: Mojang + Sync + 'static,
 {
     let settings = service.settings();
-    let address = settings.grpc_server.address;
+    let address = &settings.grpc_server.address;
     let health_enabled = settings.grpc_server.health_enabled;
     let profile_enabled = settings.grpc_server.profile_enabled;
 
@@ -225,35 +730,216 @@ where
     // build profile server
     let mut profile_server = None;
     if profile_enabled {
-        let server = ProfileServer::new(GrpcProfileService::new(Arc::clone(&service)));
-        profile_server = Some(server);
+        profile_server = Some(grpc_profile_server(Arc::clone(&service)));
     }
 
     // build health server
     let mut health_server = None;
     if health_enabled {
-        let (mut reporter, server) = health_reporter();
-        reporter
-            .set_serving::<ProfileServer<GrpcProfileService<L, R, M>>>()
-            .await;
+        let (reporter, server) = health_reporter();
+        tokio::spawn(report_health(reporter, Arc::clone(&service)));
         health_server = Some(server)
     }
 
     // register shutdown signal (as future)
-    let shutdown = tokio::signal::ctrl_c().map(|_| ());
+    let state = Arc::clone(service.shutdown());
+    let shutdown = shutdown::signal().map(move |_| state.begin());
 
     info!(
-        address = address.to_string(),
+        address = format_addresses(address),
         health = health_enabled,
         profile = profile_enabled,
+        access_log = settings.access_log.enabled,
         "gRPC server listening on {}",
-        settings.grpc_server.address
+        format_addresses(address)
     );
-    Server::builder()
+    let router = Server::builder()
+        .layer(InFlightLayer::new(Arc::clone(service.shutdown())))
+        .layer(IpFilterLayer::new(settings))
+        .layer(SensitiveHeaderLayer::new(&settings.usage.header))
+        .layer(NewSentryLayer::new_from_top())
+        .layer(sentry_http_layer(settings))
+        .layer(DeadlineLayer::new(settings.deadline.enabled))
+        .layer(AccessLogLayer::new(settings))
+        .layer(TenantLayer::new(settings))
+        .layer(SamplingLayer::new(settings))
+        .layer(FaultLayer::new(service.faults().cloned()))
+        .layer(tonic::service::interceptor(customizer.grpc_interceptors()))
         .add_optional_service(health_server)
-        .add_optional_service(profile_server)
-        .serve_with_shutdown(settings.grpc_server.address, shutdown)
-        .await?;
+        .add_optional_service(profile_server);
+    let listeners = bind_listeners(address).await?;
+    match settings.proxy.protocol {
+        true => {
+            let trusted: Arc<[IpNet]> = Arc::from(settings.proxy.trusted.as_slice());
+            let incoming = futures_util::stream::select_all(
+                listeners
+                    .into_iter()
+                    .map(|listener| Box::pin(proxy::incoming(listener, Arc::clone(&trusted)))),
+            );
+            router
+                .serve_with_incoming_shutdown(incoming, shutdown)
+                .await?
+        }
+        false => {
+            let incoming = listeners
+                .into_iter()
+                .map(|listener| TcpIncoming::from_listener(listener, false, None))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| err as Box<dyn std::error::Error>)?;
+            router
+                .serve_with_incoming_shutdown(futures_util::stream::select_all(incoming), shutdown)
+                .await?
+        }
+    }
     info!("gRPC server stopped successfully");
     Ok(())
 }
+
+/// Binds a [TcpListener](tokio::net::TcpListener) to each address. IPv6 addresses are bound IPv6-only
+/// if there is also an IPv4 address with the same port, so that both can be bound (dual-stack).
+#[cfg(any(feature = "rest-server", feature = "grpc-server"))]
+async fn bind_listeners(addresses: &[SocketAddr]) -> std::io::Result<Vec<tokio::net::TcpListener>> {
+    addresses
+        .iter()
+        .map(|address| {
+            let socket = Socket::new(Domain::for_address(*address), Type::STREAM, None)?;
+            let dual_stack = addresses
+                .iter()
+                .any(|other| other.is_ipv4() && other.port() == address.port());
+            if address.is_ipv6() && dual_stack {
+                socket.set_only_v6(true)?;
+            }
+            socket.set_reuse_address(true)?;
+            socket.set_nonblocking(true)?;
+            socket.bind(&(*address).into())?;
+            socket.listen(1024)?;
+            tokio::net::TcpListener::from_std(socket.into())
+        })
+        .collect()
+}
+
+/// Formats a list of addresses for logging, e.g. `0.0.0.0:9990, [::]:9990`.
+#[cfg(any(feature = "rest-server", feature = "grpc-server"))]
+fn format_addresses(addresses: &[SocketAddr]) -> String {
+    addresses
+        .iter()
+        .map(SocketAddr::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Periodically updates the health of the profile server. The profile server is reported as not
+/// serving while the [Service] is unhealthy (the mojang api and the remote cache are unavailable).
+/// Only changes of the health are reported.
+#[cfg(feature = "grpc-server")]
+async fn report_health<L, R, M>(mut reporter: HealthReporter, service: Arc<Service<L, R, M>>)
+where
+    L: CacheLevel + Sync + 'static,
+    R: CacheLevel + Sync + 'static,
+    M: Mojang + Sync + 'static,
+{
+    let period = service.settings().grpc_server.health_interval;
+    let mut interval = tokio::time::interval(period.max(std::time::Duration::from_secs(1)));
+    let mut serving = None;
+    loop {
+        interval.tick().await;
+        let healthy = service.is_healthy().await;
+        if serving == Some(healthy) {
+            continue;
+        }
+        if healthy {
+            info!("profile server is serving");
+            reporter
+                .set_serving::<ProfileServer<GrpcProfileService<L, R, M>>>()
+                .await;
+        } else {
+            warn!("profile server is not serving (mojang api and remote cache are unavailable)");
+            reporter
+                .set_not_serving::<ProfileServer<GrpcProfileService<L, R, M>>>()
+                .await;
+        }
+        serving = Some(healthy);
+    }
+}
+
+/// Skips the rest server as the `rest-server` feature is disabled.
+#[cfg(not(feature = "rest-server"))]
+async fn serve_rest_server<L, R, M>(
+    _service: Arc<Service<L, R, M>>,
+    _customizer: &ServerCustomizer,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    info!("rest server is disabled (enable feature rest-server)");
+    Ok(())
+}
+
+/// Skips the grpc server as the `grpc-server` feature is disabled.
+#[cfg(not(feature = "grpc-server"))]
+async fn serve_grpc_server<L, R, M>(
+    _service: Arc<Service<L, R, M>>,
+    _customizer: &ServerCustomizer,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    info!("gRPC server is disabled (enable feature grpc-server)");
+    Ok(())
+}
+
+#[cfg(all(test, feature = "rest-server"))]
+mod test {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::{oneshot, Notify};
+
+    #[tokio::test]
+    async fn serve_rest_proxied_drains_connections() {
+        // given
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (started, release) = (Arc::new(Notify::new()), Arc::new(Notify::new()));
+        let (handler_started, handler_release) = (Arc::clone(&started), Arc::clone(&release));
+        let rest_app = Router::new().route(
+            "/",
+            get(move || async move {
+                handler_started.notify_one();
+                handler_release.notified().await;
+                "drained"
+            }),
+        );
+        let (stop, stopped) = oneshot::channel::<()>();
+        let incoming = proxy::incoming(listener, Arc::from([]));
+        let server = tokio::spawn(serve_rest_proxied(incoming, rest_app, stopped.map(|_| ())));
+        let mut client = TcpStream::connect(address).await.unwrap();
+        client
+            .write_all(
+                b"PROXY TCP4 192.0.2.1 192.0.2.2 1234 80\r\nGET / HTTP/1.1\r\nhost: xenos\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        started.notified().await;
+
+        // when
+        stop.send(()).unwrap();
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        let draining = !server.is_finished();
+        release.notify_one();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        server.await.unwrap();
+
+        // then
+        assert!(draining);
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("drained"));
+    }
+}
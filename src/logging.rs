@@ -0,0 +1,78 @@
+//! The logging module provides the runtime log level. The log level of the application is wrapped in
+//! a reloadable filter on startup, so that it can be changed at runtime (rest `/admin/log_level` and
+//! grpc `SetLogLevel`), e.g. to get debug logs without a restart that would lose the cache state. The
+//! changed log level persists until the next restart.
+
+use std::str::FromStr;
+use std::sync::OnceLock;
+use tracing::info;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::reload;
+
+/// A [Reload] changes the log level (if some) and returns the current log level.
+type Reload = Box<dyn Fn(Option<LevelFilter>) -> Option<LevelFilter> + Send + Sync>;
+
+/// The reload of the log level, if the application registered a reloadable filter.
+static RELOAD: OnceLock<Reload> = OnceLock::new();
+
+/// A [LogLevelError] indicates that the log level could not be read or changed.
+#[derive(thiserror::Error, Debug)]
+pub enum LogLevelError {
+    /// The log level could not be parsed (e.g. `loud`).
+    #[error("invalid log level: {0}")]
+    Invalid(String),
+
+    /// The log level is not reloadable (e.g. no filter was registered or the subscriber was dropped).
+    #[error("log level is not reloadable")]
+    Unavailable,
+}
+
+/// Registers the [reload::Handle] of the log level filter. Only the first registered handle is used.
+pub fn register<S: 'static>(handle: reload::Handle<LevelFilter, S>) {
+    let reload: Reload = Box::new(move |level| {
+        if let Some(level) = level {
+            handle.reload(level).ok()?;
+        }
+        handle.clone_current()
+    });
+    let _ = RELOAD.set(reload);
+}
+
+/// Gets the current log level.
+pub fn level() -> Result<LevelFilter, LogLevelError> {
+    let reload = RELOAD.get().ok_or(LogLevelError::Unavailable)?;
+    reload(None).ok_or(LogLevelError::Unavailable)
+}
+
+/// Changes the log level (e.g. `debug`) until the next restart. Returns the new log level.
+pub fn set_level(level: &str) -> Result<LevelFilter, LogLevelError> {
+    let level =
+        LevelFilter::from_str(level).map_err(|_| LogLevelError::Invalid(level.to_string()))?;
+    let reload = RELOAD.get().ok_or(LogLevelError::Unavailable)?;
+    let level = reload(Some(level)).ok_or(LogLevelError::Unavailable)?;
+    info!(level = %level, "changed log level");
+    Ok(level)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tracing_subscriber::Registry;
+
+    #[test]
+    fn set_level_reloads() {
+        // given
+        let (filter, handle) = reload::Layer::<_, Registry>::new(LevelFilter::INFO);
+        register(handle);
+
+        // when
+        let changed = set_level("debug");
+        let invalid = set_level("loud");
+
+        // then
+        assert_eq!(changed.unwrap(), LevelFilter::DEBUG);
+        assert_eq!(level().unwrap(), LevelFilter::DEBUG);
+        assert!(matches!(invalid, Err(LogLevelError::Invalid(_))));
+        drop(filter);
+    }
+}
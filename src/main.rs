@@ -2,7 +2,10 @@ use std::borrow::Cow::Owned;
 use std::sync::Arc;
 use tracing::info;
 
+use tracing_subscriber::filter::FilterExt;
 use tracing_subscriber::prelude::*;
+use tracing_subscriber::reload;
+use xenos::sampling::SamplingFilter;
 use xenos::settings::Settings;
 
 /// Starts the Xenos application. It reads the application [Settings], initializes [sentry] and [tracing]
@@ -21,27 +24,55 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             debug: settings.sentry.debug,
             release: sentry::release_name!(),
             environment: Some(Owned(settings.sentry.environment.clone())),
+            traces_sample_rate: settings.sentry.traces_sample_rate,
             ..sentry::ClientOptions::default()
         },
     ));
 
-    // initialize logging with sentry hook
-    tracing_subscriber::registry()
+    // initialize logging with sentry hook (and the tokio console if enabled)
+    let registry = tracing_subscriber::registry();
+    #[cfg(feature = "diagnostics")]
+    let registry = registry.with(
+        settings
+            .diagnostics
+            .console_enabled
+            .then(console_subscriber::spawn),
+    );
+    // the log level can be changed at runtime
+    let (level, level_handle) = reload::Layer::new(settings.logging.level);
+    xenos::logging::register(level_handle);
+    let sampling = SamplingFilter::new(&settings.logging.sampling);
+    registry
         .with(
             tracing_subscriber::fmt::layer()
                 .json()
-                .with_filter(settings.logging.level),
+                .with_filter(level.and(sampling.clone())),
         )
-        .with(sentry_tracing::layer())
+        .with(sentry_tracing::layer().with_filter(sampling))
         .init();
     if _sentry.is_enabled() {
         info!("sentry is enabled");
     }
+    #[cfg(feature = "diagnostics")]
+    if settings.diagnostics.console_enabled {
+        info!("tokio console is enabled");
+    }
 
-    // run xenos blocking
-    tokio::runtime::Builder::new_multi_thread()
+    // run xenos blocking (the worker threads default to the number of cpu cores)
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    if let Some(worker_threads) = settings.runtime.worker_threads {
+        runtime.worker_threads(worker_threads.max(1));
+    }
+    info!(
+        worker_threads = settings.runtime.worker_threads,
+        max_blocking_threads = settings.runtime.max_blocking_threads,
+        event_interval = settings.runtime.event_interval,
+        "starting tokio runtime"
+    );
+    runtime
+        .max_blocking_threads(settings.runtime.max_blocking_threads.max(1))
+        .event_interval(settings.runtime.event_interval.max(1))
         .enable_all()
-        .build()
-        .unwrap()
+        .build()?
         .block_on(async { xenos::start(settings).await })
 }
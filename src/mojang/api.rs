@@ -1,16 +1,21 @@
-use crate::mojang::ApiError::{NotFound, Unavailable};
-use crate::mojang::{ApiError, Mojang, Profile, TextureBytes, UsernameResolved};
+use crate::mojang::headers::{HeaderSnapshot, LatestHeaders};
+use crate::mojang::ApiError::{NotFound, RateLimited, Unavailable};
+use crate::mojang::{headers, ApiError, Mojang, Profile, TextureBytes, UsernameResolved};
+use crate::{settings, statsd};
 use lazy_static::lazy_static;
 use metrics::MetricsEvent;
 use prometheus::{register_counter_vec, register_histogram_vec, CounterVec, HistogramVec};
 use reqwest::StatusCode;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tracing::{error, warn};
 use uuid::Uuid;
 
 lazy_static! {
     /// The shared http client with connection pool, uses arc internally
-    static ref HTTP_CLIENT: reqwest::Client = reqwest::Client::builder().build().unwrap();
+    pub(super) static ref HTTP_CLIENT: reqwest::Client = reqwest::Client::builder().build().unwrap();
 
     /// A histogram for the mojang request status and request latencies in seconds. Use the
     /// [monitor_reqwest] utility for ease of use.
@@ -35,6 +40,7 @@ fn metrics_handler<T>(event: MetricsEvent<Result<T, ApiError>>) {
     let status = match event.result {
         Ok(_) => "ok",
         Err(Unavailable) => "unavailable",
+        Err(RateLimited { .. }) => "rate_limited",
         Err(NotFound) => "not_found",
     };
     let Some(request_type) = event.labels.get("request_type") else {
@@ -44,10 +50,100 @@ fn metrics_handler<T>(event: MetricsEvent<Result<T, ApiError>>) {
     MOJANG_REQ_HISTOGRAM
         .with_label_values(&[request_type, status])
         .observe(event.time);
+    statsd::timing(
+        "mojang.request",
+        &[("request_type", request_type), ("status", status)],
+        event.time,
+    );
 }
 
-/// [MojangApi] is stateless a wrapper for the official mojang api.
-pub struct MojangApi;
+/// The headers of rate limited responses that hint when requests are accepted again, in order of their
+/// precedence.
+const RETRY_HEADERS: [&str; 3] = ["retry-after", "ratelimit-reset", "x-ratelimit-reset"];
+
+/// The default maximum retry hint of rate limited responses.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// The smallest retry hint (in seconds) that is a unix timestamp instead of a delay (2001-09-09).
+const TIMESTAMP_THRESHOLD: u64 = 1_000_000_000;
+
+/// Parses a retry hint at a point in time. The hint is either a delay in seconds, a unix timestamp in
+/// seconds (e.g. of the rate limit reset headers) or an HTTP-date (e.g. of the `Retry-After` header).
+/// Points in time that already passed are no delay.
+fn parse_retry_hint(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    let at = match value.parse::<u64>() {
+        Ok(seconds) if seconds < TIMESTAMP_THRESHOLD => return Some(Duration::from_secs(seconds)),
+        Ok(seconds) => UNIX_EPOCH + Duration::from_secs(seconds),
+        Err(_) => httpdate::parse_http_date(value).ok()?,
+    };
+    Some(at.duration_since(now).unwrap_or_default())
+}
+
+/// Builds a [RateLimited] error from a rate limited mojang response. The (optional) retry hint is read
+/// from the `Retry-After` header or the rate limit reset headers and clamped to a maximum.
+fn rate_limited(response: &reqwest::Response, max_retry_after: Duration) -> ApiError {
+    let now = SystemTime::now();
+    let retry_after = RETRY_HEADERS
+        .iter()
+        .filter_map(|name| response.headers().get(*name))
+        .filter_map(|value| value.to_str().ok())
+        .find_map(|value| parse_retry_hint(value, now))
+        .map(|retry_after| retry_after.min(max_retry_after));
+    warn!(retry_after = ?retry_after, "mojang api rate limit exceeded");
+    RateLimited { retry_after }
+}
+
+/// The headers of mojang responses that report the remaining rate budget, in order of their precedence.
+const REMAINING_HEADERS: [&str; 2] = ["ratelimit-remaining", "x-ratelimit-remaining"];
+
+/// Parses the remaining rate budget from the rate limit headers of a mojang response (if any).
+fn reported_remaining(response: &reqwest::Response) -> Option<u64> {
+    REMAINING_HEADERS
+        .iter()
+        .filter_map(|name| response.headers().get(*name))
+        .filter_map(|value| value.to_str().ok())
+        .find_map(|value| value.trim().parse().ok())
+}
+
+/// Builds the url of an endpoint from a base url and its path segments. The segments are
+/// percent-encoded, so that user input (e.g. usernames) cannot change the path or query of the url.
+fn endpoint_url(base_url: &str, segments: &[&str]) -> Result<reqwest::Url, ApiError> {
+    let mut url = reqwest::Url::parse(base_url).map_err(|err| {
+        error!(error = %err, base_url, "invalid mojang base url");
+        Unavailable
+    })?;
+    url.path_segments_mut()
+        .map_err(|_| {
+            error!(base_url, "mojang base url cannot have a path");
+            Unavailable
+        })?
+        .pop_if_empty()
+        .extend(segments);
+    Ok(url)
+}
+
+/// The base url of the official mojang api.
+const API_URL: &str = "https://api.mojang.com";
+
+/// The base url of the official mojang session server.
+const SESSION_URL: &str = "https://sessionserver.mojang.com";
+
+/// The base url of the official minecraft services api.
+const SERVICES_URL: &str = "https://api.minecraftservices.com";
+
+/// [MojangApi] is a wrapper for the mojang api. The base urls of the endpoints default to the official
+/// mojang api. It keeps the remaining rate budgets and the diagnostic headers of the latest responses
+/// per endpoint.
+pub struct MojangApi {
+    api_url: String,
+    session_url: String,
+    services_url: String,
+    diagnostic_headers: Vec<String>,
+    max_retry_after: Duration,
+    reported_remaining: Mutex<HashMap<&'static str, u64>>,
+    latest_headers: LatestHeaders,
+}
 
 impl Default for MojangApi {
     fn default() -> Self {
@@ -56,14 +152,62 @@ impl Default for MojangApi {
 }
 
 impl MojangApi {
-    /// Creates a new [MojangApi].
+    /// Creates a new [MojangApi] for the official mojang api.
     pub fn new() -> Self {
-        Self {}
+        Self {
+            api_url: API_URL.to_string(),
+            session_url: SESSION_URL.to_string(),
+            services_url: SERVICES_URL.to_string(),
+            diagnostic_headers: headers::DEFAULT_HEADERS.map(String::from).to_vec(),
+            max_retry_after: MAX_RETRY_AFTER,
+            reported_remaining: Mutex::default(),
+            latest_headers: LatestHeaders::default(),
+        }
+    }
+
+    /// Creates a new [MojangApi] with the configured base urls (e.g. of a mock server).
+    pub fn with_endpoints(settings: &settings::MojangApi) -> Self {
+        // the trailing slashes are trimmed, as the paths are appended to the base urls
+        let base_url = |url: &str| url.trim_end_matches('/').to_string();
+        Self {
+            api_url: base_url(&settings.api_url),
+            session_url: base_url(&settings.session_url),
+            services_url: base_url(&settings.services_url),
+            diagnostic_headers: settings
+                .diagnostic_headers
+                .iter()
+                .map(|name| name.to_lowercase())
+                .collect(),
+            max_retry_after: settings.max_retry_after,
+            reported_remaining: Mutex::default(),
+            latest_headers: LatestHeaders::default(),
+        }
+    }
+
+    /// Records the selected diagnostic headers of a mojang response of an endpoint (see [headers]).
+    fn record_headers(&self, endpoint: &'static str, response: &reqwest::Response) {
+        if self.diagnostic_headers.is_empty() {
+            return;
+        }
+        let captured = headers::capture(&self.diagnostic_headers, response.headers());
+        self.latest_headers
+            .record(endpoint, response.status().as_u16(), captured);
+    }
+
+    /// Keeps the remaining rate budget of an endpoint, if the mojang response has a rate limit header.
+    /// It is taken by the [UpstreamStats](crate::mojang::status::UpstreamStats) of the service.
+    fn report_remaining(&self, endpoint: &'static str, response: &reqwest::Response) {
+        if let Some(remaining) = reported_remaining(response) {
+            self.reported_remaining
+                .lock()
+                .unwrap()
+                .insert(endpoint, remaining);
+        }
     }
 
     /// Implements [Mojang::fetch_uuids] but with the constraint that the usernames slice may not be
     /// larger than the mojang api allows (currently this constraint is ten).
-    #[tracing::instrument(skip(self))]
+    #[tracing::instrument(skip(self), fields(mojang_headers))]
     #[metrics::metrics(
         metric = "mojang_api",
         labels(request_type = "uuids_chunk"),
@@ -74,7 +218,10 @@ impl MojangApi {
         usernames: &[String],
     ) -> Result<Vec<UsernameResolved>, ApiError> {
         let response = HTTP_CLIENT
-            .post("https://api.minecraftservices.com/minecraft/profile/lookup/bulk/byname")
+            .post(format!(
+                "{}/minecraft/profile/lookup/bulk/byname",
+                self.services_url
+            ))
             .json(usernames)
             .send()
             .await
@@ -86,6 +233,8 @@ impl MojangApi {
         MOJANG_REQ_COUNTER
             .with_label_values(&["uuids_chunk", response.status().as_str()])
             .inc();
+        self.report_remaining("uuids", &response);
+        self.record_headers("uuids", &response);
 
         match response.status() {
             StatusCode::NOT_FOUND | StatusCode::NO_CONTENT => Ok(vec![]),
@@ -93,6 +242,7 @@ impl MojangApi {
                 error!(error = %err, "failed to parse uuids body");
                 Unavailable
             }),
+            StatusCode::TOO_MANY_REQUESTS => Err(rate_limited(&response, self.max_retry_after)),
             code => {
                 let body = response.text().await.unwrap_or(String::new());
                 warn!(
@@ -107,28 +257,36 @@ impl MojangApi {
 }
 
 impl Mojang for MojangApi {
-    #[tracing::instrument(skip(self))]
+    fn take_reported_remaining(&self, endpoint: &str) -> Option<u64> {
+        self.reported_remaining.lock().unwrap().remove(endpoint)
+    }
+
+    fn latest_headers(&self) -> BTreeMap<&'static str, HeaderSnapshot> {
+        self.latest_headers.snapshot()
+    }
+
+    #[tracing::instrument(skip(self), fields(mojang_headers))]
     #[metrics::metrics(
         metric = "mojang_api",
         labels(request_type = "uuid"),
         handler = metrics_handler,
     )]
     async fn fetch_uuid(&self, username: &str) -> Result<UsernameResolved, ApiError> {
-        let response = HTTP_CLIENT
-            .get(format!(
-                "https://api.mojang.com/users/profiles/minecraft/{}",
-                username
-            ))
-            .send()
-            .await
-            .map_err(|err| {
-                warn!(error = %err, cause = err.source(), "failed to fetch uuid");
-                Unavailable
-            })?;
+        // dot segments would be removed from the path instead of being looked up
+        if matches!(username, "." | "..") {
+            return Err(NotFound);
+        }
+        let url = endpoint_url(&self.api_url, &["users", "profiles", "minecraft", username])?;
+        let response = HTTP_CLIENT.get(url).send().await.map_err(|err| {
+            warn!(error = %err, cause = err.source(), "failed to fetch uuid");
+            Unavailable
+        })?;
 
         MOJANG_REQ_COUNTER
             .with_label_values(&["uuid", response.status().as_str()])
             .inc();
+        self.report_remaining("uuid", &response);
+        self.record_headers("uuid", &response);
 
         match response.status() {
             StatusCode::NOT_FOUND | StatusCode::NO_CONTENT => Err(NotFound),
@@ -136,6 +294,7 @@ impl Mojang for MojangApi {
                 error!(error = %err, "failed to parse uuid body");
                 Unavailable
             }),
+            StatusCode::TOO_MANY_REQUESTS => Err(rate_limited(&response, self.max_retry_after)),
             code => {
                 let body = response.text().await.unwrap_or(String::new());
                 warn!(
@@ -164,7 +323,7 @@ impl Mojang for MojangApi {
         Ok(resolved)
     }
 
-    #[tracing::instrument(skip(self))]
+    #[tracing::instrument(skip(self), fields(mojang_headers))]
     #[metrics::metrics(
         metric = "mojang_api",
         labels(request_type = "profile"),
@@ -173,7 +332,8 @@ impl Mojang for MojangApi {
     async fn fetch_profile(&self, uuid: &Uuid, signed: bool) -> Result<Profile, ApiError> {
         let response = HTTP_CLIENT
             .get(format!(
-                "https://sessionserver.mojang.com/session/minecraft/profile/{}?unsigned={}",
+                "{}/session/minecraft/profile/{}?unsigned={}",
+                self.session_url,
                 uuid.simple(),
                 !signed,
             ))
@@ -187,6 +347,8 @@ impl Mojang for MojangApi {
         MOJANG_REQ_COUNTER
             .with_label_values(&["profile", response.status().as_str()])
             .inc();
+        self.report_remaining("profile", &response);
+        self.record_headers("profile", &response);
 
         match response.status() {
             StatusCode::NOT_FOUND | StatusCode::NO_CONTENT => Err(NotFound),
@@ -194,6 +356,7 @@ impl Mojang for MojangApi {
                 error!(error = %err, "failed to parse profile body");
                 Unavailable
             }),
+            StatusCode::TOO_MANY_REQUESTS => Err(rate_limited(&response, self.max_retry_after)),
             code => {
                 let body = response.text().await.unwrap_or(String::new());
                 warn!(
@@ -206,7 +369,7 @@ impl Mojang for MojangApi {
         }
     }
 
-    #[tracing::instrument(skip(self))]
+    #[tracing::instrument(skip(self), fields(mojang_headers))]
     #[metrics::metrics(
         metric = "mojang_api",
         labels(request_type = "bytes"),
@@ -221,6 +384,8 @@ impl Mojang for MojangApi {
         MOJANG_REQ_COUNTER
             .with_label_values(&["bytes", response.status().as_str()])
             .inc();
+        self.report_remaining("bytes", &response);
+        self.record_headers("bytes", &response);
 
         match response.status() {
             StatusCode::NOT_FOUND | StatusCode::NO_CONTENT => Err(NotFound),
@@ -228,6 +393,7 @@ impl Mojang for MojangApi {
                 error!(error = %err, "failed to parse body bytes");
                 Unavailable
             }),
+            StatusCode::TOO_MANY_REQUESTS => Err(rate_limited(&response, self.max_retry_after)),
             code => {
                 let body = response.text().await.unwrap_or(String::new());
                 warn!(
@@ -239,4 +405,97 @@ impl Mojang for MojangApi {
             }
         }
     }
+
+    #[tracing::instrument(skip(self), fields(mojang_headers))]
+    #[metrics::metrics(
+        metric = "mojang_api",
+        labels(request_type = "blocked_servers"),
+        handler = metrics_handler,
+    )]
+    async fn fetch_blocked_servers(&self) -> Result<Vec<String>, ApiError> {
+        let response = HTTP_CLIENT
+            .get(format!("{}/blockedservers", self.session_url))
+            .send()
+            .await
+            .map_err(|err| {
+                warn!(error = %err, cause = err.source(), "failed to fetch blocked servers");
+                Unavailable
+            })?;
+
+        MOJANG_REQ_COUNTER
+            .with_label_values(&["blocked_servers", response.status().as_str()])
+            .inc();
+        self.report_remaining("blocked_servers", &response);
+        self.record_headers("blocked_servers", &response);
+
+        match response.status() {
+            StatusCode::NOT_FOUND | StatusCode::NO_CONTENT => Ok(vec![]),
+            // the list consists of one hash per line
+            StatusCode::OK => response
+                .text()
+                .await
+                .map(|body| {
+                    body.lines()
+                        .map(|line| line.trim().to_lowercase())
+                        .filter(|line| !line.is_empty())
+                        .collect()
+                })
+                .map_err(|err| {
+                    error!(error = %err, "failed to parse blocked servers body");
+                    Unavailable
+                }),
+            StatusCode::TOO_MANY_REQUESTS => Err(rate_limited(&response, self.max_retry_after)),
+            code => {
+                let body = response.text().await.unwrap_or(String::new());
+                warn!(
+                    status = code.as_str(),
+                    body = body,
+                    "failed to read blocked servers: invalid status code"
+                );
+                Err(Unavailable)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn endpoint_url_encoded() {
+        // given
+        let base_url = "http://localhost:8080/mock";
+
+        // when
+        let url = endpoint_url(base_url, &["users", "a/../b?c=d#e"]).unwrap();
+        let root = endpoint_url("https://api.mojang.com", &["users", "Hydrofin"]).unwrap();
+
+        // then
+        assert_eq!(
+            "http://localhost:8080/mock/users/a%2F..%2Fb%3Fc=d%23e",
+            url.as_str()
+        );
+        assert_eq!("https://api.mojang.com/users/Hydrofin", root.as_str());
+    }
+
+    #[test]
+    fn parse_retry_hint_forms() {
+        // given
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        // when
+        let delay = parse_retry_hint(" 30 ", now);
+        let timestamp = parse_retry_hint("1700000045", now);
+        let passed = parse_retry_hint("1699999990", now);
+        let date = parse_retry_hint("Tue, 14 Nov 2023 22:14:20 GMT", now);
+        let invalid = parse_retry_hint("soon", now);
+
+        // then
+        assert_eq!(Some(Duration::from_secs(30)), delay);
+        assert_eq!(Some(Duration::from_secs(45)), timestamp);
+        assert_eq!(Some(Duration::ZERO), passed);
+        assert_eq!(Some(Duration::from_secs(60)), date);
+        assert_eq!(None, invalid);
+    }
 }
@@ -0,0 +1,100 @@
+//! The blocked module provides the matching of hostnames against the blocked servers list of mojang.
+//! Mojang publishes the list only as SHA1 hashes of (lowercase) hostname patterns. A pattern is
+//! either a hostname with a leading wildcard (e.g. `*.example.com`) or an ip address with a trailing
+//! wildcard (e.g. `192.168.*`). To test a hostname, the hashes of all patterns that match the hostname
+//! are compared with the list.
+
+use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+use std::fmt::Write;
+use std::net::Ipv4Addr;
+
+/// Gets all patterns of the blocked servers list that match a hostname, starting with the hostname
+/// itself. The hostname is case-insensitive.
+///
+/// - `mc.example.com` is matched by `mc.example.com`, `*.example.com` and `*.com`.
+/// - `192.168.0.1` is matched by `192.168.0.1`, `192.168.0.*`, `192.168.*` and `192.*`.
+pub fn server_patterns(hostname: &str) -> Vec<String> {
+    let hostname = hostname.trim().trim_end_matches('.').to_lowercase();
+    if hostname.is_empty() {
+        return vec![];
+    }
+    let parts: Vec<&str> = hostname.split('.').collect();
+    let wildcards: Vec<String> = match hostname.parse::<Ipv4Addr>() {
+        Ok(_) => (1..parts.len())
+            .rev()
+            .map(|i| format!("{}.*", parts[..i].join(".")))
+            .collect(),
+        Err(_) => (1..parts.len())
+            .map(|i| format!("*.{}", parts[i..].join(".")))
+            .collect(),
+    };
+    let mut patterns = vec![hostname];
+    patterns.extend(wildcards);
+    patterns
+}
+
+/// Gets the (lowercase hex) SHA1 hash of a pattern, as used by the blocked servers list.
+pub fn hash_pattern(pattern: &str) -> String {
+    digest(&SHA1_FOR_LEGACY_USE_ONLY, pattern.as_bytes())
+        .as_ref()
+        .iter()
+        .fold(String::with_capacity(40), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// Finds the first pattern of a hostname (see [server_patterns]) whose hash is part of the blocked
+/// servers list. It returns `None` if the hostname is not blocked.
+pub fn find_blocked(hostname: &str, hashes: &[String]) -> Option<String> {
+    server_patterns(hostname)
+        .into_iter()
+        .find(|pattern| hashes.contains(&hash_pattern(pattern)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn server_patterns_hostname() {
+        // given
+        let hostname = "MC.Example.com.";
+
+        // when
+        let patterns = server_patterns(hostname);
+
+        // then
+        assert_eq!(vec!["mc.example.com", "*.example.com", "*.com"], patterns);
+    }
+
+    #[test]
+    fn server_patterns_ip() {
+        // given
+        let hostname = "192.168.0.1";
+
+        // when
+        let patterns = server_patterns(hostname);
+
+        // then
+        assert_eq!(
+            vec!["192.168.0.1", "192.168.0.*", "192.168.*", "192.*"],
+            patterns
+        );
+    }
+
+    #[test]
+    fn find_blocked_wildcard() {
+        // given
+        let hashes = vec![hash_pattern("*.example.com")];
+
+        // when
+        let blocked = find_blocked("mc.example.com", &hashes);
+        let unblocked = find_blocked("example.org", &hashes);
+
+        // then
+        assert_eq!("8c7122d652cb7be22d1986f1f30b07fd5108d9c0", hashes[0]);
+        assert_eq!(Some("*.example.com".to_string()), blocked);
+        assert_eq!(None, unblocked);
+    }
+}
@@ -0,0 +1,256 @@
+use crate::settings;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// The [BreakerState] is the state of a [CircuitBreaker].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BreakerState {
+    /// Requests are sent to the mojang api.
+    Closed,
+
+    /// Requests are not sent to the mojang api as it failed repeatedly.
+    Open,
+
+    /// The cooldown has elapsed. A single probe request is sent to the mojang api again, all other
+    /// requests are not. If the probe fails, the [CircuitBreaker] opens again, if it succeeds, the
+    /// [CircuitBreaker] closes.
+    HalfOpen,
+}
+
+impl BreakerState {
+    /// Gets the lowercase name of the [BreakerState] (e.g. `half_open`).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BreakerState::Closed => "closed",
+            BreakerState::Open => "open",
+            BreakerState::HalfOpen => "half_open",
+        }
+    }
+}
+
+/// The [CircuitBreaker] tracks consecutive failed requests to the mojang api. It opens after the
+/// configured threshold of failures and stays open for the configured cooldown. All times are unix
+/// timestamps in seconds, so that the [Clock](crate::cache::clock::Clock) of the cache can be used.
+///
+/// Rate limited requests may hint when mojang accepts requests again (`Retry-After`). The breaker is
+/// held open until then (even if it is disabled), as requests before that would extend the rate limit.
+///
+/// ```rs
+/// let breaker = CircuitBreaker::new(&settings.circuit_breaker);
+/// if breaker.allows(now) {
+///     match mojang.fetch_uuid(username).await {
+///         Err(ApiError::Unavailable) => breaker.record_failure(now),
+///         _ => breaker.record_success(),
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    enabled: bool,
+    threshold: u32,
+    cooldown: u64,
+    failures: AtomicU32,
+    opened_at: AtomicU64,
+    probed_at: AtomicU64,
+    retry_at: AtomicU64,
+}
+
+impl CircuitBreaker {
+    /// Creates a new (closed) [CircuitBreaker] from its configuration.
+    pub fn new(settings: &settings::CircuitBreaker) -> Self {
+        Self {
+            enabled: settings.enabled,
+            threshold: settings.threshold.max(1),
+            cooldown: settings.cooldown.as_secs(),
+            failures: AtomicU32::new(0),
+            opened_at: AtomicU64::new(0),
+            probed_at: AtomicU64::new(0),
+            retry_at: AtomicU64::new(0),
+        }
+    }
+
+    /// Gets the [BreakerState] at the unix timestamp in seconds.
+    pub fn state(&self, now: u64) -> BreakerState {
+        if self.retry_at(now).is_some() {
+            return BreakerState::Open;
+        }
+        if !self.enabled || self.failures.load(Ordering::SeqCst) < self.threshold {
+            return BreakerState::Closed;
+        }
+        if now < self.opened_at.load(Ordering::SeqCst) + self.cooldown {
+            return BreakerState::Open;
+        }
+        BreakerState::HalfOpen
+    }
+
+    /// Gets the unix timestamp in seconds before which mojang asked not to retry requests, if it is
+    /// after the unix timestamp in seconds.
+    pub fn retry_at(&self, now: u64) -> Option<u64> {
+        let retry_at = self.retry_at.load(Ordering::SeqCst);
+        (retry_at > now).then_some(retry_at)
+    }
+
+    /// Checks whether a request to the mojang api is allowed at the unix timestamp in seconds. While
+    /// the [CircuitBreaker] is half-open, only a single probe is allowed. If the probe is not recorded
+    /// (e.g. because it was cancelled), another probe is allowed after the cooldown.
+    pub fn allows(&self, now: u64) -> bool {
+        match self.state(now) {
+            BreakerState::Closed => true,
+            BreakerState::Open => false,
+            BreakerState::HalfOpen => {
+                let probed_at = self.probed_at.load(Ordering::SeqCst);
+                let probing = probed_at > 0 && now < probed_at + self.cooldown;
+                !probing
+                    && self
+                        .probed_at
+                        .compare_exchange(probed_at, now, Ordering::SeqCst, Ordering::SeqCst)
+                        .is_ok()
+            }
+        }
+    }
+
+    /// Records a successful request. It closes the [CircuitBreaker].
+    pub fn record_success(&self) {
+        self.failures.store(0, Ordering::SeqCst);
+        self.probed_at.store(0, Ordering::SeqCst);
+    }
+
+    /// Records a failed request at the unix timestamp in seconds. It (re-)opens the [CircuitBreaker]
+    /// if the threshold is reached.
+    pub fn record_failure(&self, now: u64) {
+        let failures = self
+            .failures
+            .fetch_add(1, Ordering::SeqCst)
+            .saturating_add(1);
+        if failures >= self.threshold {
+            self.opened_at.store(now, Ordering::SeqCst);
+            self.probed_at.store(0, Ordering::SeqCst);
+        }
+    }
+
+    /// Records a rate limited request at the unix timestamp in seconds. It is recorded as failure and
+    /// holds the [CircuitBreaker] open until the retry hint (if any) elapsed.
+    pub fn record_rate_limited(&self, now: u64, retry_after: Option<Duration>) {
+        self.record_failure(now);
+        if let Some(retry_after) = retry_after {
+            let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            self.retry_at.fetch_max(now + secs, Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(&settings::CircuitBreaker {
+            enabled: true,
+            threshold: 2,
+            cooldown: Duration::from_secs(30),
+        })
+    }
+
+    #[test]
+    fn opens_after_threshold() {
+        // given
+        let breaker = breaker();
+
+        // when
+        breaker.record_failure(100);
+        let state_below = breaker.state(100);
+        breaker.record_failure(100);
+        let state_reached = breaker.state(100);
+
+        // then
+        assert_eq!(BreakerState::Closed, state_below);
+        assert_eq!(BreakerState::Open, state_reached);
+        assert!(!breaker.allows(129));
+    }
+
+    #[test]
+    fn half_opens_after_cooldown() {
+        // given
+        let breaker = breaker();
+        breaker.record_failure(100);
+        breaker.record_failure(100);
+
+        // when
+        let state = breaker.state(130);
+
+        // then
+        assert_eq!(BreakerState::HalfOpen, state);
+        assert!(breaker.allows(130));
+    }
+
+    #[test]
+    fn half_open_admits_single_probe() {
+        // given
+        let breaker = breaker();
+        breaker.record_failure(100);
+        breaker.record_failure(100);
+
+        // when
+        let probe = breaker.allows(130);
+        let concurrent = breaker.allows(131);
+        let lost_probe = breaker.allows(160);
+
+        // then
+        assert!(probe);
+        assert!(!concurrent);
+        assert!(lost_probe);
+    }
+
+    #[test]
+    fn reopens_on_failed_probe() {
+        // given
+        let breaker = breaker();
+        breaker.record_failure(100);
+        breaker.record_failure(100);
+        assert!(breaker.allows(130));
+
+        // when
+        breaker.record_failure(130);
+
+        // then
+        assert_eq!(BreakerState::Open, breaker.state(130));
+        assert!(!breaker.allows(159));
+        assert!(breaker.allows(160));
+        assert!(!breaker.allows(160));
+    }
+
+    #[test]
+    fn closes_on_success() {
+        // given
+        let breaker = breaker();
+        breaker.record_failure(100);
+        breaker.record_failure(100);
+
+        // when
+        breaker.record_success();
+
+        // then
+        assert_eq!(BreakerState::Closed, breaker.state(100));
+    }
+
+    #[test]
+    fn holds_open_until_retry() {
+        // given
+        let breaker = CircuitBreaker::new(&settings::CircuitBreaker {
+            enabled: false,
+            threshold: 2,
+            cooldown: Duration::from_secs(30),
+        });
+
+        // when
+        breaker.record_rate_limited(100, Some(Duration::from_millis(59_500)));
+        breaker.record_rate_limited(110, Some(Duration::from_secs(10)));
+
+        // then
+        assert_eq!(Some(160), breaker.retry_at(159));
+        assert_eq!(BreakerState::Open, breaker.state(159));
+        assert_eq!(None, breaker.retry_at(160));
+        assert_eq!(BreakerState::Closed, breaker.state(160));
+    }
+}
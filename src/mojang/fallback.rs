@@ -0,0 +1,291 @@
+use crate::mojang::api::HTTP_CLIENT;
+use crate::mojang::breaker::{BreakerState, CircuitBreaker};
+use crate::mojang::ApiError::{NotFound, RateLimited, Unavailable};
+use crate::mojang::{ApiError, Profile, ProfileProperty};
+use crate::settings;
+use crate::settings::FallbackKind;
+use futures::future::BoxFuture;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::error::Error;
+use tracing::{debug, error, warn};
+use uuid::Uuid;
+
+/// A [ProfileProvider] is a read-only source of mojang profiles other than the mojang api (e.g. a
+/// public mirror). It is used as fallback if the mojang api is unavailable or rate limiting.
+pub trait ProfileProvider: Send + Sync {
+    /// Fetches the profile of a (case-insensitive) username or an uuid (in simple form).
+    fn fetch_profile<'a>(&'a self, query: &'a str) -> BoxFuture<'a, Result<Profile, ApiError>>;
+}
+
+/// The [ProviderStatus] is the health of a single fallback provider.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderStatus {
+    /// The name of the provider.
+    pub name: String,
+
+    /// The state of the circuit breaker of the provider.
+    pub breaker: BreakerState,
+}
+
+/// A [ChainedProvider] is a [ProfileProvider] within a [ProviderChain] with its own [CircuitBreaker].
+struct ChainedProvider {
+    name: String,
+    priority: u32,
+    provider: Box<dyn ProfileProvider>,
+    breaker: CircuitBreaker,
+}
+
+/// The [ProviderChain] holds the fallback [ProfileProviders](ProfileProvider) ordered by their
+/// priority (trust). A lookup is sent to the providers in order until one of them serves the profile.
+/// Each provider has its own [CircuitBreaker], so that unhealthy providers are skipped.
+///
+/// ```rs
+/// let chain = ProviderChain::new(&settings.fallback);
+/// if let Ok((provider, profile)) = chain.fetch_profile("hydrofin", now).await {
+///     info!(provider, "profile served by fallback provider");
+/// }
+/// ```
+pub struct ProviderChain {
+    breaker: settings::CircuitBreaker,
+    providers: Vec<ChainedProvider>,
+}
+
+impl ProviderChain {
+    /// Creates a new [ProviderChain] with the configured providers. The chain is empty if the
+    /// fallback providers are disabled.
+    pub fn new(settings: &settings::Fallback) -> Self {
+        let mut chain = Self {
+            breaker: settings.circuit_breaker.clone(),
+            providers: vec![],
+        };
+        if !settings.enabled {
+            return chain;
+        }
+        for provider in &settings.providers {
+            let url = provider.url.trim_end_matches('/').to_string();
+            let api: Box<dyn ProfileProvider> = match provider.kind {
+                FallbackKind::PlayerDb => Box::new(PlayerDbApi { url }),
+                FallbackKind::Ashcon => Box::new(AshconApi { url }),
+            };
+            chain.add_provider(&provider.name, provider.priority, api);
+        }
+        chain
+    }
+
+    /// Adds a [ProfileProvider] to the [ProviderChain]. Providers with the same priority are tried in
+    /// the order they were added.
+    pub fn add_provider(&mut self, name: &str, priority: u32, provider: Box<dyn ProfileProvider>) {
+        let index = self
+            .providers
+            .partition_point(|chained| chained.priority <= priority);
+        self.providers.insert(
+            index,
+            ChainedProvider {
+                name: name.to_string(),
+                priority,
+                provider,
+                breaker: CircuitBreaker::new(&self.breaker),
+            },
+        );
+    }
+
+    /// Checks whether the [ProviderChain] has no providers.
+    pub fn is_empty(&self) -> bool {
+        self.providers.is_empty()
+    }
+
+    /// Gets the health of all providers (in order) at the unix timestamp in seconds.
+    pub fn status(&self, now: u64) -> Vec<ProviderStatus> {
+        self.providers
+            .iter()
+            .map(|chained| ProviderStatus {
+                name: chained.name.clone(),
+                breaker: chained.breaker.state(now),
+            })
+            .collect()
+    }
+
+    /// Fetches the profile of a username or an uuid (in simple form) from the first healthy provider
+    /// that serves it. It returns the name of the provider along with the profile. As the providers
+    /// are not authoritative, a profile that is not found by a provider is not looked up any further
+    /// and fails with [ApiError::NotFound]. If no provider serves the profile, it fails with
+    /// [ApiError::Unavailable].
+    pub async fn fetch_profile(&self, query: &str, now: u64) -> Result<(&str, Profile), ApiError> {
+        for chained in &self.providers {
+            if !chained.breaker.allows(now) {
+                continue;
+            }
+            match chained.provider.fetch_profile(query).await {
+                Ok(profile) => {
+                    chained.breaker.record_success();
+                    return Ok((&chained.name, profile));
+                }
+                Err(NotFound) => {
+                    chained.breaker.record_success();
+                    debug!(
+                        provider = chained.name,
+                        query, "fallback provider found no profile"
+                    );
+                    return Err(NotFound);
+                }
+                Err(Unavailable | RateLimited { .. }) => {
+                    chained.breaker.record_failure(now);
+                    warn!(
+                        provider = chained.name,
+                        query, "fallback provider unavailable"
+                    );
+                }
+            }
+        }
+        Err(Unavailable)
+    }
+}
+
+/// Sends a get request to a fallback provider and parses its json body.
+async fn fetch_json<T: DeserializeOwned>(url: String) -> Result<T, ApiError> {
+    let response = HTTP_CLIENT.get(url).send().await.map_err(|err| {
+        warn!(error = %err, cause = err.source(), "failed to fetch fallback profile");
+        Unavailable
+    })?;
+
+    match response.status() {
+        StatusCode::NOT_FOUND | StatusCode::NO_CONTENT | StatusCode::BAD_REQUEST => Err(NotFound),
+        StatusCode::OK => response.json().await.map_err(|err| {
+            error!(error = %err, "failed to parse fallback profile body");
+            Unavailable
+        }),
+        StatusCode::TOO_MANY_REQUESTS => Err(RateLimited { retry_after: None }),
+        code => {
+            let body = response.text().await.unwrap_or(String::new());
+            warn!(
+                status = code.as_str(),
+                body = body,
+                "failed to read fallback profile: invalid status code"
+            );
+            Err(Unavailable)
+        }
+    }
+}
+
+/// The [PlayerDbApi] is a [ProfileProvider] for the [PlayerDB](https://playerdb.co) api.
+struct PlayerDbApi {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct PlayerDbResponse {
+    success: bool,
+    data: Option<PlayerDbData>,
+}
+
+#[derive(Deserialize)]
+struct PlayerDbData {
+    player: PlayerDbPlayer,
+}
+
+#[derive(Deserialize)]
+struct PlayerDbPlayer {
+    id: Uuid,
+    username: String,
+    #[serde(default)]
+    properties: Vec<ProfileProperty>,
+}
+
+impl ProfileProvider for PlayerDbApi {
+    fn fetch_profile<'a>(&'a self, query: &'a str) -> BoxFuture<'a, Result<Profile, ApiError>> {
+        Box::pin(async move {
+            let response: PlayerDbResponse = fetch_json(format!("{}/{}", self.url, query)).await?;
+            let player = match response {
+                PlayerDbResponse {
+                    success: true,
+                    data: Some(data),
+                } => data.player,
+                _ => return Err(NotFound),
+            };
+            Ok(Profile {
+                id: player.id,
+                name: player.username,
+                properties: player.properties.into(),
+                profile_actions: vec![],
+            })
+        })
+    }
+}
+
+/// The [AshconApi] is a [ProfileProvider] for the [Ashcon](https://api.ashcon.app) api.
+struct AshconApi {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct AshconResponse {
+    uuid: Uuid,
+    username: String,
+    textures: Option<AshconTextures>,
+}
+
+#[derive(Deserialize)]
+struct AshconTextures {
+    raw: Option<AshconRawTextures>,
+}
+
+#[derive(Deserialize)]
+struct AshconRawTextures {
+    value: String,
+    signature: Option<String>,
+}
+
+impl ProfileProvider for AshconApi {
+    fn fetch_profile<'a>(&'a self, query: &'a str) -> BoxFuture<'a, Result<Profile, ApiError>> {
+        Box::pin(async move {
+            let response: AshconResponse = fetch_json(format!("{}/{}", self.url, query)).await?;
+            let properties = response
+                .textures
+                .and_then(|textures| textures.raw)
+                .map(|raw| ProfileProperty {
+                    name: "textures".to_string(),
+                    value: raw.value,
+                    signature: raw.signature,
+                })
+                .into_iter()
+                .collect();
+            Ok(Profile {
+                id: response.uuid,
+                name: response.username,
+                properties,
+                profile_actions: vec![],
+            })
+        })
+    }
+}
+
+#[cfg(all(test, feature = "static-testing"))]
+mod test {
+    use super::*;
+    use crate::mojang::testing::{MojangTestingApi, HYDROFIN};
+
+    #[tokio::test]
+    async fn fetch_by_priority() {
+        // given
+        let mut settings = settings::Settings::default().fallback;
+        settings.circuit_breaker.threshold = 1;
+        let mut chain = ProviderChain::new(&settings);
+        let unhealthy = MojangTestingApi::with_profiles();
+        unhealthy.fail_next(1);
+        chain.add_provider("second", 2, Box::new(MojangTestingApi::with_profiles()));
+        chain.add_provider("first", 1, Box::new(unhealthy));
+
+        // when
+        let by_name = chain.fetch_profile("hydrofin", 0).await;
+        let unknown = chain.fetch_profile("unknown", 0).await;
+
+        // then
+        let (provider, profile) = by_name.unwrap();
+        assert_eq!("second", provider);
+        assert_eq!(HYDROFIN.profile, profile);
+        assert!(matches!(unknown, Err(NotFound)));
+        assert_eq!(BreakerState::Open, chain.status(0)[0].breaker);
+    }
+}
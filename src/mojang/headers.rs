@@ -0,0 +1,126 @@
+//! The headers module provides the diagnostics of the mojang response headers. Selected headers (e.g.
+//! the CDN, region and rate limit hints) of the mojang responses are recorded into the tracing span of
+//! the request and the latest headers per endpoint are kept for the debug endpoint. They help with the
+//! escalation if only some regions (or edges) of mojang are throttled.
+
+use reqwest::header::HeaderMap;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The headers of mojang responses that are recorded by default (CDN, region and rate limit hints).
+pub const DEFAULT_HEADERS: [&str; 12] = [
+    "via",
+    "server",
+    "age",
+    "x-cache",
+    "x-served-by",
+    "x-amz-cf-pop",
+    "x-amz-cf-id",
+    "cf-ray",
+    "retry-after",
+    "ratelimit-limit",
+    "ratelimit-remaining",
+    "ratelimit-reset",
+];
+
+/// A [HeaderSnapshot] holds the selected headers of the latest mojang response of an endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HeaderSnapshot {
+    /// The status code of the response.
+    pub status: u16,
+
+    /// The unix timestamp in seconds when the response was received.
+    pub timestamp: u64,
+
+    /// The selected headers (lowercase name) of the response that were present.
+    pub headers: BTreeMap<String, String>,
+}
+
+/// Captures the selected headers (lowercase names) of a mojang response. Missing headers and values
+/// that are not visible ASCII are skipped. Multiple values of a header are joined with `, `.
+pub fn capture(names: &[String], headers: &HeaderMap) -> BTreeMap<String, String> {
+    names
+        .iter()
+        .filter_map(|name| {
+            let values: Vec<&str> = headers
+                .get_all(name.as_str())
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .collect();
+            (!values.is_empty()).then(|| (name.clone(), values.join(", ")))
+        })
+        .collect()
+}
+
+/// [LatestHeaders] holds the latest recorded headers of the mojang responses per endpoint.
+#[derive(Debug, Default)]
+pub struct LatestHeaders(Mutex<BTreeMap<&'static str, HeaderSnapshot>>);
+
+impl LatestHeaders {
+    /// Records the captured headers of a mojang response of an endpoint. The headers are recorded into
+    /// the `mojang_headers` field of the current span (if declared) and kept as the latest headers of
+    /// the endpoint.
+    pub fn record(&self, endpoint: &'static str, status: u16, headers: BTreeMap<String, String>) {
+        tracing::Span::current().record("mojang_headers", tracing::field::debug(&headers));
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let snapshot = HeaderSnapshot {
+            status,
+            timestamp,
+            headers,
+        };
+        self.0.lock().unwrap().insert(endpoint, snapshot);
+    }
+
+    /// Gets the latest recorded headers of the mojang responses per endpoint.
+    pub fn snapshot(&self) -> BTreeMap<&'static str, HeaderSnapshot> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn capture_selected_headers() {
+        // given
+        let mut headers = HeaderMap::new();
+        headers.insert("x-cache", HeaderValue::from_static("Miss from cloudfront"));
+        headers.append("via", HeaderValue::from_static("1.1 edge-a"));
+        headers.append("via", HeaderValue::from_static("1.1 edge-b"));
+        headers.insert("set-cookie", HeaderValue::from_static("secret"));
+        let names = ["via".to_string(), "x-cache".to_string(), "age".to_string()];
+
+        // when
+        let captured = capture(&names, &headers);
+
+        // then
+        assert_eq!(2, captured.len());
+        assert_eq!("1.1 edge-a, 1.1 edge-b", captured["via"]);
+        assert_eq!("Miss from cloudfront", captured["x-cache"]);
+    }
+
+    #[test]
+    fn record_latest_headers() {
+        // given
+        let latest = LatestHeaders::default();
+        let first = BTreeMap::from([("via".to_string(), "1.1 edge-a".to_string())]);
+        let second = BTreeMap::from([("via".to_string(), "1.1 edge-b".to_string())]);
+
+        // when
+        latest.record("profile", 200, first);
+        latest.record("profile", 429, second.clone());
+        let snapshot = latest.snapshot();
+
+        // then
+        assert_eq!(1, snapshot.len());
+        assert_eq!(429, snapshot["profile"].status);
+        assert_eq!(second, snapshot["profile"].headers);
+    }
+}
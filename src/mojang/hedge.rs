@@ -0,0 +1,148 @@
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use std::future::Future;
+use std::time::Duration;
+
+lazy_static! {
+    /// A counter for the hedged mojang requests by the request that won the race.
+    static ref MOJANG_HEDGE_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "xenos_mojang_hedge_total",
+        "The mojang requests by their hedging outcome.",
+        &["request_type", "hedged"]
+    )
+    .unwrap();
+}
+
+/// The [Hedged] outcome of a (potentially) hedged mojang request.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Hedged {
+    /// The request completed within the threshold, no hedge was sent.
+    None,
+
+    /// The request exceeded the threshold, but the hedge was not admitted (e.g. rate limit).
+    Skipped,
+
+    /// The request exceeded the threshold and completed before the hedge.
+    Primary,
+
+    /// The request exceeded the threshold and the hedge completed first.
+    Hedge,
+}
+
+impl Hedged {
+    /// Gets the metrics label of the outcome.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Hedged::None => "none",
+            Hedged::Skipped => "skipped",
+            Hedged::Primary => "primary",
+            Hedged::Hedge => "hedge",
+        }
+    }
+}
+
+/// Checks whether requests to a mojang api endpoint may be hedged. Only idempotent (`GET`) endpoints
+/// are hedged, the bulk uuid endpoint (`POST`) is not.
+pub fn is_hedgeable(endpoint: &str) -> bool {
+    matches!(endpoint, "uuid" | "profile" | "bytes" | "blocked_servers")
+}
+
+/// Awaits a request and, if it does not complete within the threshold, races it against a second
+/// attempt (the hedge). The first response is used and the other request is dropped. The hedge
+/// resolves to `None` if it was not admitted, in which case the request is awaited on its own.
+pub async fn hedge<T, P, H, F>(
+    request_type: &str,
+    threshold: Duration,
+    request: P,
+    hedge: H,
+) -> (T, Hedged)
+where
+    P: Future<Output = T>,
+    H: FnOnce() -> F,
+    F: Future<Output = Option<T>>,
+{
+    tokio::pin!(request);
+    let (result, hedged) = match tokio::time::timeout(threshold, &mut request).await {
+        Ok(result) => (result, Hedged::None),
+        Err(_) => {
+            let hedge = hedge();
+            tokio::pin!(hedge);
+            tokio::select! {
+                result = &mut request => (result, Hedged::Primary),
+                result = &mut hedge => match result {
+                    Some(result) => (result, Hedged::Hedge),
+                    None => (request.await, Hedged::Skipped),
+                },
+            }
+        }
+    };
+    MOJANG_HEDGE_COUNTER
+        .with_label_values(&[request_type, hedged.label()])
+        .inc();
+    (result, hedged)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::future::pending;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn hedge_none() {
+        // given
+        let threshold = Duration::ZERO;
+
+        // when
+        let result = hedge("uuid", threshold, async { 1 }, || async { Some(2) }).await;
+
+        // then
+        assert_eq!(result, (1, Hedged::None));
+    }
+
+    #[tokio::test]
+    async fn hedge_wins() {
+        // given
+        let threshold = Duration::ZERO;
+
+        // when
+        let result = hedge("uuid", threshold, pending(), || async { Some(2) }).await;
+
+        // then
+        assert_eq!(result, (2, Hedged::Hedge));
+    }
+
+    #[tokio::test]
+    async fn hedge_skipped() {
+        // given
+        let threshold = Duration::ZERO;
+        let (tx, rx) = oneshot::channel();
+
+        // when
+        let result = hedge("uuid", threshold, async { rx.await.unwrap() }, || async {
+            tx.send(1).unwrap();
+            None
+        })
+        .await;
+
+        // then
+        assert_eq!(result, (1, Hedged::Skipped));
+    }
+
+    #[tokio::test]
+    async fn hedge_primary_wins() {
+        // given
+        let threshold = Duration::ZERO;
+        let (tx, rx) = oneshot::channel();
+
+        // when
+        let result = hedge("uuid", threshold, async { rx.await.unwrap() }, || async {
+            tx.send(1).unwrap();
+            pending().await
+        })
+        .await;
+
+        // then
+        assert_eq!(result, (1, Hedged::Primary));
+    }
+}
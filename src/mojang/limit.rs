@@ -0,0 +1,207 @@
+use crate::mojang::ApiError;
+use crate::settings;
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, HistogramVec,
+    IntCounterVec, IntGauge, IntGaugeVec,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+lazy_static! {
+    /// A gauge for the number of mojang requests that wait for a concurrency permit.
+    static ref MOJANG_QUEUE_GAUGE: IntGaugeVec = register_int_gauge_vec!(
+        "xenos_mojang_queue_depth",
+        "The number of mojang requests waiting for a concurrency permit.",
+        &["request_type"]
+    )
+    .unwrap();
+
+    /// A histogram for the time in seconds that mojang requests waited for a concurrency permit.
+    static ref MOJANG_QUEUE_HISTOGRAM: HistogramVec = register_histogram_vec!(
+        "xenos_mojang_queue_wait_seconds",
+        "The time mojang requests waited for a concurrency permit in seconds.",
+        &["request_type"],
+        vec![0.001, 0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0]
+    )
+    .unwrap();
+
+    /// A counter for the mojang requests that were rejected because their queue was full.
+    static ref MOJANG_QUEUE_REJECTED_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "xenos_mojang_queue_rejected_total",
+        "The number of mojang requests rejected because their queue was full.",
+        &["request_type"]
+    )
+    .unwrap();
+}
+
+/// A [Limit] is the [Semaphore] of a request type with the number of requests waiting for a permit.
+#[derive(Debug)]
+struct Limit {
+    semaphore: Semaphore,
+    waiting: AtomicUsize,
+}
+
+/// A [Waiting] guard counts a request as waiting for a permit of a [Limit] until it is dropped, so
+/// that cancelled requests leave the queue as well.
+struct Waiting<'a> {
+    limit: &'a Limit,
+    gauge: IntGauge,
+}
+
+impl<'a> Waiting<'a> {
+    /// Creates a new [Waiting] guard for a request that was already counted by the [Limit].
+    fn new(limit: &'a Limit, request_type: &str) -> Self {
+        let gauge = MOJANG_QUEUE_GAUGE.with_label_values(&[request_type]);
+        gauge.inc();
+        Self { limit, gauge }
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.gauge.dec();
+        self.limit.waiting.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// The [ConcurrencyLimits] bound the number of concurrent requests to the mojang api per request
+/// type. Each request type has its own (fair) queue, so that a burst of one request type (e.g. skin
+/// fetches) cannot starve the others (e.g. uuid resolution). The queues are bounded, requests that
+/// find their queue full fail fast instead of piling up during overload.
+///
+/// The request types are `uuid` (the `uuid` and `uuids` endpoints), `profile` and `textures` (the
+/// `bytes` endpoint). Other endpoints (e.g. the blocked servers list) are not bounded.
+#[derive(Debug)]
+pub struct ConcurrencyLimits {
+    uuid: Option<Limit>,
+    profile: Option<Limit>,
+    textures: Option<Limit>,
+    queue_size: usize,
+}
+
+impl ConcurrencyLimits {
+    /// Creates new [ConcurrencyLimits] from its configuration. If disabled, no request is bounded.
+    pub fn new(settings: &settings::UpstreamConcurrency) -> Self {
+        let limit = |permits: usize| {
+            settings.enabled.then(|| Limit {
+                semaphore: Semaphore::new(permits.max(1)),
+                waiting: AtomicUsize::new(0),
+            })
+        };
+        Self {
+            uuid: limit(settings.uuid),
+            profile: limit(settings.profile),
+            textures: limit(settings.textures),
+            queue_size: settings.queue_size,
+        }
+    }
+
+    /// Gets the request type and its [Limit] of a mojang api endpoint, if it is bounded.
+    fn limit(&self, endpoint: &str) -> Option<(&'static str, &Limit)> {
+        let (request_type, limit) = match endpoint {
+            "uuid" | "uuids" => ("uuid", &self.uuid),
+            "profile" => ("profile", &self.profile),
+            "bytes" => ("textures", &self.textures),
+            _ => return None,
+        };
+        limit.as_ref().map(|limit| (request_type, limit))
+    }
+
+    /// Waits for a permit to call a mojang api endpoint. The request may be sent as long as the permit
+    /// is held. It returns `None` if the endpoint is not bounded and fails with [ApiError::Unavailable]
+    /// if the queue of the endpoint is full.
+    pub async fn acquire(&self, endpoint: &str) -> Result<Option<SemaphorePermit<'_>>, ApiError> {
+        let Some((request_type, limit)) = self.limit(endpoint) else {
+            return Ok(None);
+        };
+        if let Ok(permit) = limit.semaphore.try_acquire() {
+            return Ok(Some(permit));
+        }
+        if limit.waiting.fetch_add(1, Ordering::AcqRel) >= self.queue_size {
+            limit.waiting.fetch_sub(1, Ordering::AcqRel);
+            MOJANG_QUEUE_REJECTED_COUNTER
+                .with_label_values(&[request_type])
+                .inc();
+            return Err(ApiError::Unavailable);
+        }
+        let start = Instant::now();
+        let waiting = Waiting::new(limit, request_type);
+        let permit = limit.semaphore.acquire().await;
+        drop(waiting);
+        MOJANG_QUEUE_HISTOGRAM
+            .with_label_values(&[request_type])
+            .observe(start.elapsed().as_secs_f64());
+        // the semaphores are never closed
+        Ok(permit.ok())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn limits() -> ConcurrencyLimits {
+        ConcurrencyLimits::new(&settings::UpstreamConcurrency {
+            enabled: true,
+            uuid: 1,
+            profile: 1,
+            textures: 1,
+            queue_size: 1,
+        })
+    }
+
+    #[tokio::test]
+    async fn acquire_independent() {
+        // given
+        let limits = limits();
+        let _bytes = limits.acquire("bytes").await;
+
+        // when
+        let queued = timeout(Duration::from_millis(10), limits.acquire("bytes")).await;
+        let uuid = timeout(Duration::from_millis(10), limits.acquire("uuid")).await;
+        let unbounded = limits.acquire("blocked_servers").await;
+
+        // then
+        assert!(queued.is_err());
+        assert!(uuid.is_ok_and(|permit| permit.is_ok_and(|permit| permit.is_some())));
+        assert!(matches!(unbounded, Ok(None)));
+    }
+
+    #[tokio::test]
+    async fn acquire_queue_full() {
+        // given
+        let limits = limits();
+        let _profile = limits.acquire("profile").await;
+        let queued = limits.acquire("profile");
+        tokio::pin!(queued);
+        let _ = timeout(Duration::from_millis(10), &mut queued).await;
+
+        // when
+        let rejected = limits.acquire("profile").await;
+
+        // then
+        assert!(matches!(rejected, Err(ApiError::Unavailable)));
+    }
+
+    #[tokio::test]
+    async fn acquire_disabled() {
+        // given
+        let limits = ConcurrencyLimits::new(&settings::UpstreamConcurrency {
+            enabled: false,
+            uuid: 1,
+            profile: 1,
+            textures: 1,
+            queue_size: 1,
+        });
+
+        // when
+        let permit = limits.acquire("profile").await;
+
+        // then
+        assert!(matches!(permit, Ok(None)));
+    }
+}
@@ -1,15 +1,29 @@
 pub mod api;
+pub mod blocked;
+pub mod breaker;
+pub mod fallback;
+pub mod headers;
+pub mod hedge;
+pub mod limit;
+pub mod shedding;
+pub mod status;
 #[cfg(feature = "static-testing")]
 pub mod testing;
 
+use crate::mojang::headers::HeaderSnapshot;
+use crate::render::animation::frame_count;
+use crate::settings;
 use base64::prelude::BASE64_STANDARD;
 use base64::Engine;
 use bytes::Bytes;
-use image::{imageops, ColorType, GenericImageView, ImageError, ImageFormat};
+use image::{imageops, ColorType, GenericImageView, ImageError, ImageFormat, RgbaImage};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::io::Cursor;
 use std::ops::Deref;
+use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 
 /// The model key for the classic skin (e.g. "Steve")
@@ -18,6 +32,15 @@ pub const CLASSIC_MODEL: &str = "classic";
 /// The model key for the slim skin (e.g. "Alex")
 pub const SLIM_MODEL: &str = "slim";
 
+/// The profile action of profiles that use a banned skin.
+pub const USING_BANNED_SKIN: &str = "USING_BANNED_SKIN";
+
+/// The base url of all mojang textures. The textures are identified by their texture id (hash).
+pub const TEXTURES_URL: &str = "http://textures.minecraft.net/texture";
+
+/// The signature (magic bytes) at the start of every png image.
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
 /// The official mojang Steve skin (not approved by mojang).
 /// See https://assets.mojang.com/SkinTemplates/steve.png
 pub const STEVE_SKIN: Bytes =
@@ -31,28 +54,66 @@ pub const ALEX_SKIN: Bytes =
 lazy_static! {
     /// The head bytes of the official mojang Steve skin.
     pub static ref STEVE_HEAD: Bytes = Bytes::from(
-        build_skin_head(&STEVE_SKIN, false).expect("expect Steve head to be build successfully"),
+        build_skin_head(&STEVE_SKIN, false, &settings::HeadOverlay::default()).expect("expect Steve head to be build successfully"),
     );
 
     /// The head bytes of the official mojang Alex skin.
     pub static ref ALEX_HEAD: Bytes = Bytes::from(
-        build_skin_head(&ALEX_SKIN, false).expect("expect Alex head to be build successfully"),
+        build_skin_head(&ALEX_SKIN, false, &settings::HeadOverlay::default()).expect("expect Alex head to be build successfully"),
     );
 }
 
 /// [ApiError] is the error definition for the Mojang api. It maps the inconsistent error responses
 /// from Mojang into a consistent format.
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Debug, Clone)]
 pub enum ApiError {
-    /// The api is currently unavailable (outage/timeout/rate limited) or is out-of-date.
+    /// The api is currently unavailable (outage/timeout) or is out-of-date.
     #[error("unable to request resource from mojang api")]
     Unavailable,
 
+    /// The api rejected the request because of rate limiting. The api might hint when requests are
+    /// accepted again.
+    #[error("mojang api rate limit exceeded")]
+    RateLimited { retry_after: Option<Duration> },
+
     /// The requested resource was not found.
     #[error("resource not found")]
     NotFound,
 }
 
+/// [InvalidTexture] is an error that indicates that fetched texture bytes are not a valid skin or cape
+/// (e.g. a corrupt or absurd payload of a non-mojang texture host).
+#[derive(thiserror::Error, Debug)]
+pub enum InvalidTexture {
+    /// The texture exceeds the maximum size in bytes.
+    #[error("texture size of {size} bytes exceeds {max} bytes")]
+    TooLarge { size: usize, max: usize },
+
+    /// The texture is not a png image.
+    #[error("texture is not a png image")]
+    NotPng,
+
+    /// The texture has dimensions that are used by neither skins nor capes.
+    #[error("texture dimensions {width}x{height} are invalid")]
+    Dimensions { width: u32, height: u32 },
+
+    /// The texture url does not point to an allowed texture host.
+    #[error("texture url {0} does not point to an allowed host")]
+    Host(String),
+}
+
+impl InvalidTexture {
+    /// Gets the reason of the [InvalidTexture] that is used as metric label.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            InvalidTexture::TooLarge { .. } => "too_large",
+            InvalidTexture::NotPng => "not_png",
+            InvalidTexture::Dimensions { .. } => "dimensions",
+            InvalidTexture::Host(_) => "host",
+        }
+    }
+}
+
 /// [TextureError] is an error that occurred while decoding textures from a profile.
 #[derive(thiserror::Error, Debug)]
 pub enum TextureError {
@@ -82,9 +143,10 @@ pub struct Profile {
     pub id: Uuid,
     /// The current visual name of the Minecraft user profile.
     pub name: String,
-    /// The currently assigned properties of the Minecraft user profile.
+    /// The currently assigned properties of the Minecraft user profile. They are shared, as the
+    /// (signed) properties make up most of the profile and profiles are cloned on every cache hit.
     #[serde(default)]
-    pub properties: Vec<ProfileProperty>,
+    pub properties: Arc<[ProfileProperty]>,
     /// The pending imposed moderative actions of the Minecraft user profile.
     #[serde(default)]
     pub profile_actions: Vec<String>,
@@ -150,6 +212,13 @@ pub struct UsernameResolved {
 
 pub struct TextureBytes(Bytes);
 
+impl TextureBytes {
+    /// Consumes the [TextureBytes] and returns the underlying [Bytes] (without copying them).
+    pub fn into_bytes(self) -> Bytes {
+        self.0
+    }
+}
+
 impl Deref for TextureBytes {
     type Target = Bytes;
     fn deref(&self) -> &Self::Target {
@@ -157,6 +226,47 @@ impl Deref for TextureBytes {
     }
 }
 
+impl Texture {
+    /// Gets the texture id (hash) of the [Texture]. It is the last path segment of the texture url
+    /// (e.g. `http://textures.minecraft.net/texture/<id>`).
+    pub fn texture_id(&self) -> &str {
+        self.url.rsplit('/').next().unwrap_or(&self.url)
+    }
+}
+
+impl TexturesProperty {
+    /// Creates a new [TexturesProperty] for a skin with its model and an optional cape. Classic skins
+    /// have no model metadata (as in mojang profiles). The timestamp is in milliseconds.
+    pub fn new(
+        profile_id: Uuid,
+        profile_name: &str,
+        skin_url: String,
+        model: &str,
+        cape_url: Option<String>,
+        timestamp: u64,
+    ) -> Self {
+        let metadata = (model == SLIM_MODEL).then(|| TextureMetadata {
+            model: SLIM_MODEL.to_string(),
+        });
+        TexturesProperty {
+            timestamp,
+            profile_id,
+            profile_name: profile_name.to_string(),
+            signature_required: None,
+            textures: Textures {
+                skin: Some(Texture {
+                    url: skin_url,
+                    metadata,
+                }),
+                cape: cape_url.map(|url| Texture {
+                    url,
+                    metadata: None,
+                }),
+            },
+        }
+    }
+}
+
 impl Profile {
     /// Gets the [texture property](TexturesProperty) of the [profile](Profile). It is expected, that
     /// the property exists on the [profile](Profile) and is valid.
@@ -168,6 +278,13 @@ impl Profile {
             .ok_or(TextureError::NotFound)?;
         decode_texture_prop(prop.value.clone())
     }
+
+    /// Checks whether the [profile](Profile) uses a banned skin (see `profile_actions`).
+    pub fn is_using_banned_skin(&self) -> bool {
+        self.profile_actions
+            .iter()
+            .any(|action| action == USING_BANNED_SKIN)
+    }
 }
 
 /// Decodes a base64 encoded [texture property](TexturesProperty).
@@ -182,6 +299,57 @@ pub fn encode_texture_prop(prop: &TexturesProperty) -> String {
     BASE64_STANDARD.encode(vec)
 }
 
+/// Builds the url of a mojang texture from its texture id (hash).
+pub fn texture_url(texture_id: &str) -> String {
+    format!("{}/{}", TEXTURES_URL, texture_id)
+}
+
+/// The maximum number of frames of an animated cape texture (frame sheet).
+pub const MAX_CAPE_FRAMES: u32 = 32;
+
+/// Validates fetched texture bytes before they are cached. The texture has to be a png image of at most
+/// `max_bytes` with the dimensions of a skin (`64x64`, legacy `64x32`), a cape (`64x32`, legacy `22x17`)
+/// or an animated cape (`64x32` frames stacked vertically, up to [MAX_CAPE_FRAMES]). The dimensions are
+/// read from the png header, so the image is not decoded.
+pub fn validate_texture(bytes: &[u8], max_bytes: usize) -> Result<(), InvalidTexture> {
+    if bytes.len() > max_bytes {
+        return Err(InvalidTexture::TooLarge {
+            size: bytes.len(),
+            max: max_bytes,
+        });
+    }
+
+    // the png signature is followed by the IHDR chunk (length, type, width, height)
+    if bytes.len() < 24 || !bytes.starts_with(PNG_SIGNATURE) || &bytes[12..16] != b"IHDR" {
+        return Err(InvalidTexture::NotPng);
+    }
+    let width = u32::from_be_bytes(bytes[16..20].try_into().unwrap());
+    let height = u32::from_be_bytes(bytes[20..24].try_into().unwrap());
+    match (width, height) {
+        (64, 64) | (64, 32) | (22, 17) => Ok(()),
+        (64, height) if (2..=MAX_CAPE_FRAMES).contains(&frame_count(width, height)) => Ok(()),
+        _ => Err(InvalidTexture::Dimensions { width, height }),
+    }
+}
+
+/// Validates a texture url (from profile properties) before the texture is fetched. The url has to use
+/// http(s) and point to one of the allowed hosts (case-insensitive), so that forged profiles of other
+/// (yggdrasil) servers cannot make Xenos request arbitrary urls.
+pub fn validate_texture_url(url: &str, allowed_hosts: &[String]) -> Result<(), InvalidTexture> {
+    let allowed = reqwest::Url::parse(url).is_ok_and(|url| {
+        matches!(url.scheme(), "http" | "https")
+            && url.host_str().is_some_and(|host| {
+                allowed_hosts
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(host))
+            })
+    });
+    match allowed {
+        true => Ok(()),
+        false => Err(InvalidTexture::Host(url.to_string())),
+    }
+}
+
 /// Calculates the java hashcode of a [Uuid].
 /// See https://hg.openjdk.org/jdk8/jdk8/jdk/file/687fd7c7986d/src/share/classes/java/util/UUID.java#l394
 pub fn uuid_java_hashcode(uuid: &Uuid) -> i32 {
@@ -196,15 +364,23 @@ pub fn is_steve(uuid: &Uuid) -> bool {
     uuid_java_hashcode(uuid) % 2 == 0
 }
 
-/// Builds the head image bytes from a skin. Expects a valid skin.
+/// Builds the head image bytes from a skin. The overlay (hat) layer is composed according to the
+/// [HeadOverlay](settings::HeadOverlay) configuration. Expects a valid skin.
 #[tracing::instrument(skip(skin_bytes))]
-pub fn build_skin_head(skin_bytes: &[u8], overlay: bool) -> Result<Vec<u8>, ImageError> {
+pub fn build_skin_head(
+    skin_bytes: &[u8],
+    overlay: bool,
+    blending: &settings::HeadOverlay,
+) -> Result<Vec<u8>, ImageError> {
     let skin_img = image::load_from_memory_with_format(skin_bytes, ImageFormat::Png)?;
     let mut head_img = skin_img.view(8, 8, 8, 8).to_image();
 
     if overlay {
-        let overlay_head_img = skin_img.view(40, 8, 8, 8).to_image();
-        imageops::overlay(&mut head_img, &overlay_head_img, 0, 0);
+        let mut overlay_head_img = skin_img.view(40, 8, 8, 8).to_image();
+        if !(blending.black_transparent && is_black(&overlay_head_img)) {
+            apply_alpha_threshold(&mut overlay_head_img, blending.alpha_threshold);
+            imageops::overlay(&mut head_img, &overlay_head_img, 0, 0);
+        }
     }
 
     let mut head_bytes: Vec<u8> = Vec::new();
@@ -220,10 +396,188 @@ pub fn build_skin_head(skin_bytes: &[u8], overlay: bool) -> Result<Vec<u8>, Imag
     Ok(head_bytes)
 }
 
+/// Checks whether all (visible) pixels of an image are black.
+fn is_black(img: &RgbaImage) -> bool {
+    img.pixels()
+        .all(|pixel| pixel[3] == 0 || pixel.0[..3] == [0, 0, 0])
+}
+
+/// Drops all pixels with an alpha below the threshold and makes all other pixels opaque. The image is
+/// unchanged if the threshold is `0`.
+fn apply_alpha_threshold(img: &mut RgbaImage, threshold: u8) {
+    if threshold == 0 {
+        return;
+    }
+    for pixel in img.pixels_mut() {
+        pixel[3] = match pixel[3] < threshold {
+            true => 0,
+            false => u8::MAX,
+        };
+    }
+}
+
+/// Renders the head image bytes (8x8 PNG) with an integer scale (nearest neighbor). The head is either
+/// encoded as PNG or as raw RGBA pixels (row by row). Expects a valid head.
+#[tracing::instrument(skip(head_bytes))]
+pub fn render_head(head_bytes: &[u8], scale: u32, rgba: bool) -> Result<Vec<u8>, ImageError> {
+    let head_img = image::load_from_memory_with_format(head_bytes, ImageFormat::Png)?;
+    let size = head_img.width() * scale;
+    let head_img = head_img
+        .resize_exact(size, size, imageops::FilterType::Nearest)
+        .to_rgba8();
+
+    if rgba {
+        return Ok(head_img.into_raw());
+    }
+    let mut scaled_bytes: Vec<u8> = Vec::new();
+    let mut cur = Cursor::new(&mut scaled_bytes);
+    image::write_buffer_with_format(
+        &mut cur,
+        &head_img,
+        size,
+        size,
+        ColorType::Rgba8,
+        ImageFormat::Png,
+    )?;
+    Ok(scaled_bytes)
+}
+
 #[trait_variant::make(Mojang: Send)]
 pub trait LocalMojang {
     async fn fetch_uuid(&self, username: &str) -> Result<UsernameResolved, ApiError>;
     async fn fetch_uuids(&self, usernames: &[String]) -> Result<Vec<UsernameResolved>, ApiError>;
     async fn fetch_profile(&self, uuid: &Uuid, signed: bool) -> Result<Profile, ApiError>;
     async fn fetch_bytes(&self, url: String) -> Result<TextureBytes, ApiError>;
+    async fn fetch_blocked_servers(&self) -> Result<Vec<String>, ApiError>;
+
+    /// Takes the remaining rate budget of an endpoint that mojang reported in its latest response (rate
+    /// limit headers), if any.
+    fn take_reported_remaining(&self, _endpoint: &str) -> Option<u64> {
+        None
+    }
+
+    /// Gets the latest recorded diagnostic headers of the mojang responses per endpoint (see [headers]).
+    fn latest_headers(&self) -> BTreeMap<&'static str, HeaderSnapshot> {
+        BTreeMap::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use image::Rgba;
+
+    fn new_skin(overlay: Rgba<u8>) -> Vec<u8> {
+        let mut skin_img = RgbaImage::new(64, 64);
+        for (x, y) in (0..8).flat_map(|x| (0..8).map(move |y| (x, y))) {
+            skin_img.put_pixel(8 + x, 8 + y, Rgba([255, 0, 0, 255]));
+            skin_img.put_pixel(40 + x, 8 + y, overlay);
+        }
+        let mut skin_bytes: Vec<u8> = Vec::new();
+        skin_img
+            .write_to(&mut Cursor::new(&mut skin_bytes), ImageFormat::Png)
+            .unwrap();
+        skin_bytes
+    }
+
+    fn new_texture(width: u32, height: u32) -> Vec<u8> {
+        let mut texture_bytes: Vec<u8> = Vec::new();
+        RgbaImage::new(width, height)
+            .write_to(&mut Cursor::new(&mut texture_bytes), ImageFormat::Png)
+            .unwrap();
+        texture_bytes
+    }
+
+    fn head_pixel(head_bytes: &[u8]) -> Rgba<u8> {
+        let head_img = image::load_from_memory_with_format(head_bytes, ImageFormat::Png).unwrap();
+        head_img.to_rgba8().get_pixel(0, 0).to_owned()
+    }
+
+    #[test]
+    fn build_skin_head_blending() {
+        // given
+        let black = new_skin(Rgba([0, 0, 0, 255]));
+        let translucent = new_skin(Rgba([0, 255, 0, 100]));
+        let legacy = settings::HeadOverlay {
+            default: false,
+            black_transparent: true,
+            alpha_threshold: 0,
+        };
+        let threshold = |alpha_threshold| settings::HeadOverlay {
+            default: false,
+            black_transparent: false,
+            alpha_threshold,
+        };
+
+        // when
+        let black_default = build_skin_head(&black, true, &Default::default()).unwrap();
+        let black_legacy = build_skin_head(&black, true, &legacy).unwrap();
+        let dropped = build_skin_head(&translucent, true, &threshold(128)).unwrap();
+        let opaque = build_skin_head(&translucent, true, &threshold(50)).unwrap();
+
+        // then
+        assert_eq!(Rgba([0, 0, 0, 255]), head_pixel(&black_default));
+        assert_eq!(Rgba([255, 0, 0, 255]), head_pixel(&black_legacy));
+        assert_eq!(Rgba([255, 0, 0, 255]), head_pixel(&dropped));
+        assert_eq!(Rgba([0, 255, 0, 255]), head_pixel(&opaque));
+    }
+
+    #[test]
+    fn validate_texture_bytes() {
+        // given
+        let skin = new_skin(Rgba([0, 0, 0, 255]));
+        let mut oversized = Vec::new();
+        RgbaImage::new(128, 128)
+            .write_to(&mut Cursor::new(&mut oversized), ImageFormat::Png)
+            .unwrap();
+
+        // when
+        let valid = validate_texture(&skin, 16384);
+        let template = validate_texture(&ALEX_SKIN, 16384);
+        let animated = validate_texture(&new_texture(64, 32 * 4), 16384);
+        let too_many_frames = validate_texture(&new_texture(64, 32 * 33), 262144);
+        let too_large = validate_texture(&skin, 16);
+        let not_png = validate_texture(b"<html>not found</html>", 16384);
+        let dimensions = validate_texture(&oversized, 16384);
+
+        // then
+        assert!(valid.is_ok());
+        assert!(template.is_ok());
+        assert!(animated.is_ok());
+        assert!(matches!(
+            too_many_frames,
+            Err(InvalidTexture::Dimensions { height: 1056, .. })
+        ));
+        assert!(matches!(too_large, Err(InvalidTexture::TooLarge { .. })));
+        assert!(matches!(not_png, Err(InvalidTexture::NotPng)));
+        assert!(matches!(
+            dimensions,
+            Err(InvalidTexture::Dimensions {
+                width: 128,
+                height: 128
+            })
+        ));
+    }
+
+    #[test]
+    fn validate_texture_url_hosts() {
+        // given
+        let allowed_hosts = vec!["textures.minecraft.net".to_string()];
+
+        // when
+        let mojang = validate_texture_url(&texture_url("abc"), &allowed_hosts);
+        let upper =
+            validate_texture_url("https://TEXTURES.minecraft.net/texture/abc", &allowed_hosts);
+        let forged = validate_texture_url("http://169.254.169.254/latest", &allowed_hosts);
+        let suffix =
+            validate_texture_url("http://textures.minecraft.net.evil.com/a", &allowed_hosts);
+        let scheme = validate_texture_url("file://textures.minecraft.net/etc", &allowed_hosts);
+
+        // then
+        assert!(mojang.is_ok());
+        assert!(upper.is_ok());
+        assert!(matches!(forged, Err(InvalidTexture::Host(_))));
+        assert!(matches!(suffix, Err(InvalidTexture::Host(_))));
+        assert!(matches!(scheme, Err(InvalidTexture::Host(_))));
+    }
 }
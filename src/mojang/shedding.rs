@@ -0,0 +1,141 @@
+use crate::mojang::status::EndpointStats;
+use crate::settings;
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter_vec, register_int_gauge, IntCounterVec, IntGauge};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{info, warn};
+
+lazy_static! {
+    /// A gauge for whether optional traffic is currently shed (`1`) or not (`0`).
+    static ref SHEDDING_GAUGE: IntGauge = register_int_gauge!(
+        "xenos_shedding_active",
+        "Whether optional mojang traffic is currently shed."
+    )
+    .unwrap();
+
+    /// A counter for the shed optional requests by their kind (e.g. prefetch or bulk).
+    static ref SHED_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "xenos_shed_requests_total",
+        "The number of shed optional mojang requests.",
+        &["kind"]
+    )
+    .unwrap();
+}
+
+/// Records that an optional request of a kind (`prefetch`, `refresh`, `hedge` or `bulk`) was shed.
+pub fn record_shed(kind: &str) {
+    SHED_COUNTER.with_label_values(&[kind]).inc();
+}
+
+/// The [SheddingPolicy] decides whether optional traffic (prefetches, background refreshes, hedges and
+/// the items of bulk requests beyond the configured limit) is shed to protect the interactive single
+/// lookups. Optional traffic is shed while the remaining rate budget falls below the configured
+/// threshold or the recent error rate of mojang exceeds the configured threshold.
+///
+/// ```rs
+/// let shedding = SheddingPolicy::new(&settings.shedding);
+/// if shedding.evaluate(upstream.remaining_budget(now), &upstream.endpoints(now)) {
+///     return;
+/// }
+/// ```
+#[derive(Debug)]
+pub struct SheddingPolicy {
+    enabled: bool,
+    budget_threshold: u64,
+    error_rate_threshold: f64,
+    min_requests: u64,
+    active: AtomicBool,
+}
+
+impl SheddingPolicy {
+    /// Creates a new (inactive) [SheddingPolicy] from its configuration.
+    pub fn new(settings: &settings::Shedding) -> Self {
+        Self {
+            enabled: settings.enabled,
+            budget_threshold: settings.budget_threshold,
+            error_rate_threshold: settings.error_rate_threshold,
+            min_requests: settings.min_requests,
+            active: AtomicBool::new(false),
+        }
+    }
+
+    /// Evaluates whether optional traffic should be shed with the remaining rate budget and the recent
+    /// request statistics per endpoint. The error rate is only considered once there were enough
+    /// requests. Changes of the state are logged.
+    pub fn evaluate(
+        &self,
+        remaining: u64,
+        endpoints: &BTreeMap<&'static str, EndpointStats>,
+    ) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let (requests, failures) = endpoints
+            .values()
+            .fold((0, 0), |(requests, failures), stats| {
+                (requests + stats.requests, failures + stats.failures)
+            });
+        let error_rate = match requests {
+            0 => 0.0,
+            requests => failures as f64 / requests as f64,
+        };
+        let active = remaining < self.budget_threshold
+            || (requests >= self.min_requests && error_rate > self.error_rate_threshold);
+        if self.active.swap(active, Ordering::SeqCst) != active {
+            match active {
+                true => warn!(remaining, error_rate, "started shedding optional traffic"),
+                false => info!(remaining, error_rate, "stopped shedding optional traffic"),
+            }
+            SHEDDING_GAUGE.set(active as i64);
+        }
+        active
+    }
+
+    /// Checks whether optional traffic was shed at the latest evaluation.
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn policy() -> SheddingPolicy {
+        SheddingPolicy::new(&settings::Shedding {
+            enabled: true,
+            budget_threshold: 100,
+            error_rate_threshold: 0.5,
+            min_requests: 4,
+            bulk_limit: 10,
+        })
+    }
+
+    fn endpoints(requests: u64, failures: u64) -> BTreeMap<&'static str, EndpointStats> {
+        BTreeMap::from([("profile", EndpointStats { requests, failures })])
+    }
+
+    #[test]
+    fn evaluate_thresholds() {
+        // given
+        let policy = policy();
+
+        // when
+        let healthy = policy.evaluate(500, &endpoints(10, 1));
+        let few_requests = policy.evaluate(500, &endpoints(2, 2));
+        let failing = policy.evaluate(500, &endpoints(10, 6));
+        let active = policy.is_active();
+        let low_budget = policy.evaluate(50, &endpoints(0, 0));
+        let recovered = policy.evaluate(500, &endpoints(10, 0));
+
+        // then
+        assert!(!healthy);
+        assert!(!few_requests);
+        assert!(failing);
+        assert!(active);
+        assert!(low_budget);
+        assert!(!recovered);
+        assert!(!policy.is_active());
+    }
+}
@@ -0,0 +1,326 @@
+use crate::mojang::breaker::BreakerState;
+use crate::mojang::fallback::ProviderStatus;
+use crate::settings;
+use lazy_static::lazy_static;
+use prometheus::{register_int_gauge_vec, IntGaugeVec};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+lazy_static! {
+    /// A gauge for the estimated remaining rate budget of the current window per endpoint. The budget
+    /// of all endpoints is reported as `all`.
+    static ref RATE_REMAINING_GAUGE: IntGaugeVec = register_int_gauge_vec!(
+        "xenos_mojang_rate_remaining",
+        "The estimated remaining mojang rate budget of the current window.",
+        &["endpoint"]
+    )
+    .unwrap();
+}
+
+/// The [EndpointStats] are the request statistics of a single mojang api endpoint.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct EndpointStats {
+    /// The number of requests.
+    pub requests: u64,
+
+    /// The number of failed requests (unavailable or rate limited).
+    pub failures: u64,
+}
+
+impl EndpointStats {
+    /// Gets the ratio of failed requests. It is zero if there were no requests.
+    pub fn error_rate(&self) -> f64 {
+        match self.requests {
+            0 => 0.0,
+            requests => self.failures as f64 / requests as f64,
+        }
+    }
+}
+
+/// The [MojangStatus] is the current view of the [Service](crate::service::Service) on the health of
+/// the mojang api.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MojangStatus {
+    /// The state of the mojang api circuit breaker.
+    pub breaker: BreakerState,
+
+    /// Whether the service is in cache-only mode.
+    pub cache_only: bool,
+
+    /// The recent request statistics per endpoint.
+    pub endpoints: BTreeMap<&'static str, EndpointStats>,
+
+    /// The (estimated) rate limit per window.
+    pub rate_limit: u64,
+
+    /// The estimated remaining rate budget of the current window.
+    pub rate_remaining: u64,
+
+    /// The unix timestamp in seconds before which mojang asked not to retry requests (if any).
+    pub retry_at: Option<u64>,
+
+    /// The health of the fallback providers (in order of their priority).
+    pub fallback: Vec<ProviderStatus>,
+
+    /// Whether optional traffic is shed (see [SheddingPolicy](crate::mojang::shedding::SheddingPolicy)).
+    pub shedding: bool,
+}
+
+/// The [Window] holds the request statistics of all endpoints within a fixed time window.
+#[derive(Debug, Default)]
+struct Window {
+    start: u64,
+    endpoints: BTreeMap<&'static str, EndpointStats>,
+    reported: BTreeMap<&'static str, u64>,
+    exhausted: bool,
+}
+
+impl Window {
+    /// Gets the total number of requests of all endpoints within the window.
+    fn requests(&self) -> u64 {
+        self.endpoints.values().map(|stats| stats.requests).sum()
+    }
+}
+
+/// The [UpstreamStats] track the recent requests to the mojang api per endpoint (e.g. `profile`) in
+/// fixed time windows. The statistics cover the current and the previous window, so that they never
+/// start empty after a window change. All times are unix timestamps in seconds.
+///
+/// The rate budget is the configured rate limit of mojang minus the requests of the current window.
+/// Mojang does not report the remaining budget, so it is only an estimation of this instance. The
+/// budget of the current window is exhausted once mojang rate limits a request. If mojang reports the
+/// remaining budget of an endpoint (rate limit headers), the budget of the endpoint is capped by it.
+#[derive(Debug)]
+pub struct UpstreamStats {
+    window: u64,
+    rate_limit: u64,
+    reserve: u64,
+    windows: Mutex<(Window, Window)>,
+}
+
+impl UpstreamStats {
+    /// Creates new (empty) [UpstreamStats] from its configuration.
+    pub fn new(settings: &settings::UpstreamStats) -> Self {
+        Self {
+            window: settings.window.as_secs().max(1),
+            rate_limit: settings.rate_limit,
+            reserve: settings.reserve,
+            windows: Mutex::new((Window::default(), Window::default())),
+        }
+    }
+
+    /// Rotates the windows if the current window elapsed at the unix timestamp in seconds.
+    fn rotate(&self, windows: &mut (Window, Window), now: u64) {
+        let start = now - now % self.window;
+        if windows.0.start == start {
+            return;
+        }
+        let previous = std::mem::take(&mut windows.0);
+        windows.1 = match previous.start + self.window == start {
+            true => previous,
+            false => Window::default(),
+        };
+        windows.0.start = start;
+    }
+
+    /// Records a request to an endpoint at the unix timestamp in seconds.
+    pub fn record(&self, endpoint: &'static str, failed: bool, now: u64) {
+        let mut windows = self.windows.lock().unwrap();
+        self.rotate(&mut windows, now);
+        let stats = windows.0.endpoints.entry(endpoint).or_default();
+        stats.requests += 1;
+        if failed {
+            stats.failures += 1;
+        }
+        self.update_gauges(&windows.0);
+    }
+
+    /// Reports the remaining rate budget of an endpoint that mojang reported (rate limit headers) at
+    /// the unix timestamp in seconds. It caps the budget of the endpoint within the current window.
+    pub fn report_remaining(&self, endpoint: &'static str, remaining: u64, now: u64) {
+        let mut windows = self.windows.lock().unwrap();
+        self.rotate(&mut windows, now);
+        windows.0.reported.insert(endpoint, remaining);
+        self.update_gauges(&windows.0);
+    }
+
+    /// Updates the remaining rate budget gauges of all endpoints of the current window.
+    fn update_gauges(&self, current: &Window) {
+        let remaining = self.remaining(current);
+        RATE_REMAINING_GAUGE
+            .with_label_values(&["all"])
+            .set(remaining as i64);
+        for endpoint in current.endpoints.keys() {
+            RATE_REMAINING_GAUGE
+                .with_label_values(&[endpoint])
+                .set(self.endpoint_remaining(current, endpoint) as i64);
+        }
+    }
+
+    /// Gets the estimated remaining rate budget of a window.
+    fn remaining(&self, window: &Window) -> u64 {
+        if window.exhausted {
+            return 0;
+        }
+        self.rate_limit.saturating_sub(window.requests())
+    }
+
+    /// Gets the estimated remaining rate budget of an endpoint within a window.
+    fn endpoint_remaining(&self, window: &Window, endpoint: &str) -> u64 {
+        let remaining = self.remaining(window);
+        match window.reported.get(endpoint) {
+            Some(reported) => remaining.min(*reported),
+            None => remaining,
+        }
+    }
+
+    /// Marks the rate budget of the current window as exhausted at the unix timestamp in seconds (e.g.
+    /// after mojang rate limited a request).
+    pub fn exhaust(&self, now: u64) {
+        let mut windows = self.windows.lock().unwrap();
+        self.rotate(&mut windows, now);
+        windows.0.exhausted = true;
+        self.update_gauges(&windows.0);
+    }
+
+    /// Gets the [EndpointStats] of the current and previous window per endpoint at the unix timestamp
+    /// in seconds.
+    pub fn endpoints(&self, now: u64) -> BTreeMap<&'static str, EndpointStats> {
+        let mut windows = self.windows.lock().unwrap();
+        self.rotate(&mut windows, now);
+        let mut endpoints = windows.1.endpoints.clone();
+        for (endpoint, current) in &windows.0.endpoints {
+            let stats = endpoints.entry(endpoint).or_default();
+            stats.requests += current.requests;
+            stats.failures += current.failures;
+        }
+        endpoints
+    }
+
+    /// Gets the configured rate limit per window.
+    pub fn rate_limit(&self) -> u64 {
+        self.rate_limit
+    }
+
+    /// Gets the estimated remaining rate budget of the current window at the unix timestamp in seconds.
+    pub fn remaining_budget(&self, now: u64) -> u64 {
+        let mut windows = self.windows.lock().unwrap();
+        self.rotate(&mut windows, now);
+        self.remaining(&windows.0)
+    }
+
+    /// Gets the estimated remaining rate budget of an endpoint of the current window at the unix
+    /// timestamp in seconds.
+    pub fn endpoint_budget(&self, endpoint: &str, now: u64) -> u64 {
+        let mut windows = self.windows.lock().unwrap();
+        self.rotate(&mut windows, now);
+        self.endpoint_remaining(&windows.0, endpoint)
+    }
+
+    /// Checks whether optional requests (e.g. prefetches) to an endpoint may be sent at the unix
+    /// timestamp in seconds. They are only sent while the remaining budget exceeds the reserve.
+    pub fn allows_optional(&self, endpoint: &str, now: u64) -> bool {
+        self.endpoint_budget(endpoint, now) > self.reserve
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    fn stats() -> UpstreamStats {
+        UpstreamStats::new(&settings::UpstreamStats {
+            window: Duration::from_secs(60),
+            rate_limit: 10,
+            reserve: 2,
+        })
+    }
+
+    #[test]
+    fn endpoints_error_rate() {
+        // given
+        let stats = stats();
+        stats.record("profile", false, 100);
+        stats.record("profile", true, 130);
+        stats.record("uuid", false, 130);
+
+        // when
+        let endpoints = stats.endpoints(130);
+
+        // then
+        assert_eq!(0.5, endpoints["profile"].error_rate());
+        assert_eq!(0.0, endpoints["uuid"].error_rate());
+    }
+
+    #[test]
+    fn remaining_budget_resets() {
+        // given
+        let stats = stats();
+        stats.record("profile", false, 100);
+        stats.record("profile", false, 110);
+
+        // when
+        let current = stats.remaining_budget(110);
+        let next = stats.remaining_budget(150);
+        let endpoints = stats.endpoints(150);
+
+        // then
+        assert_eq!(8, current);
+        assert_eq!(10, next);
+        assert_eq!(2, endpoints["profile"].requests);
+    }
+
+    #[test]
+    fn endpoint_budget_reported() {
+        // given
+        let stats = stats();
+        stats.report_remaining("reported", 1, 100);
+        stats.record("reported", false, 100);
+        stats.record("profile", false, 100);
+
+        // when
+        let reported = stats.endpoint_budget("reported", 100);
+        let profile = stats.endpoint_budget("profile", 100);
+
+        // then
+        assert_eq!(1, reported);
+        assert_eq!(8, profile);
+        assert!(!stats.allows_optional("reported", 100));
+        assert!(stats.allows_optional("profile", 100));
+    }
+
+    #[test]
+    fn endpoint_budget_reported_per_stats() {
+        // given
+        let other = stats();
+        let stats = stats();
+        stats.report_remaining("profile", 1, 100);
+
+        // when
+        let reported = stats.endpoint_budget("profile", 100);
+        let unreported = other.endpoint_budget("profile", 100);
+        let next = stats.endpoint_budget("profile", 150);
+
+        // then
+        assert_eq!(1, reported);
+        assert_eq!(10, unreported);
+        assert_eq!(10, next);
+    }
+
+    #[test]
+    fn exhausted_until_next_window() {
+        // given
+        let stats = stats();
+        stats.record("profile", true, 100);
+
+        // when
+        stats.exhaust(100);
+        let current = stats.remaining_budget(110);
+        let next = stats.remaining_budget(150);
+
+        // then
+        assert_eq!(0, current);
+        assert_eq!(10, next);
+    }
+}
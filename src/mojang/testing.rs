@@ -1,11 +1,16 @@
-use crate::mojang::ApiError::NotFound;
+use crate::mojang::blocked::hash_pattern;
+use crate::mojang::fallback::ProfileProvider;
+use crate::mojang::ApiError::{NotFound, RateLimited, Unavailable};
 use crate::mojang::{
-    encode_texture_prop, ApiError, Mojang, Profile, ProfileProperty, Texture, TextureBytes,
-    Textures, TexturesProperty, UsernameResolved,
+    encode_texture_prop, texture_url, ApiError, Mojang, Profile, ProfileProperty, Texture,
+    TextureBytes, Textures, TexturesProperty, UsernameResolved,
 };
 use bytes::Bytes;
+use futures::future::BoxFuture;
 use lazy_static::lazy_static;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
 use uuid::{uuid, Uuid};
 
 lazy_static! {
@@ -44,7 +49,8 @@ pub struct TestingProfile {
 }
 
 impl TestingProfile {
-    /// Creates a new valid [TestingProfile] with minimal information.
+    /// Creates a new valid [TestingProfile] with minimal information. The texture ids are derived
+    /// from the profile id (skin) and its complement (cape).
     pub fn new(id: Uuid, name: &str, skin: Option<Bytes>, cape: Option<Bytes>) -> Self {
         let textures = TexturesProperty {
             timestamp: 0,
@@ -53,11 +59,11 @@ impl TestingProfile {
             signature_required: None,
             textures: Textures {
                 skin: skin.is_some().then(|| Texture {
-                    url: format!("skin_{}", id.hyphenated()),
+                    url: texture_url(&id.simple().to_string()),
                     metadata: None,
                 }),
                 cape: cape.is_some().then(|| Texture {
-                    url: format!("cape_{}", id.hyphenated()),
+                    url: texture_url(&format!("{:032x}", !id.as_u128())),
                     metadata: None,
                 }),
             },
@@ -70,7 +76,8 @@ impl TestingProfile {
                     name: "textures".to_string(),
                     value: encode_texture_prop(&textures),
                     signature: None,
-                }],
+                }]
+                .into(),
                 profile_actions: vec![],
             },
             skin,
@@ -82,11 +89,28 @@ impl TestingProfile {
 /// The [MojangTestingApi] is a [mojang api](Mojang) implementation that uses predefined static data
 /// instead of actually accessing the mojang api. It is primarily used for in- and external **integration
 /// testing**. As such, **it should not be used in production**.
-#[derive(Default, Debug)]
+///
+/// Failures and latency can be injected to exercise the fallback code paths of the
+/// [Service](crate::service::Service):
+/// - [MojangTestingApi::fail_next] fails the next requests with [Unavailable].
+/// - [MojangTestingApi::set_rate_limit] fails all requests after a number of requests with [Unavailable].
+/// - [MojangTestingApi::set_latency] delays all requests by a random (uniform) latency.
+/// - [MojangTestingApi::set_reported_remaining] reports a remaining rate budget (rate limit headers).
+#[derive(Debug)]
 pub struct MojangTestingApi<'a> {
     uuids: HashMap<String, UsernameResolved>,
     profiles: HashMap<Uuid, Profile>,
     images: HashMap<String, &'a Bytes>,
+    blocked_servers: Vec<String>,
+    // failure and latency injection
+    requests: AtomicUsize,
+    failures: AtomicUsize,
+    rate_limit: AtomicUsize,
+    retry_after: AtomicU64,
+    reported_remaining: AtomicU64,
+    latency_min: AtomicU64,
+    latency_max: AtomicU64,
+    seed: AtomicU64,
 }
 
 impl<'a> MojangTestingApi<'a> {
@@ -96,6 +120,15 @@ impl<'a> MojangTestingApi<'a> {
             uuids: Default::default(),
             profiles: Default::default(),
             images: Default::default(),
+            blocked_servers: Default::default(),
+            requests: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+            rate_limit: AtomicUsize::new(usize::MAX),
+            retry_after: AtomicU64::new(0),
+            reported_remaining: AtomicU64::new(u64::MAX),
+            latency_min: AtomicU64::new(0),
+            latency_max: AtomicU64::new(0),
+            seed: AtomicU64::new(0x2545f4914f6cdd1d),
         }
     }
 
@@ -133,10 +166,114 @@ impl<'a> MojangTestingApi<'a> {
         }
         self
     }
+
+    /// Adds a hostname pattern (e.g. `*.example.com`) to the blocked servers list of the
+    /// [api](MojangTestingApi).
+    pub fn add_blocked_server(mut self, pattern: &str) -> Self {
+        self.blocked_servers.push(hash_pattern(pattern));
+        self
+    }
+
+    /// Fails the next `count` requests with [Unavailable] (e.g. mojang outage).
+    pub fn fail_next(&self, count: usize) {
+        self.failures.store(count, Ordering::SeqCst);
+    }
+
+    /// Fails all requests after `limit` requests with [Unavailable] (e.g. mojang rate limit). The
+    /// requests are counted since creation or the last [MojangTestingApi::reset_requests].
+    pub fn set_rate_limit(&self, limit: Option<usize>) {
+        self.rate_limit
+            .store(limit.unwrap_or(usize::MAX), Ordering::SeqCst);
+    }
+
+    /// Sets the retry hint (`Retry-After`) of rate limited requests.
+    pub fn set_retry_after(&self, retry_after: Option<Duration>) {
+        self.retry_after.store(
+            retry_after
+                .map(|retry_after| retry_after.as_secs())
+                .unwrap_or(0),
+            Ordering::SeqCst,
+        );
+    }
+
+    /// Reports the remaining rate budget (rate limit headers) with the next request of any endpoint.
+    pub fn set_reported_remaining(&self, remaining: Option<u64>) {
+        self.reported_remaining
+            .store(remaining.unwrap_or(u64::MAX), Ordering::SeqCst);
+    }
+
+    /// Delays all requests by a random (uniform) latency between `min` and `max`. The latency is
+    /// pseudo-random with a fixed seed, so that test runs are reproducible.
+    pub fn set_latency(&self, min: Duration, max: Duration) {
+        self.latency_min
+            .store(min.as_millis() as u64, Ordering::SeqCst);
+        self.latency_max
+            .store(max.max(min).as_millis() as u64, Ordering::SeqCst);
+    }
+
+    /// Gets the number of requests since creation or the last [MojangTestingApi::reset_requests].
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+
+    /// Resets the number of requests (e.g. to end a simulated rate limit).
+    pub fn reset_requests(&self) {
+        self.requests.store(0, Ordering::SeqCst);
+    }
+
+    /// Simulates a request with the configured latency and failures.
+    async fn simulate(&self) -> Result<(), ApiError> {
+        let requests = self.requests.fetch_add(1, Ordering::SeqCst) + 1;
+
+        // delay request with uniform latency (using xorshift as pseudo-random generator)
+        let min = self.latency_min.load(Ordering::SeqCst);
+        let max = self.latency_max.load(Ordering::SeqCst);
+        if max > 0 {
+            let mut x = self.seed.load(Ordering::SeqCst);
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            self.seed.store(x, Ordering::SeqCst);
+            let latency = min + x % (max - min + 1);
+            tokio::time::sleep(Duration::from_millis(latency)).await;
+        }
+
+        // fail request if failures are left
+        let failed = self
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+        if failed.is_ok() {
+            return Err(Unavailable);
+        }
+
+        // fail request if rate limited
+        if requests > self.rate_limit.load(Ordering::SeqCst) {
+            let retry_after = match self.retry_after.load(Ordering::SeqCst) {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            };
+            return Err(RateLimited { retry_after });
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Default for MojangTestingApi<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'a> Mojang for MojangTestingApi<'a> {
+    fn take_reported_remaining(&self, _endpoint: &str) -> Option<u64> {
+        match self.reported_remaining.swap(u64::MAX, Ordering::SeqCst) {
+            u64::MAX => None,
+            remaining => Some(remaining),
+        }
+    }
+
     async fn fetch_uuid(&self, username: &str) -> Result<UsernameResolved, ApiError> {
+        self.simulate().await?;
         self.uuids
             .get(&username.to_lowercase())
             .cloned()
@@ -144,6 +281,7 @@ impl<'a> Mojang for MojangTestingApi<'a> {
     }
 
     async fn fetch_uuids(&self, usernames: &[String]) -> Result<Vec<UsernameResolved>, ApiError> {
+        self.simulate().await?;
         let uuids = usernames
             .iter()
             .filter_map(|username| self.uuids.get(&username.to_lowercase()))
@@ -153,10 +291,12 @@ impl<'a> Mojang for MojangTestingApi<'a> {
     }
 
     async fn fetch_profile(&self, uuid: &Uuid, _signed: bool) -> Result<Profile, ApiError> {
+        self.simulate().await?;
         self.profiles.get(uuid).cloned().ok_or(NotFound)
     }
 
     async fn fetch_bytes(&self, url: String) -> Result<TextureBytes, ApiError> {
+        self.simulate().await?;
         self.images
             .get(&url)
             .cloned()
@@ -164,6 +304,24 @@ impl<'a> Mojang for MojangTestingApi<'a> {
             .ok_or(NotFound)
             .map(TextureBytes)
     }
+
+    async fn fetch_blocked_servers(&self) -> Result<Vec<String>, ApiError> {
+        self.simulate().await?;
+        Ok(self.blocked_servers.clone())
+    }
+}
+
+impl ProfileProvider for MojangTestingApi<'static> {
+    fn fetch_profile<'a>(&'a self, query: &'a str) -> BoxFuture<'a, Result<Profile, ApiError>> {
+        Box::pin(async move {
+            self.simulate().await?;
+            let id = match Uuid::try_parse(query) {
+                Ok(id) => id,
+                Err(_) => self.uuids.get(&query.to_lowercase()).ok_or(NotFound)?.id,
+            };
+            self.profiles.get(&id).cloned().ok_or(NotFound)
+        })
+    }
 }
 
 #[cfg(test)]
@@ -326,4 +484,54 @@ mod test {
             Err(_) => panic!("failed to resolve uuids"),
         }
     }
+
+    #[tokio::test]
+    async fn fail_next() {
+        // given
+        let api = MojangTestingApi::with_profiles();
+        api.fail_next(2);
+
+        // when
+        let first = api.fetch_uuid("Hydrofin").await;
+        let second = api.fetch_uuid("Hydrofin").await;
+        let third = api.fetch_uuid("Hydrofin").await;
+
+        // then
+        assert!(matches!(first, Err(Unavailable)));
+        assert!(matches!(second, Err(Unavailable)));
+        assert!(third.is_ok());
+    }
+
+    #[tokio::test]
+    async fn rate_limit() {
+        // given
+        let api = MojangTestingApi::with_profiles();
+        api.set_rate_limit(Some(1));
+
+        // when
+        let first = api.fetch_uuid("Hydrofin").await;
+        let second = api.fetch_uuid("Hydrofin").await;
+        api.reset_requests();
+        let third = api.fetch_uuid("Hydrofin").await;
+
+        // then
+        assert!(first.is_ok());
+        assert!(matches!(second, Err(RateLimited { .. })));
+        assert!(third.is_ok());
+    }
+
+    #[tokio::test]
+    async fn latency() {
+        // given
+        let api = MojangTestingApi::with_profiles();
+        api.set_latency(Duration::from_millis(20), Duration::from_millis(40));
+
+        // when
+        let start = std::time::Instant::now();
+        let result = api.fetch_uuid("Hydrofin").await;
+
+        // then
+        assert!(result.is_ok());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
 }
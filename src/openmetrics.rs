@@ -0,0 +1,258 @@
+//! The openmetrics module provides the [OpenMetrics] text exposition of the [prometheus] metrics. The
+//! format is negotiated with the `Accept` header of the scrape request, scrapers that do not accept
+//! OpenMetrics receive the Prometheus text format.
+//!
+//! Contrary to the Prometheus text format, the metadata of counters uses the name without the `_total`
+//! suffix (that their samples always have) and the exposition is terminated by `# EOF`.
+//!
+//! [OpenMetrics]: https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md
+
+use http::header::ACCEPT;
+use http::HeaderMap;
+use prometheus::proto::{LabelPair, Metric, MetricFamily, MetricType};
+use std::fmt::Write;
+
+/// The content type of the OpenMetrics text exposition.
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Checks whether a scrape request prefers the OpenMetrics format over the Prometheus text format by
+/// the quality of the media types in its `Accept` header. The OpenMetrics format is preferred on ties.
+pub fn accepts(headers: &HeaderMap) -> bool {
+    let mut openmetrics = 0.0f32;
+    let mut text = 0.0f32;
+    let values = headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok());
+    for media_range in values.flat_map(|value| value.split(',')) {
+        let mut parts = media_range.split(';');
+        let media_type = parts.next().unwrap_or_default().trim().to_lowercase();
+        let quality = parts
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|quality| quality.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        match media_type.as_str() {
+            "application/openmetrics-text" => openmetrics = openmetrics.max(quality),
+            "text/plain" | "text/*" | "*/*" => text = text.max(quality),
+            _ => {}
+        }
+    }
+    openmetrics > 0.0 && openmetrics >= text
+}
+
+/// Encodes the metric families in the OpenMetrics text format.
+pub fn encode(families: &[MetricFamily]) -> String {
+    let mut buffer = String::new();
+    for family in families {
+        let metric_type = family.get_field_type();
+        let name = family.get_name();
+        // the metadata of counters does not include the suffix of their samples
+        let (name, suffix) = match metric_type {
+            MetricType::COUNTER => (name.strip_suffix("_total").unwrap_or(name), "_total"),
+            _ => (name, ""),
+        };
+        let type_name = match metric_type {
+            MetricType::COUNTER => "counter",
+            MetricType::GAUGE => "gauge",
+            MetricType::SUMMARY => "summary",
+            MetricType::HISTOGRAM => "histogram",
+            MetricType::UNTYPED => "unknown",
+        };
+        let _ = writeln!(buffer, "# TYPE {} {}", name, type_name);
+        if !family.get_help().is_empty() {
+            let _ = writeln!(buffer, "# HELP {} {}", name, escape(family.get_help()));
+        }
+        for metric in family.get_metric() {
+            encode_metric(&mut buffer, name, suffix, metric_type, metric);
+        }
+    }
+    buffer.push_str("# EOF\n");
+    buffer
+}
+
+/// Encodes the samples of a single metric of a family.
+fn encode_metric(
+    buffer: &mut String,
+    name: &str,
+    suffix: &str,
+    metric_type: MetricType,
+    metric: &Metric,
+) {
+    let labels = metric.get_label();
+    match metric_type {
+        MetricType::COUNTER => {
+            let value = metric.get_counter().get_value();
+            write_sample(buffer, name, suffix, labels, None, value);
+        }
+        MetricType::GAUGE => {
+            write_sample(
+                buffer,
+                name,
+                "",
+                labels,
+                None,
+                metric.get_gauge().get_value(),
+            );
+        }
+        MetricType::UNTYPED => {
+            let value = metric.get_untyped().get_value();
+            write_sample(buffer, name, "", labels, None, value);
+        }
+        MetricType::HISTOGRAM => {
+            let histogram = metric.get_histogram();
+            for bucket in histogram.get_bucket() {
+                let bound = format_float(bucket.get_upper_bound());
+                let value = bucket.get_cumulative_count() as f64;
+                write_sample(
+                    buffer,
+                    name,
+                    "_bucket",
+                    labels,
+                    Some(("le", bound.as_str())),
+                    value,
+                );
+            }
+            let count = histogram.get_sample_count() as f64;
+            // the +Inf bucket is mandatory, but is not part of the buckets of the histogram
+            write_sample(buffer, name, "_bucket", labels, Some(("le", "+Inf")), count);
+            write_sample(buffer, name, "_count", labels, None, count);
+            write_sample(
+                buffer,
+                name,
+                "_sum",
+                labels,
+                None,
+                histogram.get_sample_sum(),
+            );
+        }
+        MetricType::SUMMARY => {
+            let summary = metric.get_summary();
+            for quantile in summary.get_quantile() {
+                let rank = format_float(quantile.get_quantile());
+                let value = quantile.get_value();
+                write_sample(
+                    buffer,
+                    name,
+                    "",
+                    labels,
+                    Some(("quantile", rank.as_str())),
+                    value,
+                );
+            }
+            let count = summary.get_sample_count() as f64;
+            write_sample(buffer, name, "_count", labels, None, count);
+            write_sample(buffer, name, "_sum", labels, None, summary.get_sample_sum());
+        }
+    }
+}
+
+/// Writes a single sample with its labels and an (optional) additional label.
+fn write_sample(
+    buffer: &mut String,
+    name: &str,
+    suffix: &str,
+    labels: &[LabelPair],
+    additional: Option<(&str, &str)>,
+    value: f64,
+) {
+    buffer.push_str(name);
+    buffer.push_str(suffix);
+    let labels = labels
+        .iter()
+        .map(|label| (label.get_name(), label.get_value()))
+        .chain(additional);
+    let mut first = true;
+    for (label, label_value) in labels {
+        buffer.push(if first { '{' } else { ',' });
+        first = false;
+        let _ = write!(buffer, "{}=\"{}\"", label, escape(label_value));
+    }
+    if !first {
+        buffer.push('}');
+    }
+    let _ = writeln!(buffer, " {}", format_float(value));
+}
+
+/// Formats a float in its canonical OpenMetrics representation (e.g. `1.0`, `0.25` or `+Inf`).
+fn format_float(value: f64) -> String {
+    match value {
+        value if value.is_nan() => "NaN".to_string(),
+        f64::INFINITY => "+Inf".to_string(),
+        f64::NEG_INFINITY => "-Inf".to_string(),
+        value if value.fract() == 0.0 && value.abs() < 1e15 => format!("{:.1}", value),
+        value => value.to_string(),
+    }
+}
+
+/// Escapes the backslashes, double quotes and line feeds of a help text or label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use http::HeaderValue;
+    use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry};
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn accepts_quality() {
+        // given
+        let prometheus = accept(
+            "application/openmetrics-text;version=1.0.0,text/plain;version=0.0.4;q=0.5,*/*;q=0.1",
+        );
+        let text = accept("text/plain;version=0.0.4");
+        let preferred = accept("application/openmetrics-text;q=0.3,text/plain;q=0.5");
+
+        // when
+        let prometheus = accepts(&prometheus);
+        let text = accepts(&text);
+        let preferred = accepts(&preferred);
+
+        // then
+        assert!(prometheus);
+        assert!(!text);
+        assert!(!preferred);
+    }
+
+    #[test]
+    fn encode_families() {
+        // given
+        let registry = Registry::new();
+        let counter = IntCounterVec::new(
+            Opts::new("xenos_test_total", "A \"test\" counter."),
+            &["kind"],
+        )
+        .unwrap();
+        let histogram = HistogramVec::new(
+            HistogramOpts::new("xenos_test_seconds", "A test histogram.").buckets(vec![0.5, 1.0]),
+            &["kind"],
+        )
+        .unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+        registry.register(Box::new(histogram.clone())).unwrap();
+        counter.with_label_values(&["a"]).inc_by(2);
+        histogram.with_label_values(&["a"]).observe(0.75);
+
+        // when
+        let encoded = encode(&registry.gather());
+
+        // then
+        assert!(encoded.contains("# TYPE xenos_test counter\n"));
+        assert!(encoded.contains("# HELP xenos_test A \\\"test\\\" counter.\n"));
+        assert!(encoded.contains("xenos_test_total{kind=\"a\"} 2.0\n"));
+        assert!(encoded.contains("xenos_test_seconds_bucket{kind=\"a\",le=\"1.0\"} 1.0\n"));
+        assert!(encoded.contains("xenos_test_seconds_bucket{kind=\"a\",le=\"+Inf\"} 1.0\n"));
+        assert!(encoded.contains("xenos_test_seconds_sum{kind=\"a\"} 0.75\n"));
+        assert!(encoded.ends_with("# EOF\n"));
+    }
+}
@@ -0,0 +1,89 @@
+//! The placeholder module provides the placeholder images of the skin and head endpoints. If enabled,
+//! the placeholders are served instead of errors if mojang is unavailable and nothing is cached, as
+//! broken images are worse than slightly wrong ones for most clients. The placeholders are either
+//! configured images or the default skin and head of the profile.
+
+use crate::settings;
+use bytes::Bytes;
+use image::{GenericImageView, ImageFormat};
+use tracing::warn;
+
+/// The [Placeholders] are the configured placeholder images (if any).
+#[derive(Debug, Default)]
+pub struct Placeholders {
+    skin: Option<Bytes>,
+    head: Option<Bytes>,
+}
+
+impl Placeholders {
+    /// Loads the configured [Placeholders]. Images that cannot be read or have invalid dimensions are
+    /// ignored, so that the default skin and head are used instead.
+    pub fn load(settings: &settings::Placeholder) -> Self {
+        Self {
+            skin: settings
+                .skin
+                .as_deref()
+                .and_then(|path| load_image(path, &[(64, 64), (64, 32)])),
+            head: settings
+                .head
+                .as_deref()
+                .and_then(|path| load_image(path, &[(8, 8)])),
+        }
+    }
+
+    /// Gets the placeholder skin (PNG), if configured.
+    pub fn skin(&self) -> Option<Bytes> {
+        self.skin.clone()
+    }
+
+    /// Gets the placeholder head (8x8 PNG), if configured.
+    pub fn head(&self) -> Option<Bytes> {
+        self.head.clone()
+    }
+}
+
+/// Loads a PNG image from a path, if it has one of the dimensions.
+fn load_image(path: &str, dimensions: &[(u32, u32)]) -> Option<Bytes> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            warn!(path, error = %err, "failed to read placeholder image");
+            return None;
+        }
+    };
+    match image::load_from_memory_with_format(&bytes, ImageFormat::Png) {
+        Ok(img) if dimensions.contains(&img.dimensions()) => Some(bytes.into()),
+        Ok(img) => {
+            warn!(path, dimensions = ?img.dimensions(), "invalid placeholder image dimensions");
+            None
+        }
+        Err(err) => {
+            warn!(path, error = %err, "failed to decode placeholder image");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn load_valid_images() {
+        // given
+        let settings = settings::Placeholder {
+            enabled: true,
+            max_age: Duration::from_secs(30),
+            skin: Some("resources/profiles/steve_skin.png".to_string()),
+            head: Some("resources/profiles/steve_skin.png".to_string()),
+        };
+
+        // when
+        let placeholders = Placeholders::load(&settings);
+
+        // then
+        assert!(placeholders.skin().is_some());
+        assert!(placeholders.head().is_none());
+    }
+}
@@ -1,23 +1,228 @@
-//! The proto module [includes](tonic::include_proto!) the rust protobuf definition for both the gRPC
+//! The proto module includes the rust protobuf definition for both the gRPC
 //! and REST services. It also provides implementations for converting into these definitions from
 //! internal result formats.
 
-use crate::cache::entry::{CapeData, Dated, Entry, HeadData, ProfileData, SkinData, UuidData};
-use std::collections::HashMap;
+use crate::cache::entry::{
+    BlockedServersData, CapeData, CapeUrlData, ChecksumData, Dated, Entry, HeadData, HeadKey,
+    HeadStyle, ProfileData, SkinData, SkinUrlData, TextureData, UuidData,
+};
+use crate::capes::CapeNames;
+use crate::error::ServiceError;
+#[cfg(feature = "history")]
+use crate::history::{NameHistoryData, SkinHistoryData};
+use crate::mojang::status::MojangStatus;
+use crate::render::animation;
+use crate::service::{BulkStatus, ProfileBundle, SkinColors};
+use crate::settings::{CacheEntries, CacheEntry, UuidFormat};
+use bytes::Bytes;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
 
 // includes the rust protobuf definitions
-tonic::include_proto!("scrayosnet.xenos");
+include!(concat!(env!("OUT_DIR"), "/scrayosnet.xenos.rs"));
+
+/// Parses a uuid from a request. It accepts the hyphenated and simple format (and all other formats
+/// supported by [Uuid::try_parse]) with surrounding whitespace.
+pub fn parse_uuid(value: &str) -> Result<Uuid, uuid::Error> {
+    Uuid::try_parse(value.trim())
+}
+
+/// Implements `parse_uuid` for requests with an uuid in string and binary form. The binary form takes
+/// precedence if present.
+macro_rules! impl_parse_uuid {
+    ($($request:ty),*) => {
+        $(
+            impl $request {
+                /// Parses the uuid of the request from its binary (16 bytes) or string form.
+                pub fn parse_uuid(&self) -> Result<Uuid, uuid::Error> {
+                    match self.uuid_bin.is_empty() {
+                        true => parse_uuid(&self.uuid),
+                        false => Uuid::from_slice(&self.uuid_bin),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_parse_uuid!(
+    ProfileRequest,
+    ProfileBundleRequest,
+    SkinRequest,
+    CapeRequest,
+    HeadRequest,
+    ChecksumRequest,
+    ColorsRequest,
+    NameHistoryRequest,
+    SkinHistoryRequest
+);
+
+/// Implements `with_staleness` for responses with an age. Use it to set the age of responses and to
+/// mark responses as stale that were served from expired cache entries.
+macro_rules! impl_with_staleness {
+    ($($response:ty),*) => {
+        $(
+            impl $response {
+                /// Sets the age of the response at a unix time in seconds (of the cache
+                /// [Clock](crate::cache::clock::Clock)) and marks the response as stale if its age
+                /// exceeds the expiry of its cache entry.
+                pub fn with_staleness(mut self, expiry: &CacheEntry, now: u64) -> Self {
+                    self.age_seconds = now.saturating_sub(self.timestamp);
+                    self.stale = self.age_seconds >= expiry.exp.as_secs();
+                    self
+                }
+            }
+        )*
+    };
+}
+
+impl_with_staleness!(
+    UuidResponse,
+    ProfileResponse,
+    SkinResponse,
+    CapeResponse,
+    HeadResponse,
+    ChecksumResponse,
+    ColorsResponse,
+    TextureResponse,
+    BlockedServersResponse,
+    BlockedServerResponse
+);
+
+/// Converts a hyphenated uuid of a response into the [UuidFormat].
+fn format_uuid(uuid: String, format: UuidFormat) -> String {
+    match format {
+        UuidFormat::Hyphenated => uuid,
+        UuidFormat::Simple => uuid.replace('-', ""),
+    }
+}
 
 // conversion utility for converting service results into response data
 impl From<HashMap<String, Entry<UuidData>>> for UuidsResponse {
     fn from(value: HashMap<String, Entry<UuidData>>) -> Self {
+        let (resolved, not_found): (HashMap<_, _>, HashMap<_, _>) =
+            value.into_iter().partition(|(_, v)| v.data.is_some());
+        let mut not_found: Vec<String> = not_found.into_keys().collect();
+        not_found.sort();
         UuidsResponse {
-            resolved: value
+            resolved: resolved
                 .into_iter()
-                .filter(|(_, v)| v.data.is_some())
                 .map(|(k, v)| (k, v.unwrap().into()))
                 .collect(),
+            not_found,
+            statuses: HashMap::new(),
+        }
+    }
+}
+
+// conversion utility for converting service results into response data
+impl From<HashMap<String, (BulkStatus, Entry<UuidData>)>> for UuidsResponse {
+    fn from(value: HashMap<String, (BulkStatus, Entry<UuidData>)>) -> Self {
+        let mut statuses = HashMap::with_capacity(value.len());
+        let mut resolved = HashMap::new();
+        let mut not_found = vec![];
+        for (username, (status, entry)) in value {
+            statuses.insert(username.clone(), status.as_str().to_string());
+            match (status, entry.some_or(())) {
+                (_, Ok(entry)) => {
+                    resolved.insert(username, entry.into());
+                }
+                // unavailable usernames could exist, so they are not reported as not found
+                (BulkStatus::Unavailable, Err(_)) => {}
+                (_, Err(_)) => not_found.push(username),
+            }
+        }
+        not_found.sort();
+        UuidsResponse {
+            resolved,
+            not_found,
+            statuses,
+        }
+    }
+}
+
+impl UuidsResponse {
+    /// Removes the usernames that weren't found from the [UuidsResponse], unless they are requested.
+    pub fn with_not_found(mut self, include: bool) -> Self {
+        if !include {
+            self.not_found.clear();
+        }
+        self
+    }
+
+    /// Uses the requested usernames (with their original case) as usernames of the [UuidsResponse]
+    /// instead of the usernames in lowercase. Case variants of the same username are all included.
+    pub fn with_requested_usernames(mut self, usernames: &[String]) -> Self {
+        let not_found: HashSet<String> = self.not_found.drain(..).collect();
+        let mut resolved = HashMap::with_capacity(self.resolved.len());
+        for username in usernames {
+            let lowercase = username.to_lowercase();
+            if let Some(uuid) = self.resolved.get(&lowercase) {
+                resolved.insert(username.clone(), uuid.clone());
+            } else if not_found.contains(&lowercase) && !self.not_found.contains(username) {
+                self.not_found.push(username.clone());
+            }
+        }
+        self.resolved = resolved;
+        if !self.statuses.is_empty() {
+            self.statuses = usernames
+                .iter()
+                .filter_map(|username| {
+                    let status = self.statuses.get(&username.to_lowercase())?;
+                    Some((username.clone(), status.clone()))
+                })
+                .collect();
+        }
+        self
+    }
+
+    /// Gets the uuids of all resolved usernames of the [UuidsResponse].
+    pub fn resolved_uuids(&self) -> Vec<Uuid> {
+        self.resolved
+            .values()
+            .filter_map(|resolved| parse_uuid(&resolved.uuid).ok())
+            .collect()
+    }
+
+    /// Removes the resolved usernames with the sanctioned uuids from the [UuidsResponse]. Their status
+    /// is `sanctioned` if the statuses are present.
+    pub fn without_sanctioned(mut self, sanctioned: &HashSet<Uuid>) -> Self {
+        let excluded: Vec<String> = self
+            .resolved
+            .iter()
+            .filter(|(_, resolved)| {
+                parse_uuid(&resolved.uuid).is_ok_and(|uuid| sanctioned.contains(&uuid))
+            })
+            .map(|(username, _)| username.clone())
+            .collect();
+        for username in excluded {
+            self.resolved.remove(&username);
+            if let Some(status) = self.statuses.get_mut(&username) {
+                *status = "sanctioned".to_string();
+            }
         }
+        self
+    }
+
+    /// Sets the age of all resolved uuids at a unix time in seconds and marks those as stale whose age
+    /// exceeds the expiry of their cache entry.
+    pub fn with_staleness(mut self, expiry: &CacheEntry, now: u64) -> Self {
+        self.resolved = self
+            .resolved
+            .into_iter()
+            .map(|(username, resolved)| (username, resolved.with_staleness(expiry, now)))
+            .collect();
+        self
+    }
+
+    /// Converts all uuids of the [UuidsResponse] into the [UuidFormat].
+    pub fn with_uuid_format(mut self, format: UuidFormat) -> Self {
+        self.resolved = self
+            .resolved
+            .into_iter()
+            .map(|(username, resolved)| (username, resolved.with_uuid_format(format)))
+            .collect();
+        self
     }
 }
 
@@ -26,42 +231,187 @@ impl From<Dated<UuidData>> for UuidResponse {
     fn from(value: Dated<UuidData>) -> Self {
         UuidResponse {
             timestamp: value.timestamp,
+            age_seconds: 0,
+            stale: false,
             username: value.data.username,
             uuid: value.data.uuid.hyphenated().to_string(),
+            uuid_bin: value.data.uuid.as_bytes().to_vec(),
+            provider: value.provider,
         }
     }
 }
 
+impl UuidResponse {
+    /// Converts the uuid of the [UuidResponse] into the [UuidFormat].
+    pub fn with_uuid_format(mut self, format: UuidFormat) -> Self {
+        self.uuid = format_uuid(self.uuid, format);
+        self
+    }
+}
+
 // conversion utility for converting service results into response data
 impl From<Dated<ProfileData>> for ProfileResponse {
     fn from(value: Dated<ProfileData>) -> Self {
         ProfileResponse {
             timestamp: value.timestamp,
+            age_seconds: 0,
+            stale: false,
             uuid: value.data.id.hyphenated().to_string(),
+            uuid_bin: value.data.id.as_bytes().to_vec(),
             name: value.data.name,
             properties: value
                 .data
                 .properties
-                .into_iter()
+                .iter()
                 .map(|prop| ProfileProperty {
-                    name: prop.name,
-                    value: prop.value,
-                    signature: prop.signature,
+                    name: prop.name.clone(),
+                    value: prop.value.clone(),
+                    signature: prop.signature.clone(),
                 })
                 .collect(),
             profile_actions: value.data.profile_actions,
+            provider: value.provider,
         }
     }
 }
 
+impl ProfileResponse {
+    /// Converts the uuid of the [ProfileResponse] into the [UuidFormat].
+    pub fn with_uuid_format(mut self, format: UuidFormat) -> Self {
+        self.uuid = format_uuid(self.uuid, format);
+        self
+    }
+
+    /// Filters the [ProfileResponse] by a fields mask (`name`, `properties` and `profile_actions`).
+    /// Fields that are not part of the mask are cleared. An empty mask keeps all fields.
+    pub fn with_fields<S: AsRef<str>>(mut self, fields: &[S]) -> Result<Self, ServiceError> {
+        if fields.is_empty() {
+            return Ok(self);
+        }
+        let (mut name, mut properties, mut profile_actions) = (false, false, false);
+        for field in fields {
+            match field.as_ref() {
+                "name" => name = true,
+                "properties" => properties = true,
+                "profile_actions" => profile_actions = true,
+                field => {
+                    return Err(ServiceError::InvalidArgument(format!(
+                        "unknown profile field {}",
+                        field
+                    )))
+                }
+            }
+        }
+        if !name {
+            self.name.clear();
+        }
+        if !properties {
+            self.properties.clear();
+        }
+        if !profile_actions {
+            self.profile_actions.clear();
+        }
+        Ok(self)
+    }
+}
+
+// conversion utility for converting service results into response data
+impl From<Dated<ProfileData>> for BuildTexturesResponse {
+    fn from(value: Dated<ProfileData>) -> Self {
+        let profile = ProfileResponse::from(value);
+        BuildTexturesResponse {
+            value: profile
+                .properties
+                .iter()
+                .find(|prop| prop.name == "textures")
+                .map(|prop| prop.value.clone())
+                .unwrap_or_default(),
+            profile: Some(profile),
+        }
+    }
+}
+
+impl ValidateUuidResponse {
+    /// Validates and canonicalizes a uuid with the same parsing as all other requests (see
+    /// [parse_uuid]).
+    pub fn validate(value: &str) -> Self {
+        let uuid = match parse_uuid(value) {
+            Ok(uuid) => uuid,
+            Err(err) => {
+                return ValidateUuidResponse {
+                    error: Some(err.to_string()),
+                    ..Default::default()
+                }
+            }
+        };
+        let variant = match uuid.get_variant() {
+            uuid::Variant::NCS => "ncs",
+            uuid::Variant::RFC4122 => "rfc4122",
+            uuid::Variant::Microsoft => "microsoft",
+            _ => "future",
+        };
+        ValidateUuidResponse {
+            valid: true,
+            hyphenated: uuid.hyphenated().to_string(),
+            simple: uuid.simple().to_string(),
+            version: uuid.get_version_num() as u32,
+            variant: variant.to_string(),
+            error: None,
+        }
+    }
+}
+
+impl BuildTexturesResponse {
+    /// Converts the uuid of the built profile into the [UuidFormat].
+    pub fn with_uuid_format(mut self, format: UuidFormat) -> Self {
+        self.profile = self.profile.map(|profile| profile.with_uuid_format(format));
+        self
+    }
+
+    /// Sets the age of the built profile at a unix time in seconds and marks it as stale if the age of
+    /// its source profile exceeds the expiry of the profile cache entry.
+    pub fn with_staleness(mut self, expiry: &CacheEntry, now: u64) -> Self {
+        self.profile = self
+            .profile
+            .map(|profile| profile.with_staleness(expiry, now));
+        self
+    }
+}
+
 // conversion utility for converting service results into response data
 impl From<Dated<SkinData>> for SkinResponse {
     fn from(value: Dated<SkinData>) -> Self {
         SkinResponse {
             timestamp: value.timestamp,
+            age_seconds: 0,
+            stale: false,
             model: value.data.model,
             bytes: value.data.bytes,
             default: value.data.default,
+            suppressed: value.data.suppressed,
+            url: None,
+            texture_id: None,
+            placeholder: false,
+            provider: value.provider,
+        }
+    }
+}
+
+// conversion utility for converting service results into response data
+impl From<Dated<SkinUrlData>> for SkinResponse {
+    fn from(value: Dated<SkinUrlData>) -> Self {
+        SkinResponse {
+            timestamp: value.timestamp,
+            age_seconds: 0,
+            stale: false,
+            model: value.data.model,
+            bytes: Bytes::new(),
+            default: value.data.default,
+            suppressed: value.data.suppressed,
+            url: value.data.url,
+            texture_id: value.data.texture_id,
+            placeholder: false,
+            provider: value.provider,
         }
     }
 }
@@ -71,18 +421,664 @@ impl From<Dated<CapeData>> for CapeResponse {
     fn from(value: Dated<CapeData>) -> Self {
         CapeResponse {
             timestamp: value.timestamp,
+            age_seconds: 0,
+            stale: false,
             bytes: value.data.bytes,
+            url: None,
+            texture_id: value.data.texture_id,
+            frames: vec![],
+            name: None,
+            provider: value.provider,
         }
     }
 }
 
+// conversion utility for converting service results into response data
+impl From<Dated<CapeUrlData>> for CapeResponse {
+    fn from(value: Dated<CapeUrlData>) -> Self {
+        CapeResponse {
+            timestamp: value.timestamp,
+            age_seconds: 0,
+            stale: false,
+            bytes: Bytes::new(),
+            url: Some(value.data.url),
+            texture_id: Some(value.data.texture_id),
+            frames: vec![],
+            name: None,
+            provider: value.provider,
+        }
+    }
+}
+
+impl CapeResponse {
+    /// Identifies the name of the cape of the [CapeResponse] by its texture id (if known).
+    pub fn with_name(mut self, names: &CapeNames) -> Self {
+        self.name = self
+            .texture_id
+            .as_deref()
+            .and_then(|texture_id| names.identify(texture_id))
+            .map(str::to_string);
+        self
+    }
+
+    /// Renders the animation of the [CapeResponse]. Animated capes are returned as animated PNG (APNG)
+    /// if animated is set. The frames are returned separately if frames is set. Responses without
+    /// bytes (e.g. URL-only) are unchanged.
+    pub fn with_animation(mut self, animated: bool, frames: bool) -> Result<Self, ServiceError> {
+        if (!animated && !frames) || self.bytes.is_empty() {
+            return Ok(self);
+        }
+        let cape_frames = animation::extract_frames(&self.bytes)?;
+        if frames {
+            self.frames = animation::encode_frames(&cape_frames)?;
+        }
+        if animated && cape_frames.len() > 1 {
+            self.bytes = animation::encode_apng(&cape_frames, animation::FRAME_DELAY)?.into();
+        }
+        Ok(self)
+    }
+}
+
 // conversion utility for converting service results into response data
 impl From<Dated<HeadData>> for HeadResponse {
     fn from(value: Dated<HeadData>) -> Self {
         HeadResponse {
             timestamp: value.timestamp,
+            age_seconds: 0,
+            stale: false,
             bytes: value.data.bytes,
             default: value.data.default,
+            suppressed: value.data.suppressed,
+            size: 8,
+            placeholder: false,
+            provider: value.provider,
+        }
+    }
+}
+
+impl HeadRequest {
+    /// Gets the [HeadKey] of the requested head of an uuid, that is rendered with an integer scale
+    /// either as PNG or as raw RGBA pixels. A scale of zero is treated as one.
+    pub fn head_key(&self, uuid: Uuid, overlay: bool) -> Result<HeadKey, ServiceError> {
+        let scale = self.scale.max(1);
+        if scale > HeadResponse::MAX_SCALE {
+            return Err(ServiceError::InvalidArgument(format!(
+                "scale must not exceed {}",
+                HeadResponse::MAX_SCALE
+            )));
+        }
+        let style = match self.rgba {
+            true => HeadStyle::Rgba,
+            false => HeadStyle::Png,
+        };
+        Ok(HeadKey::rendered(uuid, overlay, scale, style))
+    }
+}
+
+impl HeadResponse {
+    /// The maximum scale of a [HeadResponse].
+    pub const MAX_SCALE: u32 = 64;
+
+    /// Sets the size of the [HeadResponse] to the size of the head of a [HeadKey].
+    pub fn with_size(mut self, key: &HeadKey) -> Self {
+        self.size = key.size;
+        self
+    }
+}
+
+// conversion utility for converting service results into response data
+impl From<SkinColors> for ColorsResponse {
+    fn from(value: SkinColors) -> Self {
+        ColorsResponse {
+            timestamp: value.timestamp,
+            age_seconds: 0,
+            stale: false,
+            default: value.default,
+            colors: value
+                .colors
+                .iter()
+                .map(|color| DominantColor {
+                    hex: color.hex(),
+                    share: color.share as f32 / 1000.0,
+                })
+                .collect(),
+        }
+    }
+}
+
+// conversion utility for converting service results into response data
+impl From<Dated<BlockedServersData>> for BlockedServersResponse {
+    fn from(value: Dated<BlockedServersData>) -> Self {
+        BlockedServersResponse {
+            timestamp: value.timestamp,
+            age_seconds: 0,
+            stale: false,
+            hashes: value.data.hashes.to_vec(),
+        }
+    }
+}
+
+impl BlockedServerResponse {
+    /// Creates a new [BlockedServerResponse] for a hostname from the (optional) pattern that blocks
+    /// the hostname.
+    pub fn new(hostname: String, pattern: Dated<Option<String>>) -> Self {
+        BlockedServerResponse {
+            timestamp: pattern.timestamp,
+            age_seconds: 0,
+            stale: false,
+            hostname,
+            blocked: pattern.data.is_some(),
+            pattern: pattern.data,
+        }
+    }
+}
+
+// conversion utility for converting service results into response data
+impl From<Dated<ChecksumData>> for ChecksumResponse {
+    fn from(value: Dated<ChecksumData>) -> Self {
+        ChecksumResponse {
+            timestamp: value.timestamp,
+            age_seconds: 0,
+            stale: false,
+            texture_id: value.data.texture_id,
+            skin_sha256: value.data.skin,
+            head_sha256: value.data.head,
+            default: value.data.default,
         }
     }
 }
+
+// conversion utility for converting service results into response data
+impl From<ProfileBundle> for ProfileBundleResponse {
+    fn from(value: ProfileBundle) -> Self {
+        ProfileBundleResponse {
+            profile: Some(value.profile.into()),
+            skin: Some(value.skin.into()),
+            has_cape: value.has_cape,
+            head: Some(value.head.into()),
+        }
+    }
+}
+
+impl ProfileBundleResponse {
+    /// Converts the uuid of the profile of the [ProfileBundleResponse] into the [UuidFormat].
+    pub fn with_uuid_format(mut self, format: UuidFormat) -> Self {
+        self.profile = self.profile.map(|profile| profile.with_uuid_format(format));
+        self
+    }
+
+    /// Sets the age of the parts of the response at a unix time in seconds and marks them as stale if
+    /// their age exceeds the expiry of their cache entry.
+    pub fn with_staleness(mut self, entries: &CacheEntries<CacheEntry>, now: u64) -> Self {
+        self.profile = self
+            .profile
+            .map(|profile| profile.with_staleness(&entries.profile, now));
+        self.skin = self
+            .skin
+            .map(|skin| skin.with_staleness(&entries.skin, now));
+        self.head = self
+            .head
+            .map(|head| head.with_staleness(&entries.head, now));
+        self
+    }
+}
+
+// conversion utility for converting service results into response data
+impl From<MojangStatus> for StatusResponse {
+    fn from(value: MojangStatus) -> Self {
+        StatusResponse {
+            circuit_breaker: value.breaker.as_str().to_string(),
+            cache_only: value.cache_only,
+            endpoints: value
+                .endpoints
+                .into_iter()
+                .map(|(endpoint, stats)| EndpointStatus {
+                    endpoint: endpoint.to_string(),
+                    requests: stats.requests,
+                    failures: stats.failures,
+                    error_rate: stats.error_rate(),
+                })
+                .collect(),
+            rate_limit: value.rate_limit,
+            retry_at: value.retry_at,
+            rate_remaining: value.rate_remaining,
+            fallback: value
+                .fallback
+                .into_iter()
+                .map(|provider| FallbackStatus {
+                    name: provider.name,
+                    circuit_breaker: provider.breaker.as_str().to_string(),
+                })
+                .collect(),
+            shedding: value.shedding,
+        }
+    }
+}
+
+// conversion utility for converting service results into response data
+impl From<Dated<TextureData>> for TextureResponse {
+    fn from(value: Dated<TextureData>) -> Self {
+        TextureResponse {
+            timestamp: value.timestamp,
+            age_seconds: 0,
+            stale: false,
+            bytes: value.data.bytes,
+        }
+    }
+}
+
+// conversion utility for converting service results into response data
+#[cfg(feature = "history")]
+impl From<Vec<NameHistoryData>> for NameHistoryResponse {
+    fn from(value: Vec<NameHistoryData>) -> Self {
+        NameHistoryResponse {
+            names: value
+                .into_iter()
+                .map(|data| NameHistoryEntry {
+                    name: data.name,
+                    first_seen: data.first_seen,
+                    last_seen: data.last_seen,
+                    age_seconds: 0,
+                    stale: false,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(feature = "history")]
+impl NameHistoryResponse {
+    /// Sets the age of all usernames at a unix time in seconds and marks those as stale that were not
+    /// observed within the expiry of the profile cache entry.
+    pub fn with_staleness(mut self, expiry: &CacheEntry, now: u64) -> Self {
+        for name in &mut self.names {
+            name.age_seconds = now.saturating_sub(name.last_seen);
+            name.stale = name.age_seconds >= expiry.exp.as_secs();
+        }
+        self
+    }
+}
+
+// conversion utility for converting service results into response data
+#[cfg(feature = "history")]
+impl From<Vec<SkinHistoryData>> for SkinHistoryResponse {
+    fn from(value: Vec<SkinHistoryData>) -> Self {
+        SkinHistoryResponse {
+            skins: value
+                .into_iter()
+                .map(|data| SkinHistoryEntry {
+                    texture_id: data.texture_id,
+                    url: data.url,
+                    first_seen: data.first_seen,
+                    last_seen: data.last_seen,
+                    age_seconds: 0,
+                    stale: false,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(feature = "history")]
+impl SkinHistoryResponse {
+    /// Sets the age of all skin textures at a unix time in seconds and marks those as stale that were
+    /// not observed within the expiry of the profile cache entry.
+    pub fn with_staleness(mut self, expiry: &CacheEntry, now: u64) -> Self {
+        for skin in &mut self.skins {
+            skin.age_seconds = now.saturating_sub(skin.last_seen);
+            skin.stale = skin.age_seconds >= expiry.exp.as_secs();
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::settings::{Jitter, Lookup};
+    use std::time::Duration;
+
+    fn new_profile_response() -> ProfileResponse {
+        ProfileResponse {
+            timestamp: 42,
+            age_seconds: 0,
+            stale: false,
+            uuid: "09879557-e479-45a9-b434-a56377674627".to_string(),
+            uuid_bin: vec![],
+            name: "Hydrofin".to_string(),
+            properties: vec![ProfileProperty {
+                name: "textures".to_string(),
+                value: "e30=".to_string(),
+                signature: None,
+            }],
+            profile_actions: vec!["FORCED_NAME_CHANGE".to_string()],
+            provider: None,
+        }
+    }
+
+    #[test]
+    fn with_fields_empty() {
+        // given
+        let profile = new_profile_response();
+
+        // when
+        let filtered = profile.clone().with_fields::<String>(&[]);
+
+        // then
+        assert_eq!(Some(profile), filtered.ok());
+    }
+
+    #[test]
+    fn with_fields_name() {
+        // given
+        let profile = new_profile_response();
+
+        // when
+        let filtered = profile.with_fields(&["name"]).unwrap();
+
+        // then
+        assert_eq!(42, filtered.timestamp);
+        assert_eq!("Hydrofin", filtered.name);
+        assert!(filtered.properties.is_empty());
+        assert!(filtered.profile_actions.is_empty());
+    }
+
+    #[test]
+    fn with_fields_unknown() {
+        // given
+        let profile = new_profile_response();
+
+        // when
+        let filtered = profile.with_fields(&["skin"]);
+
+        // then
+        assert!(matches!(filtered, Err(ServiceError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn with_uuid_format_simple() {
+        // given
+        let profile = new_profile_response();
+
+        // when
+        let formatted = profile.with_uuid_format(UuidFormat::Simple);
+
+        // then
+        assert_eq!("09879557e47945a9b434a56377674627", formatted.uuid);
+    }
+
+    #[test]
+    fn with_staleness_expired() {
+        // given
+        let profile = new_profile_response();
+        let expiry = CacheEntry {
+            exp: Duration::from_secs(60),
+            exp_empty: Duration::from_secs(60),
+            jitter: Jitter::None,
+            lookup: Lookup::Sequential,
+            pre_render: false,
+        };
+
+        // when
+        let fresh = profile.clone().with_staleness(&expiry, 100);
+        let expired = profile.with_staleness(&expiry, 162);
+
+        // then
+        assert_eq!(58, fresh.age_seconds);
+        assert!(!fresh.stale);
+        assert_eq!(120, expired.age_seconds);
+        assert!(expired.stale);
+    }
+
+    #[test]
+    fn parse_uuid_trimmed() {
+        // given
+        let hyphenated = " 09879557-e479-45a9-b434-a56377674627\n";
+        let simple = "09879557e47945a9b434a56377674627 ";
+
+        // when
+        let hyphenated = parse_uuid(hyphenated).unwrap();
+        let simple = parse_uuid(simple).unwrap();
+
+        // then
+        assert_eq!(hyphenated, simple);
+    }
+
+    #[test]
+    fn uuids_not_found() {
+        // given
+        let uuid = Uuid::try_parse("09879557-e479-45a9-b434-a56377674627").unwrap();
+        let uuids = HashMap::from([
+            (
+                "hydrofin".to_string(),
+                Dated::from(Some(UuidData {
+                    username: "Hydrofin".to_string(),
+                    uuid,
+                })),
+            ),
+            ("unknown".to_string(), Dated::from(None)),
+            ("-invalid-".to_string(), Dated::from(None)),
+        ]);
+
+        // when
+        let included = UuidsResponse::from(uuids.clone()).with_not_found(true);
+        let omitted = UuidsResponse::from(uuids).with_not_found(false);
+
+        // then
+        assert_eq!(
+            vec!["hydrofin"],
+            included.resolved.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(vec!["-invalid-", "unknown"], included.not_found);
+        assert!(omitted.not_found.is_empty());
+    }
+
+    #[test]
+    fn uuids_statuses() {
+        // given
+        let uuid = Uuid::try_parse("09879557-e479-45a9-b434-a56377674627").unwrap();
+        let hydrofin = Dated::from(Some(UuidData {
+            username: "Hydrofin".to_string(),
+            uuid,
+        }));
+        let uuids = HashMap::from([
+            ("hydrofin".to_string(), (BulkStatus::Stale, hydrofin)),
+            (
+                "unknown".to_string(),
+                (BulkStatus::Unavailable, Dated::from(None)),
+            ),
+            (
+                "-invalid-".to_string(),
+                (BulkStatus::Invalid, Dated::from(None)),
+            ),
+        ]);
+
+        // when
+        let response = UuidsResponse::from(uuids);
+
+        // then
+        assert!(response.resolved.contains_key("hydrofin"));
+        assert_eq!(vec!["-invalid-"], response.not_found);
+        assert_eq!("stale", response.statuses["hydrofin"]);
+        assert_eq!("unavailable", response.statuses["unknown"]);
+    }
+
+    #[test]
+    fn validate_uuid_forms() {
+        // given
+        let forms = [
+            "09879557-E479-45A9-B434-A56377674627",
+            "09879557e47945a9b434a56377674627",
+            " {09879557-e479-45a9-b434-a56377674627} ",
+        ];
+
+        // when
+        let responses: Vec<_> = forms
+            .iter()
+            .map(|form| ValidateUuidResponse::validate(form))
+            .collect();
+        let invalid = ValidateUuidResponse::validate("09879557-e479");
+
+        // then
+        for response in responses {
+            assert!(response.valid);
+            assert_eq!("09879557-e479-45a9-b434-a56377674627", response.hyphenated);
+            assert_eq!("09879557e47945a9b434a56377674627", response.simple);
+            assert_eq!(4, response.version);
+            assert_eq!("rfc4122", response.variant);
+        }
+        assert!(!invalid.valid);
+        assert!(invalid.error.is_some());
+    }
+
+    #[test]
+    fn uuids_without_sanctioned() {
+        // given
+        let uuid = Uuid::try_parse("09879557-e479-45a9-b434-a56377674627").unwrap();
+        let hydrofin = Dated::from(Some(UuidData {
+            username: "Hydrofin".to_string(),
+            uuid,
+        }));
+        let uuids = HashMap::from([("hydrofin".to_string(), (BulkStatus::Resolved, hydrofin))]);
+        let response = UuidsResponse::from(uuids);
+
+        // when
+        let sanctioned: HashSet<Uuid> = response.resolved_uuids().into_iter().collect();
+        let response = response.without_sanctioned(&sanctioned);
+
+        // then
+        assert!(sanctioned.contains(&uuid));
+        assert!(response.resolved.is_empty());
+        assert_eq!("sanctioned", response.statuses["hydrofin"]);
+    }
+
+    #[test]
+    fn uuids_requested_usernames() {
+        // given
+        let uuid = Uuid::try_parse("09879557-e479-45a9-b434-a56377674627").unwrap();
+        let uuids = HashMap::from([
+            (
+                "hydrofin".to_string(),
+                Dated::from(Some(UuidData {
+                    username: "Hydrofin".to_string(),
+                    uuid,
+                })),
+            ),
+            ("unknown".to_string(), Dated::from(None)),
+        ]);
+        let usernames = ["HYDROFIN", "hydroFin", "Unknown", "UNKNOWN", "Unknown"].map(String::from);
+
+        // when
+        let response = UuidsResponse::from(uuids).with_requested_usernames(&usernames);
+
+        // then
+        assert_eq!(2, response.resolved.len());
+        assert_eq!("Hydrofin", response.resolved["hydroFin"].username);
+        assert!(response.resolved.contains_key("HYDROFIN"));
+        assert_eq!(vec!["Unknown", "UNKNOWN"], response.not_found);
+    }
+
+    #[test]
+    fn parse_uuid_binary() {
+        // given
+        let uuid = Uuid::try_parse("09879557-e479-45a9-b434-a56377674627").unwrap();
+        let binary = SkinRequest {
+            uuid: "invalid".to_string(),
+            uuid_bin: uuid.as_bytes().to_vec(),
+            ..Default::default()
+        };
+        let string = SkinRequest {
+            uuid: uuid.simple().to_string(),
+            ..Default::default()
+        };
+        let truncated = SkinRequest {
+            uuid_bin: vec![0; 8],
+            ..Default::default()
+        };
+
+        // when
+        let binary = binary.parse_uuid();
+        let string = string.parse_uuid();
+        let truncated = truncated.parse_uuid();
+
+        // then
+        assert_eq!(uuid, binary.unwrap());
+        assert_eq!(uuid, string.unwrap());
+        assert!(truncated.is_err());
+    }
+
+    #[test]
+    fn head_key_rgba_scaled() {
+        // given
+        let uuid = Uuid::new_v4();
+        let request = HeadRequest {
+            rgba: true,
+            scale: 2,
+            ..Default::default()
+        };
+
+        // when
+        let key = request.head_key(uuid, true).unwrap();
+
+        // then
+        assert_eq!(HeadKey::rendered(uuid, true, 2, HeadStyle::Rgba), key);
+        assert_eq!(16, key.size);
+        assert!(!key.is_native());
+    }
+
+    #[test]
+    fn head_key_native() {
+        // given
+        let uuid = Uuid::new_v4();
+        let request = HeadRequest::default();
+
+        // when
+        let key = request.head_key(uuid, false).unwrap();
+
+        // then
+        assert_eq!(HeadKey::new(uuid, false), key);
+    }
+
+    #[test]
+    fn head_key_scale_exceeded() {
+        // given
+        let request = HeadRequest {
+            scale: HeadResponse::MAX_SCALE + 1,
+            ..Default::default()
+        };
+
+        // when
+        let key = request.head_key(Uuid::new_v4(), false);
+
+        // then
+        assert!(matches!(key, Err(ServiceError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn cape_with_name() {
+        // given
+        let settings = crate::settings::CapeNames {
+            embedded: true,
+            names: HashMap::new(),
+        };
+        let names = CapeNames::new(&settings);
+        let cape = CapeResponse::from(Dated::from(CapeData {
+            bytes: Bytes::new(),
+            texture_id: Some(
+                "2340c0e03dd24a11b15a8b33c2a7e9e32abb2051b2481d0ba7defd635ca7a933".to_string(),
+            ),
+        }));
+        let unknown = CapeResponse::from(Dated::from(CapeData {
+            bytes: Bytes::new(),
+            texture_id: None,
+        }));
+
+        // when
+        let cape = cape.with_name(&names);
+        let unknown = unknown.with_name(&names);
+
+        // then
+        assert_eq!(Some("Migrator"), cape.name.as_deref());
+        assert_eq!(None, unknown.name);
+    }
+}
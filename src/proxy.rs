@@ -0,0 +1,345 @@
+//! The proxy module provides the resolution of client ip addresses behind (reverse) proxies. The
+//! client ip address is used by the ip filter and the access log.
+//!
+//! Behind TCP load balancers, the client ip address is read from the [PROXY protocol] header (v1 or
+//! v2) that the load balancer sends at the start of each connection. Behind http proxies, it is read
+//! from the `X-Forwarded-For` header. Both are only used if the peer is a trusted proxy, as they could
+//! be forged otherwise.
+//!
+//! [PROXY protocol]: https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt
+
+use futures::Stream;
+use ipnet::IpNet;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+#[cfg(feature = "grpc-server")]
+use tonic::transport::server::{Connected, TcpConnectInfo};
+use tracing::{debug, warn};
+
+/// The header that holds the client ip address (and the proxies) of a request behind http proxies.
+pub const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// The signature of the binary PROXY protocol header (v2).
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The maximum length of the text PROXY protocol header (v1) including the line break.
+const V1_MAX_LENGTH: usize = 107;
+
+/// The time within which the PROXY protocol header has to be received after a connection is accepted.
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The time to wait after a connection could not be accepted (e.g. if the file descriptors are
+/// exhausted), so that the accept loop does not spin on repeated errors.
+pub const ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Checks whether an ip address is a trusted proxy.
+fn is_trusted(trusted: &[IpNet], ip: &IpAddr) -> bool {
+    trusted.iter().any(|network| network.contains(ip))
+}
+
+/// Resolves the client ip address of a request from its peer address and its `X-Forwarded-For`
+/// header. The header is walked from the closest proxy and the first address that is not a trusted
+/// proxy is the client ip address.
+pub fn client_ip(
+    peer: Option<IpAddr>,
+    forwarded_for: Option<&str>,
+    trusted: &[IpNet],
+) -> Option<IpAddr> {
+    // ipv4 clients of dual-stack listeners have ipv4-mapped ipv6 addresses
+    let mut ip = peer?.to_canonical();
+    let Some(forwarded_for) = forwarded_for else {
+        return Some(ip);
+    };
+    for hop in forwarded_for.rsplit(',') {
+        if !is_trusted(trusted, &ip) {
+            break;
+        }
+        match hop.trim().parse::<IpAddr>() {
+            Ok(hop) => ip = hop.to_canonical(),
+            Err(_) => break,
+        }
+    }
+    Some(ip)
+}
+
+/// Resolves the client ip address of a request from its peer address and headers.
+pub fn request_client_ip(
+    peer: Option<SocketAddr>,
+    headers: &http::HeaderMap,
+    trusted: &[IpNet],
+) -> Option<IpAddr> {
+    let forwarded_for = headers
+        .get(FORWARDED_FOR_HEADER)
+        .and_then(|value| value.to_str().ok());
+    client_ip(peer.map(|peer| peer.ip()), forwarded_for, trusted)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the PROXY protocol header (v1 or v2) from the start of a connection. It returns the source
+/// address of the proxied connection or `None` if the proxy sent no address (e.g. health checks of the
+/// proxy itself). Connections without valid header are rejected.
+pub async fn read_header<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<Option<SocketAddr>> {
+    // the shortest v1 header (`PROXY UNKNOWN\r\n`) is longer than the v2 signature
+    let mut prefix = [0u8; 12];
+    stream.read_exact(&mut prefix).await?;
+    if prefix == V2_SIGNATURE {
+        return read_header_v2(stream).await;
+    }
+    if prefix.starts_with(b"PROXY ") {
+        return read_header_v1(stream, &prefix).await;
+    }
+    Err(invalid("missing PROXY protocol header"))
+}
+
+/// Reads the remaining text PROXY protocol header (v1) after its prefix.
+async fn read_header_v1<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    prefix: &[u8],
+) -> io::Result<Option<SocketAddr>> {
+    let mut line = prefix.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LENGTH {
+            return Err(invalid("PROXY protocol header too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+    let line = std::str::from_utf8(&line[..line.len() - 2])
+        .map_err(|_| invalid("invalid PROXY protocol header"))?;
+    let parts: Vec<&str> = line.split(' ').collect();
+    match parts.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4" | "TCP6", source, _, port, _] => {
+            let ip: IpAddr = source
+                .parse()
+                .map_err(|_| invalid("invalid PROXY protocol source address"))?;
+            let port: u16 = port
+                .parse()
+                .map_err(|_| invalid("invalid PROXY protocol source port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid("invalid PROXY protocol header")),
+    }
+}
+
+/// Reads the remaining binary PROXY protocol header (v2) after its signature.
+async fn read_header_v2<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<Option<SocketAddr>> {
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await?;
+    let [version_command, family, high, low] = header;
+    let mut addresses = vec![0u8; u16::from_be_bytes([high, low]) as usize];
+    stream.read_exact(&mut addresses).await?;
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+    // the LOCAL command is used by the proxy itself (e.g. health checks)
+    if version_command & 0x0f == 0 {
+        return Ok(None);
+    }
+    let source = match family >> 4 {
+        1 if addresses.len() >= 12 => {
+            let ip: [u8; 4] = addresses[0..4].try_into().unwrap();
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Some(SocketAddr::new(Ipv4Addr::from(ip).into(), port))
+        }
+        2 if addresses.len() >= 36 => {
+            let ip: [u8; 16] = addresses[0..16].try_into().unwrap();
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Some(SocketAddr::new(Ipv6Addr::from(ip).into(), port))
+        }
+        // unspecified and unix socket addresses have no client ip address
+        _ => None,
+    };
+    Ok(source)
+}
+
+/// A [ProxiedStream] is an accepted connection whose remote address is the source address of its
+/// PROXY protocol header (if its peer is a trusted proxy).
+#[derive(Debug)]
+pub struct ProxiedStream {
+    stream: TcpStream,
+    local: Option<SocketAddr>,
+    remote: SocketAddr,
+}
+
+impl ProxiedStream {
+    /// Gets the local address of the connection.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local
+    }
+
+    /// Gets the (proxied) remote address of the connection.
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote
+    }
+}
+
+impl AsyncRead for ProxiedStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for ProxiedStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+}
+
+#[cfg(feature = "grpc-server")]
+impl Connected for ProxiedStream {
+    type ConnectInfo = TcpConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        TcpConnectInfo {
+            local_addr: self.local,
+            remote_addr: Some(self.remote),
+        }
+    }
+}
+
+/// Accepts the connections of a listener and reads their PROXY protocol headers. The headers are
+/// read concurrently, so that slow connections do not block others. Connections without valid
+/// header are closed.
+pub fn incoming(
+    listener: TcpListener,
+    trusted: Arc<[IpNet]>,
+) -> impl Stream<Item = io::Result<ProxiedStream>> + Send + 'static {
+    let (sender, receiver) = mpsc::channel(64);
+    tokio::spawn(async move {
+        // stops accepting once the stream is dropped (e.g. on shutdown)
+        while !sender.is_closed() {
+            let (mut stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    warn!(error = %err, "failed to accept connection");
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            };
+            let sender = sender.clone();
+            let trusted = Arc::clone(&trusted);
+            tokio::spawn(async move {
+                let source = match tokio::time::timeout(HEADER_TIMEOUT, read_header(&mut stream))
+                    .await
+                {
+                    Ok(Ok(source)) => source,
+                    Ok(Err(err)) => {
+                        debug!(error = %err, peer = %peer, "closing connection: invalid PROXY protocol header");
+                        return;
+                    }
+                    Err(_) => {
+                        debug!(peer = %peer, "closing connection: PROXY protocol header timed out");
+                        return;
+                    }
+                };
+                let remote = source
+                    .filter(|_| is_trusted(&trusted, &peer.ip().to_canonical()))
+                    .unwrap_or(peer);
+                let proxied = ProxiedStream {
+                    local: stream.local_addr().ok(),
+                    stream,
+                    remote,
+                };
+                let _ = sender.send(Ok(proxied)).await;
+            });
+        }
+    });
+    futures::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|stream| (stream, receiver))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn networks(values: &[&str]) -> Vec<IpNet> {
+        values.iter().map(|value| value.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn client_ip_trusted_proxies() {
+        // given
+        let trusted = networks(&["10.0.0.0/8"]);
+        let proxy = Some("10.0.0.1".parse().unwrap());
+        let untrusted = Some("203.0.113.7".parse().unwrap());
+        let forwarded_for = Some("198.51.100.1, 192.0.2.1, 10.0.0.2");
+
+        // when
+        let behind_proxy = client_ip(proxy, forwarded_for, &trusted);
+        let forged = client_ip(untrusted, forwarded_for, &trusted);
+        let mapped = client_ip(Some("::ffff:192.0.2.1".parse().unwrap()), None, &trusted);
+
+        // then
+        assert_eq!(Some("192.0.2.1".parse().unwrap()), behind_proxy);
+        assert_eq!(untrusted, forged);
+        assert_eq!(Some("192.0.2.1".parse().unwrap()), mapped);
+    }
+
+    #[tokio::test]
+    async fn read_header_versions() {
+        // given
+        let v1 = b"PROXY TCP4 192.0.2.1 10.0.0.1 56324 9990\r\nGET /".to_vec();
+        let mut v2 = V2_SIGNATURE.to_vec();
+        v2.extend([
+            0x21, 0x11, 0, 12, 192, 0, 2, 1, 10, 0, 0, 1, 0xdc, 0x04, 0x27, 0x06,
+        ]);
+        v2.extend(b"GET /");
+        let local = [V2_SIGNATURE.as_slice(), &[0x20, 0x00, 0, 0]].concat();
+
+        // when
+        let mut v1 = v1.as_slice();
+        let from_v1 = read_header(&mut v1).await.unwrap();
+        let mut v2 = v2.as_slice();
+        let from_v2 = read_header(&mut v2).await.unwrap();
+        let from_local = read_header(&mut local.as_slice()).await.unwrap();
+        let missing = read_header(&mut b"GET / HTTP/1.1\r\n".as_slice()).await;
+
+        // then
+        let expected = Some("192.0.2.1:56324".parse().unwrap());
+        assert_eq!(expected, from_v1);
+        assert_eq!(b"GET /", v1);
+        assert_eq!(expected, from_v2);
+        assert_eq!(b"GET /", v2);
+        assert_eq!(None, from_local);
+        assert!(missing.is_err());
+    }
+}
@@ -0,0 +1,124 @@
+//! The pushgateway module provides the metrics push mode of Xenos. It periodically pushes all
+//! [prometheus] metrics to a [pushgateway](https://github.com/prometheus/pushgateway). It is intended
+//! for environments where the `/metrics` endpoint cannot be scraped (e.g. serverless or NAT-ed nodes).
+
+use crate::settings::MetricsPush;
+use lazy_static::lazy_static;
+use prometheus::{Encoder, TextEncoder};
+use tracing::{info, warn};
+
+lazy_static! {
+    /// The http client for pushing metrics, uses arc internally
+    static ref HTTP_CLIENT: reqwest::Client = reqwest::Client::builder().build().unwrap();
+}
+
+/// [PushError] is an error that occurred while pushing metrics to the pushgateway.
+#[derive(thiserror::Error, Debug)]
+pub enum PushError {
+    #[error(transparent)]
+    Prometheus(#[from] prometheus::Error),
+
+    #[error(transparent)]
+    Reqwest(#[from] reqwest::Error),
+
+    /// The pushgateway rejected the metrics.
+    #[error("pushgateway responded with status {0}")]
+    Status(reqwest::StatusCode),
+}
+
+/// Builds the pushgateway url of the metrics group, e.g. `http://pushgateway:9091/metrics/job/xenos`.
+/// The group is identified by the job and optionally the instance.
+fn push_url(settings: &MetricsPush) -> String {
+    let endpoint = settings.endpoint.trim_end_matches('/');
+    match &settings.instance {
+        Some(instance) => format!(
+            "{}/metrics/job/{}/instance/{}",
+            endpoint, settings.job, instance
+        ),
+        None => format!("{}/metrics/job/{}", endpoint, settings.job),
+    }
+}
+
+/// Pushes all [prometheus] metrics to the pushgateway once. It replaces all metrics of the group.
+#[tracing::instrument(skip(settings))]
+pub async fn push(settings: &MetricsPush) -> Result<(), PushError> {
+    let encoder = TextEncoder::new();
+    let mut buffer = vec![];
+    encoder.encode(&prometheus::gather(), &mut buffer)?;
+
+    let mut request = HTTP_CLIENT
+        .put(push_url(settings))
+        .header(reqwest::header::CONTENT_TYPE, encoder.format_type())
+        .body(buffer);
+    if settings.auth_enabled {
+        request = request.basic_auth(&settings.username, Some(&settings.password));
+    }
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        return Err(PushError::Status(response.status()));
+    }
+    Ok(())
+}
+
+/// Periodically pushes all [prometheus] metrics to the pushgateway. Failed pushes are logged and
+/// retried with the next interval. It never returns.
+pub async fn push_periodically(settings: MetricsPush) {
+    info!(
+        endpoint = settings.endpoint,
+        job = settings.job,
+        "pushing metrics to pushgateway"
+    );
+    let mut interval = tokio::time::interval(settings.interval);
+    loop {
+        interval.tick().await;
+        if let Err(err) = push(&settings).await {
+            warn!(error = %err, "failed to push metrics to pushgateway");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    fn settings(instance: Option<&str>) -> MetricsPush {
+        MetricsPush {
+            enabled: true,
+            endpoint: "http://pushgateway:9091/".to_string(),
+            job: "xenos".to_string(),
+            instance: instance.map(str::to_string),
+            interval: Duration::from_secs(15),
+            auth_enabled: false,
+            username: "username".to_string(),
+            password: "password".to_string(),
+        }
+    }
+
+    #[test]
+    fn push_url_job() {
+        // given
+        let settings = settings(None);
+
+        // when
+        let url = push_url(&settings);
+
+        // then
+        assert_eq!("http://pushgateway:9091/metrics/job/xenos", url);
+    }
+
+    #[test]
+    fn push_url_instance() {
+        // given
+        let settings = settings(Some("xenos-0"));
+
+        // when
+        let url = push_url(&settings);
+
+        // then
+        assert_eq!(
+            "http://pushgateway:9091/metrics/job/xenos/instance/xenos-0",
+            url
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! The refresh module provides the access tracking for the background refresh of hot entries. The
+//! [Service](crate::service::Service) counts the accesses of profiles and skins and periodically
+//! refreshes the hottest entries shortly before they expire. This spreads the mojang requests evenly
+//! instead of in bursts when popular entries expire at once.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+tokio::task_local! {
+    /// Whether the current task is untracked (e.g. a background refresh).
+    static UNTRACKED: ();
+}
+
+/// Runs a future (e.g. a background refresh) without tracking its accesses, so that refreshing an
+/// entry does not make it (or the entries it depends on) hotter.
+pub async fn untracked<F: Future>(future: F) -> F::Output {
+    UNTRACKED.scope((), future).await
+}
+
+/// Gets whether the accesses of the current task are tracked. Only accesses outside of [untracked]
+/// are tracked.
+pub fn is_tracked() -> bool {
+    UNTRACKED.try_with(|_| ()).is_err()
+}
+
+/// A [HotKey] identifies a cache entry whose accesses are tracked.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum HotKey {
+    /// The profile of an uuid.
+    Profile(Uuid),
+
+    /// The skin of an uuid.
+    Skin(Uuid),
+}
+
+/// The [AccessTracker] counts the accesses per [HotKey]. The counts decay (halve) each time the
+/// hottest keys are taken, so that keys that are no longer accessed cool down and are eventually
+/// removed. At most `capacity` keys are tracked, accesses of further keys are ignored until keys
+/// cooled down.
+#[derive(Debug)]
+pub struct AccessTracker {
+    capacity: usize,
+    counts: Mutex<HashMap<HotKey, u64>>,
+}
+
+impl AccessTracker {
+    /// Creates a new [AccessTracker] that tracks at most `capacity` keys.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            counts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records an access of a [HotKey]. The access is ignored if the key is not tracked yet and the
+    /// tracker is full.
+    pub fn record(&self, key: HotKey) {
+        let mut counts = self.counts.lock().unwrap();
+        if let Some(count) = counts.get_mut(&key) {
+            *count += 1;
+        } else if counts.len() < self.capacity {
+            counts.insert(key, 1);
+        }
+    }
+
+    /// Takes the (up to) `n` most accessed [HotKey] in descending order of accesses. Afterward, all
+    /// counts decay.
+    pub fn hottest(&self, n: usize) -> Vec<HotKey> {
+        let mut counts = self.counts.lock().unwrap();
+        let mut hottest: Vec<_> = counts.iter().map(|(key, count)| (*key, *count)).collect();
+        hottest.sort_unstable_by_key(|(_, count)| Reverse(*count));
+        hottest.truncate(n);
+
+        // decay all counts and forget keys that cooled down completely
+        counts.retain(|_, count| {
+            *count /= 2;
+            *count > 0
+        });
+        hottest.into_iter().map(|(key, _)| key).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use uuid::uuid;
+
+    #[test]
+    fn hottest_ordered() {
+        // given
+        let tracker = AccessTracker::new(10);
+        let hydrofin = uuid!("09879557e47945a9b434a56377674627");
+        let scrayos = uuid!("9c09eef4f68d4387975172bbff53d5a0");
+        tracker.record(HotKey::Profile(hydrofin));
+        tracker.record(HotKey::Skin(scrayos));
+        tracker.record(HotKey::Skin(scrayos));
+        tracker.record(HotKey::Profile(scrayos));
+
+        // when
+        let hottest = tracker.hottest(1);
+
+        // then
+        assert_eq!(vec![HotKey::Skin(scrayos)], hottest);
+    }
+
+    #[test]
+    fn hottest_decays() {
+        // given
+        let tracker = AccessTracker::new(10);
+        let hydrofin = uuid!("09879557e47945a9b434a56377674627");
+        tracker.record(HotKey::Profile(hydrofin));
+
+        // when
+        let first = tracker.hottest(10);
+        let second = tracker.hottest(10);
+
+        // then
+        assert_eq!(vec![HotKey::Profile(hydrofin)], first);
+        assert!(second.is_empty());
+    }
+
+    #[test]
+    fn record_capped() {
+        // given
+        let tracker = AccessTracker::new(1);
+        let hydrofin = uuid!("09879557e47945a9b434a56377674627");
+        let scrayos = uuid!("9c09eef4f68d4387975172bbff53d5a0");
+        tracker.record(HotKey::Profile(hydrofin));
+
+        // when
+        tracker.record(HotKey::Profile(scrayos));
+        tracker.record(HotKey::Profile(hydrofin));
+
+        // then
+        assert_eq!(vec![HotKey::Profile(hydrofin)], tracker.hottest(10));
+    }
+
+    #[tokio::test]
+    async fn untracked_scope() {
+        // given
+        let tracked = is_tracked();
+
+        // when
+        let untracked = untracked(async { is_tracked() }).await;
+
+        // then
+        assert!(tracked);
+        assert!(!untracked);
+    }
+}
@@ -0,0 +1,128 @@
+//! The animation module provides the frame extraction of animated capes. Some custom and legacy capes
+//! encode their animation frames vertically, each frame has the aspect ratio (2:1) of a static cape.
+//! The frames can be returned separately or as animated PNG (APNG).
+
+use image::error::{EncodingError, ImageFormatHint};
+use image::{ImageError, ImageFormat, RgbaImage};
+use std::io::Cursor;
+use std::time::Duration;
+
+/// The delay between the frames of an animated cape.
+pub const FRAME_DELAY: Duration = Duration::from_millis(100);
+
+/// Gets the number of frames of a cape with its dimensions. Capes are animated if their height is a
+/// multiple (greater than one) of the frame height (half of the width), otherwise they have a single
+/// frame.
+pub fn frame_count(width: u32, height: u32) -> u32 {
+    let frame_height = width / 2;
+    if frame_height == 0 || height <= frame_height || !height.is_multiple_of(frame_height) {
+        return 1;
+    }
+    height / frame_height
+}
+
+/// Extracts the frames of a cape from its PNG bytes. Static capes have a single frame (the whole
+/// image). Expects a valid cape.
+#[tracing::instrument(skip(cape_bytes))]
+pub fn extract_frames(cape_bytes: &[u8]) -> Result<Vec<RgbaImage>, ImageError> {
+    let cape_img = image::load_from_memory_with_format(cape_bytes, ImageFormat::Png)?;
+    let (width, height) = (cape_img.width(), cape_img.height());
+    let count = frame_count(width, height);
+    let frame_height = height / count;
+    let frames = (0..count)
+        .map(|frame| {
+            cape_img
+                .crop_imm(0, frame * frame_height, width, frame_height)
+                .to_rgba8()
+        })
+        .collect();
+    Ok(frames)
+}
+
+/// Encodes each frame as a separate PNG image.
+pub fn encode_frames(frames: &[RgbaImage]) -> Result<Vec<Vec<u8>>, ImageError> {
+    frames
+        .iter()
+        .map(|frame| {
+            let mut frame_bytes: Vec<u8> = Vec::new();
+            frame.write_to(&mut Cursor::new(&mut frame_bytes), ImageFormat::Png)?;
+            Ok(frame_bytes)
+        })
+        .collect()
+}
+
+/// Encodes the frames as an infinitely looping animated PNG (APNG) with a fixed delay between the
+/// frames. Expects at least one frame and that all frames have the same dimensions.
+pub fn encode_apng(frames: &[RgbaImage], delay: Duration) -> Result<Vec<u8>, ImageError> {
+    let (width, height) = frames.first().map(RgbaImage::dimensions).unwrap_or((0, 0));
+    let delay = delay.as_millis().try_into().unwrap_or(u16::MAX);
+    let mut apng_bytes: Vec<u8> = Vec::new();
+    let mut encoder = png::Encoder::new(&mut apng_bytes, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .set_animated(frames.len() as u32, 0)
+        .map_err(encoding_error)?;
+    encoder
+        .set_frame_delay(delay, 1000)
+        .map_err(encoding_error)?;
+    let mut writer = encoder.write_header().map_err(encoding_error)?;
+    for frame in frames {
+        writer
+            .write_image_data(frame.as_raw())
+            .map_err(encoding_error)?;
+    }
+    writer.finish().map_err(encoding_error)?;
+    Ok(apng_bytes)
+}
+
+/// Maps a [png::EncodingError] into an [ImageError] (like the png encoder of [image]).
+fn encoding_error(err: png::EncodingError) -> ImageError {
+    ImageError::Encoding(EncodingError::new(
+        ImageFormatHint::Exact(ImageFormat::Png),
+        err,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use image::Rgba;
+
+    fn new_cape(frames: u32) -> Vec<u8> {
+        let mut cape_img = RgbaImage::new(64, 32 * frames);
+        for frame in 0..frames {
+            cape_img.put_pixel(0, frame * 32, Rgba([frame as u8, 0, 0, 255]));
+        }
+        let mut cape_bytes: Vec<u8> = Vec::new();
+        cape_img
+            .write_to(&mut Cursor::new(&mut cape_bytes), ImageFormat::Png)
+            .unwrap();
+        cape_bytes
+    }
+
+    #[test]
+    fn frame_counts() {
+        assert_eq!(1, frame_count(64, 32));
+        assert_eq!(1, frame_count(22, 17));
+        assert_eq!(1, frame_count(64, 48));
+        assert_eq!(4, frame_count(64, 128));
+    }
+
+    #[test]
+    fn extract_animated_frames() {
+        // given
+        let cape = new_cape(3);
+
+        // when
+        let frames = extract_frames(&cape).unwrap();
+        let apng = encode_apng(&frames, FRAME_DELAY).unwrap();
+
+        // then
+        assert_eq!(3, frames.len());
+        assert!(frames.iter().all(|frame| frame.dimensions() == (64, 32)));
+        assert_eq!(&Rgba([2, 0, 0, 255]), frames[2].get_pixel(0, 0));
+        let decoder = png::Decoder::new(Cursor::new(apng)).read_info().unwrap();
+        assert_eq!(3, decoder.info().animation_control.unwrap().num_frames);
+    }
+}
@@ -0,0 +1,118 @@
+//! The colors module provides the dominant colors of skins and heads. The opaque pixels are quantized
+//! to a coarse palette and the most frequent buckets are returned with their average color, so that
+//! similar shades are combined into a single dominant color.
+
+use image::{ImageError, ImageFormat};
+use std::collections::HashMap;
+
+/// The default number of dominant colors.
+pub const DEFAULT_COUNT: usize = 5;
+
+/// The maximum number of dominant colors.
+pub const MAX_COUNT: usize = 16;
+
+/// The number of bits per channel of the quantized palette (e.g. `4` for 16 levels per channel).
+const QUANTIZATION_BITS: u32 = 4;
+
+/// The minimum alpha of pixels to be analyzed. Translucent pixels barely contribute to the look.
+const MIN_ALPHA: u8 = 128;
+
+/// A [DominantColor] is a color of an image with its share (in per mille) of the analyzed pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DominantColor {
+    pub rgb: [u8; 3],
+    pub share: u16,
+}
+
+impl DominantColor {
+    /// Gets the color as hex string (e.g. `#1f2e3d`).
+    pub fn hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.rgb[0], self.rgb[1], self.rgb[2])
+    }
+}
+
+/// The accumulated pixels of a quantized bucket.
+#[derive(Default)]
+struct Bucket {
+    count: u64,
+    sum: [u64; 3],
+}
+
+/// Computes up to `count` dominant colors of the opaque pixels of a PNG image, the most dominant
+/// first. Images without opaque pixels have no dominant colors.
+#[tracing::instrument(skip(image_bytes))]
+pub fn dominant_colors(image_bytes: &[u8], count: usize) -> Result<Vec<DominantColor>, ImageError> {
+    let image = image::load_from_memory_with_format(image_bytes, ImageFormat::Png)?.to_rgba8();
+    let mut buckets: HashMap<[u8; 3], Bucket> = HashMap::new();
+    let mut total = 0u64;
+    for pixel in image.pixels().filter(|pixel| pixel[3] >= MIN_ALPHA) {
+        let key = [0, 1, 2].map(|channel| pixel[channel] >> (8 - QUANTIZATION_BITS));
+        let bucket = buckets.entry(key).or_default();
+        bucket.count += 1;
+        for channel in 0..3 {
+            bucket.sum[channel] += pixel[channel] as u64;
+        }
+        total += 1;
+    }
+
+    let mut buckets: Vec<_> = buckets.into_iter().collect();
+    // the key breaks ties, so that the result is deterministic
+    buckets.sort_by(|(a_key, a), (b_key, b)| b.count.cmp(&a.count).then(a_key.cmp(b_key)));
+    let colors = buckets
+        .into_iter()
+        .take(count)
+        .map(|(_, bucket)| DominantColor {
+            rgb: bucket.sum.map(|sum| (sum / bucket.count) as u8),
+            share: (bucket.count * 1000 / total) as u16,
+        })
+        .collect();
+    Ok(colors)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+    use std::io::Cursor;
+
+    fn encode(image: &RgbaImage) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn dominant_colors_ordered() {
+        // given
+        let mut image = RgbaImage::from_pixel(4, 4, Rgba([200, 10, 10, 255]));
+        for x in 0..4 {
+            image.put_pixel(x, 0, Rgba([10, 10, 200, 255]));
+            image.put_pixel(x, 1, Rgba([0, 0, 0, 0]));
+        }
+        image.put_pixel(0, 2, Rgba([202, 12, 12, 255]));
+
+        // when
+        let colors = dominant_colors(&encode(&image), 4).unwrap();
+
+        // then
+        assert_eq!(2, colors.len());
+        assert_eq!("#c80a0a", colors[0].hex());
+        assert_eq!(666, colors[0].share);
+        assert_eq!([10, 10, 200], colors[1].rgb);
+        assert_eq!(333, colors[1].share);
+    }
+
+    #[test]
+    fn dominant_colors_transparent() {
+        // given
+        let image = RgbaImage::from_pixel(8, 8, Rgba([255, 255, 255, 0]));
+
+        // when
+        let colors = dominant_colors(&encode(&image), 4).unwrap();
+
+        // then
+        assert!(colors.is_empty());
+    }
+}
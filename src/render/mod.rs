@@ -0,0 +1,8 @@
+//! The render module provides the rendering of textures beyond the static images of mojang (e.g. the
+//! animation frames of capes, the conversion of legacy skins or the dominant colors of skins). The
+//! image work is run on the [render pool](pool::RenderPool).
+
+pub mod animation;
+pub mod colors;
+pub mod pool;
+pub mod skin_convert;
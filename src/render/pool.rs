@@ -0,0 +1,108 @@
+//! The pool module provides the offloading of image work (PNG decoding, rendering and encoding) to the
+//! blocking thread pool of tokio. Large skins take milliseconds to decode and encode, which would stall
+//! the async workers under burst load. The number of concurrent renders is capped, so that bursts
+//! cannot occupy the whole blocking pool.
+
+use crate::settings;
+use lazy_static::lazy_static;
+use prometheus::{register_histogram_vec, register_int_gauge_vec, HistogramVec, IntGaugeVec};
+use std::time::Instant;
+use tokio::sync::Semaphore;
+
+lazy_static! {
+    /// A gauge for the number of renders that wait for a concurrency permit.
+    static ref RENDER_QUEUE_GAUGE: IntGaugeVec = register_int_gauge_vec!(
+        "xenos_render_queue_depth",
+        "The number of renders waiting for a concurrency permit.",
+        &["kind"]
+    )
+    .unwrap();
+
+    /// A histogram for the time in seconds that renders waited for a concurrency permit.
+    static ref RENDER_QUEUE_HISTOGRAM: HistogramVec = register_histogram_vec!(
+        "xenos_render_queue_wait_seconds",
+        "The time renders waited for a concurrency permit in seconds.",
+        &["kind"],
+        vec![0.001, 0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.0, 5.0]
+    )
+    .unwrap();
+}
+
+/// The [RenderPool] runs image work on the blocking thread pool with a concurrency cap. If the
+/// offloading is disabled, the image work runs on the calling task instead.
+///
+/// ```rs
+/// let pool = RenderPool::new(&settings.render);
+/// let head = pool.run("head", move || build_skin_head(&skin, overlay, &settings)).await?;
+/// ```
+#[derive(Debug)]
+pub struct RenderPool {
+    permits: Option<Semaphore>,
+}
+
+impl RenderPool {
+    /// Creates a new [RenderPool] from its configuration.
+    pub fn new(settings: &settings::Render) -> Self {
+        Self {
+            permits: settings
+                .offload
+                .then(|| Semaphore::new(settings.concurrency.max(1))),
+        }
+    }
+
+    /// Runs image work of a kind (e.g. `head`) on the blocking thread pool, once a concurrency permit
+    /// is available. The time waited for the permit is observed per kind. Panics of the image work are
+    /// propagated to the caller.
+    pub async fn run<T, F>(&self, kind: &'static str, render: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let Some(permits) = &self.permits else {
+            return render();
+        };
+        let start = Instant::now();
+        let gauge = RENDER_QUEUE_GAUGE.with_label_values(&[kind]);
+        gauge.inc();
+        // the semaphore is never closed
+        let permit = permits.acquire().await.ok();
+        gauge.dec();
+        RENDER_QUEUE_HISTOGRAM
+            .with_label_values(&[kind])
+            .observe(start.elapsed().as_secs_f64());
+        let result = tokio::task::spawn_blocking(render).await;
+        drop(permit);
+        match result {
+            Ok(result) => result,
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            // blocking tasks are only cancelled if the runtime shuts down
+            Err(err) => panic!("render was cancelled: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn run_offloaded() {
+        // given
+        let pool = RenderPool::new(&settings::Render {
+            offload: true,
+            concurrency: 1,
+            queue: 1,
+        });
+        let caller = std::thread::current().id();
+
+        // when
+        let (first, second) = tokio::join!(
+            pool.run("test", || std::thread::current().id()),
+            pool.run("test", || 2 + 2),
+        );
+
+        // then
+        assert_ne!(caller, first);
+        assert_eq!(4, second);
+    }
+}
@@ -0,0 +1,98 @@
+//! The skin convert module provides the conversion of legacy skins (`64x32`) into the modern skin layout
+//! (`64x64`). Legacy skins have no separate left arm and leg, the modern client mirrors the right limbs
+//! instead. The conversion applies the same mirroring, so that all skins can be processed uniformly.
+
+use image::{imageops, GenericImageView, ImageError, ImageFormat, RgbaImage};
+use std::io::Cursor;
+
+/// The faces of the right limbs that are mirrored into the left limbs. Each face is described by its
+/// source position, the offset to its target position and its dimensions (like in the modern client).
+const MIRRORED_FACES: [(u32, u32, i64, i64, u32, u32); 12] = [
+    // right leg (top, bottom, sides) to left leg
+    (4, 16, 16, 32, 4, 4),
+    (8, 16, 16, 32, 4, 4),
+    (0, 20, 24, 32, 4, 12),
+    (4, 20, 16, 32, 4, 12),
+    (8, 20, 8, 32, 4, 12),
+    (12, 20, 16, 32, 4, 12),
+    // right arm (top, bottom, sides) to left arm
+    (44, 16, -8, 32, 4, 4),
+    (48, 16, -8, 32, 4, 4),
+    (40, 20, 0, 32, 4, 12),
+    (44, 20, -8, 32, 4, 12),
+    (48, 20, -16, 32, 4, 12),
+    (52, 20, -8, 32, 4, 12),
+];
+
+/// Checks whether a skin with the dimensions has the legacy layout.
+pub fn is_legacy(width: u32, height: u32) -> bool {
+    width == 64 && height == 32
+}
+
+/// Converts a legacy skin into the modern skin layout. The left arm and leg are mirrored from the right
+/// ones, the overlay layers of the body and limbs stay empty. Expects a legacy skin.
+pub fn convert_legacy(legacy: &RgbaImage) -> RgbaImage {
+    let mut skin = RgbaImage::new(64, 64);
+    imageops::replace(&mut skin, legacy, 0, 0);
+    for (x, y, offset_x, offset_y, width, height) in MIRRORED_FACES {
+        let face = imageops::flip_horizontal(&*legacy.view(x, y, width, height));
+        imageops::replace(&mut skin, &face, x as i64 + offset_x, y as i64 + offset_y);
+    }
+    skin
+}
+
+/// Converts the PNG bytes of a legacy skin into the modern skin layout. Returns `None` if the skin
+/// already has the modern layout. Expects a valid skin.
+#[tracing::instrument(skip(skin_bytes))]
+pub fn convert_legacy_bytes(skin_bytes: &[u8]) -> Result<Option<Vec<u8>>, ImageError> {
+    let skin_img = image::load_from_memory_with_format(skin_bytes, ImageFormat::Png)?;
+    if !is_legacy(skin_img.width(), skin_img.height()) {
+        return Ok(None);
+    }
+    let skin = convert_legacy(&skin_img.to_rgba8());
+    let mut converted_bytes: Vec<u8> = Vec::new();
+    skin.write_to(&mut Cursor::new(&mut converted_bytes), ImageFormat::Png)?;
+    Ok(Some(converted_bytes))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn convert_mirrors_limbs() {
+        // given
+        let red = Rgba([255, 0, 0, 255]);
+        let blue = Rgba([0, 0, 255, 255]);
+        let mut legacy = RgbaImage::new(64, 32);
+        // outer side of the right leg and arm
+        legacy.put_pixel(0, 20, red);
+        legacy.put_pixel(40, 20, blue);
+
+        // when
+        let skin = convert_legacy(&legacy);
+
+        // then
+        assert_eq!((64, 64), skin.dimensions());
+        assert_eq!(&red, skin.get_pixel(0, 20));
+        assert_eq!(&red, skin.get_pixel(27, 52));
+        assert_eq!(&blue, skin.get_pixel(40, 20));
+        assert_eq!(&blue, skin.get_pixel(43, 52));
+    }
+
+    #[test]
+    fn convert_bytes_skips_modern() {
+        // given
+        let mut modern_bytes: Vec<u8> = Vec::new();
+        RgbaImage::new(64, 64)
+            .write_to(&mut Cursor::new(&mut modern_bytes), ImageFormat::Png)
+            .unwrap();
+
+        // when
+        let converted = convert_legacy_bytes(&modern_bytes).unwrap();
+
+        // then
+        assert_eq!(None, converted);
+    }
+}
@@ -0,0 +1,322 @@
+//! The response cache module provides an in-process cache of the encoded rest responses of the image
+//! endpoints (skin, cape, head and texture). Repeated identical requests are served from the cache
+//! with a short time-to-live, so that the responses are not encoded again (e.g. JSON, base64 and
+//! PNG rendering). The responses are only cached in addition to the entries of the [Cache](crate::cache::Cache).
+
+use crate::cache::clock::{Clock, SystemClock};
+use crate::settings;
+use crate::tenant;
+use axum::body::{to_bytes, Body};
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use bytes::Bytes;
+use lazy_static::lazy_static;
+use moka::future::Cache;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use std::sync::Arc;
+use tracing::warn;
+
+lazy_static! {
+    /// A counter for the response cache lookups by their result (`hit` or `miss`).
+    static ref RESPONSE_CACHE_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "xenos_response_cache_total",
+        "The rest response cache lookups by their result.",
+        &["route", "result"]
+    )
+    .unwrap();
+}
+
+/// The rest routes whose responses are cached.
+pub const CACHED_ROUTES: [&str; 4] = ["/skin", "/cape", "/head", "/texture/:texture_id"];
+
+/// The max size of the request bodies of the cached routes in bytes.
+pub const MAX_REQUEST_BYTES: usize = 64 * 1024;
+
+/// The request headers that change the encoding of a response. They are part of the [ResponseKey].
+const KEY_HEADERS: [&str; 3] = ["accept", "accept-encoding", "x-uuid-format"];
+
+/// A [ResponseKey] identifies a response by the route and parameters of its request (method, uri,
+/// body and encoding headers). Responses are isolated per tenant.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ResponseKey {
+    tenant: Option<Arc<str>>,
+    method: String,
+    uri: String,
+    headers: Vec<Option<String>>,
+    body: Bytes,
+}
+
+impl ResponseKey {
+    /// Creates a new [ResponseKey] from the parts and (buffered) body of a request.
+    pub fn new(parts: &Parts, body: &Bytes) -> Self {
+        let headers = KEY_HEADERS
+            .iter()
+            .map(|name| {
+                parts
+                    .headers
+                    .get(*name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string)
+            })
+            .collect();
+        Self {
+            tenant: tenant::current(),
+            method: parts.method.to_string(),
+            uri: parts.uri.to_string(),
+            headers,
+            body: body.clone(),
+        }
+    }
+}
+
+/// The JSON key of the age of a response body.
+const AGE_KEY: &[u8] = b"\"age_seconds\":";
+
+/// An [AgeField] is the position and value of the age within an encoded response body.
+#[derive(Debug, Clone, Copy)]
+struct AgeField {
+    start: usize,
+    end: usize,
+    age_seconds: u64,
+}
+
+impl AgeField {
+    /// Finds the age within an encoded (JSON) response body, if any.
+    fn find(body: &[u8]) -> Option<Self> {
+        let start = body
+            .windows(AGE_KEY.len())
+            .position(|window| window == AGE_KEY)?
+            + AGE_KEY.len();
+        let len = body[start..]
+            .iter()
+            .take_while(|byte| byte.is_ascii_digit())
+            .count();
+        let end = start + len;
+        let age_seconds = std::str::from_utf8(&body[start..end]).ok()?.parse().ok()?;
+        Some(Self {
+            start,
+            end,
+            age_seconds,
+        })
+    }
+}
+
+/// A [CachedResponse] is an encoded (successful) rest response. The age within its body is updated
+/// when it is served, so that it does not stay at the age of the time of its encoding.
+#[derive(Debug, Clone)]
+struct CachedResponse {
+    headers: HeaderMap,
+    body: Bytes,
+    age: Option<AgeField>,
+    created: u64,
+}
+
+impl CachedResponse {
+    /// Converts the [CachedResponse] into a response at a unix time in seconds.
+    fn into_response(self, now: u64) -> Response {
+        let body = match self.age {
+            Some(age) => {
+                let age_seconds = age.age_seconds + now.saturating_sub(self.created);
+                let mut body = Vec::with_capacity(self.body.len() + 4);
+                body.extend_from_slice(&self.body[..age.start]);
+                body.extend_from_slice(age_seconds.to_string().as_bytes());
+                body.extend_from_slice(&self.body[age.end..]);
+                Bytes::from(body)
+            }
+            None => self.body,
+        };
+        let mut headers = self.headers;
+        headers.remove(header::CONTENT_LENGTH);
+        let mut response = Response::new(Body::from(body));
+        *response.headers_mut() = headers;
+        response
+    }
+}
+
+/// The [ResponseCache] holds the encoded rest responses of the image endpoints. Its capacity is
+/// weighted by the size of the response bodies.
+#[derive(Debug)]
+pub struct ResponseCache {
+    responses: Cache<ResponseKey, CachedResponse>,
+    clock: Arc<dyn Clock>,
+}
+
+impl ResponseCache {
+    /// Creates a new (empty) [ResponseCache] from its configuration.
+    pub fn new(settings: &settings::ResponseCache) -> Self {
+        let responses = Cache::builder()
+            .max_capacity(settings.max_bytes)
+            .weigher(|_, response: &CachedResponse| {
+                response.body.len().try_into().unwrap_or(u32::MAX)
+            })
+            .time_to_live(settings.ttl)
+            .build();
+        Self {
+            responses,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Replaces the [Clock] of the [ResponseCache]. The clock is used to update the age of the
+    /// cached responses.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Gets the cached response of a request on a route, if any.
+    pub async fn get(&self, route: &str, key: &ResponseKey) -> Option<Response> {
+        let response = self.responses.get(key).await;
+        let result = match response {
+            Some(_) => "hit",
+            None => "miss",
+        };
+        RESPONSE_CACHE_COUNTER
+            .with_label_values(&[route, result])
+            .inc();
+        let now = self.clock.now_seconds();
+        response.map(|response| response.into_response(now))
+    }
+
+    /// Caches a response of a request and returns it. Only successful responses are cached, that do
+    /// not limit their caching themselves (e.g. placeholders).
+    pub async fn insert(&self, key: ResponseKey, response: Response) -> Response {
+        if response.status() != StatusCode::OK
+            || response.headers().contains_key(header::CACHE_CONTROL)
+        {
+            return response;
+        }
+        let (parts, body) = response.into_parts();
+        let body = match to_bytes(body, usize::MAX).await {
+            Ok(body) => body,
+            Err(err) => {
+                warn!(error = %err, "failed to buffer response body");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+        let cached = CachedResponse {
+            headers: parts.headers.clone(),
+            age: AgeField::find(&body),
+            body: body.clone(),
+            created: self.clock.now_seconds(),
+        };
+        self.responses.insert(key, cached).await;
+        Response::from_parts(parts, Body::from(body))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::clock::ManualClock;
+    use axum::http::Request;
+    use std::time::Duration;
+
+    fn new_cache() -> ResponseCache {
+        ResponseCache::new(&settings::ResponseCache {
+            enabled: true,
+            ttl: Duration::from_secs(10),
+            max_bytes: 1024,
+        })
+    }
+
+    fn new_key(body: &'static str) -> ResponseKey {
+        let (parts, _) = Request::post("/head")
+            .header("accept", "application/json")
+            .body(())
+            .unwrap()
+            .into_parts();
+        ResponseKey::new(&parts, &Bytes::from_static(body.as_bytes()))
+    }
+
+    #[tokio::test]
+    async fn insert_only_successful() {
+        // given
+        let cache = new_cache();
+        let failed = Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap();
+        cache
+            .insert(
+                new_key("{\"uuid\":\"a\"}"),
+                Response::new(Body::from("head")),
+            )
+            .await;
+        cache.insert(new_key("{\"uuid\":\"b\"}"), failed).await;
+
+        // when
+        let hit = cache.get("/head", &new_key("{\"uuid\":\"a\"}")).await;
+        let failed = cache.get("/head", &new_key("{\"uuid\":\"b\"}")).await;
+
+        // then
+        let body = to_bytes(hit.unwrap().into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, Bytes::from_static(b"head"));
+        assert!(failed.is_none());
+    }
+
+    #[tokio::test]
+    async fn get_updates_age() {
+        // given
+        let clock = Arc::new(ManualClock::new(1000));
+        let cache = new_cache().with_clock(clock.clone());
+        let response = Response::new(Body::from(
+            "{\"timestamp\":990,\"age_seconds\":10,\"stale\":false}",
+        ));
+        cache.insert(new_key("{\"uuid\":\"a\"}"), response).await;
+        clock.advance(Duration::from_secs(5));
+
+        // when
+        let hit = cache.get("/head", &new_key("{\"uuid\":\"a\"}")).await;
+
+        // then
+        let body = to_bytes(hit.unwrap().into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            body,
+            Bytes::from_static(b"{\"timestamp\":990,\"age_seconds\":15,\"stale\":false}")
+        );
+    }
+
+    #[tokio::test]
+    async fn insert_no_placeholder() {
+        // given
+        let cache = new_cache();
+        let placeholder = Response::builder()
+            .header(header::CACHE_CONTROL, "max-age=60")
+            .body(Body::from("head"))
+            .unwrap();
+
+        // when
+        let response = cache.insert(new_key("{\"uuid\":\"a\"}"), placeholder).await;
+
+        // then
+        assert_eq!(StatusCode::OK, response.status());
+        assert!(cache
+            .get("/head", &new_key("{\"uuid\":\"a\"}"))
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn insert_buffering_failed() {
+        // given
+        let cache = new_cache();
+        let chunks = vec![Err::<Bytes, _>(std::io::Error::other("failed"))];
+        let response = Response::new(Body::from_stream(futures_util::stream::iter(chunks)));
+
+        // when
+        let response = cache.insert(new_key("{\"uuid\":\"a\"}"), response).await;
+
+        // then
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert!(cache
+            .get("/head", &new_key("{\"uuid\":\"a\"}"))
+            .await
+            .is_none());
+    }
+}
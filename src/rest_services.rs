@@ -1,46 +1,150 @@
+use crate::access_log;
+use crate::access_log::AccessEntry;
 use crate::cache::level::CacheLevel;
+use crate::deadline;
+use crate::deadline::{parse_request_timeout, REQUEST_TIMEOUT_HEADER};
+#[cfg(feature = "diagnostics")]
+use crate::diagnostics;
 use crate::error::ServiceError;
+use crate::events::ProfileEvent;
+use crate::faults::FaultState;
+use crate::identity::IdentityResolver;
+use crate::ip_filter;
+use crate::logging::{self, LogLevelError};
 use crate::mojang::Mojang;
+use crate::openmetrics;
 use crate::proto::{
-    CapeRequest, CapeResponse, HeadRequest, HeadResponse, ProfileRequest, ProfileResponse,
-    SkinRequest, SkinResponse, UuidRequest, UuidResponse, UuidsRequest, UuidsResponse,
+    parse_uuid, BlockedServerRequest, BlockedServerResponse, BlockedServersResponse,
+    BuildTexturesRequest, BuildTexturesResponse, CapeRequest, CapeResponse, ChecksumRequest,
+    ChecksumResponse, ColorsRequest, ColorsResponse, HeadRequest, HeadResponse,
+    ProfileBundleRequest, ProfileBundleResponse, ProfileRequest, ProfileResponse, SkinRequest,
+    SkinResponse, StatusResponse, UuidRequest, UuidResponse, UuidsRequest, UuidsResponse,
+    ValidateUuidRequest, ValidateUuidResponse,
 };
-use crate::service::Service;
+#[cfg(feature = "history")]
+use crate::proto::{
+    NameHistoryRequest, NameHistoryResponse, SkinHistoryRequest, SkinHistoryResponse,
+};
+use crate::proxy::request_client_ip;
+use crate::response_cache::{ResponseCache, ResponseKey, CACHED_ROUTES, MAX_REQUEST_BYTES};
+use crate::sampling;
+use crate::service::{record_image_source, Service};
+use crate::settings::{Admin, Capability, Placeholder, UuidFormat};
+use crate::tenant;
+use crate::usage::{UsageReport, ANONYMOUS_CLIENT};
 use axum::{
+    body::{to_bytes, Body},
+    extract::{ConnectInfo, MatchedPath, Path, Query, Request},
     http,
     http::StatusCode,
-    response::{IntoResponse, Response},
+    middleware::Next,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
     Extension, Json,
 };
 use axum_auth::AuthBasic;
+use futures_util::Stream;
+use ipnet::IpNet;
 use prometheus::{Encoder, TextEncoder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing_subscriber::filter::LevelFilter;
 use uuid::Uuid;
 
 /// [RestResult] is an alias for a rest [Json] result with [ServiceError]
 type RestResult<T> = Result<Json<T>, ServiceError>;
 
+/// [PlaceholderResult] is an alias for a rest [Json] result of an image that may be a placeholder. The
+/// headers limit the caching of placeholders.
+type PlaceholderResult<T> = Result<(http::HeaderMap, Json<T>), ServiceError>;
+
+/// Builds the headers of an image response. Placeholders may only be cached briefly by clients.
+fn placeholder_headers(settings: &Placeholder, placeholder: bool) -> http::HeaderMap {
+    let mut headers = http::HeaderMap::new();
+    if placeholder {
+        let cache_control = format!("max-age={}", settings.max_age.as_secs());
+        headers.insert(
+            http::header::CACHE_CONTROL,
+            http::HeaderValue::from_str(&cache_control).unwrap(),
+        );
+    }
+    headers
+}
+
+/// A [Problem] is a structured error payload as described in [RFC 7807](https://www.rfc-editor.org/rfc/rfc7807).
+/// It is extended by the machine-readable reason and the (optional) retry hint of the error.
+#[derive(Debug, Serialize)]
+struct Problem {
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    title: &'static str,
+    status: u16,
+    detail: String,
+    reason: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after: Option<u64>,
+}
+
 // implement automatic ServiceError to response conversion
 // with that, ServiceError can be returned in a result
 impl IntoResponse for ServiceError {
     fn into_response(self) -> Response {
-        match self {
+        let (status, detail) = match &self {
             ServiceError::Unavailable => (
                 StatusCode::SERVICE_UNAVAILABLE,
-                "unable to request resource from mojang api",
-            )
-                .into_response(),
-            ServiceError::NotFound => (StatusCode::NOT_FOUND, "not found").into_response(),
-            _ => (StatusCode::INTERNAL_SERVER_ERROR, "internal error").into_response(),
+                "unable to request resource from mojang api".to_string(),
+            ),
+            ServiceError::NotFound => (StatusCode::NOT_FOUND, "not found".to_string()),
+            err @ ServiceError::Disabled(_) => (StatusCode::NOT_IMPLEMENTED, err.to_string()),
+            err @ ServiceError::InvalidTexture(_) => (StatusCode::BAD_GATEWAY, err.to_string()),
+            err @ ServiceError::InvalidArgument(_) => (StatusCode::BAD_REQUEST, err.to_string()),
+            ServiceError::UuidError(_) => (StatusCode::BAD_REQUEST, "invalid uuid".to_string()),
+            err @ (ServiceError::QuotaExceeded { .. } | ServiceError::RateLimited { .. }) => {
+                (StatusCode::TOO_MANY_REQUESTS, err.to_string())
+            }
+            _ => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal error".to_string(),
+            ),
+        };
+        let retry_after = self.retry_after().map(|retry_after| retry_after.as_secs());
+        let problem = Problem {
+            problem_type: "about:blank",
+            title: status.canonical_reason().unwrap_or("Unknown"),
+            status: status.as_u16(),
+            detail,
+            reason: self.reason(),
+            retry_after,
+        };
+        let mut response = (
+            status,
+            [(http::header::CONTENT_TYPE, "application/problem+json")],
+            Json(problem),
+        )
+            .into_response();
+        if let Some(retry_after) = retry_after {
+            response
+                .headers_mut()
+                .insert(http::header::RETRY_AFTER, retry_after.into());
         }
+        response
     }
 }
 
 /// An [axum] handler for providing [prometheus] metrics. If enabled by the service, it validates
-/// basic auth.
+/// basic auth. The exposition format (OpenMetrics or Prometheus text) and the compression are
+/// negotiated with the headers of the scrape request.
 pub async fn metrics<L, R, M>(
     auth: Option<AuthBasic>,
     Extension(service): Extension<Arc<Service<L, R, M>>>,
+    request_headers: http::HeaderMap,
 ) -> Response
 where
     L: CacheLevel,
@@ -48,32 +152,657 @@ where
     M: Mojang,
 {
     // check basic auth
-    let ms = &service.settings().metrics;
-    if ms.auth_enabled {
-        if let Some(AuthBasic((username, password))) = auth {
-            if username != ms.username || password != Some(ms.password.clone()) {
-                return (StatusCode::UNAUTHORIZED, "invalid auth").into_response();
-            }
-        } else {
-            return (StatusCode::UNAUTHORIZED, "missing basic auth").into_response();
-        }
+    if let Err(reason) = check_admin_auth(auth, &service.settings().admin) {
+        return (StatusCode::UNAUTHORIZED, reason).into_response();
     }
+    let ms = &service.settings().metrics;
 
-    // get metrics
-    let encoder = TextEncoder::new();
+    // get metrics in the negotiated format
     let metric_families = prometheus::gather();
-    let mut buffer = vec![];
-    encoder.encode(&metric_families, &mut buffer).unwrap();
+    let (content_type, buffer) = match ms.openmetrics && openmetrics::accepts(&request_headers) {
+        true => (
+            openmetrics::CONTENT_TYPE,
+            openmetrics::encode(&metric_families).into_bytes(),
+        ),
+        false => {
+            let mut buffer = vec![];
+            TextEncoder::new()
+                .encode(&metric_families, &mut buffer)
+                .unwrap();
+            (prometheus::TEXT_FORMAT, buffer)
+        }
+    };
     Response::builder()
         .status(StatusCode::OK)
-        .header(http::header::CONTENT_TYPE, encoder.format_type())
+        .header(http::header::CONTENT_TYPE, content_type)
+        .header(http::header::VARY, "accept")
         .body(buffer.into())
         .expect("failed to build metrics response")
 }
 
+/// An [axum] middleware for the usage accounting of the rest gateway. It counts the request for the
+/// client (api key) of the configured header and rejects it if the client exceeded its quota.
+pub async fn usage<L, R, M>(
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    request: Request,
+    next: Next,
+) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    let client = client_identity(&request, service.identity());
+    if let Err(err) = service.record_usage(&client).await {
+        return err.into_response();
+    }
+    next.run(request).await
+}
+
+/// An [axum] middleware that serves the responses of the [cached routes](CACHED_ROUTES) from the
+/// [ResponseCache]. The responses of other routes are not cached.
+pub async fn response_cache(
+    Extension(cache): Extension<Arc<ResponseCache>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string());
+    let Some(route) = route.filter(|route| CACHED_ROUTES.contains(&route.as_str())) else {
+        return next.run(request).await;
+    };
+    let (parts, body) = request.into_parts();
+    let body = match to_bytes(body, MAX_REQUEST_BYTES).await {
+        Ok(body) => body,
+        Err(_) => return (StatusCode::PAYLOAD_TOO_LARGE, "request too large").into_response(),
+    };
+    let key = ResponseKey::new(&parts, &body);
+    if let Some(response) = cache.get(&route, &key).await {
+        return response;
+    }
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    cache.insert(key, response).await
+}
+
+/// An [axum] middleware that binds the tenant of the client (api key or its alias) of the configured
+/// usage header to the request.
+pub async fn tenant<L, R, M>(
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    request: Request,
+    next: Next,
+) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    let settings = service.settings();
+    let client = request
+        .headers()
+        .get(&settings.usage.header)
+        .and_then(|value| value.to_str().ok())
+        .map_or(ANONYMOUS_CLIENT, |api_key| settings.usage.client(api_key));
+    let tenant = tenant::resolve(&settings.tenancy, client);
+    tenant::scope(tenant, next.run(request)).await
+}
+
+/// Resolves the client ip address of a request. The peer address requires the [ConnectInfo] of the
+/// server.
+fn client_ip(request: &Request, trusted: &[IpNet]) -> Option<IpAddr> {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0);
+    request_client_ip(peer, request.headers(), trusted)
+}
+
+/// Resolves the identity of the client of a request (see [IdentityResolver]). The peer address
+/// requires the [ConnectInfo] of the server.
+fn client_identity(request: &Request, resolver: &IdentityResolver) -> String {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0);
+    resolver.resolve(request.headers(), peer)
+}
+
+/// An [axum] middleware that rejects requests of filtered ip addresses with `403 Forbidden`. The
+/// peer address requires the [ConnectInfo] of the server.
+pub async fn ip_filter<L, R, M>(
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    request: Request,
+    next: Next,
+) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    let settings = service.settings();
+    let ip = client_ip(&request, &settings.proxy.trusted);
+    if !ip_filter::is_allowed(&settings.ip_filter, ip) {
+        return (StatusCode::FORBIDDEN, "client ip address is not allowed").into_response();
+    }
+    next.run(request).await
+}
+
+/// An [axum] middleware that injects the [faults](crate::faults::Faults) of the service into the
+/// requests. Failed requests are answered with the status `503 Service Unavailable`.
+pub async fn faults<L, R, M>(
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    request: Request,
+    next: Next,
+) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    if let Some(faults) = service.faults() {
+        if faults.inject().await {
+            return (StatusCode::SERVICE_UNAVAILABLE, "injected fault").into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// An [axum] middleware that decides whether the tracing spans of a request are sampled, based on its
+/// matched route.
+pub async fn sampling<L, R, M>(
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    matched_path: Option<MatchedPath>,
+    request: Request,
+    next: Next,
+) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    let endpoint = match &matched_path {
+        Some(path) => path.as_str(),
+        None => request.uri().path(),
+    };
+    let sampled = sampling::sample(&service.settings().logging.sampling, endpoint);
+    sampling::scope(sampled, next.run(request)).await
+}
+
+/// An [axum] middleware that logs every request to the access log.
+pub async fn access_log<L, R, M>(
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    matched_path: Option<MatchedPath>,
+    request: Request,
+    next: Next,
+) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    let method = request.method().to_string();
+    let route = match matched_path {
+        Some(path) => path.as_str().to_string(),
+        None => request.uri().path().to_string(),
+    };
+    let client = client_identity(&request, service.identity());
+    let ip = client_ip(&request, &service.settings().proxy.trusted);
+    let start = Instant::now();
+    let (response, record) = access_log::scope(next.run(request)).await;
+    AccessEntry {
+        protocol: "rest",
+        method,
+        route,
+        client,
+        ip,
+        status: response.status().as_u16(),
+        latency: start.elapsed(),
+        record,
+    }
+    .log();
+    response
+}
+
+/// An [axum] middleware that propagates the deadline of the `X-Request-Timeout` header (in seconds).
+/// The request is cancelled with [StatusCode::GATEWAY_TIMEOUT] once the deadline is exceeded.
+pub async fn deadline(request: Request, next: Next) -> Response {
+    let timeout = request
+        .headers()
+        .get(REQUEST_TIMEOUT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_request_timeout);
+    let Some(timeout) = timeout else {
+        return next.run(request).await;
+    };
+    let deadline = tokio::time::Instant::now() + timeout;
+    let response =
+        tokio::time::timeout_at(deadline, deadline::scope(Some(deadline), next.run(request))).await;
+    match response {
+        Ok(response) => response,
+        Err(_) => (StatusCode::GATEWAY_TIMEOUT, "request deadline exceeded").into_response(),
+    }
+}
+
+/// An [axum] handler for providing the [UsageReport] of all clients. If enabled by the service, it
+/// validates basic auth.
+pub async fn usage_report<L, R, M>(
+    auth: Option<AuthBasic>,
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    // check basic auth
+    if let Err(reason) = check_admin_auth(auth, &service.settings().admin) {
+        return (StatusCode::UNAUTHORIZED, reason).into_response();
+    }
+
+    let report: Vec<UsageReport> = service.get_usage_report().await;
+    Json(report).into_response()
+}
+
+/// [DiagnosticsQuery] is the query of the runtime diagnostics.
+#[cfg(feature = "diagnostics")]
+#[derive(Debug, Deserialize)]
+pub struct DiagnosticsQuery {
+    /// Whether the traces of all tasks should be dumped.
+    #[serde(default)]
+    tasks: bool,
+}
+
+/// An [axum] handler for providing the [RuntimeDiagnostics](crate::diagnostics::RuntimeDiagnostics).
+/// If enabled by the service, it validates basic auth.
+#[cfg(feature = "diagnostics")]
+pub async fn runtime_diagnostics<L, R, M>(
+    auth: Option<AuthBasic>,
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    Query(query): Query<DiagnosticsQuery>,
+) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    // check basic auth
+    if let Err(reason) = check_admin_auth(auth, &service.settings().admin) {
+        return (StatusCode::UNAUTHORIZED, reason).into_response();
+    }
+
+    Json(diagnostics::collect(query.tasks).await).into_response()
+}
+
+/// An [axum] handler for providing the [StatusResponse], the current view of the service on the
+/// health of the mojang api.
+pub async fn status<L, R, M>(
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+) -> Json<StatusResponse>
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    Json(service.get_status().into())
+}
+
+/// [CacheOnlyState] is the state of the cache-only mode. It is used as request and response of the
+/// cache-only admin toggle.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CacheOnlyState {
+    /// Whether the cache-only mode is enabled.
+    enabled: bool,
+}
+
+/// Validates the basic auth of an admin (or debug) endpoint with the shared [Admin] credentials if
+/// enabled. It returns the reason if the request is unauthorized.
+fn check_admin_auth(auth: Option<AuthBasic>, settings: &Admin) -> Result<(), &'static str> {
+    if !settings.auth_enabled {
+        return Ok(());
+    }
+    match auth {
+        Some(AuthBasic((username, password))) => {
+            if !settings.is_authorized(&username, password.as_deref()) {
+                return Err("invalid auth");
+            }
+            Ok(())
+        }
+        None => Err("missing basic auth"),
+    }
+}
+
+/// An [axum] handler for providing the [CacheOnlyState]. If enabled by the service, it validates
+/// basic auth.
+pub async fn get_cache_only<L, R, M>(
+    auth: Option<AuthBasic>,
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    if let Err(reason) = check_admin_auth(auth, &service.settings().admin) {
+        return (StatusCode::UNAUTHORIZED, reason).into_response();
+    }
+    let enabled = service.is_cache_only();
+    Json(CacheOnlyState { enabled }).into_response()
+}
+
+/// An [axum] handler for changing the [CacheOnlyState] at runtime. If enabled by the service, it
+/// validates basic auth.
+pub async fn set_cache_only<L, R, M>(
+    auth: Option<AuthBasic>,
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    Json(payload): Json<CacheOnlyState>,
+) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    if let Err(reason) = check_admin_auth(auth, &service.settings().admin) {
+        return (StatusCode::UNAUTHORIZED, reason).into_response();
+    }
+    service.set_cache_only(payload.enabled);
+    let enabled = service.is_cache_only();
+    Json(CacheOnlyState { enabled }).into_response()
+}
+
+/// [PurgeRequest] is the request of the cache purge admin endpoint.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurgeRequest {
+    /// The glob pattern of the keys of the purged entries (e.g. `head.<uuid>.*`).
+    pattern: String,
+}
+
+/// [PurgeResponse] is the response of the cache purge admin endpoint.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurgeResponse {
+    /// The number of purged entries (of all cache levels).
+    purged: u64,
+}
+
+/// An [axum] handler for purging the cache entries that match a key pattern. If enabled by the
+/// service, it validates basic auth.
+pub async fn purge<L, R, M>(
+    auth: Option<AuthBasic>,
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    Json(payload): Json<PurgeRequest>,
+) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    if let Err(reason) = check_admin_auth(auth, &service.settings().admin) {
+        return (StatusCode::UNAUTHORIZED, reason).into_response();
+    }
+    match service.purge_cache(&payload.pattern).await {
+        Ok(purged) => Json(PurgeResponse { purged }).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+/// [PinnedState] is the list of pinned profiles. It is used as response of the pinned profiles admin
+/// endpoint.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PinnedState {
+    /// The (sorted) uuids of the pinned profiles in hyphenated form.
+    uuids: Vec<String>,
+}
+
+/// [PinRequest] is the request of the pinned profiles admin endpoint.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PinRequest {
+    /// The uuid of the profile that is pinned (or unpinned).
+    uuid: String,
+
+    /// Whether the profile should be pinned.
+    pinned: bool,
+}
+
+/// Builds the [PinnedState] response of the pinned profiles of a [Service].
+async fn pinned_response<L, R, M>(service: &Service<L, R, M>) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    let mut uuids: Vec<String> = service
+        .pinned()
+        .await
+        .iter()
+        .map(|uuid| uuid.hyphenated().to_string())
+        .collect();
+    uuids.sort();
+    Json(PinnedState { uuids }).into_response()
+}
+
+/// An [axum] handler for providing the [PinnedState]. If enabled by the service, it validates basic
+/// auth.
+pub async fn get_pinned<L, R, M>(
+    auth: Option<AuthBasic>,
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    if let Err(reason) = check_admin_auth(auth, &service.settings().admin) {
+        return (StatusCode::UNAUTHORIZED, reason).into_response();
+    }
+    pinned_response(&service).await
+}
+
+/// An [axum] handler for pinning (or unpinning) a profile. It responds with the resulting
+/// [PinnedState]. If enabled by the service, it validates basic auth.
+pub async fn set_pinned<L, R, M>(
+    auth: Option<AuthBasic>,
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    Json(payload): Json<PinRequest>,
+) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    if let Err(reason) = check_admin_auth(auth, &service.settings().admin) {
+        return (StatusCode::UNAUTHORIZED, reason).into_response();
+    }
+    let uuid = match parse_uuid(&payload.uuid) {
+        Ok(uuid) => uuid,
+        Err(err) => return ServiceError::from(err).into_response(),
+    };
+    if let Err(err) = service.set_pinned(&uuid, payload.pinned).await {
+        return err.into_response();
+    }
+    pinned_response(&service).await
+}
+
+/// [LogLevelState] is the runtime log level. It is used as request and response of the log level
+/// admin toggle.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogLevelState {
+    /// The log level (e.g. `debug`).
+    level: String,
+}
+
+/// Converts the result of a log level change into a [LogLevelState] response.
+fn log_level_response(result: Result<LevelFilter, LogLevelError>) -> Response {
+    match result {
+        Ok(level) => Json(LogLevelState {
+            level: level.to_string().to_lowercase(),
+        })
+        .into_response(),
+        Err(err @ LogLevelError::Invalid(_)) => {
+            (StatusCode::BAD_REQUEST, err.to_string()).into_response()
+        }
+        Err(err @ LogLevelError::Unavailable) => {
+            (StatusCode::SERVICE_UNAVAILABLE, err.to_string()).into_response()
+        }
+    }
+}
+
+/// An [axum] handler for providing the [LogLevelState]. If enabled by the service, it validates
+/// basic auth.
+pub async fn get_log_level<L, R, M>(
+    auth: Option<AuthBasic>,
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    if let Err(reason) = check_admin_auth(auth, &service.settings().admin) {
+        return (StatusCode::UNAUTHORIZED, reason).into_response();
+    }
+    log_level_response(logging::level())
+}
+
+/// An [axum] handler for changing the [LogLevelState] at runtime (until restart). If enabled by the
+/// service, it validates basic auth.
+pub async fn set_log_level<L, R, M>(
+    auth: Option<AuthBasic>,
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    Json(payload): Json<LogLevelState>,
+) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    if let Err(reason) = check_admin_auth(auth, &service.settings().admin) {
+        return (StatusCode::UNAUTHORIZED, reason).into_response();
+    }
+    log_level_response(logging::set_level(&payload.level))
+}
+
+/// An [axum] handler for providing the latest recorded mojang response headers per endpoint (see
+/// [HeaderSnapshot](crate::mojang::headers::HeaderSnapshot)). If enabled by the service, it validates basic auth.
+pub async fn mojang_headers<L, R, M>(
+    auth: Option<AuthBasic>,
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    if let Err(reason) = check_admin_auth(auth, &service.settings().admin) {
+        return (StatusCode::UNAUTHORIZED, reason).into_response();
+    }
+    Json(service.mojang().latest_headers()).into_response()
+}
+
+/// An [axum] handler for providing the injected [FaultState]. If enabled by the service, it validates
+/// basic auth.
+pub async fn get_faults<L, R, M>(
+    auth: Option<AuthBasic>,
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    if let Err(reason) = check_admin_auth(auth, &service.settings().admin) {
+        return (StatusCode::UNAUTHORIZED, reason).into_response();
+    }
+    match service.faults() {
+        Some(faults) => Json(faults.state()).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// An [axum] handler for changing the injected [FaultState] at runtime (until restart). If enabled by
+/// the service, it validates basic auth.
+pub async fn set_faults<L, R, M>(
+    auth: Option<AuthBasic>,
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    Json(payload): Json<FaultState>,
+) -> Response
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    if let Err(reason) = check_admin_auth(auth, &service.settings().admin) {
+        return (StatusCode::UNAUTHORIZED, reason).into_response();
+    }
+    let Some(faults) = service.faults() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match faults.set_state(payload) {
+        Ok(state) => Json(state).into_response(),
+        Err(err) => (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    }
+}
+
+/// [EventsQuery] holds the query parameters of the events stream.
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    /// The comma-separated uuids to subscribe to (e.g. `?uuids=<uuid>,<uuid>`). If not present,
+    /// the events of all profiles are streamed.
+    uuids: Option<String>,
+}
+
+/// An [axum] handler for the profile change events. The [events](ProfileEvent) are streamed as
+/// server-sent events with the event type as event name and the event as json data.
+pub async fn events<L, R, M>(
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    Query(query): Query<EventsQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, ServiceError>
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    let uuids = query
+        .uuids
+        .map(|uuids| uuids.split(',').map(parse_uuid).collect())
+        .transpose()?;
+    let receiver = service.subscribe_events();
+    let stream = futures_util::stream::unfold((receiver, uuids), |(mut receiver, uuids)| async {
+        let event = next_event(&mut receiver, uuids.as_ref()).await?;
+        let event = Event::default().event(event.event_type()).json_data(&event);
+        Some((event, (receiver, uuids)))
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Receives the next [ProfileEvent] of the subscribed uuids. Events that were missed by a slow
+/// subscriber are skipped. It returns `None` if the [Service] was dropped.
+async fn next_event(
+    receiver: &mut broadcast::Receiver<ProfileEvent>,
+    uuids: Option<&HashSet<Uuid>>,
+) -> Option<ProfileEvent> {
+    loop {
+        match receiver.recv().await {
+            Ok(event) if uuids.is_none_or(|uuids| uuids.contains(event.uuid())) => {
+                return Some(event)
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// [FormatQuery] holds the query parameters of the rest gateways that respond with uuids.
+#[derive(Debug, Deserialize)]
+pub struct FormatQuery {
+    /// The format of the uuids in the response (e.g. `?uuid_format=simple`). It overrides the
+    /// configured format.
+    uuid_format: Option<UuidFormat>,
+}
+
 /// An [axum] handler for [UuidRequest] rest gateway.
 pub async fn uuid<L, R, M>(
     Extension(service): Extension<Arc<Service<L, R, M>>>,
+    Query(query): Query<FormatQuery>,
     Json(payload): Json<UuidRequest>,
 ) -> RestResult<UuidResponse>
 where
@@ -81,13 +810,19 @@ where
     R: CacheLevel,
     M: Mojang,
 {
-    let username = &payload.username;
-    Ok(Json(service.get_uuid(username).await?.into()))
+    let format = query.uuid_format.unwrap_or(service.settings().uuid_format);
+    let uuid = UuidResponse::from(service.get_uuid(&payload.username).await?);
+    let expiry = &service.cache_entries().uuid;
+    Ok(Json(
+        uuid.with_uuid_format(format)
+            .with_staleness(expiry, service.now_seconds()),
+    ))
 }
 
 /// An [axum] handler for [UuidsRequest] rest gateway.
 pub async fn uuids<L, R, M>(
     Extension(service): Extension<Arc<Service<L, R, M>>>,
+    Query(query): Query<FormatQuery>,
     Json(payload): Json<UuidsRequest>,
 ) -> RestResult<UuidsResponse>
 where
@@ -95,13 +830,44 @@ where
     R: CacheLevel,
     M: Mojang,
 {
-    let usernames = &payload.usernames;
-    Ok(Json(service.get_uuids(usernames).await?.into()))
+    let format = query.uuid_format.unwrap_or(service.settings().uuid_format);
+    // partial failures are only reported per username if the statuses are requested
+    let mut uuids = match payload.include_status {
+        true => UuidsResponse::from(service.get_uuids_partial(&payload.usernames).await?),
+        false => UuidsResponse::from(service.get_uuids(&payload.usernames).await?),
+    };
+    if payload.exclude_sanctioned {
+        let sanctioned = service.get_sanctioned(&uuids.resolved_uuids()).await;
+        uuids = uuids.without_sanctioned(&sanctioned);
+    }
+    if payload.preserve_case {
+        uuids = uuids.with_requested_usernames(&payload.usernames);
+    }
+    let expiry = &service.cache_entries().uuid;
+    Ok(Json(
+        uuids
+            .with_not_found(payload.include_not_found)
+            .with_uuid_format(format)
+            .with_staleness(expiry, service.now_seconds()),
+    ))
+}
+
+/// [ProfileQuery] holds the query parameters of the [ProfileRequest] rest gateway.
+#[derive(Debug, Deserialize)]
+pub struct ProfileQuery {
+    /// The comma-separated fields mask (e.g. `?fields=name,properties`). It overrides the fields mask
+    /// of the request body.
+    fields: Option<String>,
+
+    /// The format of the uuid in the response (e.g. `?uuid_format=simple`). It overrides the
+    /// configured format.
+    uuid_format: Option<UuidFormat>,
 }
 
 /// An [axum] handler for [ProfileRequest] rest gateway.
 pub async fn profile<L, R, M>(
     Extension(service): Extension<Arc<Service<L, R, M>>>,
+    Query(query): Query<ProfileQuery>,
     Json(payload): Json<ProfileRequest>,
 ) -> RestResult<ProfileResponse>
 where
@@ -109,22 +875,78 @@ where
     R: CacheLevel,
     M: Mojang,
 {
-    let uuid = Uuid::try_parse(&payload.uuid)?;
-    Ok(Json(service.get_profile(&uuid).await?.into()))
+    let uuid = payload.parse_uuid()?;
+    let format = query.uuid_format.unwrap_or(service.settings().uuid_format);
+    let expiry = &service.cache_entries().profile;
+    let profile = ProfileResponse::from(service.get_profile(&uuid).await?)
+        .with_uuid_format(format)
+        .with_staleness(expiry, service.now_seconds());
+    let profile = match query.fields {
+        Some(fields) => profile.with_fields(&fields.split(',').collect::<Vec<_>>())?,
+        None => profile.with_fields(&payload.fields)?,
+    };
+    Ok(Json(profile))
+}
+
+/// An [axum] handler for [ProfileBundleRequest] rest gateway.
+pub async fn profile_bundle<L, R, M>(
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    Query(query): Query<FormatQuery>,
+    Json(payload): Json<ProfileBundleRequest>,
+) -> RestResult<ProfileBundleResponse>
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    let uuid = payload.parse_uuid()?;
+    let format = query.uuid_format.unwrap_or(service.settings().uuid_format);
+    let overlay = payload
+        .overlay
+        .unwrap_or(service.settings().head_overlay.default);
+    let bundle = service.get_profile_bundle(&uuid, overlay).await?;
+    Ok(Json(
+        ProfileBundleResponse::from(bundle)
+            .with_uuid_format(format)
+            .with_staleness(service.cache_entries(), service.now_seconds()),
+    ))
 }
 
 /// An [axum] handler for [SkinRequest] rest gateway.
 pub async fn skin<L, R, M>(
     Extension(service): Extension<Arc<Service<L, R, M>>>,
     Json(payload): Json<SkinRequest>,
-) -> RestResult<SkinResponse>
+) -> PlaceholderResult<SkinResponse>
 where
     L: CacheLevel,
     R: CacheLevel,
     M: Mojang,
 {
-    let uuid = Uuid::try_parse(&payload.uuid)?;
-    Ok(Json(service.get_skin(&uuid).await?.into()))
+    let uuid = payload.parse_uuid()?;
+    let entries = &service.cache_entries();
+    let skin = match payload.url_only {
+        true => SkinResponse::from(service.get_skin_url(&uuid).await?)
+            .with_staleness(&entries.profile, service.now_seconds()),
+        false => {
+            let model = payload.model.as_deref();
+            let skin = match service
+                .get_skin_styled(&uuid, payload.force_default, model)
+                .await
+            {
+                Err(err) if service.serves_placeholder(&err) => SkinResponse {
+                    placeholder: true,
+                    ..SkinResponse::from(service.get_placeholder_skin(&uuid))
+                },
+                skin => {
+                    SkinResponse::from(skin?).with_staleness(&entries.skin, service.now_seconds())
+                }
+            };
+            record_image_source("rest", "skin", skin.default, skin.placeholder);
+            skin
+        }
+    };
+    let headers = placeholder_headers(&service.settings().placeholder, skin.placeholder);
+    Ok((headers, Json(skin)))
 }
 
 /// An [axum] handler for [CapeRequest] rest gateway.
@@ -137,21 +959,545 @@ where
     R: CacheLevel,
     M: Mojang,
 {
-    let uuid = Uuid::try_parse(&payload.uuid)?;
-    Ok(Json(service.get_cape(&uuid).await?.into()))
+    let uuid = payload.parse_uuid()?;
+    let entries = &service.cache_entries();
+    let cape = match payload.url_only {
+        true => CapeResponse::from(service.get_cape_url(&uuid).await?)
+            .with_staleness(&entries.profile, service.now_seconds()),
+        false => CapeResponse::from(service.get_cape(&uuid).await?)
+            .with_staleness(&entries.cape, service.now_seconds()),
+    }
+    .with_name(service.cape_names());
+    let (animated, frames) = (payload.animated, payload.frames);
+    let cape = service
+        .render("cape", move || cape.with_animation(animated, frames))
+        .await?;
+    Ok(Json(cape))
 }
 
 /// An [axum] handler for [HeadRequest] rest gateway.
 pub async fn head<L, R, M>(
     Extension(service): Extension<Arc<Service<L, R, M>>>,
     Json(payload): Json<HeadRequest>,
-) -> RestResult<HeadResponse>
+) -> PlaceholderResult<HeadResponse>
 where
     L: CacheLevel,
     R: CacheLevel,
     M: Mojang,
 {
-    let uuid = Uuid::try_parse(&payload.uuid)?;
-    let overlay = payload.overlay;
-    Ok(Json(service.get_head(&uuid, overlay).await?.into()))
+    let uuid = payload.parse_uuid()?;
+    let overlay = payload
+        .overlay
+        .unwrap_or(service.settings().head_overlay.default);
+    let key = payload.head_key(uuid, overlay)?;
+    let model = payload.model.as_deref();
+    let head = match service
+        .get_head_styled(&key, payload.force_default, model)
+        .await
+    {
+        Err(err) if service.serves_placeholder(&err) => {
+            let placeholder = service.get_placeholder_head(&uuid);
+            HeadResponse {
+                placeholder: true,
+                ..HeadResponse::from(service.format_head(placeholder, &key).await?)
+            }
+        }
+        head => HeadResponse::from(head?)
+            .with_staleness(&service.cache_entries().head, service.now_seconds()),
+    };
+    record_image_source("rest", "head", head.default, head.placeholder);
+    let headers = placeholder_headers(&service.settings().placeholder, head.placeholder);
+    Ok((headers, Json(head.with_size(&key))))
+}
+
+/// An [axum] handler for [ChecksumRequest] rest gateway.
+pub async fn checksum<L, R, M>(
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    Json(payload): Json<ChecksumRequest>,
+) -> RestResult<ChecksumResponse>
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    let uuid = payload.parse_uuid()?;
+    let overlay = payload
+        .overlay
+        .unwrap_or(service.settings().head_overlay.default);
+    let checksum = service.get_checksum(&uuid, overlay).await?;
+    Ok(Json(ChecksumResponse::from(checksum).with_staleness(
+        &service.cache_entries().skin,
+        service.now_seconds(),
+    )))
+}
+
+/// An [axum] handler for [ColorsRequest] rest gateway.
+pub async fn colors<L, R, M>(
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    Json(payload): Json<ColorsRequest>,
+) -> RestResult<ColorsResponse>
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    if payload.head {
+        service.ensure_enabled(&[Capability::Heads])?;
+    }
+    let uuid = payload.parse_uuid()?;
+    let colors = service
+        .get_colors(&uuid, payload.head, payload.count as usize)
+        .await?;
+    let entries = service.cache_entries();
+    let expiry = match payload.head {
+        true => &entries.head,
+        false => &entries.skin,
+    };
+    Ok(Json(
+        ColorsResponse::from(colors).with_staleness(expiry, service.now_seconds()),
+    ))
+}
+
+/// An [axum] handler for [BuildTexturesRequest] rest gateway.
+pub async fn build_textures<L, R, M>(
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    Query(query): Query<FormatQuery>,
+    Json(payload): Json<BuildTexturesRequest>,
+) -> RestResult<BuildTexturesResponse>
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    let uuid = payload.uuid.map(|uuid| parse_uuid(&uuid)).transpose()?;
+    let profile_id = payload.profile_uuid.map(|id| parse_uuid(&id)).transpose()?;
+    let textures = service
+        .build_textures(
+            uuid.as_ref(),
+            payload.skin.as_deref(),
+            payload.model.as_deref(),
+            payload.cape.as_deref(),
+            profile_id,
+            payload.profile_name.as_deref(),
+        )
+        .await?;
+    let format = query.uuid_format.unwrap_or(service.settings().uuid_format);
+    Ok(Json(
+        BuildTexturesResponse::from(textures)
+            .with_uuid_format(format)
+            .with_staleness(&service.cache_entries().profile, service.now_seconds()),
+    ))
+}
+
+/// An [axum] handler for [ValidateUuidRequest] rest gateway.
+pub async fn validate_uuid(Json(payload): Json<ValidateUuidRequest>) -> Json<ValidateUuidResponse> {
+    Json(ValidateUuidResponse::validate(&payload.uuid))
+}
+
+/// An [axum] handler for providing the blocked servers list of mojang.
+pub async fn blocked_servers<L, R, M>(
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+) -> RestResult<BlockedServersResponse>
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    let blocked = BlockedServersResponse::from(service.get_blocked_servers().await?);
+    let expiry = &service.cache_entries().blocked_servers;
+    Ok(Json(blocked.with_staleness(expiry, service.now_seconds())))
+}
+
+/// An [axum] handler for [BlockedServerRequest] rest gateway.
+pub async fn blocked_server<L, R, M>(
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    Json(payload): Json<BlockedServerRequest>,
+) -> RestResult<BlockedServerResponse>
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    let pattern = service.is_server_blocked(&payload.hostname).await?;
+    let blocked = BlockedServerResponse::new(payload.hostname, pattern);
+    let expiry = &service.cache_entries().blocked_servers;
+    Ok(Json(blocked.with_staleness(expiry, service.now_seconds())))
+}
+
+/// An [axum] handler for serving a texture by its texture id (hash) at `/texture/{texture_id}`. Contrary
+/// to the other rest gateway handlers, it responds with the PNG image of the texture.
+pub async fn texture<L, R, M>(
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    Path(texture_id): Path<String>,
+) -> Result<Response, ServiceError>
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    let texture = service.get_texture(&texture_id).await?;
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(http::header::CONTENT_TYPE, "image/png")
+        .body(texture.data.bytes.into())
+        .expect("failed to build texture response");
+    Ok(response)
+}
+
+/// An [axum] handler for [NameHistoryRequest] rest gateway.
+#[cfg(feature = "history")]
+pub async fn name_history<L, R, M>(
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    Json(payload): Json<NameHistoryRequest>,
+) -> RestResult<NameHistoryResponse>
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    let uuid = payload.parse_uuid()?;
+    let names = service.get_name_history(&uuid).await?;
+    Ok(Json(NameHistoryResponse::from(names).with_staleness(
+        &service.cache_entries().profile,
+        service.now_seconds(),
+    )))
+}
+
+/// An [axum] handler for [SkinHistoryRequest] rest gateway.
+#[cfg(feature = "history")]
+pub async fn skin_history<L, R, M>(
+    Extension(service): Extension<Arc<Service<L, R, M>>>,
+    Json(payload): Json<SkinHistoryRequest>,
+) -> RestResult<SkinHistoryResponse>
+where
+    L: CacheLevel,
+    R: CacheLevel,
+    M: Mojang,
+{
+    let uuid = payload.parse_uuid()?;
+    let skins = service.get_skin_history(&uuid).await?;
+    Ok(Json(SkinHistoryResponse::from(skins).with_staleness(
+        &service.cache_entries().profile,
+        service.now_seconds(),
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::clock::ManualClock;
+    use crate::cache::entry::ProfileData;
+    use crate::cache::level::moka::MokaCache;
+    use crate::cache::level::no::NoCache;
+    use crate::cache::Cache;
+    use crate::mojang::testing::{MojangTestingApi, HYDROFIN, SCRAYOS};
+    use crate::settings::Settings;
+    use axum::http::header::{ACCEPT, CONTENT_TYPE, VARY};
+    use axum::http::HeaderMap;
+    use futures_util::StreamExt;
+
+    fn metrics_service(
+        openmetrics: bool,
+    ) -> Arc<Service<NoCache, NoCache, MojangTestingApi<'static>>> {
+        let mut settings = Settings::default();
+        settings.metrics.openmetrics = openmetrics;
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        Arc::new(Service::new(Arc::new(settings), cache, mojang))
+    }
+
+    async fn json(response: Response) -> serde_json::Value {
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, value.parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn metrics_negotiate_openmetrics() {
+        // given
+        let service = metrics_service(true);
+        let headers = accept("application/openmetrics-text;version=1.0.0,text/plain;q=0.5");
+
+        // when
+        let response = metrics(None, Extension(service), headers).await;
+
+        // then
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(openmetrics::CONTENT_TYPE, response.headers()[CONTENT_TYPE]);
+        assert_eq!("accept", response.headers()[VARY]);
+    }
+
+    #[tokio::test]
+    async fn metrics_negotiate_text() {
+        // given
+        let service = metrics_service(true);
+        let headers = accept("text/plain;version=0.0.4");
+
+        // when
+        let response = metrics(None, Extension(service), headers).await;
+
+        // then
+        assert_eq!(prometheus::TEXT_FORMAT, response.headers()[CONTENT_TYPE]);
+    }
+
+    #[tokio::test]
+    async fn metrics_openmetrics_disabled() {
+        // given
+        let service = metrics_service(false);
+        let headers = accept("application/openmetrics-text;version=1.0.0");
+
+        // when
+        let response = metrics(None, Extension(service), headers).await;
+
+        // then
+        assert_eq!(prometheus::TEXT_FORMAT, response.headers()[CONTENT_TYPE]);
+    }
+
+    #[tokio::test]
+    async fn problem_invalid_uuid() {
+        // given
+        let error = ServiceError::UuidError(uuid::Uuid::parse_str("invalid").unwrap_err());
+
+        // when
+        let response = error.into_response();
+
+        // then
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!("application/problem+json", response.headers()[CONTENT_TYPE]);
+        let problem = json(response).await;
+        assert_eq!(400, problem["status"]);
+        assert_eq!("INVALID_UUID", problem["reason"]);
+        assert!(problem.get("retry_after").is_none());
+    }
+
+    #[tokio::test]
+    async fn problem_rate_limited() {
+        // given
+        let error = ServiceError::RateLimited {
+            retry_after: Some(std::time::Duration::from_secs(30)),
+        };
+
+        // when
+        let response = error.into_response();
+
+        // then
+        assert_eq!(StatusCode::TOO_MANY_REQUESTS, response.status());
+        assert_eq!("30", response.headers()[http::header::RETRY_AFTER]);
+        let problem = json(response).await;
+        assert_eq!(429, problem["status"]);
+        assert_eq!("UPSTREAM_RATE_LIMITED", problem["reason"]);
+        assert_eq!(30, problem["retry_after"]);
+    }
+
+    #[tokio::test]
+    async fn profile_age_from_cache_clock() {
+        // given
+        let settings = Settings::default();
+        let clock = Arc::new(ManualClock::new(1000));
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache =
+            Cache::new(settings.cache.entries.clone(), moka, NoCache).with_clock(clock.clone());
+        cache
+            .set_profile(&HYDROFIN.profile.id, Some(HYDROFIN.profile.clone()))
+            .await;
+        clock.advance(std::time::Duration::from_secs(30));
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Arc::new(Service::new(Arc::new(settings), cache, mojang));
+        let query = ProfileQuery {
+            fields: None,
+            uuid_format: None,
+        };
+        let payload = ProfileRequest {
+            uuid: HYDROFIN.profile.id.to_string(),
+            ..Default::default()
+        };
+
+        // when
+        let Json(response) = profile(Extension(service), Query(query), Json(payload))
+            .await
+            .unwrap();
+
+        // then
+        assert_eq!(1000, response.timestamp);
+        assert_eq!(30, response.age_seconds);
+        assert!(!response.stale);
+    }
+
+    fn name_changed(uuid: Uuid) -> ProfileEvent {
+        ProfileEvent::NameChanged {
+            uuid,
+            timestamp: 0,
+            previous: "previous".to_string(),
+            current: "current".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn events_stream_profile_changes() {
+        // given
+        let settings = Settings::default();
+        let clock = Arc::new(ManualClock::new(1000));
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache =
+            Cache::new(settings.cache.entries.clone(), moka, NoCache).with_clock(clock.clone());
+        let renamed = ProfileData {
+            name: "Renamed".to_string(),
+            ..HYDROFIN.profile.clone()
+        };
+        cache.set_profile(&HYDROFIN.profile.id, Some(renamed)).await;
+        let exp = settings.cache.entries.profile.exp;
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Arc::new(Service::new(Arc::new(settings), cache, mojang));
+        let query = EventsQuery {
+            uuids: Some(HYDROFIN.profile.id.simple().to_string()),
+        };
+        let response = events(Extension(Arc::clone(&service)), Query(query))
+            .await
+            .unwrap()
+            .into_response();
+        clock.advance(exp);
+
+        // when
+        service.get_profile(&HYDROFIN.profile.id).await.unwrap();
+        let mut body = response.into_body().into_data_stream();
+        let frame = body.next().await.unwrap().unwrap();
+
+        // then
+        let frame = String::from_utf8(frame.to_vec()).unwrap();
+        assert!(frame.starts_with("event: name_changed\n"));
+        assert!(frame.contains(r#""previous":"Renamed""#));
+        assert!(frame.contains(r#""current":"Hydrofin""#));
+    }
+
+    #[tokio::test]
+    async fn events_invalid_uuid() {
+        // given
+        let service = metrics_service(false);
+        let query = EventsQuery {
+            uuids: Some("invalid".to_string()),
+        };
+
+        // when
+        let result = events(Extension(service), Query(query)).await;
+
+        // then
+        assert!(matches!(result, Err(ServiceError::UuidError(_))));
+    }
+
+    #[tokio::test]
+    async fn next_event_filtered() {
+        // given
+        let (sender, mut receiver) = broadcast::channel(4);
+        let uuids = HashSet::from([SCRAYOS.profile.id]);
+        sender.send(name_changed(HYDROFIN.profile.id)).unwrap();
+        sender.send(name_changed(SCRAYOS.profile.id)).unwrap();
+
+        // when
+        let event = next_event(&mut receiver, Some(&uuids)).await;
+
+        // then
+        assert_eq!(Some(name_changed(SCRAYOS.profile.id)), event);
+    }
+
+    #[tokio::test]
+    async fn next_event_skips_lagged() {
+        // given
+        let (sender, mut receiver) = broadcast::channel(1);
+        sender.send(name_changed(HYDROFIN.profile.id)).unwrap();
+        sender.send(name_changed(SCRAYOS.profile.id)).unwrap();
+
+        // when
+        let event = next_event(&mut receiver, None).await;
+
+        // then
+        assert_eq!(Some(name_changed(SCRAYOS.profile.id)), event);
+    }
+
+    #[tokio::test]
+    async fn next_event_closed() {
+        // given
+        let (sender, mut receiver) = broadcast::channel::<ProfileEvent>(1);
+        drop(sender);
+
+        // when
+        let event = next_event(&mut receiver, None).await;
+
+        // then
+        assert_eq!(None, event);
+    }
+
+    #[cfg(feature = "diagnostics")]
+    fn diagnostics_service() -> Arc<Service<NoCache, NoCache, MojangTestingApi<'static>>> {
+        let mut settings = Settings::default();
+        settings.admin.auth_enabled = true;
+        settings.admin.username = "admin".to_string();
+        settings.admin.password = "secret".to_string();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        Arc::new(Service::new(Arc::new(settings), cache, mojang))
+    }
+
+    #[cfg(feature = "diagnostics")]
+    #[tokio::test]
+    async fn runtime_diagnostics_authorized() {
+        // given
+        let service = diagnostics_service();
+        let auth = AuthBasic(("admin".to_string(), Some("secret".to_string())));
+
+        // when
+        let response = runtime_diagnostics(
+            Some(auth),
+            Extension(service),
+            Query(DiagnosticsQuery { tasks: false }),
+        )
+        .await;
+
+        // then
+        assert_eq!(StatusCode::OK, response.status());
+        let body = json(response).await;
+        assert_eq!(1, body["workers"]);
+        assert!(body["tasks"].is_null());
+    }
+
+    #[cfg(feature = "diagnostics")]
+    #[tokio::test]
+    async fn runtime_diagnostics_missing_auth() {
+        // given
+        let service = diagnostics_service();
+
+        // when
+        let response = runtime_diagnostics(
+            None,
+            Extension(service),
+            Query(DiagnosticsQuery { tasks: false }),
+        )
+        .await;
+
+        // then
+        assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+    }
+
+    #[cfg(feature = "diagnostics")]
+    #[tokio::test]
+    async fn runtime_diagnostics_invalid_auth() {
+        // given
+        let service = diagnostics_service();
+        let auth = AuthBasic(("admin".to_string(), Some("wrong".to_string())));
+
+        // when
+        let response = runtime_diagnostics(
+            Some(auth),
+            Extension(service),
+            Query(DiagnosticsQuery { tasks: false }),
+        )
+        .await;
+
+        // then
+        assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+    }
 }
@@ -0,0 +1,215 @@
+//! The sampling module provides the head-based sampling of the tracing spans. Whether the spans of a
+//! request are recorded is decided once at the start of the request (per endpoint rate) and bound to
+//! the task that handles the request. The [SamplingFilter] then drops the spans (and events) of
+//! unsampled requests, so that high-traffic deployments can keep tracing enabled without
+//! overwhelming the collector (e.g. sentry).
+//!
+//! Error events are always recorded (if configured), even if the request is not sampled. The access
+//! log is never sampled. Spans and events outside of requests (e.g. background tasks) are always
+//! recorded.
+
+use crate::settings::Sampling;
+#[cfg(feature = "grpc-server")]
+use crate::settings::Settings;
+#[cfg(feature = "grpc-server")]
+use futures::future::BoxFuture;
+use std::future::Future;
+#[cfg(feature = "grpc-server")]
+use std::sync::Arc;
+#[cfg(feature = "grpc-server")]
+use std::task::{Context, Poll};
+#[cfg(feature = "grpc-server")]
+use tonic::codegen::http;
+use tracing::subscriber::Interest;
+use tracing::{Level, Metadata};
+use tracing_subscriber::layer;
+use uuid::Uuid;
+
+tokio::task_local! {
+    /// Whether the request that is handled by the current task is sampled.
+    static SAMPLED: bool;
+}
+
+/// Runs a future (e.g. a request handler) with a sampling decision.
+pub async fn scope<F: Future>(sampled: bool, future: F) -> F::Output {
+    SAMPLED.scope(sampled, future).await
+}
+
+/// Checks whether the request of the current task is sampled. Outside a [scope] (e.g. background
+/// tasks), everything is sampled.
+pub fn is_sampled() -> bool {
+    SAMPLED.try_with(|sampled| *sampled).unwrap_or(true)
+}
+
+/// Decides whether a request to an endpoint (the rest route or the gRPC method path) is sampled. Each
+/// endpoint is sampled with its configured rate or the default rate. All requests are sampled if the
+/// sampling is disabled.
+pub fn sample(settings: &Sampling, endpoint: &str) -> bool {
+    if !settings.enabled {
+        return true;
+    }
+    let rate = settings
+        .rates
+        .get(endpoint)
+        .copied()
+        .unwrap_or(settings.rate);
+    if rate >= 1.0 {
+        return true;
+    }
+    if rate <= 0.0 {
+        return false;
+    }
+    // the random bits of a v4 uuid are uniformly distributed
+    let random = (Uuid::new_v4().as_u128() as u64) as f64 / u64::MAX as f64;
+    random < rate
+}
+
+/// The [SamplingFilter] is a per-layer tracing filter that drops the spans and events of unsampled
+/// requests. Error events (if configured) and the access log are always recorded. If the sampling is
+/// disabled, everything is recorded and the interest is cached per callsite.
+#[derive(Debug, Clone)]
+pub struct SamplingFilter {
+    enabled: bool,
+    always_sample_errors: bool,
+}
+
+impl SamplingFilter {
+    /// Creates a new [SamplingFilter] from the [sampling configuration](Sampling).
+    pub fn new(settings: &Sampling) -> Self {
+        Self {
+            enabled: settings.enabled,
+            always_sample_errors: settings.always_sample_errors,
+        }
+    }
+
+    /// Checks whether a span or event is recorded within an unsampled request.
+    fn is_exempt(&self, meta: &Metadata<'_>) -> bool {
+        meta.is_event()
+            && (meta.target() == "access_log"
+                || (self.always_sample_errors && *meta.level() == Level::ERROR))
+    }
+}
+
+impl<S> layer::Filter<S> for SamplingFilter {
+    fn enabled(&self, meta: &Metadata<'_>, _: &layer::Context<'_, S>) -> bool {
+        !self.enabled || is_sampled() || self.is_exempt(meta)
+    }
+
+    fn callsite_enabled(&self, _: &'static Metadata<'static>) -> Interest {
+        // the sampling decision differs per request, so it cannot be cached per callsite
+        match self.enabled {
+            true => Interest::sometimes(),
+            false => Interest::always(),
+        }
+    }
+}
+
+/// The [SamplingLayer] is a tower layer for the gRPC server that decides whether a request is sampled
+/// based on its method path (e.g. `/scrayosnet.xenos.Profile/GetUuid`).
+#[cfg(feature = "grpc-server")]
+#[derive(Debug, Clone)]
+pub struct SamplingLayer {
+    settings: Arc<Sampling>,
+}
+
+#[cfg(feature = "grpc-server")]
+impl SamplingLayer {
+    /// Creates a new [SamplingLayer] from the application [Settings].
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            settings: Arc::new(settings.logging.sampling.clone()),
+        }
+    }
+}
+
+#[cfg(feature = "grpc-server")]
+impl<S> tower::Layer<S> for SamplingLayer {
+    type Service = SamplingService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SamplingService {
+            settings: Arc::clone(&self.settings),
+            inner,
+        }
+    }
+}
+
+/// The [SamplingService] is the tower service of the [SamplingLayer].
+#[cfg(feature = "grpc-server")]
+#[derive(Debug, Clone)]
+pub struct SamplingService<S> {
+    settings: Arc<Sampling>,
+    inner: S,
+}
+
+#[cfg(feature = "grpc-server")]
+impl<S, B> tower::Service<http::Request<B>> for SamplingService<S>
+where
+    S: tower::Service<http::Request<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let sampled = sample(&self.settings, request.uri().path());
+        Box::pin(scope(sampled, self.inner.call(request)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn sample_per_endpoint() {
+        // given
+        let settings = Sampling {
+            enabled: true,
+            rate: 0.0,
+            always_sample_errors: true,
+            rates: HashMap::from([("/uuid".to_string(), 1.0)]),
+        };
+
+        // when
+        let uuid = sample(&settings, "/uuid");
+        let profile = sample(&settings, "/profile");
+        let inside = scope(profile, async { is_sampled() }).await;
+        let outside = is_sampled();
+
+        // then
+        assert!(uuid);
+        assert!(!profile);
+        assert!(!inside);
+        assert!(outside);
+    }
+
+    #[test]
+    fn callsite_interest() {
+        // given
+        let mut settings = Sampling {
+            enabled: false,
+            rate: 0.0,
+            always_sample_errors: true,
+            rates: HashMap::new(),
+        };
+        let disabled = SamplingFilter::new(&settings);
+        settings.enabled = true;
+        let enabled = SamplingFilter::new(&settings);
+        let meta = tracing::span!(Level::INFO, "callsite").metadata().unwrap();
+
+        // when
+        let disabled = <SamplingFilter as layer::Filter<()>>::callsite_enabled(&disabled, meta);
+        let enabled = <SamplingFilter as layer::Filter<()>>::callsite_enabled(&enabled, meta);
+
+        // then
+        assert!(disabled.is_always());
+        assert!(enabled.is_sometimes());
+    }
+}
@@ -0,0 +1,146 @@
+//! The sanctions module provides the alerting on sanctioned profiles (see `profile_actions`). Whenever
+//! a profile is fetched from mojang with actions that its previously cached version did not have, a
+//! [SanctionAlert] is logged, counted and (if configured) posted to a webhook.
+//!
+//! Like the [profile events](crate::events), sanctions are only observed by the instance that fetches
+//! the profile. Profiles that are not requested are never fetched and therefore never alerted.
+
+use crate::cache::entry::ProfileData;
+use crate::settings::Sanctions;
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use serde::Serialize;
+use std::time::Duration;
+use tracing::warn;
+use uuid::Uuid;
+
+/// The timeout of posting an alert to the webhook, so that a stalled webhook does not pile up tasks.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    /// The http client for posting alerts, uses arc internally
+    static ref HTTP_CLIENT: reqwest::Client = reqwest::Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()
+        .unwrap();
+
+    /// A counter for the newly observed sanctions of fetched profiles by their action.
+    static ref SANCTIONS_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "xenos_sanctions_total",
+        "The newly observed sanctions of fetched profiles.",
+        &["action"]
+    )
+    .unwrap();
+}
+
+/// A [SanctionAlert] is an observed sanction of a profile. It is posted as JSON to the webhook.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct SanctionAlert {
+    pub uuid: Uuid,
+    pub name: String,
+    pub timestamp: u64,
+    /// The actions that were not imposed on the previously cached profile.
+    pub actions: Vec<String>,
+}
+
+/// Detects the new sanctions of the current version of a profile compared to its previous version.
+/// All actions are new if there is no previous version. The alert has the provided unix timestamp in
+/// seconds.
+pub fn detect_sanctions(
+    previous: Option<&ProfileData>,
+    current: &ProfileData,
+    timestamp: u64,
+) -> Option<SanctionAlert> {
+    let actions: Vec<String> = current
+        .profile_actions
+        .iter()
+        .filter(|action| previous.is_none_or(|previous| !previous.profile_actions.contains(action)))
+        .cloned()
+        .collect();
+    if actions.is_empty() {
+        return None;
+    }
+    Some(SanctionAlert {
+        uuid: current.id,
+        name: current.name.clone(),
+        timestamp,
+        actions,
+    })
+}
+
+/// Logs and counts a [SanctionAlert] and posts it to the webhook of the [settings](Sanctions) (if
+/// configured). The webhook is called in the background, failures are logged.
+pub fn alert(settings: &Sanctions, alert: SanctionAlert) {
+    for action in &alert.actions {
+        SANCTIONS_COUNTER.with_label_values(&[action]).inc();
+    }
+    warn!(
+        uuid = %alert.uuid,
+        name = alert.name,
+        actions = ?alert.actions,
+        "observed sanctioned profile"
+    );
+    let Some(webhook) = settings.webhook.clone() else {
+        return;
+    };
+    tokio::spawn(async move {
+        if let Err(err) = post_alert(&webhook, &alert).await {
+            warn!(error = %err, uuid = %alert.uuid, "failed to post sanction alert");
+        }
+    });
+}
+
+/// Posts a [SanctionAlert] as JSON to a webhook.
+async fn post_alert(webhook: &str, alert: &SanctionAlert) -> Result<(), reqwest::Error> {
+    HTTP_CLIENT
+        .post(webhook)
+        .json(alert)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use uuid::uuid;
+
+    fn profile(actions: &[&str]) -> ProfileData {
+        ProfileData {
+            id: uuid!("09879557e47945a9b434a56377674627"),
+            name: "Hydrofin".to_string(),
+            properties: vec![].into(),
+            profile_actions: actions.iter().map(|action| action.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn detect_sanctions_new_actions() {
+        // given
+        let previous = profile(&["FORCED_NAME_CHANGE"]);
+        let current = profile(&["FORCED_NAME_CHANGE", "USING_BANNED_SKIN"]);
+
+        // when
+        let alert = detect_sanctions(Some(&previous), &current, 42).unwrap();
+
+        // then
+        assert_eq!(vec!["USING_BANNED_SKIN"], alert.actions);
+        assert_eq!(42, alert.timestamp);
+    }
+
+    #[test]
+    fn detect_sanctions_unchanged() {
+        // given
+        let previous = profile(&["FORCED_NAME_CHANGE"]);
+        let current = profile(&["FORCED_NAME_CHANGE"]);
+
+        // when
+        let alert = detect_sanctions(Some(&previous), &current, 42);
+        let first = detect_sanctions(None, &current, 42);
+
+        // then
+        assert!(alert.is_none());
+        assert_eq!(vec!["FORCED_NAME_CHANGE"], first.unwrap().actions);
+    }
+}
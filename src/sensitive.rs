@@ -0,0 +1,90 @@
+//! The sensitive module provides a tower layer that marks the api key header of requests as sensitive.
+//! Sensitive headers are neither attached to the errors nor to the performance transactions that are
+//! sent to sentry.
+
+use http::header::{Entry, HeaderName};
+use http::HeaderMap;
+use std::task::{Context, Poll};
+
+/// Marks all values of a header as sensitive.
+fn mark_sensitive(headers: &mut HeaderMap, header: &HeaderName) {
+    if let Entry::Occupied(mut entry) = headers.entry(header) {
+        entry.iter_mut().for_each(|value| value.set_sensitive(true));
+    }
+}
+
+/// The [SensitiveHeaderLayer] is a tower layer for the rest and gRPC server that marks a header (e.g.
+/// the api key of the usage header) as sensitive.
+#[derive(Debug, Clone)]
+pub struct SensitiveHeaderLayer {
+    header: Option<HeaderName>,
+}
+
+impl SensitiveHeaderLayer {
+    /// Creates a new [SensitiveHeaderLayer] for a (lowercase) header. Invalid header names are ignored.
+    pub fn new(header: &str) -> Self {
+        Self {
+            header: HeaderName::from_bytes(header.as_bytes()).ok(),
+        }
+    }
+}
+
+impl<S> tower::Layer<S> for SensitiveHeaderLayer {
+    type Service = SensitiveHeaderService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SensitiveHeaderService {
+            header: self.header.clone(),
+            inner,
+        }
+    }
+}
+
+/// The [SensitiveHeaderService] is the tower service of the [SensitiveHeaderLayer].
+#[derive(Debug, Clone)]
+pub struct SensitiveHeaderService<S> {
+    header: Option<HeaderName>,
+    inner: S,
+}
+
+impl<S, B> tower::Service<http::Request<B>> for SensitiveHeaderService<S>
+where
+    S: tower::Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<B>) -> Self::Future {
+        if let Some(header) = &self.header {
+            mark_sensitive(request.headers_mut(), header);
+        }
+        self.inner.call(request)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use http::HeaderValue;
+
+    #[test]
+    fn marks_header_sensitive() {
+        // given
+        let header = HeaderName::from_static("x-api-key");
+        let mut headers = HeaderMap::new();
+        headers.insert(&header, HeaderValue::from_static("secret"));
+        headers.insert("x-other", HeaderValue::from_static("other"));
+
+        // when
+        mark_sensitive(&mut headers, &header);
+
+        // then
+        assert!(headers["x-api-key"].is_sensitive());
+        assert!(!headers["x-other"].is_sensitive());
+    }
+}
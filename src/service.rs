@@ -1,37 +1,78 @@
+use crate::access_log;
 use crate::cache::entry::Cached::{Expired, Hit, Miss};
-use crate::cache::entry::{CapeData, HeadData, SkinData, UuidData};
-use crate::cache::entry::{Dated, Entry, ProfileData};
-use crate::cache::level::CacheLevel;
+use crate::cache::entry::{
+    BlockedServersData, CapeData, CapeUrlData, ChecksumData, HeadData, SkinData, SkinUrlData,
+    TextureData, UuidData,
+};
+use crate::cache::entry::{Dated, Entry, HeadKey, HeadStyle, ProfileData};
+use crate::cache::level::{CacheLevel, KeyPattern, PinnedError};
 use crate::cache::Cache;
+use crate::capes::CapeNames;
+use crate::deadline;
 use crate::error::ServiceError;
-use crate::error::ServiceError::{NotFound, Unavailable};
+use crate::error::ServiceError::{InvalidArgument, NotFound, Unavailable};
+use crate::events::{detect_changes, ProfileEvent};
+use crate::faults::Faults;
+#[cfg(feature = "history")]
+use crate::history::{HistoryError, NameHistoryData, PostgresHistory, SkinHistoryData};
+use crate::identity::IdentityResolver;
 use crate::mojang;
+use crate::mojang::blocked::find_blocked;
+use crate::mojang::breaker::{BreakerState, CircuitBreaker};
+use crate::mojang::fallback::{ProfileProvider, ProviderChain};
+use crate::mojang::hedge;
+use crate::mojang::limit::ConcurrencyLimits;
+use crate::mojang::shedding::{self, SheddingPolicy};
+use crate::mojang::status::{MojangStatus, UpstreamStats};
 use crate::mojang::{
-    build_skin_head, ApiError, Mojang, ALEX_HEAD, ALEX_SKIN, CLASSIC_MODEL, SLIM_MODEL, STEVE_HEAD,
-    STEVE_SKIN,
+    build_skin_head, encode_texture_prop, render_head, texture_url, ApiError, InvalidTexture,
+    Mojang, Profile, ProfileProperty, Texture, TexturesProperty, ALEX_HEAD, ALEX_SKIN,
+    CLASSIC_MODEL, SLIM_MODEL, STEVE_HEAD, STEVE_SKIN, TEXTURES_URL,
+};
+use crate::placeholder::Placeholders;
+use crate::refresh;
+use crate::refresh::{AccessTracker, HotKey};
+use crate::render::colors::{self, DominantColor};
+use crate::render::pool::RenderPool;
+use crate::render::skin_convert;
+use crate::sanctions;
+use crate::settings::{
+    CacheEntries, CacheEntry, Capability, Settings, SkinFallbackTier, UsageQuota,
 };
-use crate::settings::Settings;
+use crate::shutdown::Shutdown;
+use crate::slo;
+use crate::statsd;
+use crate::tenant;
+use crate::usage::{self, UsagePeriod, UsageReport};
+use bytes::Bytes;
+use futures::stream::{self, StreamExt};
 use lazy_static::lazy_static;
 use metrics::MetricsEvent;
-use prometheus::{register_histogram_vec, HistogramVec};
+use prometheus::{register_histogram_vec, register_int_counter_vec, HistogramVec, IntCounterVec};
 use regex::Regex;
-use std::collections::HashMap;
-use std::fmt::Debug;
-use std::sync::Arc;
-use tracing::warn;
+use ring::digest::{digest, SHA256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Debug, Write};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, Semaphore};
+use tokio::time::{Instant, MissedTickBehavior};
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
 lazy_static! {
-    /// The username regex is used to check if a given username could be a valid username.
-    /// If a string does not match the regex, the mojang API will never find a matching user id.
-    static ref USERNAME_REGEX: Regex = Regex::new("^[a-zA-Z0-9_]{2,16}$").unwrap();
+    /// The texture id regex is used to check if a given texture id could be a valid texture id.
+    /// If a string does not match the regex, the mojang API will never find a matching texture.
+    static ref TEXTURE_ID_REGEX: Regex = Regex::new("^[a-f0-9]{1,64}$").unwrap();
 
     /// A histogram for the age in seconds of cache results. Use the [monitor_service_call_with_age]
     /// utility for ease of use.
     pub static ref PROFILE_REQ_AGE_HISTOGRAM: HistogramVec = register_histogram_vec!(
         "xenos_profile_age_seconds",
         "The grpc profile response age in seconds.",
-        &["request_type"],
+        &["request_type", "tenant"],
         vec![5.0, 10.0, 60.0, 600.0, 3600.0, 86400.0, 604800.0, 2419200.0]
     )
     .unwrap();
@@ -41,16 +82,58 @@ lazy_static! {
     pub static ref PROFILE_REQ_LAT_HISTOGRAM: HistogramVec = register_histogram_vec!(
         "xenos_profile_latency_seconds",
         "The grpc profile request latency in seconds.",
-        &["request_type", "status"],
+        &["request_type", "status", "tenant"],
         vec![0.005, 0.01, 0.025, 0.05, 0.075, 0.1, 0.175, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0]
     )
     .unwrap();
+
+    /// A counter for the skins whose texture could not be fetched by the resolved fallback tier.
+    static ref SKIN_FALLBACK_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "xenos_skin_fallback_total",
+        "The skins whose texture could not be fetched by the resolved fallback tier.",
+        &["tier"]
+    )
+    .unwrap();
+
+    /// A counter for the fetched textures that were rejected by the texture validation.
+    static ref INVALID_TEXTURES_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "xenos_invalid_textures_total",
+        "The fetched textures that were rejected as invalid.",
+        &["reason"]
+    )
+    .unwrap();
+
+    /// A counter for the served skins and heads by their source (texture, default or placeholder).
+    static ref IMAGE_SOURCE_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "xenos_image_source_total",
+        "The served skins and heads by their source.",
+        &["server", "request_type", "source"]
+    )
+    .unwrap();
+}
+
+/// Gets the source of a served skin or head. It is either the `texture` of the profile, a `default`
+/// skin (Steve/Alex, including suppressed skins) or a `placeholder` that is served on errors.
+pub fn image_source(default: bool, placeholder: bool) -> &'static str {
+    match (default, placeholder) {
+        (_, true) => "placeholder",
+        (true, false) => "default",
+        (false, false) => "texture",
+    }
+}
+
+/// Records the source (see [image_source]) of a skin or head served by a server (rest or grpc).
+pub fn record_image_source(server: &str, request_type: &str, default: bool, placeholder: bool) {
+    IMAGE_SOURCE_COUNTER
+        .with_label_values(&[server, request_type, image_source(default, placeholder)])
+        .inc();
 }
 
 fn metrics_age_handler<T: Clone + Debug + Eq>(event: MetricsEvent<Result<Dated<T>, ServiceError>>) {
     let status = match event.result {
         Ok(_) => "ok",
         Err(Unavailable) => "unavailable",
+        Err(ServiceError::RateLimited { .. }) => "rate_limited",
         Err(NotFound) => "not_found",
         Err(_) => "error",
     };
@@ -58,14 +141,30 @@ fn metrics_age_handler<T: Clone + Debug + Eq>(event: MetricsEvent<Result<Dated<T
         warn!("Failed to retrieve label 'request_type' for metric!");
         return;
     };
+    let tenant = tenant::label();
     PROFILE_REQ_LAT_HISTOGRAM
-        .with_label_values(&[request_type, status])
+        .with_label_values(&[request_type, status, &tenant])
         .observe(event.time);
+    statsd::timing(
+        "profile.latency",
+        &[
+            ("request_type", request_type),
+            ("status", status),
+            ("tenant", &tenant),
+        ],
+        event.time,
+    );
+    slo::record(request_type, status, event.time);
 
     if let Ok(dated) = event.result {
         PROFILE_REQ_AGE_HISTOGRAM
-            .with_label_values(&[request_type])
+            .with_label_values(&[request_type, &tenant])
             .observe(dated.current_age() as f64);
+        statsd::timing(
+            "profile.age",
+            &[("request_type", request_type), ("tenant", &tenant)],
+            dated.current_age() as f64,
+        );
     }
 }
 
@@ -73,6 +172,7 @@ fn metrics_handler<T: Clone + Debug + Eq>(event: MetricsEvent<Result<T, ServiceE
     let status = match event.result {
         Ok(_) => "ok",
         Err(Unavailable) => "unavailable",
+        Err(ServiceError::RateLimited { .. }) => "rate_limited",
         Err(NotFound) => "not_found",
         Err(_) => "error",
     };
@@ -80,9 +180,83 @@ fn metrics_handler<T: Clone + Debug + Eq>(event: MetricsEvent<Result<T, ServiceE
         warn!("Failed to retrieve label 'request_type' for metric!");
         return;
     };
+    let tenant = tenant::label();
     PROFILE_REQ_LAT_HISTOGRAM
-        .with_label_values(&[request_type, status])
+        .with_label_values(&[request_type, status, &tenant])
         .observe(event.time);
+    statsd::timing(
+        "profile.latency",
+        &[
+            ("request_type", request_type),
+            ("status", status),
+            ("tenant", &tenant),
+        ],
+        event.time,
+    );
+    slo::record(request_type, status, event.time);
+}
+
+/// A [PrefetchRequest] is a profile (uuid) whose skin and heads are prefetched for a tenant (if any).
+type PrefetchRequest = (Option<Arc<str>>, Uuid);
+
+/// A [PreRenderRequest] is a freshly fetched skin (bytes) of a profile (uuid) whose heads are
+/// pre-rendered for a tenant (if any).
+type PreRenderRequest = (Option<Arc<str>>, Uuid, Bytes);
+
+/// A [ProfileBundle] holds the profile, skin and head of a profile and whether it has a cape. Each
+/// part has its own timestamp.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileBundle {
+    pub profile: Dated<ProfileData>,
+    pub skin: Dated<SkinData>,
+    pub has_cape: bool,
+    pub head: Dated<HeadData>,
+}
+
+/// A [SkinColors] holds the dominant colors of the skin (or head) of a profile with the timestamp of
+/// the analyzed image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkinColors {
+    pub timestamp: u64,
+    pub default: bool,
+    pub colors: Vec<DominantColor>,
+}
+
+/// A [BulkStatus] is the status of an individual item of a bulk request (e.g. a username of
+/// [Service::get_uuids_partial]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BulkStatus {
+    /// The item was resolved from cache or mojang.
+    Resolved,
+    /// The item doesn't exist.
+    NotFound,
+    /// The item is invalid (e.g. it doesn't match the username pattern) and was not looked up.
+    Invalid,
+    /// The item could not be updated (e.g. during mojang outages) and its expired entry was served.
+    Stale,
+    /// The item could not be looked up (e.g. during mojang outages) and has no cached entry.
+    Unavailable,
+}
+
+impl BulkStatus {
+    /// Gets the name of the [BulkStatus] as used in the responses.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BulkStatus::Resolved => "resolved",
+            BulkStatus::NotFound => "not_found",
+            BulkStatus::Invalid => "invalid",
+            BulkStatus::Stale => "stale",
+            BulkStatus::Unavailable => "unavailable",
+        }
+    }
+
+    /// Gets the [BulkStatus] of a looked up entry from its data.
+    fn of<D: Clone + Debug + Eq>(entry: &Entry<D>) -> Self {
+        match entry.data {
+            Some(_) => BulkStatus::Resolved,
+            None => BulkStatus::NotFound,
+        }
+    }
 }
 
 /// The [Service] is the backbone of Xenos. All exposed services (gRPC/REST) use a shared instance of
@@ -98,6 +272,26 @@ where
     settings: Arc<Settings>,
     cache: Cache<L, R>,
     mojang: M,
+    breaker: CircuitBreaker,
+    fallback: ProviderChain,
+    upstream: UpstreamStats,
+    shedding: SheddingPolicy,
+    limits: ConcurrencyLimits,
+    cache_only: AtomicBool,
+    faults: Option<Arc<Faults>>,
+    identity: IdentityResolver,
+    events: broadcast::Sender<ProfileEvent>,
+    prefetch: mpsc::Sender<PrefetchRequest>,
+    prefetch_queue: Mutex<Option<mpsc::Receiver<PrefetchRequest>>>,
+    pre_render: mpsc::Sender<PreRenderRequest>,
+    pre_render_queue: Mutex<Option<mpsc::Receiver<PreRenderRequest>>>,
+    access: AccessTracker,
+    shutdown: Arc<Shutdown>,
+    placeholders: Placeholders,
+    cape_names: CapeNames,
+    render: RenderPool,
+    #[cfg(feature = "history")]
+    history: Option<PostgresHistory>,
 }
 
 impl<L, R, M> Service<L, R, M>
@@ -107,20 +301,476 @@ where
     M: Mojang,
 {
     /// Builds a new [Service] with provided cache and mojang api implementation. It is expected, that
-    /// the provided settings match the settings used to construct the cache and api.
+    /// the provided settings match the settings used to construct the cache and api. The service uses
+    /// the [Clock](crate::cache::clock::Clock) of the cache.
     pub fn new(settings: Arc<Settings>, cache: Cache<L, R>, mojang: M) -> Self {
+        let (prefetch, prefetch_queue) = mpsc::channel(settings.prefetch.queue.max(1));
+        let (pre_render, pre_render_queue) = mpsc::channel(settings.render.queue.max(1));
         Self {
+            breaker: CircuitBreaker::new(&settings.circuit_breaker),
+            fallback: ProviderChain::new(&settings.fallback),
+            upstream: UpstreamStats::new(&settings.upstream_stats),
+            shedding: SheddingPolicy::new(&settings.shedding),
+            limits: ConcurrencyLimits::new(&settings.upstream_concurrency),
+            cache_only: AtomicBool::new(settings.cache_only.enabled),
+            faults: settings
+                .faults
+                .enabled
+                .then(|| Arc::new(Faults::new(&settings.faults))),
+            identity: IdentityResolver::new(&settings),
+            events: broadcast::channel(settings.events.capacity.max(1)).0,
+            prefetch,
+            prefetch_queue: Mutex::new(Some(prefetch_queue)),
+            pre_render,
+            pre_render_queue: Mutex::new(Some(pre_render_queue)),
+            access: AccessTracker::new(settings.refresh.capacity),
+            shutdown: Arc::default(),
+            placeholders: Placeholders::load(&settings.placeholder),
+            cape_names: CapeNames::new(&settings.cape_names),
+            render: RenderPool::new(&settings.render),
             settings,
             cache,
             mojang,
+            #[cfg(feature = "history")]
+            history: None,
         }
     }
 
+    /// Adds a [profile history](PostgresHistory) to the [Service]. Every profile fetched from mojang
+    /// is then recorded in the history.
+    #[cfg(feature = "history")]
+    pub fn with_history(mut self, history: PostgresHistory) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Adds a fallback [ProfileProvider] (in addition to the configured providers) to the [Service].
+    /// Providers with lower priority are tried first.
+    pub fn with_fallback_provider(
+        mut self,
+        name: &str,
+        priority: u32,
+        provider: impl ProfileProvider + 'static,
+    ) -> Self {
+        self.fallback
+            .add_provider(name, priority, Box::new(provider));
+        self
+    }
+
     /// Returns the [application settings](Settings) that were used to construct the [Service].
     pub fn settings(&self) -> &Settings {
         &self.settings
     }
 
+    /// Returns the [Mojang] implementation of the [Service] (e.g. to inspect a testing api).
+    pub fn mojang(&self) -> &M {
+        &self.mojang
+    }
+
+    /// Subscribes to the [profile change events](ProfileEvent). Only events published after the
+    /// subscription are received.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<ProfileEvent> {
+        self.events.subscribe()
+    }
+
+    /// Checks whether the [Service] is in cache-only mode. In cache-only mode, no requests are sent
+    /// to mojang and (expired) cache entries are used instead.
+    pub fn is_cache_only(&self) -> bool {
+        self.cache_only.load(Ordering::SeqCst)
+    }
+
+    /// Enables or disables the cache-only mode at runtime.
+    pub fn set_cache_only(&self, enabled: bool) {
+        if self.cache_only.swap(enabled, Ordering::SeqCst) != enabled {
+            info!(enabled, "changed cache-only mode");
+        }
+    }
+
+    /// Runs image work of a kind (e.g. `head`) on the [RenderPool] of the [Service].
+    pub async fn render<T, F>(&self, kind: &'static str, render: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.render.run(kind, render).await
+    }
+
+    /// Returns the [CacheEntry](crate::settings::CacheEntry) configurations of the tenant of the
+    /// current task (or the global configurations without tenant).
+    pub fn cache_entries(&self) -> &CacheEntries<CacheEntry> {
+        self.cache.expiry()
+    }
+
+    /// Returns the current unix time in seconds of the [Clock](crate::cache::clock::Clock) of the
+    /// cache. The ages of the responses are relative to it.
+    pub fn now_seconds(&self) -> u64 {
+        self.cache.now_seconds()
+    }
+
+    /// Returns the [IdentityResolver] that identifies the clients of the [Service] for the usage quotas
+    /// and the access log.
+    pub fn identity(&self) -> &IdentityResolver {
+        &self.identity
+    }
+
+    /// Returns the [CapeNames] that identify the capes of the [Service] by their texture id.
+    pub fn cape_names(&self) -> &CapeNames {
+        &self.cape_names
+    }
+
+    /// Returns the injected [Faults] of the [Service], if the fault injection is enabled.
+    pub fn faults(&self) -> Option<&Arc<Faults>> {
+        self.faults.as_ref()
+    }
+
+    /// Returns the [Shutdown] state of the [Service] that tracks the in-flight requests of its servers.
+    pub fn shutdown(&self) -> &Arc<Shutdown> {
+        &self.shutdown
+    }
+
+    /// Purges all cache entries whose key matches a glob pattern (e.g. `head.<uuid>.*`) and returns the
+    /// number of purged entries. See [KeyPattern] for the format of the pattern.
+    pub async fn purge_cache(&self, pattern: &str) -> Result<u64, ServiceError> {
+        let Some(pattern) = KeyPattern::new(pattern) else {
+            return Err(InvalidArgument(format!("invalid pattern {}", pattern)));
+        };
+        let purged = self.cache.purge(&pattern).await;
+        info!(pattern = pattern.glob(), purged, "purged cache entries");
+        Ok(purged)
+    }
+
+    /// Pins (or unpins) a profile, so that it is kept fresh on a schedule regardless of its accesses.
+    /// It fails if the maximum number of pinned profiles is reached or the pinned profiles cannot be
+    /// changed.
+    pub async fn set_pinned(&self, uuid: &Uuid, pinned: bool) -> Result<(), ServiceError> {
+        let max = self.settings.pinned.max;
+        match self.cache.set_pinned(uuid, pinned, max).await {
+            Ok(()) => {}
+            Err(PinnedError::Exceeded) => {
+                return Err(InvalidArgument(format!(
+                    "at most {} profiles can be pinned",
+                    max
+                )))
+            }
+            Err(PinnedError::Unavailable) => return Err(Unavailable),
+        }
+        info!(%uuid, pinned, "changed pinned profile");
+        Ok(())
+    }
+
+    /// Gets the uuids of all pinned profiles.
+    pub async fn pinned(&self) -> HashSet<Uuid> {
+        self.cache.get_pinned().await
+    }
+
+    /// Checks whether all [Capabilities](crate::settings::Capabilities) are enabled. Returns a
+    /// [Disabled](ServiceError::Disabled) error for the first disabled [Capability].
+    pub fn ensure_enabled(&self, capabilities: &[Capability]) -> Result<(), ServiceError> {
+        match capabilities
+            .iter()
+            .find(|capability| !self.settings.capabilities.is_enabled(**capability))
+        {
+            Some(capability) => Err(ServiceError::Disabled(*capability)),
+            None => Ok(()),
+        }
+    }
+
+    /// Gets the current [state](BreakerState) of the mojang api [CircuitBreaker].
+    pub fn breaker_state(&self) -> BreakerState {
+        self.breaker.state(self.cache.now_seconds())
+    }
+
+    /// Gets the current view of the [Service] on the health of the mojang api.
+    pub fn get_status(&self) -> MojangStatus {
+        let now = self.cache.now_seconds();
+        MojangStatus {
+            breaker: self.breaker.state(now),
+            cache_only: self.is_cache_only(),
+            endpoints: self.upstream.endpoints(now),
+            rate_limit: self.upstream.rate_limit(),
+            rate_remaining: self.upstream.remaining_budget(now),
+            retry_at: self.breaker.retry_at(now),
+            fallback: self.fallback.status(now),
+            shedding: self.is_shedding(now),
+        }
+    }
+
+    /// Checks whether optional traffic is shed at the unix timestamp in seconds (see
+    /// [SheddingPolicy]).
+    fn is_shedding(&self, now: u64) -> bool {
+        self.shedding.evaluate(
+            self.upstream.remaining_budget(now),
+            &self.upstream.endpoints(now),
+        )
+    }
+
+    /// Checks whether an optional request of a kind (e.g. `prefetch`) to an endpoint may be sent at the
+    /// unix timestamp in seconds. It is shed if the remaining rate budget is reserved for client
+    /// requests or the [SheddingPolicy] is active.
+    fn allows_optional(&self, kind: &str, endpoint: &str, now: u64) -> bool {
+        let allowed = self.upstream.allows_optional(endpoint, now) && !self.is_shedding(now);
+        if !allowed {
+            shedding::record_shed(kind);
+        }
+        allowed
+    }
+
+    /// Checks whether the [Service] is able to serve requests. It is unhealthy if the mojang api
+    /// [CircuitBreaker] is open and the remote cache is unreachable, as only the local cache could
+    /// be used to serve requests.
+    #[tracing::instrument(skip(self))]
+    pub async fn is_healthy(&self) -> bool {
+        self.breaker_state() != BreakerState::Open || self.cache.ping_remote().await
+    }
+
+    /// Sends a request to the mojang api through the [CircuitBreaker]. If the circuit breaker is open
+    /// or the [Service] is in cache-only mode, the request is not sent and the mojang api is
+    /// considered unavailable. If mojang asked to retry later (`Retry-After`), the request is not sent
+    /// and considered rate limited until then. The request is recorded in the [UpstreamStats] of the endpoint. It waits
+    /// for a permit of the [ConcurrencyLimits] of the endpoint before it is sent and fails fast if the
+    /// queue of the endpoint is full. If the request cannot complete before the deadline of the client,
+    /// it is not sent either. The deadline is checked before and after waiting for the permit.
+    ///
+    /// Requests to idempotent endpoints are [hedged](mojang::hedge) if enabled. The hedge waits for its
+    /// own permit and is only sent while the estimated rate budget is not exhausted.
+    #[tracing::instrument(skip(self, request))]
+    async fn call_mojang<T, F>(
+        &self,
+        endpoint: &'static str,
+        request: impl Fn() -> F,
+    ) -> Result<T, ApiError>
+    where
+        F: Future<Output = Result<T, ApiError>>,
+    {
+        if self.is_cache_only() {
+            return Err(ApiError::Unavailable);
+        }
+        let now = self.cache.now_seconds();
+        if let Some(retry_at) = self.breaker.retry_at(now) {
+            let retry_after = Some(Duration::from_secs(retry_at - now));
+            return Err(ApiError::RateLimited { retry_after });
+        }
+        if !self.breaker.allows(now) {
+            return Err(ApiError::Unavailable);
+        }
+        // requests that cannot complete before the deadline of the client would waste rate limit, the
+        // deadline is checked again after waiting for the permit
+        if self.exceeds_deadline(endpoint) {
+            return Err(ApiError::Unavailable);
+        }
+        let _permit = self.limits.acquire(endpoint).await?;
+        if self.exceeds_deadline(endpoint) {
+            return Err(ApiError::Unavailable);
+        }
+        access_log::record_upstream(endpoint);
+        let hedging = &self.settings.hedging;
+        let hedge_sent = AtomicBool::new(false);
+        let result = match hedging.enabled && hedge::is_hedgeable(endpoint) {
+            true => {
+                let hedge = || async {
+                    if !self.allows_optional("hedge", endpoint, now) {
+                        return None;
+                    }
+                    // hedges are skipped instead of waiting in a full queue
+                    let _permit = self.limits.acquire(endpoint).await.ok()?;
+                    debug!(endpoint, "sending hedged mojang request");
+                    access_log::record_upstream(endpoint);
+                    hedge_sent.store(true, Ordering::Relaxed);
+                    Some(request().await)
+                };
+                hedge::hedge(endpoint, hedging.threshold, request(), hedge)
+                    .await
+                    .0
+            }
+            false => request().await,
+        };
+        let failed = match &result {
+            Err(ApiError::RateLimited { retry_after }) => {
+                self.breaker.record_rate_limited(now, *retry_after);
+                self.upstream.exhaust(now);
+                true
+            }
+            Err(ApiError::Unavailable) => {
+                self.breaker.record_failure(now);
+                true
+            }
+            _ => {
+                self.breaker.record_success();
+                false
+            }
+        };
+        if let Some(remaining) = self.mojang.take_reported_remaining(endpoint) {
+            self.upstream.report_remaining(endpoint, remaining, now);
+        }
+        self.upstream.record(endpoint, failed, now);
+        // the hedge consumed rate budget, it is recorded with the outcome of the used result
+        if hedge_sent.load(Ordering::Relaxed) {
+            self.upstream.record(endpoint, failed, now);
+        }
+        result
+    }
+
+    /// Checks whether the remaining time until the deadline of the client is too short to send a
+    /// request to a mojang api endpoint.
+    fn exceeds_deadline(&self, endpoint: &'static str) -> bool {
+        let min_upstream = self.settings.deadline.min_upstream;
+        let exceeded = deadline::remaining().is_some_and(|remaining| remaining < min_upstream);
+        if exceeded {
+            debug!(endpoint, "skipping mojang request: deadline exceeded");
+        }
+        exceeded
+    }
+
+    /// Fetches a profile (by username or uuid) from the fallback providers. It is used if neither
+    /// mojang nor the cache can serve a request. The fallback requests are bounded by the
+    /// [ConcurrencyLimits] of the mojang endpoint they replace. The data is marked with the serving
+    /// provider and is not cached. If no provider serves the profile, the error of mojang is returned.
+    async fn fetch_fallback<D>(
+        &self,
+        endpoint: &'static str,
+        query: &str,
+        err: ApiError,
+        map: impl FnOnce(Profile) -> D,
+    ) -> Result<Dated<D>, ServiceError>
+    where
+        D: Clone + Debug + Eq,
+    {
+        if self.fallback.is_empty() {
+            return Err(err.into());
+        }
+        let _permit = self.limits.acquire(endpoint).await?;
+        let now = self.cache.now_seconds();
+        match self.fallback.fetch_profile(query, now).await {
+            Ok((provider, profile)) => {
+                info!(provider, query, "served by fallback provider");
+                Ok(Dated::at(map(profile), now).with_provider(provider))
+            }
+            Err(ApiError::NotFound) => Err(NotFound),
+            Err(_) => Err(err.into()),
+        }
+    }
+
+    /// Tries to acquire the (distributed) fetch lock for refreshing an expired cache entry. It prevents
+    /// multiple instances from fetching the same resource from mojang at once.
+    async fn try_lock(&self, request_type: &str, key: &str) -> bool {
+        self.cache.try_lock(&lock_key(request_type, key)).await
+    }
+
+    /// Tries to acquire the (distributed) fetch locks for refreshing multiple expired cache entries at
+    /// once, see [try_lock](Service::try_lock). Returns the keys whose lock was acquired.
+    async fn try_lock_batch(&self, request_type: &str, keys: Vec<String>) -> Vec<String> {
+        let lock_keys: Vec<String> = keys.iter().map(|key| lock_key(request_type, key)).collect();
+        let acquired = self.cache.try_lock_batch(&lock_keys).await;
+        keys.into_iter()
+            .zip(acquired)
+            .filter_map(|(key, acquired)| acquired.then_some(key))
+            .collect()
+    }
+
+    /// Runs the fetch of a cache entry and releases its fetch lock afterward (if it was `locked`, see
+    /// [try_lock](Service::try_lock)), so that the next refresh does not wait for the lock to expire.
+    async fn release_after<F: Future>(
+        &self,
+        request_type: &str,
+        key: &str,
+        locked: bool,
+        fetch: F,
+    ) -> F::Output {
+        let result = fetch.await;
+        if locked {
+            self.cache.unlock(&[lock_key(request_type, key)]).await;
+        }
+        result
+    }
+
+    /// Counts a request of a client (api key) and checks it against the client's quota. If the client
+    /// belongs to a tenant, the request is also counted for the tenant and checked against the
+    /// tenant's quota. Fails with [QuotaExceeded](ServiceError::QuotaExceeded) if the request exceeds
+    /// any quota. Does nothing if the usage accounting is disabled.
+    #[tracing::instrument(skip(self))]
+    pub async fn record_usage(&self, client: &str) -> Result<(), ServiceError> {
+        let usage = &self.settings.usage;
+        if !usage.enabled {
+            return Ok(());
+        }
+
+        // the request is counted for all periods, even if a quota is already exceeded
+        let now = self.cache.now_seconds();
+        let mut exceeded = self.count_usage(client, usage.quota(client), now).await;
+        if let Some((name, tenant)) = self.settings.tenancy.tenant(client) {
+            let tenant_exceeded = self
+                .count_usage(&usage::tenant_client(name), &tenant.quota, now)
+                .await;
+            exceeded = exceeded.or(tenant_exceeded);
+        }
+        match exceeded {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Counts a request of a client for all [usage periods](UsagePeriod). It returns the error of the
+    /// first exceeded quota (if any).
+    async fn count_usage(
+        &self,
+        client: &str,
+        quota: &UsageQuota,
+        now: u64,
+    ) -> Option<ServiceError> {
+        let mut exceeded = None;
+        for period in UsagePeriod::ALL {
+            let window = period.window(now);
+            let count = self.cache.incr_usage(&window, client, period.ttl()).await;
+            match period.limit(quota) {
+                Some(limit) if count > limit && exceeded.is_none() => {
+                    exceeded = Some(ServiceError::QuotaExceeded {
+                        period,
+                        limit,
+                        retry_after: period.remaining(now),
+                    });
+                }
+                _ => {}
+            }
+        }
+        exceeded
+    }
+
+    /// Gets the [UsageReport] of all clients with requests in the current month, ordered by client.
+    /// The requests of tenants are reported as clients with the `tenant:` prefix.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_usage_report(&self) -> Vec<UsageReport> {
+        let now = self.cache.now_seconds();
+        let daily = self.cache.get_usage(&UsagePeriod::Daily.window(now)).await;
+        let monthly = self
+            .cache
+            .get_usage(&UsagePeriod::Monthly.window(now))
+            .await;
+
+        // clients with daily requests also have monthly requests, but the windows may expire differently
+        let tenancy = &self.settings.tenancy;
+        let mut reports = BTreeMap::new();
+        for client in daily.keys().chain(monthly.keys()) {
+            if reports.contains_key(client) {
+                continue;
+            }
+            let tenant_quota = client
+                .strip_prefix(usage::TENANT_CLIENT_PREFIX)
+                .and_then(|name| tenancy.tenants.get(name))
+                .map(|tenant| &tenant.quota);
+            let quota = tenant_quota.unwrap_or_else(|| self.settings.usage.quota(client));
+            let report = UsageReport {
+                client: client.clone(),
+                tenant: tenancy.tenant(client).map(|(name, _)| name.to_string()),
+                daily: daily.get(client).copied().unwrap_or_default(),
+                monthly: monthly.get(client).copied().unwrap_or_default(),
+                daily_limit: quota.daily,
+                monthly_limit: quota.monthly,
+            };
+            reports.insert(client.clone(), report);
+        }
+        reports.into_values().collect()
+    }
+
     /// Resolves the provided (case-insensitive) username to its (case-sensitive) username and uuid
     /// from cache or mojang.
     #[tracing::instrument(skip(self))]
@@ -130,28 +780,48 @@ where
         let cached = self.cache.get_uuid(username).await;
         let fallback = match cached {
             Hit(entry) => return entry.some_or(NotFound),
-            Expired(entry) => Some(entry),
+            Expired(entry) => {
+                // only one instance refreshes the expired entry, others use the expired entry
+                if !self.try_lock("uuid", username).await {
+                    return entry.some_or(NotFound);
+                }
+                Some(entry)
+            }
             Miss => None,
         };
-
-        // try to fetch from mojang and update cache
-        match self.mojang.fetch_uuid(username).await {
-            Ok(uuid) => {
-                let data = UuidData {
-                    username: uuid.name,
-                    uuid: uuid.id,
-                };
-                let dated = self.cache.set_uuid(username, Some(data)).await.unwrap();
-                Ok(dated)
-            }
-            Err(ApiError::NotFound) => {
-                self.cache.set_uuid(username, None).await;
-                Err(NotFound)
+        let locked = fallback.is_some();
+        self.release_after("uuid", username, locked, async {
+            // try to fetch from mojang and update cache
+            match self
+                .call_mojang("uuid", || self.mojang.fetch_uuid(username))
+                .await
+            {
+                Ok(uuid) => {
+                    let data = UuidData {
+                        username: uuid.name,
+                        uuid: uuid.id,
+                    };
+                    let dated = self.cache.set_uuid(username, Some(data)).await.unwrap();
+                    Ok(dated)
+                }
+                Err(ApiError::NotFound) => {
+                    self.cache.set_uuid(username, None).await;
+                    Err(NotFound)
+                }
+                Err(err @ (ApiError::Unavailable | ApiError::RateLimited { .. })) => match fallback
+                {
+                    Some(entry) => entry.some_or(NotFound),
+                    None => {
+                        self.fetch_fallback("uuid", username, err, |profile| UuidData {
+                            username: profile.name,
+                            uuid: profile.id,
+                        })
+                        .await
+                    }
+                },
             }
-            Err(ApiError::Unavailable) => fallback
-                .ok_or(Unavailable)
-                .and_then(|entry| entry.some_or(NotFound)),
-        }
+        })
+        .await
     }
 
     /// Resolves the provided (case-insensitive) usernames to their (case-sensitive) username and uuid
@@ -162,52 +832,135 @@ where
         &self,
         usernames: &[String],
     ) -> Result<HashMap<String, Entry<UuidData>>, ServiceError> {
+        let uuids = self.lookup_uuids(usernames, false).await?;
+        Ok(uuids
+            .into_iter()
+            .map(|(username, (_, entry))| (username, entry))
+            .collect())
+    }
+
+    /// Resolves the provided (case-insensitive) usernames to their (case-sensitive) username and uuid
+    /// from cache or mojang with the [BulkStatus] of each username. Contrary to [Service::get_uuids],
+    /// the usernames that could not be looked up (e.g. during mojang outages) are reported as
+    /// [BulkStatus::Unavailable] instead of failing the whole request.
+    #[tracing::instrument(skip(self))]
+    #[metrics::metrics(metric = "service", labels(request_type = "uuids"), handler = metrics_handler)]
+    pub async fn get_uuids_partial(
+        &self,
+        usernames: &[String],
+    ) -> Result<HashMap<String, (BulkStatus, Entry<UuidData>)>, ServiceError> {
+        self.lookup_uuids(usernames, true).await
+    }
+
+    /// Resolves the provided usernames with the [BulkStatus] of each username. If `partial` is not set,
+    /// the request fails if mojang is unavailable and any username has no cached entry.
+    async fn lookup_uuids(
+        &self,
+        usernames: &[String],
+        partial: bool,
+    ) -> Result<HashMap<String, (BulkStatus, Entry<UuidData>)>, ServiceError> {
         // 1. initialize with uuid not found
         // contrary to the mojang api, we want all requested usernames to map to something instead of
         // being omitted in case the username is invalid/unused
-        let mut uuids: HashMap<String, Entry<UuidData>> = HashMap::from_iter(
-            usernames
-                .iter()
-                .map(|username| (username.to_lowercase(), Dated::from(None))),
-        );
+        let mut uuids: HashMap<String, (BulkStatus, Entry<UuidData>)> =
+            HashMap::from_iter(usernames.iter().map(|username| {
+                (
+                    username.to_lowercase(),
+                    (
+                        BulkStatus::Invalid,
+                        Dated::at(None, self.cache.now_seconds()),
+                    ),
+                )
+            }));
 
         // append cache expired onto cache misses so that the misses are fetched first
         // if cache misses are only expired values, then it forms a valid response
         let mut cache_misses = vec![];
         let mut cache_expired = vec![];
         let mut has_misses = false;
-        for (username, uuid) in uuids.iter_mut() {
-            // 2. filter invalid usernames (regex)
-            // evidently unused (invalid) usernames should not clutter the cache nor should they fill
-            // to the mojang request rate limit. As such, they are excluded beforehand
-            if !USERNAME_REGEX.is_match(username.as_str()) {
-                continue;
-            }
-            // 3. get from cache; if cache result is expired, try to fetch and refresh
-            let cached = self.cache.get_uuid(username).await;
+        // 2. filter invalid usernames (regex)
+        // evidently unused (invalid) usernames should not clutter the cache nor should they fill
+        // to the mojang request rate limit. As such, they are excluded beforehand
+        let valid: Vec<String> = uuids
+            .keys()
+            .filter(|username| self.settings.usernames.pattern.is_match(username.as_str()))
+            .cloned()
+            .collect();
+        // 3. get from cache (in one batch); if cache result is expired, try to fetch and refresh
+        let cached = self.cache.get_uuids_batch(&valid).await;
+        for (username, cached) in valid.into_iter().zip(cached) {
             match cached {
                 Hit(entry) => {
-                    *uuid = entry;
+                    uuids.insert(username, (BulkStatus::of(&entry), entry));
                 }
                 Expired(entry) => {
-                    *uuid = entry;
-                    cache_expired.push(username.clone());
+                    uuids.insert(username.clone(), (BulkStatus::Stale, entry));
+                    cache_expired.push(username);
                 }
                 Miss => {
                     has_misses = true;
-                    cache_misses.push(username.clone());
+                    if let Some((status, _)) = uuids.get_mut(&username) {
+                        *status = BulkStatus::Unavailable;
+                    }
+                    cache_misses.push(username);
                 }
             }
         }
-        cache_misses.extend(cache_expired);
+        // while shedding, only the entries up to the bulk limit are fetched (missing entries first),
+        // so that single lookups keep the remaining budget; the other expired entries are served
+        // as-is and the other missing entries are unavailable
+        let bulk_limit = self.settings.shedding.bulk_limit;
+        let requested = cache_misses.len() + cache_expired.len();
+        if requested > bulk_limit && self.is_shedding(self.cache.now_seconds()) {
+            shedding::record_shed("bulk");
+            if cache_misses.len() > bulk_limit {
+                if !partial {
+                    debug!(
+                        missing = cache_misses.len(),
+                        bulk_limit, "rejecting bulk request: optional traffic shed"
+                    );
+                    return Err(Unavailable);
+                }
+                cache_misses.truncate(bulk_limit);
+            }
+            cache_expired.truncate(bulk_limit - cache_misses.len());
+        }
+
+        // only one instance refreshes the expired entries, others use the expired entries; the locks
+        // are only acquired for the entries that are fetched
+        let locked = self.try_lock_batch("uuid", cache_expired).await;
+        cache_misses.extend(locked.iter().cloned());
+        let result = self
+            .fetch_uuids(uuids, cache_misses, has_misses, partial)
+            .await;
+        let lock_keys: Vec<String> = locked.iter().map(|key| lock_key("uuid", key)).collect();
+        self.cache.unlock(&lock_keys).await;
+        result
+    }
 
+    /// Fetches the usernames that are missing (or expired) in the cache from mojang in one request
+    /// and updates the cache. If mojang is unavailable, the (expired) cached entries are used if
+    /// there are no misses (or partial results are requested).
+    async fn fetch_uuids(
+        &self,
+        mut uuids: HashMap<String, (BulkStatus, Entry<UuidData>)>,
+        cache_misses: Vec<String>,
+        has_misses: bool,
+        partial: bool,
+    ) -> Result<HashMap<String, (BulkStatus, Entry<UuidData>)>, ServiceError> {
         // 4. all others get from mojang in one request
         if !cache_misses.is_empty() {
-            let response = match self.mojang.fetch_uuids(&cache_misses).await {
+            let response = match self
+                .call_mojang("uuids", || self.mojang.fetch_uuids(&cache_misses))
+                .await
+            {
                 Ok(r) => r,
                 Err(err) => {
-                    // 4a. if it has no misses, use (expired) cached entries instead
-                    if !has_misses {
+                    // 4a. try to resolve the misses with the fallback providers
+                    let unresolved = self.fetch_uuids_fallback(&mut uuids, &err).await;
+                    // 4b. if it has no (unresolved) misses (or partial results are requested), use
+                    // (expired) cached entries instead
+                    if !has_misses || unresolved == 0 || partial {
                         return Ok(uuids);
                     }
                     return Err(err.into());
@@ -217,473 +970,3262 @@ where
                 .into_iter()
                 .map(|data| (data.name.to_lowercase(), data))
                 .collect();
-            for username in cache_misses {
-                // build new cache entry
-                let data = found.remove(&username).map(|res| UuidData {
-                    username: res.name.to_string(),
-                    uuid: res.id,
-                });
-                // update response and cache
-                let entry = self.cache.set_uuid(&username, data).await;
-                uuids.insert(username.clone(), entry);
-            }
+            // build new cache entries
+            let data = cache_misses
+                .into_iter()
+                .map(|username| {
+                    let data = found.remove(&username).map(|res| UuidData {
+                        username: res.name.to_string(),
+                        uuid: res.id,
+                    });
+                    (username, data)
+                })
+                .collect::<Vec<_>>();
+            // update response and cache (in one batch)
+            let usernames: Vec<String> =
+                data.iter().map(|(username, _)| username.clone()).collect();
+            let entries = self.cache.set_uuids_batch(data).await;
+            uuids.extend(
+                usernames
+                    .into_iter()
+                    .zip(entries)
+                    .map(|(username, entry)| (username, (BulkStatus::of(&entry), entry))),
+            );
         }
 
         Ok(uuids)
     }
 
+    /// Resolves the usernames that are missing in the cache (and could not be fetched from mojang)
+    /// with the fallback providers. The resolved entries are marked with the serving provider and are
+    /// not cached. It returns the number of usernames that remain unavailable.
+    async fn fetch_uuids_fallback(
+        &self,
+        uuids: &mut HashMap<String, (BulkStatus, Entry<UuidData>)>,
+        err: &ApiError,
+    ) -> usize {
+        let missing: Vec<String> = uuids
+            .iter()
+            .filter(|(_, (status, _))| *status == BulkStatus::Unavailable)
+            .map(|(username, _)| username.clone())
+            .collect();
+        if self.fallback.is_empty() {
+            return missing.len();
+        }
+        let mut unresolved = 0;
+        for username in missing {
+            let fetched = self
+                .fetch_fallback("uuids", &username, err.clone(), |profile| UuidData {
+                    username: profile.name,
+                    uuid: profile.id,
+                })
+                .await;
+            let entry = match fetched {
+                Ok(dated) => Dated {
+                    timestamp: dated.timestamp,
+                    data: Some(dated.data),
+                    provider: dated.provider,
+                },
+                Err(NotFound) => Dated::at(None, self.cache.now_seconds()),
+                Err(_) => {
+                    unresolved += 1;
+                    continue;
+                }
+            };
+            uuids.insert(username, (BulkStatus::of(&entry), entry));
+        }
+        unresolved
+    }
+
+    /// Queues the prefetching of the skin and heads of a profile (by uuid), if enabled. The prefetch is
+    /// dropped if the queue is full.
+    fn queue_prefetch(&self, uuid: &Uuid) {
+        if self.settings.prefetch.enabled && self.settings.capabilities.heads {
+            let _ = self.prefetch.try_send((tenant::current(), *uuid));
+        }
+    }
+
     /// Gets the profile for an uuid from cache or mojang.
     #[tracing::instrument(skip(self))]
     #[metrics::metrics(metric = "service", labels(request_type = "profile"), handler = metrics_age_handler)]
     pub async fn get_profile(&self, uuid: &Uuid) -> Result<Dated<ProfileData>, ServiceError> {
+        self.record_access(HotKey::Profile(*uuid));
+
         // try to get from cache
         let cached = self.cache.get_profile(uuid).await;
         let fallback = match cached {
-            Hit(entry) => return entry.some_or(NotFound),
-            Expired(entry) => Some(entry),
+            Hit(entry) => {
+                if entry.has_some() {
+                    self.queue_prefetch(uuid);
+                }
+                return entry.some_or(NotFound);
+            }
+            Expired(entry) => {
+                // only one instance refreshes the expired entry, others use the expired entry
+                if !self.try_lock("profile", &uuid.simple().to_string()).await {
+                    if entry.has_some() {
+                        self.queue_prefetch(uuid);
+                    }
+                    return entry.some_or(NotFound);
+                }
+                Some(entry)
+            }
             Miss => None,
         };
-
-        // try to fetch from mojang and update cache
-        match self
-            .mojang
-            .fetch_profile(uuid, self.settings.signed_profiles)
-            .await
-        {
-            Ok(profile) => {
-                let dated = self.cache.set_profile(uuid, Some(profile)).await.unwrap();
-                Ok(dated)
-            }
-            Err(ApiError::NotFound) => {
-                self.cache.set_profile(uuid, None).await;
-                Err(NotFound)
+        let locked = fallback.is_some();
+        self.release_after("profile", &uuid.simple().to_string(), locked, async {
+            // try to fetch from mojang and update cache
+            match self
+                .call_mojang("profile", || {
+                    self.mojang
+                        .fetch_profile(uuid, self.settings.signed_profiles)
+                })
+                .await
+            {
+                Ok(profile) => {
+                    let previous = fallback.and_then(|entry| entry.data);
+                    let dated = self.store_profile(uuid, profile, previous.as_ref()).await;
+                    self.queue_prefetch(uuid);
+                    Ok(dated)
+                }
+                Err(ApiError::NotFound) => {
+                    self.cache.set_profile(uuid, None).await;
+                    Err(NotFound)
+                }
+                Err(err @ (ApiError::Unavailable | ApiError::RateLimited { .. })) => match fallback
+                {
+                    Some(entry) => entry.some_or(NotFound),
+                    None => {
+                        let query = uuid.simple().to_string();
+                        self.fetch_fallback("profile", &query, err, |profile| profile)
+                            .await
+                    }
+                },
             }
-            Err(ApiError::Unavailable) => fallback
-                .ok_or(Unavailable)
-                .and_then(|entry| entry.some_or(NotFound)),
-        }
+        })
+        .await
+    }
+
+    /// Gets the uuids of the profiles that are sanctioned (see `profile_actions`). The profiles are
+    /// fetched from cache or mojang with the configured concurrency. The check fails open: profiles
+    /// that cannot be fetched (e.g. during mojang outages) are considered not sanctioned.
+    pub async fn get_sanctioned(&self, uuids: &[Uuid]) -> HashSet<Uuid> {
+        let concurrency = self.settings.sanctions.concurrency.max(1);
+        let profiles: Vec<_> = stream::iter(uuids.iter().copied())
+            .map(|uuid| async move { self.get_profile(&uuid).await })
+            .buffer_unordered(concurrency)
+            .collect()
+            .await;
+        profiles
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|profile| !profile.data.profile_actions.is_empty())
+            .map(|profile| profile.data.id)
+            .collect()
     }
 
     /// Gets the profile skin for an uuid from cache or mojang.
     #[tracing::instrument(skip(self))]
     #[metrics::metrics(metric = "service", labels(request_type = "skin"), handler = metrics_age_handler)]
     pub async fn get_skin(&self, uuid: &Uuid) -> Result<Dated<SkinData>, ServiceError> {
+        self.record_access(HotKey::Skin(*uuid));
+
         // try to get from cache
         let cached = self.cache.get_skin(uuid).await;
         let fallback = match cached {
             Hit(entry) => return entry.some_or(NotFound),
-            Expired(entry) => Some(entry),
+            Expired(entry) => {
+                // only one instance refreshes the expired entry, others use the expired entry
+                if !self.try_lock("skin", &uuid.simple().to_string()).await {
+                    return entry.some_or(NotFound);
+                }
+                Some(entry)
+            }
             Miss => None,
         };
+        let locked = fallback.is_some();
+        self.release_after("skin", &uuid.simple().to_string(), locked, async {
+            // try to get profile
+            let (profile, provider) = match self.get_profile(uuid).await {
+                Ok(profile) => (profile.data, profile.provider),
+                Err(err @ (Unavailable | ServiceError::RateLimited { .. })) => {
+                    return fallback
+                        .ok_or(err)
+                        .and_then(|entry| entry.some_or(NotFound))
+                }
+                Err(NotFound) => {
+                    self.cache.set_skin(uuid, None).await;
+                    return Err(NotFound);
+                }
+                Err(err) => return Err(err),
+            };
 
-        // try to get profile
-        let profile = match self.get_profile(uuid).await {
-            Ok(profile) => profile.data,
-            Err(Unavailable) => {
-                return fallback
-                    .ok_or(Unavailable)
-                    .and_then(|entry| entry.some_or(NotFound))
-            }
-            Err(NotFound) => {
-                self.cache.set_skin(uuid, None).await;
-                return Err(NotFound);
+            // replace banned skins, the suppressed skin and heads replace the cached banned ones
+            if self.settings.sanctions.suppress_banned_skins && profile.is_using_banned_skin() {
+                let skin = SkinData {
+                    suppressed: true,
+                    ..get_default_skin(uuid)
+                };
+                // skins of fallback profiles are untrusted, they are marked and not cached
+                if let Some(provider) = &provider {
+                    let now = self.cache.now_seconds();
+                    return Ok(Dated::at(skin, now).with_provider(provider));
+                }
+                let head = HeadData {
+                    suppressed: true,
+                    ..get_default_head(uuid)
+                };
+                self.cache
+                    .set_head(&HeadKey::new(*uuid, false), Some(head.clone()))
+                    .await;
+                self.cache
+                    .set_head(&HeadKey::new(*uuid, true), Some(head))
+                    .await;
+                return Ok(self.cache.set_skin(uuid, Some(skin)).await.unwrap());
             }
-            Err(err) => return Err(err),
-        };
 
-        // get textures or return default skin
-        let Some(textures) = profile.get_textures()?.textures.skin else {
-            return Ok(Dated::from(get_default_skin(uuid)));
-        };
-        let skin_model = textures
-            .metadata
-            .map(|md| md.model)
-            // fallback to classic model (I didn't check that this is the correct default behavior)
-            .unwrap_or(CLASSIC_MODEL.to_string());
+            // get textures or return default skin
+            let Some(textures) = profile.get_textures()?.textures.skin else {
+                let now = self.cache.now_seconds();
+                return Ok(Dated {
+                    provider,
+                    ..Dated::at(get_default_skin(uuid), now)
+                });
+            };
 
-        // try to fetch from mojang and update cache
-        match self.mojang.fetch_bytes(textures.url).await {
-            Ok(skin_bytes) => {
-                let skin = SkinData {
-                    bytes: skin_bytes.to_vec(),
-                    model: skin_model,
-                    default: false,
-                };
-                let dated = self.cache.set_skin(uuid, Some(skin)).await.unwrap();
-                Ok(dated)
+            // try to fetch from mojang and update cache
+            self.validate_texture_url(&textures.url)?;
+            match self.fetch_skin(textures).await {
+                Ok(skin) => {
+                    // invalid skins are not cached, so that the next request retries
+                    if let Err(err) = self.validate_texture(&skin.bytes) {
+                        return self.skin_fallback(uuid, fallback, err.into());
+                    }
+                    let skin = self.convert_legacy_skin(skin).await;
+                    // skins of fallback profiles are untrusted, they are marked and not cached
+                    if let Some(provider) = &provider {
+                        let now = self.cache.now_seconds();
+                        return Ok(Dated::at(skin, now).with_provider(provider));
+                    }
+                    if self.settings.cache.entries.head.pre_render
+                        && self.settings.capabilities.heads
+                    {
+                        // the pre-render is dropped if the queue is full
+                        let request = (tenant::current(), *uuid, skin.bytes.clone());
+                        let _ = self.pre_render.try_send(request);
+                    }
+                    let dated = self.cache.set_skin(uuid, Some(skin)).await.unwrap();
+                    Ok(dated)
+                }
+                // handle NotFound as Unavailable as the profile (and therefore the skin) should exist
+                Err(ApiError::NotFound | ApiError::Unavailable) => {
+                    self.skin_fallback(uuid, fallback, Unavailable)
+                }
+                Err(err @ ApiError::RateLimited { .. }) => {
+                    self.skin_fallback(uuid, fallback, err.into())
+                }
+            }
+        })
+        .await
+    }
+
+    /// Resolves a skin whose texture could not be fetched (or is invalid) with the tiers of the
+    /// [SkinFallback](crate::settings::SkinFallback). The error is returned if no tier applies.
+    fn skin_fallback(
+        &self,
+        uuid: &Uuid,
+        mut stale: Option<Entry<SkinData>>,
+        err: ServiceError,
+    ) -> Result<Dated<SkinData>, ServiceError> {
+        for tier in &self.settings.skin_fallback.tiers {
+            match tier {
+                SkinFallbackTier::Stale => {
+                    if let Some(entry) = stale.take() {
+                        SKIN_FALLBACK_COUNTER.with_label_values(&["stale"]).inc();
+                        return entry.some_or(NotFound);
+                    }
+                }
+                SkinFallbackTier::Default => {
+                    SKIN_FALLBACK_COUNTER.with_label_values(&["default"]).inc();
+                    let now = self.cache.now_seconds();
+                    return Ok(Dated::at(get_default_skin(uuid), now));
+                }
             }
-            // handle NotFound as Unavailable as the profile (and therefore the skin) should exist
-            Err(ApiError::NotFound) | Err(ApiError::Unavailable) => fallback
-                .ok_or(Unavailable)
-                .and_then(|entry| entry.some_or(NotFound)),
         }
+        SKIN_FALLBACK_COUNTER.with_label_values(&["error"]).inc();
+        Err(err)
     }
 
-    /// Gets the profile cape for an uuid from cache or mojang.
+    /// Gets the mojang texture url of the profile skin for an uuid without downloading the skin. Only
+    /// the profile is resolved (from cache or mojang), so the url is as old as the profile. The url is
+    /// absent for default (and suppressed) skins.
     #[tracing::instrument(skip(self))]
-    #[metrics::metrics(metric = "service", labels(request_type = "cape"), handler = metrics_age_handler)]
-    pub async fn get_cape(&self, uuid: &Uuid) -> Result<Dated<CapeData>, ServiceError> {
-        // try to get from cache
-        let cached = self.cache.get_cape(uuid).await;
-        let fallback = match cached {
-            Hit(entry) => return entry.some_or(NotFound),
+    #[metrics::metrics(metric = "service", labels(request_type = "skin_url"), handler = metrics_age_handler)]
+    pub async fn get_skin_url(&self, uuid: &Uuid) -> Result<Dated<SkinUrlData>, ServiceError> {
+        let profile = self.get_profile(uuid).await?;
+        let default = SkinUrlData {
+            url: None,
+            texture_id: None,
+            model: get_default_model(uuid).to_string(),
+            default: true,
+            suppressed: false,
+        };
+        let suppressed =
+            self.settings.sanctions.suppress_banned_skins && profile.data.is_using_banned_skin();
+        let skin = match profile.data.get_textures()?.textures.skin {
+            _ if suppressed => SkinUrlData {
+                suppressed: true,
+                ..default
+            },
+            Some(texture) => SkinUrlData {
+                texture_id: Some(texture.texture_id().to_string()),
+                model: texture
+                    .metadata
+                    .map(|metadata| metadata.model)
+                    .unwrap_or(CLASSIC_MODEL.to_string()),
+                url: Some(texture.url),
+                default: false,
+                suppressed: false,
+            },
+            None => default,
+        };
+        Ok(Dated {
+            provider: profile.provider,
+            ..Dated::at(skin, profile.timestamp)
+        })
+    }
+
+    /// Gets the mojang texture url of the profile cape for an uuid without downloading the cape. Only
+    /// the profile is resolved (from cache or mojang), so the url is as old as the profile.
+    #[tracing::instrument(skip(self))]
+    #[metrics::metrics(metric = "service", labels(request_type = "cape_url"), handler = metrics_age_handler)]
+    pub async fn get_cape_url(&self, uuid: &Uuid) -> Result<Dated<CapeUrlData>, ServiceError> {
+        let profile = self.get_profile(uuid).await?;
+        let Some(texture) = profile.data.get_textures()?.textures.cape else {
+            return Err(NotFound);
+        };
+        let cape = CapeUrlData {
+            texture_id: texture.texture_id().to_string(),
+            url: texture.url,
+        };
+        Ok(Dated {
+            provider: profile.provider,
+            ..Dated::at(cape, profile.timestamp)
+        })
+    }
+
+    /// Gets the profile cape for an uuid from cache or mojang.
+    #[tracing::instrument(skip(self))]
+    #[metrics::metrics(metric = "service", labels(request_type = "cape"), handler = metrics_age_handler)]
+    pub async fn get_cape(&self, uuid: &Uuid) -> Result<Dated<CapeData>, ServiceError> {
+        // try to get from cache
+        let cached = self.cache.get_cape(uuid).await;
+        let fallback = match cached {
+            Hit(entry) => return entry.some_or(NotFound),
+            Expired(entry) => {
+                // only one instance refreshes the expired entry, others use the expired entry
+                if !self.try_lock("cape", &uuid.simple().to_string()).await {
+                    return entry.some_or(NotFound);
+                }
+                Some(entry)
+            }
+            Miss => None,
+        };
+        let locked = fallback.is_some();
+        self.release_after("cape", &uuid.simple().to_string(), locked, async {
+            // try to get profile
+            let (profile, provider) = match self.get_profile(uuid).await {
+                Ok(profile) => (profile.data, profile.provider),
+                Err(err @ (Unavailable | ServiceError::RateLimited { .. })) => {
+                    return fallback
+                        .ok_or(err)
+                        .and_then(|entry| entry.some_or(NotFound))
+                }
+                Err(NotFound) => {
+                    self.cache.set_cape(uuid, None).await;
+                    return Err(NotFound);
+                }
+                Err(err) => return Err(err),
+            };
+
+            // try to get textures
+            let Some(textures) = profile.get_textures()?.textures.cape else {
+                return Err(NotFound);
+            };
+
+            // try to fetch from mojang and update cache
+            self.validate_texture_url(&textures.url)?;
+            match self
+                .call_mojang("bytes", || self.mojang.fetch_bytes(textures.url.clone()))
+                .await
+            {
+                Ok(cape_bytes) => {
+                    // invalid capes are not cached, so that the next request retries
+                    if let Err(err) = self.validate_texture(&cape_bytes) {
+                        return fallback
+                            .ok_or(err.into())
+                            .and_then(|entry| entry.some_or(NotFound));
+                    }
+                    let cape = CapeData {
+                        bytes: cape_bytes.into_bytes(),
+                        texture_id: Some(textures.texture_id().to_string()),
+                    };
+                    // capes of fallback profiles are untrusted, they are marked and not cached
+                    if let Some(provider) = &provider {
+                        let now = self.cache.now_seconds();
+                        return Ok(Dated::at(cape, now).with_provider(provider));
+                    }
+                    let dated = self.cache.set_cape(uuid, Some(cape)).await.unwrap();
+                    Ok(dated)
+                }
+                // handle NotFound as Unavailable as the profile (and therefore the cape) should exist
+                Err(ApiError::NotFound | ApiError::Unavailable) => fallback
+                    .ok_or(Unavailable)
+                    .and_then(|entry| entry.some_or(NotFound)),
+                Err(err @ ApiError::RateLimited { .. }) => fallback
+                    .ok_or_else(|| err.into())
+                    .and_then(|entry| entry.some_or(NotFound)),
+            }
+        })
+        .await
+    }
+
+    /// Gets a texture (e.g. skin or cape) for a (case-insensitive) texture id from cache or mojang.
+    /// The texture is resolved independent of any profile.
+    #[tracing::instrument(skip(self))]
+    #[metrics::metrics(metric = "service", labels(request_type = "texture"), handler = metrics_age_handler)]
+    pub async fn get_texture(&self, texture_id: &str) -> Result<Dated<TextureData>, ServiceError> {
+        // the texture id is used to build the texture url, so only valid texture ids are allowed
+        let texture_id = texture_id.to_lowercase();
+        if !TEXTURE_ID_REGEX.is_match(&texture_id) {
+            return Err(NotFound);
+        }
+
+        // try to get from cache
+        let cached = self.cache.get_texture(&texture_id).await;
+        let fallback = match cached {
+            Hit(entry) => return entry.some_or(NotFound),
+            Expired(entry) => {
+                // only one instance refreshes the expired entry, others use the expired entry
+                if !self.try_lock("texture", &texture_id).await {
+                    return entry.some_or(NotFound);
+                }
+                Some(entry)
+            }
+            Miss => None,
+        };
+        let locked = fallback.is_some();
+        self.release_after("texture", &texture_id, locked, async {
+            // try to fetch from mojang and update cache
+            match self
+                .call_mojang("bytes", || {
+                    self.mojang.fetch_bytes(texture_url(&texture_id))
+                })
+                .await
+            {
+                Ok(texture_bytes) => {
+                    // invalid textures are not cached, so that the next request retries
+                    if let Err(err) = self.validate_texture(&texture_bytes) {
+                        return fallback
+                            .ok_or(err.into())
+                            .and_then(|entry| entry.some_or(NotFound));
+                    }
+                    let texture = TextureData {
+                        bytes: texture_bytes.into_bytes(),
+                    };
+                    let dated = self.cache.set_texture(&texture_id, Some(texture)).await;
+                    Ok(dated.unwrap())
+                }
+                Err(ApiError::NotFound) => {
+                    self.cache.set_texture(&texture_id, None).await;
+                    Err(NotFound)
+                }
+                Err(err @ (ApiError::Unavailable | ApiError::RateLimited { .. })) => fallback
+                    .ok_or_else(|| err.into())
+                    .and_then(|entry| entry.some_or(NotFound)),
+            }
+        })
+        .await
+    }
+
+    /// Gets the blocked servers list (SHA1 hashes of hostname patterns) from cache or mojang.
+    #[tracing::instrument(skip(self))]
+    #[metrics::metrics(metric = "service", labels(request_type = "blocked_servers"), handler = metrics_age_handler)]
+    pub async fn get_blocked_servers(&self) -> Result<Dated<BlockedServersData>, ServiceError> {
+        // try to get from cache
+        let cached = self.cache.get_blocked_servers().await;
+        let fallback = match cached {
+            Hit(entry) => return entry.some_or(NotFound),
+            Expired(entry) => {
+                // only one instance refreshes the expired entry, others use the expired entry
+                if !self.try_lock("blocked_servers", "list").await {
+                    return entry.some_or(NotFound);
+                }
+                Some(entry)
+            }
+            Miss => None,
+        };
+        let locked = fallback.is_some();
+        self.release_after("blocked_servers", "list", locked, async {
+            // try to fetch from mojang and update cache
+            match self
+                .call_mojang("blocked_servers", || self.mojang.fetch_blocked_servers())
+                .await
+            {
+                Ok(hashes) => {
+                    let blocked = BlockedServersData {
+                        hashes: hashes.into(),
+                    };
+                    let dated = self.cache.set_blocked_servers(Some(blocked)).await;
+                    Ok(dated.unwrap())
+                }
+                Err(err) => fallback
+                    .ok_or_else(|| err.into())
+                    .and_then(|entry| entry.some_or(NotFound)),
+            }
+        })
+        .await
+    }
+
+    /// Checks whether a (case-insensitive) hostname is blocked by mojang. The wildcard patterns of the
+    /// hostname are computed and hashed by the service, so that clients only need the hostname. It
+    /// resolves to the matching pattern if the hostname is blocked.
+    #[tracing::instrument(skip(self))]
+    #[metrics::metrics(metric = "service", labels(request_type = "blocked_server"), handler = metrics_age_handler)]
+    pub async fn is_server_blocked(
+        &self,
+        hostname: &str,
+    ) -> Result<Dated<Option<String>>, ServiceError> {
+        if hostname.trim().is_empty() {
+            return Err(InvalidArgument("hostname must not be empty".into()));
+        }
+        let blocked = self.get_blocked_servers().await?;
+        Ok(Dated::at(
+            find_blocked(hostname, &blocked.data.hashes),
+            blocked.timestamp,
+        ))
+    }
+
+    /// Builds a synthetic (unsigned) profile with a `textures` property, e.g. for spawning NPCs. The
+    /// skin is either provided as texture url or texture id or taken from the profile of an uuid.
+    /// The model and cape default to those of the profile (if any) or to the classic model without
+    /// cape. The synthetic profile uses a random uuid and a derived name if not provided. It is dated
+    /// with the timestamp of the source profile (if any), as its textures are taken from it.
+    #[tracing::instrument(skip(self))]
+    #[metrics::metrics(metric = "service", labels(request_type = "textures"), handler = metrics_age_handler)]
+    pub async fn build_textures(
+        &self,
+        uuid: Option<&Uuid>,
+        skin: Option<&str>,
+        model: Option<&str>,
+        cape: Option<&str>,
+        profile_id: Option<Uuid>,
+        profile_name: Option<&str>,
+    ) -> Result<Dated<ProfileData>, ServiceError> {
+        // get the textures of the source profile (if any)
+        let now = self.cache.now_seconds();
+        let (timestamp, source) = match uuid {
+            Some(uuid) => {
+                let profile = self.get_profile(uuid).await?;
+                (
+                    profile.timestamp,
+                    Some(profile.data.get_textures()?.textures),
+                )
+            }
+            None => (now, None),
+        };
+        let source_skin = source.as_ref().and_then(|textures| textures.skin.clone());
+        let source_cape = source.and_then(|textures| textures.cape);
+
+        // resolve the skin, model and cape (explicit arguments override the source profile)
+        let skin_url = match (skin, &source_skin) {
+            (Some(skin), _) => resolve_texture_url(skin)?,
+            (None, Some(texture)) => texture.url.clone(),
+            (None, None) if uuid.is_some() => return Err(NotFound),
+            (None, None) => return Err(InvalidArgument("either uuid or skin is required".into())),
+        };
+        let model = match (model, source_skin.and_then(|texture| texture.metadata)) {
+            (Some(model @ (CLASSIC_MODEL | SLIM_MODEL)), _) => model.to_string(),
+            (Some(model), _) => return Err(InvalidArgument(format!("unknown model {}", model))),
+            (None, Some(metadata)) => metadata.model,
+            (None, None) => CLASSIC_MODEL.to_string(),
+        };
+        let cape_url = match (cape, source_cape) {
+            (Some(cape), _) => Some(resolve_texture_url(cape)?),
+            (None, source_cape) => source_cape.map(|texture| texture.url),
+        };
+
+        // build the synthetic profile
+        let profile_id = profile_id.unwrap_or_else(Uuid::new_v4);
+        let profile_name = match profile_name {
+            Some(name) if self.settings.usernames.pattern.is_match(name) => name.to_string(),
+            Some(name) => return Err(InvalidArgument(format!("invalid profile name {}", name))),
+            None => format!("npc_{}", &profile_id.simple().to_string()[..8]),
+        };
+        let textures = TexturesProperty::new(
+            profile_id,
+            &profile_name,
+            skin_url,
+            &model,
+            cape_url,
+            now * 1000,
+        );
+        Ok(Dated {
+            timestamp,
+            data: ProfileData {
+                id: profile_id,
+                name: profile_name,
+                properties: vec![ProfileProperty {
+                    name: "textures".to_string(),
+                    value: encode_texture_prop(&textures),
+                    signature: None,
+                }]
+                .into(),
+                profile_actions: vec![],
+            },
+            provider: None,
+        })
+    }
+
+    /// Gets the profile skin for an uuid with the style overrides of a request. If the default skin is
+    /// forced, the actual skin is ignored and the default skin (of the model) is returned, only the
+    /// profile is resolved. The model overrides the model of the skin, default skins are replaced with
+    /// the default skin of the model.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_skin_styled(
+        &self,
+        uuid: &Uuid,
+        force_default: bool,
+        model: Option<&str>,
+    ) -> Result<Dated<SkinData>, ServiceError> {
+        let model = model.map(parse_model).transpose()?;
+        if force_default {
+            let profile = self.get_profile(uuid).await?;
+            let model = model.unwrap_or(get_default_model(uuid));
+            return Ok(Dated::at(get_model_skin(model), profile.timestamp));
+        }
+        let mut skin = self.get_skin(uuid).await?;
+        match model {
+            Some(model) if skin.data.default => {
+                skin.data = SkinData {
+                    suppressed: skin.data.suppressed,
+                    ..get_model_skin(model)
+                };
+            }
+            Some(model) => skin.data.model = model.to_string(),
+            None => {}
+        }
+        Ok(skin)
+    }
+
+    /// Gets the profile head for a [HeadKey] with the style overrides of a request. If the default skin
+    /// is forced, the actual skin is ignored and the default head (of the model) is returned, only the
+    /// profile is resolved. Default heads are replaced with the default head of the model, the model
+    /// does not change other heads.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_head_styled(
+        &self,
+        key: &HeadKey,
+        force_default: bool,
+        model: Option<&str>,
+    ) -> Result<Dated<HeadData>, ServiceError> {
+        let model = model.map(parse_model).transpose()?;
+        if force_default {
+            let profile = self.get_profile(&key.uuid).await?;
+            let model = model.unwrap_or(get_default_model(&key.uuid));
+            let head = Dated::at(get_model_head(model), profile.timestamp);
+            return self.format_head(head, key).await;
+        }
+        let mut head = self.get_head(key).await?;
+        match model.filter(|_| head.data.default) {
+            Some(model) => {
+                head.data = HeadData {
+                    suppressed: head.data.suppressed,
+                    ..get_model_head(model)
+                };
+                self.format_head(head, key).await
+            }
+            None => Ok(head),
+        }
+    }
+
+    /// Checks whether a placeholder should be served instead of an error. Placeholders are only served
+    /// (if enabled) if mojang is unavailable and nothing is cached.
+    pub fn serves_placeholder(&self, err: &ServiceError) -> bool {
+        self.settings.placeholder.enabled
+            && matches!(err, Unavailable | ServiceError::RateLimited { .. })
+    }
+
+    /// Gets the placeholder skin for an uuid. It is either the configured placeholder or the default
+    /// skin of the profile.
+    pub fn get_placeholder_skin(&self, uuid: &Uuid) -> Dated<SkinData> {
+        let skin = match self.placeholders.skin() {
+            Some(bytes) => SkinData {
+                bytes,
+                model: CLASSIC_MODEL.to_string(),
+                default: true,
+                suppressed: false,
+            },
+            None => get_default_skin(uuid),
+        };
+        Dated::at(skin, self.cache.now_seconds())
+    }
+
+    /// Gets the placeholder head for an uuid. It is either the configured placeholder or the default
+    /// head of the profile.
+    pub fn get_placeholder_head(&self, uuid: &Uuid) -> Dated<HeadData> {
+        let head = match self.placeholders.head() {
+            Some(bytes) => HeadData {
+                bytes,
+                default: true,
+                suppressed: false,
+            },
+            None => get_default_head(uuid),
+        };
+        Dated::at(head, self.cache.now_seconds())
+    }
+
+    /// Gets the profile head for a [HeadKey] from cache or mojang. The head may include the head
+    /// overlay. Other sizes and styles than the native head are rendered from the native head and
+    /// cached under their own key, so that they are not rendered for every request.
+    #[tracing::instrument(skip(self))]
+    #[metrics::metrics(metric = "service", labels(request_type = "head"), handler = metrics_age_handler)]
+    pub async fn get_head(&self, key: &HeadKey) -> Result<Dated<HeadData>, ServiceError> {
+        if key.is_native() {
+            return self.get_native_head(&key.uuid, key.overlay).await;
+        }
+
+        // try to get from cache
+        let cached = self.cache.get_head(key).await;
+        let fallback = match cached {
+            Hit(entry) => return entry.some_or(NotFound),
             Expired(entry) => Some(entry),
             Miss => None,
         };
 
-        // try to get profile
-        let profile = match self.get_profile(uuid).await {
-            Ok(profile) => profile.data,
-            Err(Unavailable) => {
+        // try to get native head
+        let native = match self.get_native_head(&key.uuid, key.overlay).await {
+            Ok(native) => native,
+            Err(err @ (Unavailable | ServiceError::RateLimited { .. })) => {
                 return fallback
-                    .ok_or(Unavailable)
+                    .ok_or(err)
+                    .and_then(|entry| entry.some_or(NotFound))
+            }
+            Err(err) => return Err(err),
+        };
+
+        // render head, default heads and heads of fallback profiles are not cached (like their
+        // native heads)
+        let head = self.format_head(native, key).await?;
+        if head.data.default || head.provider.is_some() {
+            return Ok(head);
+        }
+        let dated = self.cache.set_head(key, Some(head.data)).await.unwrap();
+        Ok(dated)
+    }
+
+    /// Renders a native head in the size and style of a [HeadKey] on the [RenderPool]. Native heads
+    /// are returned unchanged.
+    pub async fn format_head(
+        &self,
+        mut head: Dated<HeadData>,
+        key: &HeadKey,
+    ) -> Result<Dated<HeadData>, ServiceError> {
+        if key.is_native() {
+            return Ok(head);
+        }
+        let bytes = head.data.bytes.clone();
+        let (scale, rgba) = (key.scale(), key.style == HeadStyle::Rgba);
+        let rendered = self
+            .render("head_format", move || render_head(&bytes, scale, rgba))
+            .await?;
+        head.data.bytes = rendered.into();
+        Ok(head)
+    }
+
+    /// Gets the native profile head for an uuid from cache or mojang. The head may include the head
+    /// overlay.
+    async fn get_native_head(
+        &self,
+        uuid: &Uuid,
+        overlay: bool,
+    ) -> Result<Dated<HeadData>, ServiceError> {
+        // try to get from cache
+        let cached = self.cache.get_head(&HeadKey::new(*uuid, overlay)).await;
+        let fallback = match cached {
+            Hit(entry) => return entry.some_or(NotFound),
+            Expired(entry) => Some(entry),
+            Miss => None,
+        };
+
+        // try to get skin
+        let (skin, provider) = match self.get_skin(uuid).await {
+            Ok(skin) => (skin.data, skin.provider),
+            Err(err @ (Unavailable | ServiceError::RateLimited { .. })) => {
+                return fallback
+                    .ok_or(err)
                     .and_then(|entry| entry.some_or(NotFound))
             }
             Err(NotFound) => {
-                self.cache.set_cape(uuid, None).await;
+                self.cache.set_head(&HeadKey::new(*uuid, false), None).await;
+                self.cache.set_head(&HeadKey::new(*uuid, true), None).await;
                 return Err(NotFound);
             }
             Err(err) => return Err(err),
         };
 
-        // try to get textures
-        let Some(textures) = profile.get_textures()?.textures.cape else {
-            return Err(NotFound);
+        // handle default (and suppressed) skins
+        if skin.default {
+            let head = HeadData {
+                suppressed: skin.suppressed,
+                ..get_default_head(uuid)
+            };
+            let now = self.cache.now_seconds();
+            return Ok(Dated {
+                provider,
+                ..Dated::at(head, now)
+            });
+        }
+
+        // build head, heads of fallback profiles are untrusted, they are marked and not cached
+        let head = self.build_head(skin.bytes, overlay).await?;
+        if let Some(provider) = &provider {
+            let now = self.cache.now_seconds();
+            return Ok(Dated::at(head, now).with_provider(provider));
+        }
+        let dated = self
+            .cache
+            .set_head(&HeadKey::new(*uuid, overlay), Some(head))
+            .await
+            .unwrap();
+        Ok(dated)
+    }
+
+    /// Builds the head (with or without overlay) of a (non-default) skin on the [RenderPool].
+    async fn build_head(&self, skin_bytes: Bytes, overlay: bool) -> Result<HeadData, ServiceError> {
+        let head_overlay = self.settings.head_overlay.clone();
+        let head_bytes = self
+            .render("head", move || {
+                build_skin_head(&skin_bytes, overlay, &head_overlay)
+            })
+            .await?;
+        Ok(HeadData {
+            bytes: head_bytes.into(),
+            default: false,
+            suppressed: false,
+        })
+    }
+
+    /// Gets the checksums (SHA-256) of the profile skin and head for an uuid along with the texture id
+    /// of the skin. Clients can use them to check whether their local images are still current before
+    /// downloading the images. The checksums are as old as the oldest of the images.
+    #[tracing::instrument(skip(self))]
+    #[metrics::metrics(metric = "service", labels(request_type = "checksum"), handler = metrics_age_handler)]
+    pub async fn get_checksum(
+        &self,
+        uuid: &Uuid,
+        overlay: bool,
+    ) -> Result<Dated<ChecksumData>, ServiceError> {
+        let skin = self.get_skin(uuid).await?;
+        let head = self.get_head(&HeadKey::new(*uuid, overlay)).await?;
+        // default (and suppressed) skins have no texture
+        let texture_id = match skin.data.default {
+            true => None,
+            false => self
+                .get_profile(uuid)
+                .await?
+                .data
+                .get_textures()
+                .ok()
+                .and_then(|textures| textures.textures.skin)
+                .map(|skin| skin.texture_id().to_string()),
+        };
+        let checksum = ChecksumData {
+            texture_id,
+            skin: sha256(&skin.data.bytes),
+            head: sha256(&head.data.bytes),
+            default: skin.data.default,
+        };
+        Ok(Dated::at(checksum, skin.timestamp.min(head.timestamp)))
+    }
+
+    /// Gets the [ProfileBundle] of a profile (by uuid) with or without the head overlay. The parts are
+    /// resolved with their own caches, so that the bundle is as fresh as its parts. The profile is
+    /// resolved first, as the skin and head are derived from it and its textures tell whether it has a
+    /// cape. The cape itself is not fetched.
+    #[tracing::instrument(skip(self))]
+    #[metrics::metrics(metric = "service", labels(request_type = "profile_bundle"), handler = metrics_handler)]
+    pub async fn get_profile_bundle(
+        &self,
+        uuid: &Uuid,
+        overlay: bool,
+    ) -> Result<ProfileBundle, ServiceError> {
+        let profile = self.get_profile(uuid).await?;
+        let skin = self.get_skin(uuid).await?;
+        let has_cape = profile.data.get_textures()?.textures.cape.is_some();
+        let head = self.get_head(&HeadKey::new(*uuid, overlay)).await?;
+        Ok(ProfileBundle {
+            profile,
+            skin,
+            has_cape,
+            head,
+        })
+    }
+
+    /// Gets the dominant colors of the skin (or head) of a profile (by uuid), the most dominant first.
+    /// The head is analyzed with the configured overlay default. A count of zero is treated as the
+    /// default count.
+    #[tracing::instrument(skip(self))]
+    #[metrics::metrics(metric = "service", labels(request_type = "colors"), handler = metrics_handler)]
+    pub async fn get_colors(
+        &self,
+        uuid: &Uuid,
+        head: bool,
+        count: usize,
+    ) -> Result<SkinColors, ServiceError> {
+        let count = match count {
+            0 => colors::DEFAULT_COUNT,
+            count if count > colors::MAX_COUNT => {
+                return Err(InvalidArgument(format!(
+                    "count must not exceed {}",
+                    colors::MAX_COUNT
+                )))
+            }
+            count => count,
+        };
+        let (timestamp, default, bytes) = match head {
+            true => {
+                let overlay = self.settings.head_overlay.default;
+                let head = self.get_head(&HeadKey::new(*uuid, overlay)).await?;
+                (head.timestamp, head.data.default, head.data.bytes)
+            }
+            false => {
+                let skin = self.get_skin(uuid).await?;
+                (skin.timestamp, skin.data.default, skin.data.bytes)
+            }
+        };
+        let colors = self
+            .render("colors", move || colors::dominant_colors(&bytes, count))
+            .await?;
+        Ok(SkinColors {
+            timestamp,
+            default,
+            colors,
+        })
+    }
+
+    /// Gets all observed usernames for an uuid from the profile history.
+    #[cfg(feature = "history")]
+    #[tracing::instrument(skip(self))]
+    #[metrics::metrics(metric = "service", labels(request_type = "name_history"), handler = metrics_handler)]
+    pub async fn get_name_history(
+        &self,
+        uuid: &Uuid,
+    ) -> Result<Vec<NameHistoryData>, ServiceError> {
+        let history = self.history.as_ref().ok_or(HistoryError::Disabled)?;
+        Ok(history.get_name_history(uuid).await?)
+    }
+
+    /// Gets all observed skin textures for an uuid from the profile history.
+    #[cfg(feature = "history")]
+    #[tracing::instrument(skip(self))]
+    #[metrics::metrics(metric = "service", labels(request_type = "skin_history"), handler = metrics_handler)]
+    pub async fn get_skin_history(
+        &self,
+        uuid: &Uuid,
+    ) -> Result<Vec<SkinHistoryData>, ServiceError> {
+        let history = self.history.as_ref().ok_or(HistoryError::Disabled)?;
+        Ok(history.get_skin_history(uuid).await?)
+    }
+
+    /// Stores a freshly fetched profile in the cache. It is recorded in the profile history (if
+    /// enabled) and its changes to the previously cached profile are published.
+    async fn store_profile(
+        &self,
+        uuid: &Uuid,
+        profile: ProfileData,
+        previous: Option<&ProfileData>,
+    ) -> Dated<ProfileData> {
+        #[cfg(feature = "history")]
+        self.record_history(&profile).await;
+        if let Some(previous) = previous {
+            self.publish_changes(previous, &profile);
+        }
+        if self.settings.sanctions.alert_enabled {
+            let timestamp = self.cache.now_seconds();
+            if let Some(alert) = sanctions::detect_sanctions(previous, &profile, timestamp) {
+                sanctions::alert(&self.settings.sanctions, alert);
+            }
+        }
+        self.cache.set_profile(uuid, Some(profile)).await.unwrap()
+    }
+
+    /// Fetches the skin of a skin [Texture] from mojang.
+    async fn fetch_skin(&self, texture: Texture) -> Result<SkinData, ApiError> {
+        let model = texture
+            .metadata
+            .map(|md| md.model)
+            // fallback to classic model (I didn't check that this is the correct default behavior)
+            .unwrap_or(CLASSIC_MODEL.to_string());
+        let bytes = self
+            .call_mojang("bytes", || self.mojang.fetch_bytes(texture.url.clone()))
+            .await?;
+        Ok(SkinData {
+            bytes: bytes.into_bytes(),
+            model,
+            default: false,
+            suppressed: false,
+        })
+    }
+
+    /// Validates fetched texture bytes (if enabled), see [validate_texture](mojang::validate_texture).
+    /// Rejected textures are counted and logged.
+    fn validate_texture(&self, bytes: &[u8]) -> Result<(), InvalidTexture> {
+        let validation = &self.settings.texture_validation;
+        if !validation.enabled {
+            return Ok(());
+        }
+        mojang::validate_texture(bytes, validation.max_bytes).inspect_err(|err| {
+            INVALID_TEXTURES_COUNTER
+                .with_label_values(&[err.reason()])
+                .inc();
+            warn!(error = %err, "rejected invalid texture");
+        })
+    }
+
+    /// Validates a texture url before the texture is fetched, see
+    /// [validate_texture_url](mojang::validate_texture_url). Rejected urls are counted and logged.
+    fn validate_texture_url(&self, url: &str) -> Result<(), InvalidTexture> {
+        mojang::validate_texture_url(url, &self.settings.texture_hosts.allowed).inspect_err(|err| {
+            INVALID_TEXTURES_COUNTER
+                .with_label_values(&[err.reason()])
+                .inc();
+            warn!(error = %err, "rejected texture url");
+        })
+    }
+
+    /// Converts a legacy skin into the modern skin layout (if enabled), see
+    /// [skin_convert](crate::render::skin_convert). Skins that fail to convert are kept as is.
+    async fn convert_legacy_skin(&self, skin: SkinData) -> SkinData {
+        if !self.settings.legacy_skins.convert {
+            return skin;
+        }
+        let skin_bytes = skin.bytes.clone();
+        let converted = self
+            .render("skin_convert", move || {
+                skin_convert::convert_legacy_bytes(&skin_bytes)
+            })
+            .await;
+        match converted {
+            Ok(Some(bytes)) => SkinData {
+                bytes: bytes.into(),
+                ..skin
+            },
+            Ok(None) => skin,
+            Err(err) => {
+                warn!(error = %err, "failed to convert legacy skin");
+                skin
+            }
+        }
+    }
+
+    /// Records an access of a [HotKey] for the background refresh (if enabled). Accesses of the
+    /// refreshes themselves are not recorded (see [refresh::untracked]).
+    fn record_access(&self, key: HotKey) {
+        if self.settings.refresh.enabled && refresh::is_tracked() {
+            self.access.record(key);
+        }
+    }
+
+    /// Refreshes the cache entry of a [HotKey] if it expires within the lead. Entries that are
+    /// missing, empty or already expired are refreshed by the next request instead. The refresh is
+    /// skipped if the remaining rate budget is reserved for client requests.
+    async fn refresh_if_expiring(&self, key: HotKey, lead: Duration) {
+        let endpoint = match key {
+            HotKey::Profile(_) => "profile",
+            HotKey::Skin(_) => "bytes",
+        };
+        if !self.allows_optional("refresh", endpoint, self.cache.now_seconds()) {
+            debug!(endpoint, "skipping refresh: optional traffic shed");
+            return;
+        }
+        let now = self.cache.now_seconds() + lead.as_secs();
+        let entries = self.cache_entries();
+        match key {
+            HotKey::Profile(uuid) => {
+                let Hit(entry) = self.cache.get_profile(&uuid).await else {
+                    return;
+                };
+                if entry.has_none() || !entry.is_expired_at(&entries.profile.jittered(&uuid), now) {
+                    return;
+                }
+                let signed = self.settings.signed_profiles;
+                match self
+                    .call_mojang("profile", || self.mojang.fetch_profile(&uuid, signed))
+                    .await
+                {
+                    Ok(profile) => {
+                        self.store_profile(&uuid, profile, entry.data.as_ref())
+                            .await;
+                    }
+                    Err(ApiError::NotFound) => {
+                        self.cache.set_profile(&uuid, None).await;
+                    }
+                    Err(err) => warn!(error = %err, "failed to refresh profile"),
+                }
+            }
+            HotKey::Skin(uuid) => {
+                let Hit(entry) = self.cache.get_skin(&uuid).await else {
+                    return;
+                };
+                if entry.has_none() || !entry.is_expired_at(&entries.skin.jittered(&uuid), now) {
+                    return;
+                }
+                let Ok(profile) = self.get_profile(&uuid).await else {
+                    return;
+                };
+                let Ok(textures) = profile.data.get_textures() else {
+                    return;
+                };
+                let Some(texture) = textures.textures.skin else {
+                    return;
+                };
+                if self.validate_texture_url(&texture.url).is_err() {
+                    return;
+                }
+                match self.fetch_skin(texture).await {
+                    Ok(skin) => {
+                        if self.validate_texture(&skin.bytes).is_ok() {
+                            let skin = self.convert_legacy_skin(skin).await;
+                            self.cache.set_skin(&uuid, Some(skin)).await;
+                        }
+                    }
+                    Err(err) => warn!(error = %err, "failed to refresh skin"),
+                }
+            }
+        }
+    }
+
+    /// Keeps the profile and skin of a pinned profile fresh. Missing and expired entries are fetched
+    /// like by a request, entries that expire within the configured lead are refreshed ahead of time.
+    async fn refresh_pinned(&self, uuid: Uuid) {
+        if let Err(err) = self.get_profile(&uuid).await {
+            warn!(error = %err, %uuid, "failed to refresh pinned profile");
+            return;
+        }
+        let lead = self.settings.pinned.lead;
+        self.refresh_if_expiring(HotKey::Profile(uuid), lead).await;
+        if !self.settings.capabilities.skins {
+            return;
+        }
+        if let Err(err) = self.get_skin(&uuid).await {
+            warn!(error = %err, %uuid, "failed to refresh pinned skin");
+            return;
+        }
+        self.refresh_if_expiring(HotKey::Skin(uuid), lead).await;
+    }
+
+    /// Publishes the changes between a previously cached and a freshly fetched profile as
+    /// [ProfileEvent]. Events without subscribers are dropped.
+    fn publish_changes(&self, previous: &ProfileData, current: &ProfileData) {
+        for event in detect_changes(previous, current, self.cache.now_seconds()) {
+            let _ = self.events.send(event);
+        }
+    }
+
+    /// Records a freshly fetched profile in the profile history (if enabled). Failures are logged but
+    /// do not fail the request.
+    #[cfg(feature = "history")]
+    async fn record_history(&self, profile: &ProfileData) {
+        let Some(history) = &self.history else {
+            return;
         };
+        if let Err(err) = history
+            .record_profile(profile, self.cache.now_seconds())
+            .await
+        {
+            warn!(error = %err, "failed to record profile history");
+        }
+    }
+}
+
+impl<L, R, M> Service<L, R, M>
+where
+    L: CacheLevel + Sync + 'static,
+    R: CacheLevel + Sync + 'static,
+    M: Mojang + Sync + 'static,
+{
+    /// Runs the background refresh of hot entries of the [Service]. In each interval, the hottest
+    /// profiles and skins that expire soon are refreshed. The refreshes are spread evenly over the
+    /// interval. It never returns.
+    pub async fn run_refresh(self: Arc<Self>) {
+        let settings = &self.settings.refresh;
+        let mut interval = tokio::time::interval(settings.interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let hottest = self.access.hottest(settings.top);
+            if hottest.is_empty() {
+                continue;
+            }
+            let start = Instant::now();
+            let spacing = settings.interval / hottest.len() as u32;
+            for (i, key) in hottest.into_iter().enumerate() {
+                // the refreshes start at fixed offsets, so that their duration does not stretch the cycle
+                tokio::time::sleep_until(start + spacing * i as u32).await;
+                refresh::untracked(self.refresh_if_expiring(key, settings.lead)).await;
+            }
+        }
+    }
+
+    /// Runs the scheduled refresh of the pinned profiles of the [Service]. In each interval, the
+    /// pinned profiles are read from the cache (so that profiles pinned at other instances are
+    /// included) and their missing, expired or soon expiring entries are refreshed. The refreshes are
+    /// spread evenly over the interval. It never returns.
+    pub async fn run_pinned(self: Arc<Self>) {
+        let settings = &self.settings.pinned;
+        let mut interval = tokio::time::interval(settings.interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let pinned = self.cache.get_pinned().await;
+            if pinned.is_empty() {
+                continue;
+            }
+            let start = Instant::now();
+            let spacing = settings.interval / pinned.len() as u32;
+            for (i, uuid) in pinned.into_iter().enumerate() {
+                // the refreshes start at fixed offsets, so that their duration does not stretch the cycle
+                tokio::time::sleep_until(start + spacing * i as u32).await;
+                refresh::untracked(self.refresh_pinned(uuid)).await;
+            }
+        }
+    }
+
+    /// Prefetches the skin and both heads (with and without overlay) of a profile (by uuid) into the
+    /// cache. The head with overlay fetches the skin, the head without overlay reuses it.
+    async fn prefetch_heads(&self, uuid: Uuid) {
+        for overlay in [true, false] {
+            if let Err(err) = self.get_head(&HeadKey::new(uuid, overlay)).await {
+                warn!(error = %err, "failed to prefetch head");
+                break;
+            }
+        }
+    }
+
+    /// Runs the skin and head prefetching of the [Service]. The skin and heads of all requested
+    /// profiles are fetched in the background with the configured concurrency, unless they are
+    /// cached already. The prefetches are dropped if the remaining rate budget is reserved for
+    /// client requests. It should be spawned once, later calls return immediately.
+    pub async fn run_prefetch(self: Arc<Self>) {
+        let Some(mut queue) = self.prefetch_queue.lock().unwrap().take() else {
+            return;
+        };
+        let permits = Arc::new(Semaphore::new(self.settings.prefetch.concurrency.max(1)));
+        while let Some((tenant, uuid)) = queue.recv().await {
+            if !self.allows_optional("prefetch", "bytes", self.cache.now_seconds()) {
+                debug!("skipping prefetch: optional traffic shed");
+                continue;
+            }
+            let permit = Arc::clone(&permits).acquire_owned().await.unwrap();
+            let service = Arc::clone(&self);
+            tokio::spawn(async move {
+                // the prefetch uses the cache entry configurations and metrics label of the requesting
+                // tenant, the prefetched entries are shared by all tenants
+                tenant::scope(tenant, service.prefetch_heads(uuid)).await;
+                drop(permit);
+            });
+        }
+    }
+
+    /// Runs the head pre-rendering of the [Service]. Both heads (with and without overlay) of all
+    /// freshly fetched skins are rendered in the background and cached, so that head requests do not
+    /// render them again. It should be spawned once, later calls return immediately.
+    pub async fn run_pre_render(self: Arc<Self>) {
+        let Some(mut queue) = self.pre_render_queue.lock().unwrap().take() else {
+            return;
+        };
+        // requests are only taken from the queue if a render is free, so that pending pre-renders
+        // remain in the (bounded) queue and further requests are dropped once it is full
+        let concurrency = self.settings.render.concurrency.max(1);
+        stream::poll_fn(|cx| queue.poll_recv(cx))
+            .for_each_concurrent(concurrency, |request| self.pre_render_heads(request))
+            .await;
+    }
+
+    /// Pre-renders both heads (with and without overlay) of a [PreRenderRequest] and caches them with
+    /// the cache entry configurations of the requesting tenant. The heads are shared by all tenants.
+    async fn pre_render_heads(&self, (tenant, uuid, skin_bytes): PreRenderRequest) {
+        tenant::scope(tenant, async {
+            for overlay in [true, false] {
+                match self.build_head(skin_bytes.clone(), overlay).await {
+                    Ok(head) => {
+                        let key = HeadKey::new(uuid, overlay);
+                        self.cache.set_head(&key, Some(head)).await;
+                    }
+                    Err(err) => {
+                        warn!(error = %err, "failed to pre-render head");
+                        break;
+                    }
+                }
+            }
+        })
+        .await;
+    }
+}
+
+/// Builds the key of a fetch lock (e.g. `uuid.hydrofin`). The keys are lowercase, so that all
+/// spellings of a (case-insensitive) username share the same lock.
+fn lock_key(request_type: &str, key: &str) -> String {
+    format!("{}.{}", request_type, key.to_lowercase())
+}
+
+/// Resolves a texture url from either a mojang texture url or a texture id. Other urls are rejected,
+/// as the minecraft client only accepts mojang textures.
+fn resolve_texture_url(texture: &str) -> Result<String, ServiceError> {
+    let texture_id = texture
+        .strip_prefix(TEXTURES_URL)
+        .or_else(|| texture.strip_prefix(&TEXTURES_URL.replacen("http", "https", 1)))
+        .map(|path| path.trim_start_matches('/'))
+        .unwrap_or(texture)
+        .to_lowercase();
+    if !TEXTURE_ID_REGEX.is_match(&texture_id) {
+        return Err(InvalidArgument(format!("invalid texture {}", texture)));
+    }
+    Ok(texture_url(&texture_id))
+}
+
+/// Gets the (lowercase hex) SHA-256 checksum of some bytes.
+fn sha256(bytes: &[u8]) -> String {
+    digest(&SHA256, bytes)
+        .as_ref()
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// Gets the default skin model for a [Uuid].
+fn get_default_model(uuid: &Uuid) -> &'static str {
+    match mojang::is_steve(uuid) {
+        true => CLASSIC_MODEL,
+        false => SLIM_MODEL,
+    }
+}
+
+/// Parses a skin model of a request (`classic` or `slim`).
+fn parse_model(model: &str) -> Result<&'static str, ServiceError> {
+    match model {
+        CLASSIC_MODEL => Ok(CLASSIC_MODEL),
+        SLIM_MODEL => Ok(SLIM_MODEL),
+        model => Err(InvalidArgument(format!("unknown model {}", model))),
+    }
+}
+
+/// Gets the default [SkinData] for a [Uuid].
+fn get_default_skin(uuid: &Uuid) -> SkinData {
+    get_model_skin(get_default_model(uuid))
+}
+
+/// Gets the default [SkinData] of a skin model (Steve for classic and Alex for slim).
+fn get_model_skin(model: &str) -> SkinData {
+    match model {
+        SLIM_MODEL => SkinData {
+            bytes: ALEX_SKIN,
+            model: SLIM_MODEL.to_string(),
+            default: true,
+            suppressed: false,
+        },
+        _ => SkinData {
+            bytes: STEVE_SKIN,
+            model: CLASSIC_MODEL.to_string(),
+            default: true,
+            suppressed: false,
+        },
+    }
+}
+
+/// Gets the default [HeadData] for a [Uuid].
+fn get_default_head(uuid: &Uuid) -> HeadData {
+    get_model_head(get_default_model(uuid))
+}
+
+/// Gets the default [HeadData] of a skin model (Steve for classic and Alex for slim).
+fn get_model_head(model: &str) -> HeadData {
+    match model {
+        SLIM_MODEL => HeadData {
+            bytes: ALEX_HEAD.clone(),
+            default: true,
+            suppressed: false,
+        },
+        _ => HeadData {
+            bytes: STEVE_HEAD.clone(),
+            default: true,
+            suppressed: false,
+        },
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::useless_vec)]
+mod test {
+    use super::*;
+    use crate::cache::clock::ManualClock;
+    use crate::cache::level::moka::MokaCache;
+    use crate::cache::level::no::NoCache;
+    use crate::mojang::testing::{MojangTestingApi, TestingProfile, HERBERT, HYDROFIN, SCRAYOS};
+    use crate::settings::{Tenant, UsageQuota};
+    use uuid::uuid;
+
+    /// A [CacheLevel] that stores no entries (like [NoCache]), but holds fetch locks like a shared
+    /// remote cache.
+    #[derive(Debug, Default)]
+    struct LockingCache {
+        locks: Mutex<HashSet<String>>,
+    }
+
+    impl CacheLevel for LockingCache {
+        async fn get_uuid(&self, key: &str) -> Option<Entry<UuidData>> {
+            NoCache.get_uuid(key).await
+        }
+
+        async fn set_uuid(&self, _: &str, _: Entry<UuidData>) {}
+
+        async fn get_profile(&self, key: &Uuid) -> Option<Entry<ProfileData>> {
+            NoCache.get_profile(key).await
+        }
+
+        async fn set_profile(&self, _: &Uuid, _: Entry<ProfileData>) {}
+
+        async fn get_uuids_batch(&self, keys: &[String]) -> Vec<Option<Entry<UuidData>>> {
+            NoCache.get_uuids_batch(keys).await
+        }
+
+        async fn set_uuids_batch(&self, _: Vec<(String, Entry<UuidData>)>) {}
+
+        async fn get_skin(&self, key: &Uuid) -> Option<Entry<SkinData>> {
+            NoCache.get_skin(key).await
+        }
+
+        async fn set_skin(&self, _: &Uuid, _: Entry<SkinData>) {}
+
+        async fn get_cape(&self, key: &Uuid) -> Option<Entry<CapeData>> {
+            NoCache.get_cape(key).await
+        }
+
+        async fn set_cape(&self, _: &Uuid, _: Entry<CapeData>) {}
+
+        async fn get_head(&self, key: &HeadKey) -> Option<Entry<HeadData>> {
+            NoCache.get_head(key).await
+        }
+
+        async fn set_head(&self, _: &HeadKey, _: Entry<HeadData>) {}
+
+        async fn get_texture(&self, key: &str) -> Option<Entry<TextureData>> {
+            NoCache.get_texture(key).await
+        }
+
+        async fn set_texture(&self, _: &str, _: Entry<TextureData>) {}
+
+        async fn get_blocked_servers(&self) -> Option<Entry<BlockedServersData>> {
+            NoCache.get_blocked_servers().await
+        }
+
+        async fn set_blocked_servers(&self, _: Entry<BlockedServersData>) {}
+
+        async fn try_lock(&self, key: &str) -> bool {
+            self.locks.lock().unwrap().insert(key.to_string())
+        }
+
+        async fn try_lock_batch(&self, keys: &[String]) -> Vec<bool> {
+            let mut locks = self.locks.lock().unwrap();
+            keys.iter().map(|key| locks.insert(key.clone())).collect()
+        }
+
+        async fn unlock(&self, keys: &[String]) {
+            let mut locks = self.locks.lock().unwrap();
+            for key in keys {
+                locks.remove(key);
+            }
+        }
+
+        async fn incr_usage(&self, window: &str, client: &str, ttl: Duration) -> Option<u64> {
+            NoCache.incr_usage(window, client, ttl).await
+        }
+
+        async fn get_usage(&self, window: &str) -> Option<HashMap<String, u64>> {
+            NoCache.get_usage(window).await
+        }
+
+        async fn set_pinned(
+            &self,
+            key: &Uuid,
+            pinned: bool,
+            max: usize,
+        ) -> Option<Result<(), PinnedError>> {
+            NoCache.set_pinned(key, pinned, max).await
+        }
+
+        async fn get_pinned(&self) -> Option<HashSet<Uuid>> {
+            NoCache.get_pinned().await
+        }
+
+        async fn purge(&self, pattern: &KeyPattern) -> u64 {
+            NoCache.purge(pattern).await
+        }
+
+        async fn ping(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn new_nocache() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+
+        // when
+        let _ = Service::new(Arc::new(settings), cache, mojang);
+    }
+
+    #[tokio::test]
+    async fn get_uuid_found() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let result = service.get_uuid("Hydrofin").await;
+
+        // then
+        let expected_hydrofin = UuidData {
+            username: "Hydrofin".to_string(),
+            uuid: uuid!("09879557e47945a9b434a56377674627"),
+        };
+        assert!(matches!(result, Ok(Dated{ data, .. }) if data == expected_hydrofin));
+    }
+
+    #[tokio::test]
+    async fn get_uuid_not_found() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let result = service.get_uuid("xXSlayer42Xx").await;
+
+        // then
+        assert!(matches!(result, Err(NotFound)));
+    }
+
+    #[tokio::test]
+    async fn get_uuid_invalid() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let result = service.get_uuid("56789äas#").await;
+
+        // then
+        assert!(matches!(result, Err(NotFound)));
+    }
+
+    #[tokio::test]
+    async fn get_uuid_empty_not_found() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::new();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let result = service.get_uuid("Hydrofin").await;
+
+        // then
+        assert!(matches!(result, Err(NotFound)));
+    }
+
+    #[tokio::test]
+    async fn get_uuids_found() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let result = service.get_uuids(&vec!["Hydrofin".to_string()]).await;
+
+        // then
+        match result {
+            Ok(resolved) => {
+                assert_eq!(1, resolved.len());
+
+                // User 'Hydrofin' is found
+                let Some(hydrofin) = resolved.get("hydrofin") else {
+                    panic!("failed to resolve user 'Hydrofin'")
+                };
+                assert_eq!(
+                    hydrofin.data,
+                    Some(UuidData {
+                        username: "Hydrofin".to_string(),
+                        uuid: uuid!("09879557e47945a9b434a56377674627")
+                    }),
+                );
+            }
+            Err(err) => panic!("failed to resolve uuid: {}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn get_uuids_not_found() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let result = service.get_uuids(&vec!["xXSlayer42Xx".to_string()]).await;
+
+        // then
+        match result {
+            Ok(resolved) => {
+                assert_eq!(1, resolved.len());
+
+                // User 'xXSlayer42Xx' not found
+                let other = resolved.get("xxslayer42xx");
+                assert!(matches!(other, Some(Dated { data: None, .. })));
+            }
+            Err(err) => panic!("failed to resolve uuid: {}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn get_uuids_invalid() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let result = service.get_uuids(&vec!["#+".to_string()]).await;
+
+        // then
+        match result {
+            Ok(resolved) => {
+                assert_eq!(1, resolved.len());
+
+                // User '#+' not found
+                let other = resolved.get("#+");
+                assert!(matches!(other, Some(Dated { data: None, .. })));
+            }
+            Err(err) => panic!("failed to resolve uuid: {}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn get_uuids_relaxed_pattern() {
+        // given
+        let mut settings = Settings::default();
+        settings.usernames.pattern = Regex::new("^[^\\s]{1,25}$").unwrap();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let result = service.get_uuids(&["legacy-name".to_string()]).await;
+
+        // then
+        assert!(result.is_ok());
+        assert_eq!(1, service.mojang.requests());
+    }
+
+    #[test]
+    fn ensure_enabled_capabilities() {
+        // given
+        let mut settings = Settings::default();
+        settings.capabilities.capes = false;
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let heads = service.ensure_enabled(&[Capability::Skins, Capability::Heads]);
+        let bundle = service.ensure_enabled(&[Capability::Skins, Capability::Capes]);
+
+        // then
+        assert!(heads.is_ok());
+        assert!(matches!(
+            bundle,
+            Err(ServiceError::Disabled(Capability::Capes))
+        ));
+    }
+
+    #[tokio::test]
+    async fn purge_cache_heads() {
+        // given
+        let settings = Settings::default();
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        let uuid = HYDROFIN.profile.id;
+        service.get_head(&HeadKey::new(uuid, true)).await.unwrap();
+        service.get_head(&HeadKey::new(uuid, false)).await.unwrap();
+
+        // when
+        let invalid = service.purge_cache("head.[a-z]").await;
+        let purged = service
+            .purge_cache(&format!("head.{}.*", uuid.simple()))
+            .await;
+
+        // then
+        assert!(matches!(invalid, Err(InvalidArgument(_))));
+        assert!(matches!(purged, Ok(2)));
+    }
+
+    #[tokio::test]
+    async fn set_pinned_max() {
+        // given
+        let mut settings = Settings::default();
+        settings.pinned.max = 1;
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let pinned = service.set_pinned(&HYDROFIN.profile.id, true).await;
+        let exceeded = service.set_pinned(&HERBERT.profile.id, true).await;
+        service
+            .set_pinned(&HYDROFIN.profile.id, false)
+            .await
+            .unwrap();
+        let replaced = service.set_pinned(&HERBERT.profile.id, true).await;
+
+        // then
+        assert!(pinned.is_ok());
+        assert!(matches!(exceeded, Err(InvalidArgument(_))));
+        assert!(replaced.is_ok());
+        assert_eq!(HashSet::from([HERBERT.profile.id]), service.pinned().await);
+    }
+
+    #[tokio::test]
+    async fn set_pinned_concurrent() {
+        // given
+        let mut settings = Settings::default();
+        settings.pinned.max = 1;
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let (hydrofin, herbert) = tokio::join!(
+            service.set_pinned(&HYDROFIN.profile.id, true),
+            service.set_pinned(&HERBERT.profile.id, true),
+        );
+
+        // then
+        assert!(hydrofin.is_ok() ^ herbert.is_ok());
+        assert_eq!(1, service.pinned().await.len());
+    }
+
+    #[tokio::test]
+    async fn get_uuids_partial_found() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let result = service
+            .get_uuids(&vec!["Hydrofin".to_string(), "xXSlayer42Xx".to_string()])
+            .await;
+
+        // then
+        match result {
+            Ok(resolved) => {
+                assert_eq!(2, resolved.len());
+
+                // User 'xXSlayer42Xx' not found
+                let other = resolved.get("xxslayer42xx");
+                assert!(matches!(other, Some(Dated { data: None, .. })));
+
+                // User 'Hydrofin' is found
+                let Some(hydrofin) = resolved.get("hydrofin") else {
+                    panic!("failed to resolve user 'Hydrofin'")
+                };
+                assert_eq!(
+                    hydrofin.data,
+                    Some(UuidData {
+                        username: "Hydrofin".to_string(),
+                        uuid: uuid!("09879557e47945a9b434a56377674627")
+                    }),
+                );
+            }
+            Err(err) => panic!("failed to resolve uuid: {}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn get_uuids_partial_unavailable() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        mojang.fail_next(10);
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        let usernames = ["Hydrofin".to_string(), "i<ia9".to_string()];
+
+        // when
+        let failed = service.get_uuids(&usernames).await;
+        let partial = service.get_uuids_partial(&usernames).await.unwrap();
+
+        // then
+        assert!(failed.is_err());
+        assert_eq!(2, partial.len());
+        assert_eq!(BulkStatus::Unavailable, partial["hydrofin"].0);
+        assert_eq!(BulkStatus::Invalid, partial["i<ia9"].0);
+    }
+
+    #[tokio::test]
+    async fn get_uuids_partial_invalid() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let result = service
+            .get_uuids(&vec!["Hydrofin".to_string(), "i<ia9".to_string()])
+            .await;
+
+        // then
+        match result {
+            Ok(resolved) => {
+                assert_eq!(2, resolved.len());
+
+                // User 'i<ia9' not found
+                let other = resolved.get("i<ia9");
+                assert!(matches!(other, Some(Dated { data: None, .. })));
+
+                // User 'Hydrofin' is found
+                let Some(hydrofin) = resolved.get("hydrofin") else {
+                    panic!("failed to resolve user 'Hydrofin'")
+                };
+                assert_eq!(
+                    hydrofin.data,
+                    Some(UuidData {
+                        username: "Hydrofin".to_string(),
+                        uuid: uuid!("09879557e47945a9b434a56377674627")
+                    }),
+                );
+            }
+            Err(err) => panic!("failed to resolve uuid: {}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn record_usage_quota_exceeded() {
+        // given
+        let mut settings = Settings::default();
+        settings.usage.enabled = true;
+        settings.usage.default = UsageQuota {
+            daily: Some(2),
+            monthly: None,
+        };
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let first = service.record_usage("client").await;
+        let second = service.record_usage("client").await;
+        let third = service.record_usage("client").await;
+        let other = service.record_usage("other").await;
+
+        // then
+        assert!(first.is_ok());
+        assert!(second.is_ok());
+        assert!(matches!(
+            third,
+            Err(ServiceError::QuotaExceeded {
+                period: UsagePeriod::Daily,
+                limit: 2,
+                ..
+            })
+        ));
+        assert!(other.is_ok());
+        let report = service.get_usage_report().await;
+        assert_eq!(2, report.len());
+        assert_eq!("client", report[0].client);
+        assert_eq!(3, report[0].daily);
+        assert_eq!(3, report[0].monthly);
+        assert_eq!(Some(2), report[0].daily_limit);
+    }
+
+    #[tokio::test]
+    async fn get_profile_shared_by_tenants() {
+        // given
+        let mut settings = Settings::default();
+        settings.tenancy.enabled = true;
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        let uuid = HYDROFIN.profile.id;
+
+        // when
+        let network = tenant::scope(Some(Arc::from("network")), service.get_profile(&uuid)).await;
+        let other = tenant::scope(Some(Arc::from("other")), service.get_profile(&uuid)).await;
+
+        // then
+        assert!(network.is_ok());
+        assert!(other.is_ok());
+        assert_eq!(1, service.mojang.requests());
+    }
+
+    #[tokio::test]
+    async fn record_usage_tenant_quota_exceeded() {
+        // given
+        let mut settings = Settings::default();
+        settings.usage.enabled = true;
+        settings.usage.default = UsageQuota::default();
+        settings.tenancy.enabled = true;
+        settings.tenancy.tenants.insert(
+            "network".to_string(),
+            Tenant {
+                keys: vec!["first".to_string(), "second".to_string()],
+                quota: UsageQuota {
+                    daily: Some(2),
+                    monthly: None,
+                },
+                cache_entries: Default::default(),
+            },
+        );
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let first = service.record_usage("first").await;
+        let second = service.record_usage("second").await;
+        let third = service.record_usage("first").await;
+        let other = service.record_usage("other").await;
+
+        // then
+        assert!(first.is_ok());
+        assert!(second.is_ok());
+        assert!(matches!(
+            third,
+            Err(ServiceError::QuotaExceeded {
+                period: UsagePeriod::Daily,
+                limit: 2,
+                ..
+            })
+        ));
+        assert!(other.is_ok());
+        let report = service.get_usage_report().await;
+        let clients: Vec<_> = report.iter().map(|report| report.client.as_str()).collect();
+        assert_eq!(vec!["first", "other", "second", "tenant:network"], clients);
+        assert_eq!(Some("network".to_string()), report[0].tenant);
+        assert_eq!(3, report[3].daily);
+        assert_eq!(Some(2), report[3].daily_limit);
+    }
+
+    #[tokio::test]
+    async fn get_texture_found() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let result = service
+            .get_texture("09879557E47945A9B434A56377674627")
+            .await;
+
+        // then
+        let expected = HYDROFIN.skin.clone().unwrap();
+        assert!(matches!(result, Ok(Dated { data, .. }) if data.bytes == expected));
+    }
+
+    #[tokio::test]
+    async fn get_texture_not_found() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let result = service.get_texture("deadbeef").await;
+
+        // then
+        assert!(matches!(result, Err(NotFound)));
+    }
+
+    #[tokio::test]
+    async fn get_texture_invalid() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let result = service.get_texture("../../profile").await;
+
+        // then
+        assert!(matches!(result, Err(NotFound)));
+    }
+
+    #[tokio::test]
+    async fn build_textures_from_uuid() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        let hydrofin = uuid!("09879557e47945a9b434a56377674627");
+        let npc = uuid!("1119fff4f68d4388875172bbff53d5a1");
+
+        // when
+        let result = service
+            .build_textures(
+                Some(&hydrofin),
+                None,
+                Some(SLIM_MODEL),
+                None,
+                Some(npc),
+                Some("Npc"),
+            )
+            .await;
+
+        // then
+        let profile = result.expect("expected textures to be built").data;
+        assert_eq!(npc, profile.id);
+        assert_eq!("Npc", profile.name);
+        let textures = profile.get_textures().expect("expected valid textures");
+        let skin = textures.textures.skin.expect("expected skin");
+        assert_eq!(texture_url("09879557e47945a9b434a56377674627"), skin.url);
+        assert_eq!(SLIM_MODEL, skin.metadata.expect("expected metadata").model);
+        assert_eq!(None, textures.textures.cape);
+    }
+
+    #[tokio::test]
+    async fn build_textures_invalid_model() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let result = service
+            .build_textures(None, Some("deadbeef"), Some("wide"), None, None, None)
+            .await;
+
+        // then
+        assert!(matches!(result, Err(InvalidArgument(_))));
+    }
+
+    #[tokio::test]
+    async fn build_textures_foreign_url() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let result = service
+            .build_textures(
+                None,
+                Some("https://example.com/skin.png"),
+                None,
+                None,
+                None,
+                None,
+            )
+            .await;
+
+        // then
+        assert!(matches!(result, Err(InvalidArgument(_))));
+    }
+
+    #[tokio::test]
+    async fn get_uuid_expired_unavailable() {
+        // given
+        let settings = Settings::default();
+        let clock = Arc::new(ManualClock::new(1000));
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache =
+            Cache::new(settings.cache.entries.clone(), moka, NoCache).with_clock(clock.clone());
+        let mojang = MojangTestingApi::with_profiles();
+        let exp = settings.cache.entries.uuid.exp;
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        service.get_uuid("Hydrofin").await.unwrap();
+
+        // when
+        clock.advance(exp);
+        service.mojang.fail_next(1);
+        let result = service.get_uuid("Hydrofin").await;
+
+        // then
+        assert!(matches!(
+            result,
+            Ok(Dated {
+                timestamp: 1000,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn get_uuid_miss_unavailable() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        mojang.fail_next(1);
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let result = service.get_uuid("Hydrofin").await;
+
+        // then
+        assert!(matches!(result, Err(Unavailable)));
+    }
+
+    #[tokio::test]
+    async fn get_uuid_miss_rate_limited() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        mojang.set_rate_limit(Some(0));
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let result = service.get_uuid("Hydrofin").await;
+
+        // then
+        assert!(matches!(result, Err(ServiceError::RateLimited { .. })));
+    }
+
+    #[tokio::test]
+    async fn get_uuid_respects_retry_after() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        mojang.set_rate_limit(Some(0));
+        mojang.set_retry_after(Some(Duration::from_secs(60)));
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        service.get_uuid("Hydrofin").await.unwrap_err();
+        service.mojang.set_rate_limit(None);
+
+        // when
+        let result = service.get_uuid("Hydrofin").await;
+        let status = service.get_status();
+
+        // then
+        let Err(ServiceError::RateLimited { retry_after }) = result else {
+            panic!("expected rate limited, got {result:?}");
+        };
+        assert!(retry_after.is_some_and(|retry_after| retry_after.as_secs() <= 60));
+        assert!(status.retry_at.is_some());
+        assert_eq!(BreakerState::Open, status.breaker);
+        assert_eq!(0, status.rate_remaining);
+    }
+
+    #[tokio::test]
+    async fn get_uuid_miss_fallback_provider() {
+        // given
+        let settings = Settings::default();
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        mojang.set_rate_limit(Some(0));
+        let service = Service::new(Arc::new(settings), cache, mojang).with_fallback_provider(
+            "mirror",
+            1,
+            MojangTestingApi::with_profiles(),
+        );
+
+        // when
+        let uuid = service.get_uuid("Hydrofin").await.unwrap();
+        let profile = service.get_profile(&HYDROFIN.profile.id).await.unwrap();
+        service.mojang.set_rate_limit(None);
+        let refreshed = service.get_uuid("Hydrofin").await.unwrap();
+
+        // then
+        assert_eq!(Some("mirror"), uuid.provider.as_deref());
+        assert_eq!(HYDROFIN.profile.id, uuid.data.uuid);
+        assert_eq!(Some("mirror"), profile.provider.as_deref());
+        assert_eq!(HYDROFIN.profile, profile.data);
+        assert_eq!(None, refreshed.provider);
+    }
+
+    #[tokio::test]
+    async fn get_uuid_fallback_provider_not_found() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        mojang.set_rate_limit(Some(0));
+        let service = Service::new(Arc::new(settings), cache, mojang).with_fallback_provider(
+            "mirror",
+            1,
+            MojangTestingApi::with_profiles(),
+        );
+
+        // when
+        let result = service.get_uuid("unknown").await;
+
+        // then
+        assert!(matches!(result, Err(NotFound)));
+    }
+
+    #[tokio::test]
+    async fn get_uuids_fallback_provider() {
+        // given
+        let settings = Settings::default();
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        mojang.set_rate_limit(Some(0));
+        let service = Service::new(Arc::new(settings), cache, mojang).with_fallback_provider(
+            "mirror",
+            1,
+            MojangTestingApi::with_profiles(),
+        );
+        let usernames = vec!["Hydrofin".to_string(), "unknown".to_string()];
+
+        // when
+        let uuids = service.get_uuids(&usernames).await.unwrap();
+
+        // then
+        let hydrofin = &uuids["hydrofin"];
+        assert_eq!(Some("mirror"), hydrofin.provider.as_deref());
+        assert_eq!(
+            Some(HYDROFIN.profile.id),
+            hydrofin.data.as_ref().map(|data| data.uuid)
+        );
+        assert!(uuids["unknown"].has_none());
+    }
+
+    #[tokio::test]
+    async fn get_profile_fallback_provider_limited() {
+        // given
+        let mut settings = Settings::default();
+        settings.upstream_concurrency.enabled = true;
+        settings.upstream_concurrency.profile = 1;
+        settings.upstream_concurrency.queue_size = 0;
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang).with_fallback_provider(
+            "mirror",
+            1,
+            MojangTestingApi::with_profiles(),
+        );
+        let _permit = service.limits.acquire("profile").await.unwrap();
+
+        // when
+        let result = service.get_profile(&HYDROFIN.profile.id).await;
+
+        // then
+        assert!(matches!(result, Err(Unavailable)));
+    }
+
+    #[tokio::test]
+    async fn get_skin_fallback_provider_not_cached() {
+        // given
+        let settings = Settings::default();
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang).with_fallback_provider(
+            "mirror",
+            1,
+            MojangTestingApi::with_profiles(),
+        );
+        service.mojang.fail_next(1);
+
+        // when
+        let skin = service.get_skin(&HYDROFIN.profile.id).await.unwrap();
+        service.mojang.fail_next(1);
+        let head = service
+            .get_head(&HeadKey::new(HYDROFIN.profile.id, false))
+            .await
+            .unwrap();
+
+        // then
+        assert_eq!(Some("mirror"), skin.provider.as_deref());
+        assert!(!skin.data.default);
+        assert_eq!(Some("mirror"), head.provider.as_deref());
+        let cached_skin = service.cache.get_skin(&HYDROFIN.profile.id).await;
+        let cached_head = service
+            .cache
+            .get_head(&HeadKey::new(HYDROFIN.profile.id, false))
+            .await;
+        assert!(matches!(cached_skin, Miss));
+        assert!(matches!(cached_head, Miss));
+    }
+
+    #[tokio::test]
+    async fn cache_only_uses_expired() {
+        // given
+        let settings = Settings::default();
+        let clock = Arc::new(ManualClock::new(1000));
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache =
+            Cache::new(settings.cache.entries.clone(), moka, NoCache).with_clock(clock.clone());
+        let mojang = MojangTestingApi::with_profiles();
+        let exp = settings.cache.entries.uuid.exp;
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        service.get_uuid("Hydrofin").await.unwrap();
+
+        // when
+        clock.advance(exp);
+        service.set_cache_only(true);
+        let expired = service.get_uuid("Hydrofin").await;
+        let missed = service.get_uuid("Scrayos").await;
+
+        // then
+        assert!(matches!(
+            expired,
+            Ok(Dated {
+                timestamp: 1000,
+                ..
+            })
+        ));
+        assert!(matches!(missed, Err(Unavailable)));
+        assert_eq!(1, service.mojang.requests());
+        assert_eq!(BreakerState::Closed, service.breaker_state());
+    }
+
+    #[tokio::test]
+    async fn is_server_blocked_wildcard() {
+        // given
+        let settings = Settings::default();
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles().add_blocked_server("*.example.com");
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let blocked = service.is_server_blocked("MC.example.com").await.unwrap();
+        let unblocked = service.is_server_blocked("example.org").await.unwrap();
+        let empty = service.is_server_blocked(" ").await;
+
+        // then
+        assert_eq!(Some("*.example.com".to_string()), blocked.data);
+        assert_eq!(None, unblocked.data);
+        assert!(matches!(empty, Err(InvalidArgument(_))));
+        assert_eq!(1, service.mojang.requests());
+    }
+
+    #[tokio::test]
+    async fn deadline_skips_mojang() {
+        // given
+        let settings = Settings::default();
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_millis(10);
+
+        // when
+        let skipped = deadline::scope(Some(deadline), service.get_uuid("Hydrofin")).await;
+        let resolved = service.get_uuid("Hydrofin").await;
+
+        // then
+        assert!(matches!(skipped, Err(Unavailable)));
+        assert!(resolved.is_ok());
+        assert_eq!(1, service.mojang.requests());
+    }
+
+    #[tokio::test]
+    async fn hedge_recorded_with_result() {
+        // given
+        let mut settings = Settings::default();
+        settings.hedging.enabled = true;
+        settings.hedging.threshold = Duration::ZERO;
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        mojang.set_latency(Duration::from_millis(20), Duration::from_millis(20));
+        mojang.fail_next(2);
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let result = service.get_profile(&HYDROFIN.profile.id).await;
+
+        // then
+        let stats = service.upstream.endpoints(service.cache.now_seconds());
+        assert!(matches!(result, Err(Unavailable)));
+        assert_eq!(2, service.mojang.requests());
+        assert_eq!(2, stats["profile"].requests);
+        assert_eq!(2, stats["profile"].failures);
+    }
+
+    #[tokio::test]
+    async fn deadline_skips_permit() {
+        // given
+        let mut settings = Settings::default();
+        settings.upstream_concurrency.enabled = true;
+        settings.upstream_concurrency.uuid = 1;
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        let _permit = service.limits.acquire("uuid").await.unwrap();
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_millis(10);
+
+        // when
+        let skipped = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            deadline::scope(Some(deadline), service.get_uuid("Hydrofin")),
+        )
+        .await;
+
+        // then
+        assert!(matches!(skipped, Ok(Err(Unavailable))));
+        assert_eq!(0, service.mojang.requests());
+    }
+
+    #[tokio::test]
+    async fn get_uuids_shed_bulk() {
+        // given
+        let mut settings = Settings::default();
+        settings.shedding.enabled = true;
+        settings.shedding.budget_threshold = settings.upstream_stats.rate_limit + 1;
+        settings.shedding.bulk_limit = 1;
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        let usernames = ["hydrofin".to_string(), "scrayos".to_string()];
+
+        // when
+        let bulk = service.get_uuids(&usernames).await;
+        let single = service.get_uuid("Hydrofin").await;
+        let status = service.get_status();
+
+        // then
+        assert!(matches!(bulk, Err(Unavailable)));
+        assert!(single.is_ok());
+        assert!(status.shedding);
+    }
+
+    #[tokio::test]
+    async fn get_uuids_partial_shed_beyond_limit() {
+        // given
+        let mut settings = Settings::default();
+        settings.shedding.enabled = true;
+        settings.shedding.budget_threshold = settings.upstream_stats.rate_limit + 1;
+        settings.shedding.bulk_limit = 1;
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        let usernames = ["hydrofin".to_string(), "scrayos".to_string()];
+
+        // when
+        let bulk = service.get_uuids_partial(&usernames).await.unwrap();
+
+        // then
+        let mut statuses: Vec<_> = bulk.values().map(|(status, _)| *status).collect();
+        statuses.sort_by_key(|status| status.as_str());
+        assert_eq!(
+            vec![BulkStatus::Resolved, BulkStatus::Unavailable],
+            statuses
+        );
+        assert_eq!(1, service.mojang.requests());
+    }
+
+    #[tokio::test]
+    async fn get_uuids_shed_expired_beyond_limit() {
+        // given
+        let mut settings = Settings::default();
+        settings.shedding.enabled = true;
+        settings.shedding.budget_threshold = settings.upstream_stats.rate_limit + 1;
+        settings.shedding.bulk_limit = 1;
+        let clock = Arc::new(ManualClock::new(1000));
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache =
+            Cache::new(settings.cache.entries.clone(), moka, NoCache).with_clock(clock.clone());
+        let mojang = MojangTestingApi::with_profiles();
+        let exp = settings.cache.entries.uuid.exp;
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        let usernames = ["hydrofin".to_string(), "scrayos".to_string()];
+        let data = [&*HYDROFIN, &*SCRAYOS].map(|profile| {
+            let data = UuidData {
+                username: profile.profile.name.clone(),
+                uuid: profile.profile.id,
+            };
+            (profile.profile.name.to_lowercase(), Some(data))
+        });
+        service.cache.set_uuids_batch(data.into()).await;
+
+        // when
+        clock.advance(exp);
+        let bulk = service.get_uuids(&usernames).await.unwrap();
+
+        // then
+        let mut timestamps: Vec<_> = bulk.values().map(|entry| entry.timestamp).collect();
+        timestamps.sort();
+        assert_eq!(vec![1000, 1000 + exp.as_secs()], timestamps);
+        assert_eq!(1, service.mojang.requests());
+    }
+
+    #[tokio::test]
+    async fn get_status_counts_failures() {
+        // given
+        let settings = Settings::default();
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let rate_limit = settings.upstream_stats.rate_limit;
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        service.mojang.fail_next(1);
+
+        // when
+        let failed = service.get_uuid("Hydrofin").await;
+        let resolved = service.get_uuid("Scrayos").await;
+        let status = service.get_status();
+
+        // then
+        assert!(matches!(failed, Err(Unavailable)));
+        assert!(resolved.is_ok());
+        assert_eq!(0.5, status.endpoints["uuid"].error_rate());
+        assert_eq!(rate_limit - 2, status.rate_remaining);
+        assert!(!status.cache_only);
+    }
+
+    #[tokio::test]
+    async fn get_status_reported_remaining() {
+        // given
+        let settings = Settings::default();
+        let clock = Arc::new(ManualClock::new(1000));
+        let cache =
+            Cache::new(settings.cache.entries.clone(), NoCache, NoCache).with_clock(clock.clone());
+        let mojang = MojangTestingApi::with_profiles();
+        let rate_limit = settings.upstream_stats.rate_limit;
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        service.mojang.set_reported_remaining(Some(3));
+
+        // when
+        service.get_uuid("Hydrofin").await.unwrap();
+        let uuid = service.upstream.endpoint_budget("uuid", 1000);
+        let profile = service.upstream.endpoint_budget("profile", 1000);
+
+        // then
+        assert_eq!(3, uuid);
+        assert_eq!(rate_limit - 1, profile);
+    }
+
+    #[tokio::test]
+    async fn get_profile_prefetches_head() {
+        // given
+        let mut settings = Settings::default();
+        settings.prefetch.enabled = true;
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        let mut queue = service.prefetch_queue.lock().unwrap().take().unwrap();
+
+        // when
+        service.get_profile(&HYDROFIN.profile.id).await.unwrap();
+        let (_, uuid) = queue.try_recv().unwrap();
+        service.prefetch_heads(uuid).await;
+        service.mojang.reset_requests();
+        let result = service
+            .get_head(&HeadKey::new(HYDROFIN.profile.id, true))
+            .await;
+
+        // then
+        assert!(result.is_ok());
+        assert_eq!(0, service.mojang.requests());
+    }
+
+    #[tokio::test]
+    async fn get_profile_hit_prefetches_head() {
+        // given
+        let mut settings = Settings::default();
+        settings.prefetch.enabled = true;
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        cache
+            .set_profile(&HYDROFIN.profile.id, Some(HYDROFIN.profile.clone()))
+            .await;
+        cache.set_profile(&HERBERT.profile.id, None).await;
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        let mut queue = service.prefetch_queue.lock().unwrap().take().unwrap();
+
+        // when
+        service.get_profile(&HYDROFIN.profile.id).await.unwrap();
+        service.get_profile(&HERBERT.profile.id).await.unwrap_err();
+
+        // then
+        assert_eq!(HYDROFIN.profile.id, queue.try_recv().unwrap().1);
+        assert!(queue.try_recv().is_err());
+        assert_eq!(0, service.mojang.requests());
+    }
+
+    #[test]
+    fn image_source_placeholder_first() {
+        // given
+        let cases = [(false, false), (true, false), (true, true), (false, true)];
+
+        // when
+        let sources = cases.map(|(default, placeholder)| image_source(default, placeholder));
+
+        // then
+        assert_eq!(
+            ["texture", "default", "placeholder", "placeholder"],
+            sources
+        );
+    }
+
+    /// Takes the next queued [PreRenderRequest] of a [Service] without running the pre-rendering.
+    fn next_pre_render<L, R, M>(service: &Service<L, R, M>) -> Option<PreRenderRequest>
+    where
+        L: CacheLevel,
+        R: CacheLevel,
+        M: Mojang,
+    {
+        let mut queue = service.pre_render_queue.lock().unwrap();
+        queue.as_mut()?.try_recv().ok()
+    }
+
+    #[tokio::test]
+    async fn get_skin_pre_render_queue_bounded() {
+        // given
+        let mut settings = Settings::default();
+        settings.cache.entries.head.pre_render = true;
+        settings.render.queue = 1;
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        service.get_skin(&HYDROFIN.profile.id).await.unwrap();
+        service.get_skin(&SCRAYOS.profile.id).await.unwrap();
+        let first = next_pre_render(&service);
+        let second = next_pre_render(&service);
+
+        // then
+        assert!(matches!(first, Some((_, uuid, _)) if uuid == HYDROFIN.profile.id));
+        assert!(second.is_none());
+    }
+
+    #[tokio::test]
+    async fn get_skin_pre_renders_heads() {
+        // given
+        let mut settings = Settings::default();
+        settings.cache.entries.head.pre_render = true;
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        service.get_skin(&HYDROFIN.profile.id).await.unwrap();
+        let request = next_pre_render(&service).unwrap();
+        service.pre_render_heads(request).await;
+        let with_overlay = service
+            .cache
+            .get_head(&HeadKey::new(HYDROFIN.profile.id, true))
+            .await;
+        let without_overlay = service
+            .cache
+            .get_head(&HeadKey::new(HYDROFIN.profile.id, false))
+            .await;
+
+        // then
+        assert!(matches!(with_overlay, Hit(_)));
+        assert!(matches!(without_overlay, Hit(_)));
+    }
+
+    #[tokio::test]
+    async fn get_head_banned_skin_suppressed() {
+        // given
+        let mut settings = Settings::default();
+        settings.sanctions.suppress_banned_skins = true;
+        let mut banned = TestingProfile::new(
+            uuid!("1119fff4f68d4388875172bbff53d5a1"),
+            "Banned",
+            HYDROFIN.skin.clone(),
+            None,
+        );
+        banned
+            .profile
+            .profile_actions
+            .push(mojang::USING_BANNED_SKIN.to_string());
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::new().add_profile(&banned);
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let skin = service.get_skin(&banned.profile.id).await.unwrap();
+        let head = service
+            .get_head(&HeadKey::new(banned.profile.id, true))
+            .await
+            .unwrap();
+
+        // then
+        assert!(skin.data.default && skin.data.suppressed);
+        assert_eq!(get_default_skin(&banned.profile.id).bytes, skin.data.bytes);
+        assert!(head.data.default && head.data.suppressed);
+    }
+
+    #[tokio::test]
+    async fn get_sanctioned_fail_open() {
+        // given
+        let mut settings = Settings::default();
+        settings.sanctions.concurrency = 1;
+        let mut banned = TestingProfile::new(
+            uuid!("1119fff4f68d4388875172bbff53d5a1"),
+            "Banned",
+            HYDROFIN.skin.clone(),
+            None,
+        );
+        banned
+            .profile
+            .profile_actions
+            .push(mojang::USING_BANNED_SKIN.to_string());
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles().add_profile(&banned);
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        let uuids = [banned.profile.id, HYDROFIN.profile.id];
+
+        // when
+        service.mojang.fail_next(1);
+        let unavailable = service.get_sanctioned(&uuids).await;
+        let sanctioned = service.get_sanctioned(&uuids).await;
+
+        // then
+        assert!(unavailable.is_empty());
+        assert_eq!(HashSet::from([banned.profile.id]), sanctioned);
+    }
+
+    #[tokio::test]
+    async fn get_profile_bundle_without_cape() {
+        // given
+        let settings = Settings::default();
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let bundle = service
+            .get_profile_bundle(&HYDROFIN.profile.id, false)
+            .await
+            .unwrap();
+        let cached = service
+            .get_head(&HeadKey::new(HYDROFIN.profile.id, false))
+            .await
+            .unwrap();
+
+        // then
+        assert_eq!(HYDROFIN.profile, bundle.profile.data);
+        assert_eq!(HYDROFIN.skin.as_ref().unwrap(), &bundle.skin.data.bytes);
+        assert!(!bundle.has_cape);
+        assert_eq!(cached, bundle.head);
+    }
+
+    #[tokio::test]
+    async fn get_profile_bundle_with_cape() {
+        // given
+        let settings = Settings::default();
+        let caped = TestingProfile::new(
+            uuid!("1119fff4f68d4388875172bbff53d5a2"),
+            "Caped",
+            HYDROFIN.skin.clone(),
+            Some(Bytes::from_static(b"cape")),
+        );
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::new().add_profile(&caped);
+        let service = Service::new(Arc::new(settings), cache, mojang);
+
+        // when
+        let bundle = service
+            .get_profile_bundle(&caped.profile.id, false)
+            .await
+            .unwrap();
+        let cape = service.cache.get_cape(&caped.profile.id).await;
 
-        // try to fetch from mojang and update cache
-        match self.mojang.fetch_bytes(textures.url).await {
-            Ok(cape_bytes) => {
-                let cape = CapeData {
-                    bytes: cape_bytes.to_vec(),
-                };
-                let dated = self.cache.set_cape(uuid, Some(cape)).await.unwrap();
-                Ok(dated)
-            }
-            // handle NotFound as Unavailable as the profile (and therefore the cape) should exist
-            Err(ApiError::NotFound) | Err(ApiError::Unavailable) => fallback
-                .ok_or(Unavailable)
-                .and_then(|entry| entry.some_or(NotFound)),
-        }
+        // then
+        assert!(bundle.has_cape);
+        assert!(matches!(cape, Miss));
     }
 
-    /// Gets the profile head for an uuid from cache or mojang. The head may include the head overlay.
-    #[tracing::instrument(skip(self))]
-    #[metrics::metrics(metric = "service", labels(request_type = "head"), handler = metrics_age_handler)]
-    pub async fn get_head(
-        &self,
-        uuid: &Uuid,
-        overlay: bool,
-    ) -> Result<Dated<HeadData>, ServiceError> {
-        // try to get from cache
-        let cached = self.cache.get_head(&(*uuid, overlay)).await;
-        let fallback = match cached {
-            Hit(entry) => return entry.some_or(NotFound),
-            Expired(entry) => Some(entry),
-            Miss => None,
-        };
+    #[tokio::test]
+    async fn get_head_rendered_cached() {
+        // given
+        let settings = Settings::default();
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        let key = HeadKey::rendered(HYDROFIN.profile.id, true, 2, HeadStyle::Rgba);
 
-        // try to get skin
-        let skin = match self.get_skin(uuid).await {
-            Ok(skin) => skin.data,
-            Err(Unavailable) => {
-                return fallback
-                    .ok_or(Unavailable)
-                    .and_then(|entry| entry.some_or(NotFound))
+        // when
+        let head = service.get_head(&key).await.unwrap();
+        let cached = service.cache.get_head(&key).await;
+        let native = service.cache.get_head(&HeadKey::new(key.uuid, true)).await;
+
+        // then
+        assert_eq!(16 * 16 * 4, head.data.bytes.len());
+        assert!(matches!(cached, Hit(entry) if entry.data == Some(head.data)));
+        assert!(matches!(native, Hit(entry) if entry.data.is_some()));
+    }
+
+    /// Gets the skin of Hydrofin while its texture cannot be fetched with the fallback tiers. The skin
+    /// is cached (and expired) beforehand if stale is set.
+    async fn get_skin_failing(
+        tiers: Vec<SkinFallbackTier>,
+        stale: bool,
+    ) -> Result<Dated<SkinData>, ServiceError> {
+        let mut settings = Settings::default();
+        settings.skin_fallback.tiers = tiers;
+        let clock = Arc::new(ManualClock::new(1000));
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache =
+            Cache::new(settings.cache.entries.clone(), moka, NoCache).with_clock(clock.clone());
+        let mojang = MojangTestingApi::with_profiles();
+        let exp = settings.cache.entries.skin.exp;
+        let service = Service::new(Arc::new(settings), cache, mojang);
+        match stale {
+            true => {
+                service.get_skin(&HYDROFIN.profile.id).await.unwrap();
+                clock.advance(exp);
             }
-            Err(NotFound) => {
-                self.cache.set_head(&(*uuid, false), None).await;
-                self.cache.set_head(&(*uuid, true), None).await;
-                return Err(NotFound);
+            false => {
+                service.get_profile(&HYDROFIN.profile.id).await.unwrap();
             }
-            Err(err) => return Err(err),
-        };
-
-        // handle default skins
-        if skin.default {
-            return Ok(Dated::from(get_default_head(uuid)));
         }
+        service.mojang.fail_next(10);
+        service.get_skin(&HYDROFIN.profile.id).await
+    }
 
-        // build head
-        let head_bytes = build_skin_head(&skin.bytes, overlay)?;
-        let head = HeadData {
-            bytes: head_bytes,
-            default: skin.default,
-        };
-        let dated = self
-            .cache
-            .set_head(&(*uuid, overlay), Some(head))
-            .await
-            .unwrap();
-        Ok(dated)
+    #[tokio::test]
+    async fn get_skin_fallback_stale() {
+        // given
+        let tiers = vec![SkinFallbackTier::Stale, SkinFallbackTier::Default];
+
+        // when
+        let stale = get_skin_failing(tiers.clone(), true).await.unwrap();
+        let missing = get_skin_failing(tiers, false).await.unwrap();
+
+        // then
+        assert_eq!(1000, stale.timestamp);
+        assert!(!stale.data.default);
+        assert_eq!(HYDROFIN.skin.as_ref().unwrap(), &stale.data.bytes);
+        assert!(missing.data.default);
+        assert_eq!(get_default_skin(&HYDROFIN.profile.id), missing.data);
     }
-}
 
-/// Gets the default [SkinData] for a [Uuid].
-fn get_default_skin(uuid: &Uuid) -> SkinData {
-    match mojang::is_steve(uuid) {
-        true => SkinData {
-            bytes: STEVE_SKIN.to_vec(),
-            model: CLASSIC_MODEL.to_string(),
-            default: true,
-        },
-        false => SkinData {
-            bytes: ALEX_SKIN.to_vec(),
-            model: SLIM_MODEL.to_string(),
-            default: true,
-        },
+    #[tokio::test]
+    async fn get_skin_fallback_default() {
+        // given
+        let tiers = vec![SkinFallbackTier::Default, SkinFallbackTier::Stale];
+
+        // when
+        let stale = get_skin_failing(tiers.clone(), true).await.unwrap();
+        let missing = get_skin_failing(tiers, false).await.unwrap();
+
+        // then
+        assert!(stale.data.default);
+        assert!(missing.data.default);
     }
-}
 
-/// Gets the default [HeadData] for a [Uuid].
-fn get_default_head(uuid: &Uuid) -> HeadData {
-    match mojang::is_steve(uuid) {
-        true => HeadData {
-            bytes: STEVE_HEAD.to_vec(),
-            default: true,
-        },
-        false => HeadData {
-            bytes: ALEX_HEAD.to_vec(),
-            default: true,
-        },
+    #[tokio::test]
+    async fn get_skin_fallback_stale_only() {
+        // given
+        let tiers = vec![SkinFallbackTier::Stale];
+
+        // when
+        let stale = get_skin_failing(tiers.clone(), true).await;
+        let missing = get_skin_failing(tiers, false).await;
+
+        // then
+        assert!(stale.is_ok_and(|stale| !stale.data.default));
+        assert!(matches!(missing, Err(Unavailable)));
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use crate::cache::level::no::NoCache;
-    use crate::mojang::testing::MojangTestingApi;
-    use uuid::uuid;
+    #[tokio::test]
+    async fn get_skin_fallback_error() {
+        // given
+        let tiers = vec![];
+
+        // when
+        let stale = get_skin_failing(tiers.clone(), true).await;
+        let missing = get_skin_failing(tiers, false).await;
+
+        // then
+        assert!(matches!(stale, Err(Unavailable)));
+        assert!(matches!(missing, Err(Unavailable)));
+    }
 
     #[tokio::test]
-    async fn new_nocache() {
+    async fn get_colors_skin_and_head() {
         // given
         let settings = Settings::default();
-        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
         let mojang = MojangTestingApi::with_profiles();
+        let service = Service::new(Arc::new(settings), cache, mojang);
 
         // when
-        let _ = Service::new(Arc::new(settings), cache, mojang);
+        let skin = service
+            .get_colors(&HYDROFIN.profile.id, false, 0)
+            .await
+            .unwrap();
+        let head = service
+            .get_colors(&HYDROFIN.profile.id, true, 1)
+            .await
+            .unwrap();
+        let exceeded = service
+            .get_colors(&HYDROFIN.profile.id, false, colors::MAX_COUNT + 1)
+            .await;
+
+        // then
+        assert_eq!(colors::DEFAULT_COUNT, skin.colors.len());
+        assert!(!skin.default);
+        assert_eq!(1, head.colors.len());
+        assert!(matches!(exceeded, Err(InvalidArgument(_))));
     }
 
     #[tokio::test]
-    async fn get_uuid_found() {
+    async fn get_skin_url_without_bytes() {
         // given
         let settings = Settings::default();
-        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
         let mojang = MojangTestingApi::with_profiles();
         let service = Service::new(Arc::new(settings), cache, mojang);
 
         // when
-        let result = service.get_uuid("Hydrofin").await;
+        let custom = service.get_skin_url(&HYDROFIN.profile.id).await.unwrap();
+        let default = service.get_skin_url(&HERBERT.profile.id).await.unwrap();
+        let cape = service.get_cape_url(&HYDROFIN.profile.id).await;
 
         // then
-        let expected_hydrofin = UuidData {
-            username: "Hydrofin".to_string(),
-            uuid: uuid!("09879557e47945a9b434a56377674627"),
-        };
-        assert!(matches!(result, Ok(Dated{ data, .. }) if data == expected_hydrofin));
+        let texture = HYDROFIN
+            .profile
+            .get_textures()
+            .unwrap()
+            .textures
+            .skin
+            .unwrap();
+        assert_eq!(Some(texture.url.clone()), custom.data.url);
+        assert_eq!(
+            Some(texture.texture_id().to_string()),
+            custom.data.texture_id
+        );
+        assert!(!custom.data.default);
+        assert_eq!(None, default.data.url);
+        assert!(default.data.default);
+        assert!(matches!(cape, Err(NotFound)));
     }
 
     #[tokio::test]
-    async fn get_uuid_not_found() {
+    async fn get_styled_overrides() {
         // given
         let settings = Settings::default();
-        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
         let mojang = MojangTestingApi::with_profiles();
         let service = Service::new(Arc::new(settings), cache, mojang);
 
         // when
-        let result = service.get_uuid("xXSlayer42Xx").await;
+        let forced = service
+            .get_skin_styled(&HYDROFIN.profile.id, true, Some(SLIM_MODEL))
+            .await
+            .unwrap();
+        let model = service
+            .get_skin_styled(&HYDROFIN.profile.id, false, Some(SLIM_MODEL))
+            .await
+            .unwrap();
+        let head = service
+            .get_head_styled(
+                &HeadKey::new(HYDROFIN.profile.id, true),
+                true,
+                Some(CLASSIC_MODEL),
+            )
+            .await
+            .unwrap();
+        let invalid = service
+            .get_skin_styled(&HYDROFIN.profile.id, false, Some("wide"))
+            .await;
 
         // then
-        assert!(matches!(result, Err(NotFound)));
+        assert_eq!(ALEX_SKIN, forced.data.bytes);
+        assert!(forced.data.default);
+        assert_eq!(HYDROFIN.skin.as_ref().unwrap(), &model.data.bytes);
+        assert_eq!(SLIM_MODEL, model.data.model);
+        assert_eq!(*STEVE_HEAD, head.data.bytes);
+        assert!(matches!(invalid, Err(InvalidArgument(_))));
     }
 
     #[tokio::test]
-    async fn get_uuid_invalid() {
+    async fn serves_placeholder_if_unavailable() {
         // given
-        let settings = Settings::default();
+        let mut settings = Settings::default();
+        settings.placeholder.enabled = true;
         let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
         let mojang = MojangTestingApi::with_profiles();
+        mojang.fail_next(1);
         let service = Service::new(Arc::new(settings), cache, mojang);
 
         // when
-        let result = service.get_uuid("56789äas#").await;
+        let err = service
+            .get_head(&HeadKey::new(HYDROFIN.profile.id, true))
+            .await
+            .unwrap_err();
+        let head = service.get_placeholder_head(&HYDROFIN.profile.id);
 
         // then
-        assert!(matches!(result, Err(NotFound)));
+        assert!(service.serves_placeholder(&err));
+        assert!(!service.serves_placeholder(&NotFound));
+        assert!(head.data.default);
     }
 
     #[tokio::test]
-    async fn get_uuid_empty_not_found() {
+    async fn get_checksum_texture_id() {
         // given
         let settings = Settings::default();
-        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
-        let mojang = MojangTestingApi::new();
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(settings.cache.entries.clone(), moka, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
         let service = Service::new(Arc::new(settings), cache, mojang);
 
         // when
-        let result = service.get_uuid("Hydrofin").await;
+        let custom = service
+            .get_checksum(&HYDROFIN.profile.id, true)
+            .await
+            .unwrap();
+        let default = service
+            .get_checksum(&HERBERT.profile.id, true)
+            .await
+            .unwrap();
 
         // then
-        assert!(matches!(result, Err(NotFound)));
+        let expected_id = HYDROFIN.profile.id.simple().to_string();
+        assert_eq!(Some(expected_id), custom.data.texture_id);
+        assert_eq!(sha256(HYDROFIN.skin.as_ref().unwrap()), custom.data.skin);
+        assert_eq!(64, custom.data.head.len());
+        assert_eq!(None, default.data.texture_id);
+        assert_eq!(
+            sha256(&get_default_skin(&HERBERT.profile.id).bytes),
+            default.data.skin
+        );
     }
 
     #[tokio::test]
-    async fn get_uuids_found() {
+    async fn refresh_expiring_profile() {
         // given
-        let settings = Settings::default();
-        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mut settings = Settings::default();
+        settings.refresh.enabled = true;
+        let clock = Arc::new(ManualClock::new(1000));
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache =
+            Cache::new(settings.cache.entries.clone(), moka, NoCache).with_clock(clock.clone());
         let mojang = MojangTestingApi::with_profiles();
+        let lead = settings.refresh.lead;
+        let advance = settings.cache.entries.profile.exp - lead / 2;
         let service = Service::new(Arc::new(settings), cache, mojang);
+        let uuid = HYDROFIN.profile.id;
+        service.get_profile(&uuid).await.unwrap();
 
         // when
-        let result = service.get_uuids(&vec!["Hydrofin".to_string()]).await;
+        clock.advance(advance);
+        let key = service.access.hottest(1)[0];
+        service.refresh_if_expiring(key, lead).await;
+        let result = service.get_profile(&uuid).await;
 
         // then
-        match result {
-            Ok(resolved) => {
-                assert_eq!(1, resolved.len());
-
-                // User 'Hydrofin' is found
-                let Some(hydrofin) = resolved.get("hydrofin") else {
-                    panic!("failed to resolve user 'Hydrofin'")
-                };
-                assert_eq!(
-                    hydrofin.data,
-                    Some(UuidData {
-                        username: "Hydrofin".to_string(),
-                        uuid: uuid!("09879557e47945a9b434a56377674627")
-                    }),
-                );
-            }
-            Err(err) => panic!("failed to resolve uuid: {}", err),
-        }
+        assert_eq!(HotKey::Profile(uuid), key);
+        assert!(
+            matches!(result, Ok(Dated { timestamp, .. }) if timestamp == 1000 + advance.as_secs())
+        );
+        assert_eq!(2, service.mojang.requests());
     }
 
     #[tokio::test]
-    async fn get_uuids_not_found() {
+    async fn refresh_expiring_skin_untracked() {
         // given
-        let settings = Settings::default();
-        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mut settings = Settings::default();
+        settings.refresh.enabled = true;
+        let clock = Arc::new(ManualClock::new(1000));
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache =
+            Cache::new(settings.cache.entries.clone(), moka, NoCache).with_clock(clock.clone());
         let mojang = MojangTestingApi::with_profiles();
+        let lead = settings.refresh.lead;
+        let advance = settings.cache.entries.skin.exp - lead / 2;
         let service = Service::new(Arc::new(settings), cache, mojang);
+        let uuid = HYDROFIN.profile.id;
+        service.get_skin(&uuid).await.unwrap();
+        let accessed = service.access.hottest(10);
+        let requests = service.mojang.requests();
 
         // when
-        let result = service.get_uuids(&vec!["xXSlayer42Xx".to_string()]).await;
+        clock.advance(advance);
+        refresh::untracked(service.refresh_if_expiring(HotKey::Skin(uuid), lead)).await;
 
         // then
-        match result {
-            Ok(resolved) => {
-                assert_eq!(1, resolved.len());
-
-                // User 'xXSlayer42Xx' not found
-                let other = resolved.get("xxslayer42xx");
-                assert!(matches!(other, Some(Dated { data: None, .. })));
-            }
-            Err(err) => panic!("failed to resolve uuid: {}", err),
-        }
+        assert!(accessed.contains(&HotKey::Skin(uuid)));
+        assert!(service.access.hottest(10).is_empty());
+        assert!(service.mojang.requests() > requests);
     }
 
     #[tokio::test]
-    async fn get_uuids_invalid() {
+    async fn lock_released_after_fetch() {
         // given
         let settings = Settings::default();
-        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let clock = Arc::new(ManualClock::new(1000));
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(
+            settings.cache.entries.clone(),
+            moka,
+            LockingCache::default(),
+        )
+        .with_clock(clock.clone());
         let mojang = MojangTestingApi::with_profiles();
+        let exp = settings.cache.entries.profile.exp;
         let service = Service::new(Arc::new(settings), cache, mojang);
+        let uuid = HYDROFIN.profile.id;
+        service.get_profile(&uuid).await.unwrap();
 
         // when
-        let result = service.get_uuids(&vec!["#+".to_string()]).await;
+        clock.advance(exp);
+        service.get_profile(&uuid).await.unwrap();
+        clock.advance(exp);
+        let result = service.get_profile(&uuid).await;
 
         // then
-        match result {
-            Ok(resolved) => {
-                assert_eq!(1, resolved.len());
-
-                // User '#+' not found
-                let other = resolved.get("#+");
-                assert!(matches!(other, Some(Dated { data: None, .. })));
-            }
-            Err(err) => panic!("failed to resolve uuid: {}", err),
-        }
+        assert!(
+            matches!(result, Ok(Dated { timestamp, .. }) if timestamp == 1000 + 2 * exp.as_secs())
+        );
+        assert_eq!(3, service.mojang.requests());
+        assert!(
+            service
+                .cache
+                .try_lock("profile.09879557e47945a9b434a56377674627")
+                .await
+        );
     }
 
     #[tokio::test]
-    async fn get_uuids_partial_found() {
+    async fn lock_held_during_fetch() {
         // given
         let settings = Settings::default();
-        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let clock = Arc::new(ManualClock::new(1000));
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(
+            settings.cache.entries.clone(),
+            moka,
+            LockingCache::default(),
+        )
+        .with_clock(clock.clone());
         let mojang = MojangTestingApi::with_profiles();
+        let exp = settings.cache.entries.uuid.exp;
         let service = Service::new(Arc::new(settings), cache, mojang);
+        service.get_uuid("Hydrofin").await.unwrap();
+        clock.advance(exp);
 
         // when
-        let result = service
-            .get_uuids(&vec!["Hydrofin".to_string(), "xXSlayer42Xx".to_string()])
-            .await;
+        let locked = service.try_lock("uuid", "HYDROFIN").await;
+        let result = service.get_uuid("hydrofin").await;
+        let bulk = service.get_uuids(&["hydroFIN".to_string()]).await.unwrap();
 
         // then
-        match result {
-            Ok(resolved) => {
-                assert_eq!(2, resolved.len());
-
-                // User 'xXSlayer42Xx' not found
-                let other = resolved.get("xxslayer42xx");
-                assert!(matches!(other, Some(Dated { data: None, .. })));
-
-                // User 'Hydrofin' is found
-                let Some(hydrofin) = resolved.get("hydrofin") else {
-                    panic!("failed to resolve user 'Hydrofin'")
-                };
-                assert_eq!(
-                    hydrofin.data,
-                    Some(UuidData {
-                        username: "Hydrofin".to_string(),
-                        uuid: uuid!("09879557e47945a9b434a56377674627")
-                    }),
-                );
-            }
-            Err(err) => panic!("failed to resolve uuid: {}", err),
-        }
+        assert!(locked);
+        assert!(matches!(
+            result,
+            Ok(Dated {
+                timestamp: 1000,
+                ..
+            })
+        ));
+        assert_eq!(1000, bulk["hydrofin"].timestamp);
+        assert_eq!(1, service.mojang.requests());
     }
 
     #[tokio::test]
-    async fn get_uuids_partial_invalid() {
+    async fn bulk_lock_released_after_fetch() {
         // given
         let settings = Settings::default();
-        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let clock = Arc::new(ManualClock::new(1000));
+        let moka = MokaCache::new(settings.cache.moka.clone());
+        let cache = Cache::new(
+            settings.cache.entries.clone(),
+            moka,
+            LockingCache::default(),
+        )
+        .with_clock(clock.clone());
         let mojang = MojangTestingApi::with_profiles();
+        let exp = settings.cache.entries.uuid.exp;
         let service = Service::new(Arc::new(settings), cache, mojang);
+        service.get_uuids(&["Hydrofin".to_string()]).await.unwrap();
 
         // when
-        let result = service
-            .get_uuids(&vec!["Hydrofin".to_string(), "i<ia9".to_string()])
-            .await;
+        clock.advance(exp);
+        service.get_uuids(&["Hydrofin".to_string()]).await.unwrap();
+        let locked = service.try_lock("uuid", "hydrofin").await;
 
         // then
-        match result {
-            Ok(resolved) => {
-                assert_eq!(2, resolved.len());
+        assert!(locked);
+        assert_eq!(2, service.mojang.requests());
+    }
 
-                // User 'i<ia9' not found
-                let other = resolved.get("i<ia9");
-                assert!(matches!(other, Some(Dated { data: None, .. })));
+    #[tokio::test]
+    async fn circuit_breaker_opens() {
+        // given
+        let mut settings = Settings::default();
+        settings.circuit_breaker.enabled = true;
+        settings.circuit_breaker.threshold = 2;
+        let cache = Cache::new(settings.cache.entries.clone(), NoCache, NoCache);
+        let mojang = MojangTestingApi::with_profiles();
+        mojang.fail_next(2);
+        let service = Service::new(Arc::new(settings), cache, mojang);
 
-                // User 'Hydrofin' is found
-                let Some(hydrofin) = resolved.get("hydrofin") else {
-                    panic!("failed to resolve user 'Hydrofin'")
-                };
-                assert_eq!(
-                    hydrofin.data,
-                    Some(UuidData {
-                        username: "Hydrofin".to_string(),
-                        uuid: uuid!("09879557e47945a9b434a56377674627")
-                    }),
-                );
-            }
-            Err(err) => panic!("failed to resolve uuid: {}", err),
-        }
+        // when
+        let _ = service.get_uuid("Hydrofin").await;
+        let _ = service.get_uuid("Hydrofin").await;
+        let result = service.get_uuid("Hydrofin").await;
+
+        // then
+        assert!(matches!(result, Err(Unavailable)));
+        assert_eq!(2, service.mojang.requests());
+        assert_eq!(BreakerState::Open, service.breaker_state());
+        // the service stays healthy without remote cache
+        assert!(service.is_healthy().await);
     }
 }
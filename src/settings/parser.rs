@@ -1,6 +1,10 @@
+use ipnet::IpNet;
+use regex::Regex;
 use serde::de::{Error, Unexpected, Visitor};
-use serde::Deserializer;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
 use std::fmt;
+use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
 use std::time::Duration;
 use tracing::level_filters::LevelFilter;
@@ -87,3 +91,71 @@ where
 
     deserializer.deserialize_any(DurationVisitor)
 }
+
+/// Deserializer that parses a regular expression string to a [Regex]. E.g. `^[a-zA-Z0-9_]{2,16}$`.
+pub fn parse_regex<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    Regex::new(&value)
+        .map_err(|_| Error::invalid_value(Unexpected::Str(&value), &"a regular expression"))
+}
+
+/// Deserializer that parses a map of [iso8601] duration strings or numbers of seconds to [Durations](Duration).
+/// E.g. `{ uuid = "PT0.1S", head = 1 }`.
+pub fn parse_durations<'de, D>(deserializer: D) -> Result<HashMap<String, Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Parsed(#[serde(deserialize_with = "parse_duration")] Duration);
+
+    let durations = HashMap::<String, Parsed>::deserialize(deserializer)?;
+    Ok(durations
+        .into_iter()
+        .map(|(key, Parsed(duration))| (key, duration))
+        .collect())
+}
+
+/// Deserializer that parses a socket address or a non-empty list of socket addresses. E.g.
+/// `0.0.0.0:9990` or `["0.0.0.0:9990", "[::]:9990"]`.
+pub fn parse_addresses<'de, D>(deserializer: D) -> Result<Vec<SocketAddr>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Addresses {
+        One(SocketAddr),
+        Many(Vec<SocketAddr>),
+    }
+
+    match Addresses::deserialize(deserializer)? {
+        Addresses::One(address) => Ok(vec![address]),
+        Addresses::Many(addresses) if addresses.is_empty() => Err(Error::invalid_length(
+            0,
+            &"a socket address or a non-empty list of socket addresses",
+        )),
+        Addresses::Many(addresses) => Ok(addresses),
+    }
+}
+
+/// Deserializer that parses a list of ip networks (CIDR notation) or ip addresses to [IpNet]s. An
+/// ip address is a network of a single address. E.g. `["10.0.0.0/8", "192.168.1.1"]`.
+pub fn parse_networks<'de, D>(deserializer: D) -> Result<Vec<IpNet>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|value| {
+            let value = value.trim();
+            IpNet::from_str(value)
+                .or_else(|_| IpAddr::from_str(value).map(IpNet::from))
+                .map_err(|_| {
+                    Error::invalid_value(Unexpected::Str(value), &"an ip network or ip address")
+                })
+        })
+        .collect()
+}
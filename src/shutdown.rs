@@ -0,0 +1,255 @@
+//! The shutdown module tracks the in-flight requests of the rest and gRPC server, so that a summary can
+//! be reported on shutdown. Requests that complete after the shutdown signal are counted as drained,
+//! requests that are dropped (or still in flight once the servers stopped) are counted as aborted.
+//! The caches are written through, so there is nothing to flush on shutdown.
+
+use futures::future::BoxFuture;
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter, register_int_counter_vec, IntCounter, IntCounterVec};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tracing::{info, warn};
+
+lazy_static! {
+    /// A counter for the requests that were drained or aborted on shutdown.
+    static ref SHUTDOWN_REQUESTS_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "xenos_shutdown_requests_total",
+        "The requests that were drained or aborted on shutdown.",
+        &["result"]
+    )
+    .unwrap();
+
+    /// A counter for the background tasks that were cancelled on shutdown.
+    static ref SHUTDOWN_TASKS_COUNTER: IntCounter = register_int_counter!(
+        "xenos_shutdown_tasks_cancelled_total",
+        "The background tasks that were cancelled on shutdown."
+    )
+    .unwrap();
+}
+
+/// The [ShutdownReport] summarizes the requests and background tasks on shutdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// The requests that completed after the shutdown signal.
+    pub drained: u64,
+
+    /// The requests that were dropped or still in flight once the servers stopped.
+    pub aborted: u64,
+
+    /// The background tasks (e.g. refresh) that were still running and cancelled.
+    pub tasks_cancelled: u64,
+}
+
+/// The [Shutdown] state of a [Service](crate::service::Service). It tracks the in-flight requests
+/// of its servers and whether the shutdown signal was received.
+#[derive(Debug, Default)]
+pub struct Shutdown {
+    /// The number of requests that are currently in flight.
+    in_flight: AtomicU64,
+
+    /// Whether the shutdown signal was received.
+    shutting_down: AtomicBool,
+
+    /// The number of requests that completed after the shutdown signal.
+    drained: AtomicU64,
+
+    /// The number of requests that were dropped after the shutdown signal.
+    aborted: AtomicU64,
+}
+
+impl Shutdown {
+    /// Marks the start of the shutdown. All requests that complete afterward are counted as drained.
+    /// It should be called once the shutdown signal is received, later calls are ignored.
+    pub fn begin(&self) {
+        if !self.shutting_down.swap(true, Ordering::SeqCst) {
+            info!(
+                in_flight = self.in_flight.load(Ordering::SeqCst),
+                "shutting down, draining requests"
+            );
+        }
+    }
+
+    /// Builds the [ShutdownReport] once the servers stopped and exports it as metrics and log event.
+    pub fn report(&self, tasks_cancelled: u64) -> ShutdownReport {
+        let report = ShutdownReport {
+            drained: self.drained.load(Ordering::SeqCst),
+            aborted: self.aborted.load(Ordering::SeqCst) + self.in_flight.load(Ordering::SeqCst),
+            tasks_cancelled,
+        };
+        SHUTDOWN_REQUESTS_COUNTER
+            .with_label_values(&["drained"])
+            .inc_by(report.drained);
+        SHUTDOWN_REQUESTS_COUNTER
+            .with_label_values(&["aborted"])
+            .inc_by(report.aborted);
+        SHUTDOWN_TASKS_COUNTER.inc_by(report.tasks_cancelled);
+        info!(
+            drained = report.drained,
+            aborted = report.aborted,
+            tasks_cancelled = report.tasks_cancelled,
+            "shutdown report"
+        );
+        report
+    }
+}
+
+/// Waits for the shutdown signal, that is either ctrl-c (SIGINT) or SIGTERM on unix platforms.
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {},
+                    _ = terminate.recv() => {},
+                }
+            }
+            Err(err) => {
+                warn!(error = %err, "failed to register SIGTERM handler");
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// An [InFlight] request is counted until it is dropped. If it is dropped after the shutdown signal,
+/// it is counted as drained if it completed and as aborted otherwise.
+struct InFlight {
+    shutdown: Arc<Shutdown>,
+    completed: bool,
+}
+
+impl InFlight {
+    /// Starts counting a new in-flight request.
+    fn start(shutdown: Arc<Shutdown>) -> Self {
+        shutdown.in_flight.fetch_add(1, Ordering::SeqCst);
+        Self {
+            shutdown,
+            completed: false,
+        }
+    }
+
+    /// Marks the request as completed, so that it is counted as drained.
+    fn complete(&mut self) {
+        self.completed = true;
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.shutdown.in_flight.fetch_sub(1, Ordering::SeqCst);
+        if !self.shutdown.shutting_down.load(Ordering::SeqCst) {
+            return;
+        }
+        match self.completed {
+            true => self.shutdown.drained.fetch_add(1, Ordering::SeqCst),
+            false => self.shutdown.aborted.fetch_add(1, Ordering::SeqCst),
+        };
+    }
+}
+
+/// The [InFlightLayer] is a tower layer for the rest and gRPC server that tracks the in-flight
+/// requests for the [ShutdownReport].
+#[derive(Debug, Clone)]
+pub struct InFlightLayer {
+    shutdown: Arc<Shutdown>,
+}
+
+impl InFlightLayer {
+    /// Creates a new [InFlightLayer] that tracks the requests in the provided [Shutdown] state.
+    pub fn new(shutdown: Arc<Shutdown>) -> Self {
+        Self { shutdown }
+    }
+}
+
+impl<S> tower::Layer<S> for InFlightLayer {
+    type Service = InFlightService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        InFlightService {
+            inner,
+            shutdown: Arc::clone(&self.shutdown),
+        }
+    }
+}
+
+/// The [InFlightService] is the tower service of the [InFlightLayer].
+#[derive(Debug, Clone)]
+pub struct InFlightService<S> {
+    inner: S,
+    shutdown: Arc<Shutdown>,
+}
+
+impl<S, Req> tower::Service<Req> for InFlightService<S>
+where
+    S: tower::Service<Req>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Req) -> Self::Future {
+        // the request is in flight from now on, even if the future is never polled
+        let mut in_flight = InFlight::start(Arc::clone(&self.shutdown));
+        let future = self.inner.call(request);
+        Box::pin(async move {
+            let response = future.await;
+            in_flight.complete();
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts_drained_and_aborted() {
+        // given
+        let shutdown = Arc::new(Shutdown::default());
+        let mut drained = InFlight::start(Arc::clone(&shutdown));
+        let aborted = InFlight::start(Arc::clone(&shutdown));
+        let _in_flight = InFlight::start(Arc::clone(&shutdown));
+
+        // when
+        shutdown.begin();
+        drained.complete();
+        drop(drained);
+        drop(aborted);
+        let report = shutdown.report(2);
+
+        // then
+        assert_eq!(1, report.drained);
+        assert_eq!(2, report.aborted);
+        assert_eq!(2, report.tasks_cancelled);
+    }
+
+    #[test]
+    fn ignores_requests_before_shutdown() {
+        // given
+        let shutdown = Arc::new(Shutdown::default());
+        let mut completed = InFlight::start(Arc::clone(&shutdown));
+        let dropped = InFlight::start(Arc::clone(&shutdown));
+
+        // when
+        completed.complete();
+        drop(completed);
+        drop(dropped);
+        shutdown.begin();
+        let report = shutdown.report(0);
+
+        // then
+        assert_eq!(0, report.drained);
+        assert_eq!(0, report.aborted);
+    }
+}
@@ -0,0 +1,94 @@
+//! The slo module provides precomputed service level indicators (SLIs) of the profile requests. Each
+//! request is counted as total and, if it succeeded within the latency threshold of its request type,
+//! as good request. Burn rates of service level objectives can be computed from the ratio of both
+//! counters without histogram quantiles, e.g.
+//! `1 - rate(xenos_sli_good_requests_total[1h]) / rate(xenos_sli_requests_total[1h])`.
+
+use crate::settings;
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use std::sync::OnceLock;
+use tracing::info;
+
+lazy_static! {
+    /// A counter for all profile requests per request type.
+    static ref SLI_REQUESTS_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "xenos_sli_requests_total",
+        "The profile requests that are considered for the service level indicators.",
+        &["request_type"]
+    )
+    .unwrap();
+
+    /// A counter for the good profile requests (succeeded within the latency threshold) per request type.
+    static ref SLI_GOOD_REQUESTS_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "xenos_sli_good_requests_total",
+        "The profile requests that succeeded within the latency threshold of their request type.",
+        &["request_type"]
+    )
+    .unwrap();
+}
+
+/// The global service level indicator configuration. It is only set if the indicators are enabled.
+static OBJECTIVES: OnceLock<settings::Slo> = OnceLock::new();
+
+/// Initializes the service level indicators. It should only be called once on startup, later calls
+/// are ignored.
+pub fn init(settings: &settings::Slo) {
+    if OBJECTIVES.set(settings.clone()).is_ok() {
+        info!(
+            latency = settings.latency.as_secs_f64(),
+            "recording service level indicators"
+        );
+    }
+}
+
+/// Checks whether a request with a status (e.g. `ok`) and latency in seconds is good. Requests are
+/// good if they succeeded (or the resource was not found) within the latency threshold.
+fn is_good(settings: &settings::Slo, request_type: &str, status: &str, seconds: f64) -> bool {
+    matches!(status, "ok" | "not_found") && seconds <= settings.latency(request_type).as_secs_f64()
+}
+
+/// Records a request with a status (e.g. `ok`) and latency in seconds if the service level indicators
+/// are enabled.
+pub fn record(request_type: &str, status: &str, seconds: f64) {
+    let Some(settings) = OBJECTIVES.get() else {
+        return;
+    };
+    SLI_REQUESTS_COUNTER
+        .with_label_values(&[request_type])
+        .inc();
+    if is_good(settings, request_type, status, seconds) {
+        SLI_GOOD_REQUESTS_COUNTER
+            .with_label_values(&[request_type])
+            .inc();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    #[test]
+    fn good_requests() {
+        // given
+        let settings = settings::Slo {
+            enabled: true,
+            latency: Duration::from_millis(500),
+            latencies: HashMap::from([("uuid".to_string(), Duration::from_millis(100))]),
+        };
+
+        // when
+        let fast = is_good(&settings, "head", "ok", 0.4);
+        let slow = is_good(&settings, "uuid", "ok", 0.4);
+        let not_found = is_good(&settings, "uuid", "not_found", 0.05);
+        let unavailable = is_good(&settings, "head", "unavailable", 0.01);
+
+        // then
+        assert!(fast);
+        assert!(!slow);
+        assert!(not_found);
+        assert!(!unavailable);
+    }
+}
@@ -0,0 +1,119 @@
+//! The statsd module provides an alternative metrics sink for environments without prometheus (e.g.
+//! datadog). If enabled, the metrics handlers emit their events additionally as statsd timings to a
+//! (Dog)StatsD UDP endpoint.
+//!
+//! Plain statsd has no tags, so the tag values are appended to the metric name instead (e.g.
+//! `xenos.cache.get.moka.uuid.filled`). DogStatsD supports tags natively (e.g.
+//! `xenos.cache.get:0.2|ms|#cache_variant:moka,request_type:uuid,cache_result:filled`).
+
+use crate::settings;
+use std::io;
+use std::net::UdpSocket;
+use std::sync::OnceLock;
+use tracing::info;
+
+/// The global statsd sink. It is only set if the statsd sink is enabled.
+static SINK: OnceLock<StatsdSink> = OnceLock::new();
+
+/// The [StatsdSink] sends metrics over UDP to a (Dog)StatsD endpoint.
+#[derive(Debug)]
+struct StatsdSink {
+    socket: UdpSocket,
+    prefix: String,
+    dogstatsd: bool,
+}
+
+impl StatsdSink {
+    /// Formats a timing (in seconds) as statsd line.
+    fn format_timing(&self, metric: &str, tags: &[(&str, &str)], seconds: f64) -> String {
+        let millis = seconds * 1000.0;
+        if self.dogstatsd {
+            let tags: Vec<_> = tags.iter().map(|(k, v)| format!("{k}:{v}")).collect();
+            return match tags.is_empty() {
+                true => format!("{}.{metric}:{millis}|ms", self.prefix),
+                false => format!("{}.{metric}:{millis}|ms|#{}", self.prefix, tags.join(",")),
+            };
+        }
+        let mut name = format!("{}.{metric}", self.prefix);
+        for (_, value) in tags {
+            name.push('.');
+            name.push_str(value);
+        }
+        format!("{name}:{millis}|ms")
+    }
+}
+
+/// Initializes the global statsd sink. It should only be called once on startup, later calls are
+/// ignored.
+pub fn init(settings: &settings::Statsd) -> io::Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(&settings.address)?;
+    // metrics must never block the request handling, failed sends are dropped
+    socket.set_nonblocking(true)?;
+    let sink = StatsdSink {
+        socket,
+        prefix: settings.prefix.clone(),
+        dogstatsd: settings.dogstatsd,
+    };
+    if SINK.set(sink).is_ok() {
+        info!(
+            address = settings.address,
+            dogstatsd = settings.dogstatsd,
+            "sending metrics to statsd"
+        );
+    }
+    Ok(())
+}
+
+/// Emits a timing (in seconds) with tags to the statsd sink if enabled.
+pub fn timing(metric: &str, tags: &[(&str, &str)], seconds: f64) {
+    if let Some(sink) = SINK.get() {
+        let _ = sink
+            .socket
+            .send(sink.format_timing(metric, tags, seconds).as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sink(dogstatsd: bool) -> StatsdSink {
+        StatsdSink {
+            socket: UdpSocket::bind("127.0.0.1:0").unwrap(),
+            prefix: "xenos".to_string(),
+            dogstatsd,
+        }
+    }
+
+    #[test]
+    fn format_timing_statsd() {
+        // given
+        let sink = sink(false);
+
+        // when
+        let line = sink.format_timing("cache.get", &[("cache_variant", "moka")], 0.5);
+
+        // then
+        assert_eq!("xenos.cache.get.moka:500|ms", line);
+    }
+
+    #[test]
+    fn format_timing_dogstatsd() {
+        // given
+        let sink = sink(true);
+
+        // when
+        let line = sink.format_timing(
+            "cache.get",
+            &[("cache_variant", "moka"), ("request_type", "uuid")],
+            0.5,
+        );
+
+        // then
+        assert_eq!(
+            "xenos.cache.get:500|ms|#cache_variant:moka,request_type:uuid",
+            line
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! The tenant module provides the multi-tenancy of Xenos. A tenant (e.g. a network) is derived from
+//! the api key of a request and bound to the task that handles the request. The tenant is used to
+//! select the cache entry configurations (e.g. a shorter expiry), to enforce per-tenant quotas and to
+//! attribute the service metrics (`tenant` label). The cache entries are derived from mojang and are
+//! shared by all tenants, so that each profile is only fetched once.
+//!
+//! The cache keys are deliberately not isolated (prefixed) per tenant: the entries are public mojang
+//! data, so isolating them would only multiply the mojang requests (and rate limit) and the cache
+//! size by the number of tenants. Tenants that need fresher data configure a shorter expiry instead.
+//! Deployments that need fully separated caches run separate instances with their own redis
+//! `namespace`.
+//!
+//! Requests without a (configured) tenant use the shared `default` tenant. Background tasks (e.g.
+//! the refresh) are not bound to any tenant and therefore also use the `default` tenant.
+
+#[cfg(feature = "grpc-server")]
+use crate::settings::Settings;
+use crate::settings::Tenancy;
+#[cfg(feature = "grpc-server")]
+use crate::settings::Usage;
+#[cfg(feature = "grpc-server")]
+use crate::usage::ANONYMOUS_CLIENT;
+#[cfg(feature = "grpc-server")]
+use futures::future::BoxFuture;
+use std::future::Future;
+use std::sync::Arc;
+#[cfg(feature = "grpc-server")]
+use std::task::{Context, Poll};
+#[cfg(feature = "grpc-server")]
+use tonic::codegen::http;
+
+/// The tenant name that is used for requests without (configured) tenant.
+pub const DEFAULT_TENANT: &str = "default";
+
+tokio::task_local! {
+    /// The tenant of the request that is handled by the current task.
+    static TENANT: Arc<str>;
+}
+
+/// Runs a future (e.g. a request handler) for an (optional) tenant. Without tenant, the future uses
+/// the `default` tenant.
+pub async fn scope<F: Future>(tenant: Option<Arc<str>>, future: F) -> F::Output {
+    match tenant {
+        Some(tenant) => TENANT.scope(tenant, future).await,
+        None => future.await,
+    }
+}
+
+/// Gets the tenant of the request of the current task. It returns `None` for the `default` tenant.
+pub fn current() -> Option<Arc<str>> {
+    TENANT.try_with(Arc::clone).ok()
+}
+
+/// Gets the tenant of the request of the current task as metrics label.
+pub fn label() -> Arc<str> {
+    current().unwrap_or_else(|| Arc::from(DEFAULT_TENANT))
+}
+
+/// Resolves the tenant of a client (api key). It returns `None` if the multi-tenancy is disabled or
+/// the client belongs to no tenant.
+pub fn resolve(tenancy: &Tenancy, client: &str) -> Option<Arc<str>> {
+    tenancy.tenant(client).map(|(name, _)| Arc::from(name))
+}
+
+/// The [TenantLayer] is a tower layer for the gRPC server that binds the tenant of the api key to the
+/// request. It does nothing if the multi-tenancy is disabled.
+#[cfg(feature = "grpc-server")]
+#[derive(Debug, Clone)]
+pub struct TenantLayer {
+    tenancy: Arc<Tenancy>,
+    usage: Arc<Usage>,
+}
+
+#[cfg(feature = "grpc-server")]
+impl TenantLayer {
+    /// Creates a new [TenantLayer] from the application [Settings]. The api key is read from the
+    /// usage header and resolved to the client of its alias (if any).
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            tenancy: Arc::new(settings.tenancy.clone()),
+            usage: Arc::new(settings.usage.clone()),
+        }
+    }
+}
+
+#[cfg(feature = "grpc-server")]
+impl<S> tower::Layer<S> for TenantLayer {
+    type Service = TenantService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TenantService {
+            layer: self.clone(),
+            inner,
+        }
+    }
+}
+
+/// The [TenantService] is the tower service of the [TenantLayer].
+#[cfg(feature = "grpc-server")]
+#[derive(Debug, Clone)]
+pub struct TenantService<S> {
+    layer: TenantLayer,
+    inner: S,
+}
+
+#[cfg(feature = "grpc-server")]
+impl<S, B> tower::Service<http::Request<B>> for TenantService<S>
+where
+    S: tower::Service<http::Request<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let usage = &self.layer.usage;
+        let client = request
+            .headers()
+            .get(&usage.header)
+            .and_then(|value| value.to_str().ok())
+            .map_or(ANONYMOUS_CLIENT, |api_key| usage.client(api_key));
+        let tenant = resolve(&self.layer.tenancy, client);
+        Box::pin(scope(tenant, self.inner.call(request)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::settings::Tenant;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn scope_resolve() {
+        // given
+        let tenancy = Tenancy {
+            enabled: true,
+            tenants: HashMap::from([(
+                "network".to_string(),
+                Tenant {
+                    keys: vec!["key".to_string()],
+                    quota: Default::default(),
+                    cache_entries: Default::default(),
+                },
+            )]),
+            index: Default::default(),
+        };
+
+        // when
+        let tenant = resolve(&tenancy, "key");
+        let unknown = resolve(&tenancy, "other");
+        let inside = scope(tenant, async { label() }).await;
+        let outside = label();
+
+        // then
+        assert_eq!(None, unknown);
+        assert_eq!("network", &*inside);
+        assert_eq!(DEFAULT_TENANT, &*outside);
+    }
+}
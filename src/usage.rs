@@ -0,0 +1,191 @@
+//! The usage module provides the usage accounting of Xenos. Every request of the rest gateway and the
+//! grpc profile api is counted per client (identified by its api key) and quota window. The counters
+//! are stored in the [Cache](crate::cache::Cache), so that they are persisted to the remote cache (e.g.
+//! redis) and survive restarts.
+
+use crate::settings;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+/// The client name that is used for requests without api key.
+pub const ANONYMOUS_CLIENT: &str = "anonymous";
+
+/// The client prefix of the requests of a tenant, e.g. `tenant:my-network`.
+pub const TENANT_CLIENT_PREFIX: &str = "tenant:";
+
+/// Gets the client name that is used to count the requests of a tenant.
+pub fn tenant_client(tenant: &str) -> String {
+    format!("{TENANT_CLIENT_PREFIX}{tenant}")
+}
+
+/// A [UsagePeriod] is a period for which the requests of a client are counted and limited.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum UsagePeriod {
+    Daily,
+    Monthly,
+}
+
+impl UsagePeriod {
+    /// All [usage periods](UsagePeriod) that are tracked.
+    pub const ALL: [UsagePeriod; 2] = [UsagePeriod::Daily, UsagePeriod::Monthly];
+
+    /// Gets the quota window of the [UsagePeriod] that contains the timestamp (in seconds), e.g.
+    /// `daily.2024-08-13` or `monthly.2024-08`.
+    pub fn window(&self, timestamp: u64) -> String {
+        let (year, month, day) = civil_date(timestamp);
+        match self {
+            UsagePeriod::Daily => format!("daily.{year:04}-{month:02}-{day:02}"),
+            UsagePeriod::Monthly => format!("monthly.{year:04}-{month:02}"),
+        }
+    }
+
+    /// Gets the remaining time of the quota window that contains the timestamp (in seconds). A new
+    /// quota window starts afterward.
+    pub fn remaining(&self, timestamp: u64) -> Duration {
+        let end = match self {
+            UsagePeriod::Daily => (timestamp / 86400 + 1) * 86400,
+            UsagePeriod::Monthly => {
+                let (year, month, _) = civil_date(timestamp);
+                let (year, month) = match month {
+                    12 => (year + 1, 1),
+                    month => (year, month + 1),
+                };
+                days_from_civil(year, month, 1) * 86400
+            }
+        };
+        Duration::from_secs(end.saturating_sub(timestamp))
+    }
+
+    /// Gets the time-to-live of the counters of a quota window. The counters are kept for at least
+    /// the whole window.
+    pub fn ttl(&self) -> Duration {
+        match self {
+            UsagePeriod::Daily => Duration::from_secs(86400),
+            UsagePeriod::Monthly => Duration::from_secs(31 * 86400),
+        }
+    }
+
+    /// Gets the limit of the [UsagePeriod] from a [quota](settings::UsageQuota).
+    pub fn limit(&self, quota: &settings::UsageQuota) -> Option<u64> {
+        match self {
+            UsagePeriod::Daily => quota.daily,
+            UsagePeriod::Monthly => quota.monthly,
+        }
+    }
+}
+
+impl fmt::Display for UsagePeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UsagePeriod::Daily => write!(f, "daily"),
+            UsagePeriod::Monthly => write!(f, "monthly"),
+        }
+    }
+}
+
+/// A [UsageReport] is the current usage of a single client with its quota limits.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct UsageReport {
+    /// The client (api key) of the report.
+    pub client: String,
+
+    /// The tenant of the client (if any).
+    pub tenant: Option<String>,
+
+    /// The number of requests in the current day.
+    pub daily: u64,
+
+    /// The number of requests in the current month.
+    pub monthly: u64,
+
+    /// The daily request limit of the client (if any).
+    pub daily_limit: Option<u64>,
+
+    /// The monthly request limit of the client (if any).
+    pub monthly_limit: Option<u64>,
+}
+
+/// Converts a unix timestamp (in seconds) to its (UTC) civil date as year, month and day.
+fn civil_date(timestamp: u64) -> (i64, u32, u32) {
+    // see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = (timestamp / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Converts a (UTC) civil date to the number of days since the unix epoch.
+fn days_from_civil(year: i64, month: u32, day: u32) -> u64 {
+    // see http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (i64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    (era * 146097 + doe - 719468) as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn civil_date_epoch() {
+        // given
+        let timestamp = 0;
+
+        // when
+        let date = civil_date(timestamp);
+
+        // then
+        assert_eq!((1970, 1, 1), date);
+    }
+
+    #[test]
+    fn civil_date_leap_day() {
+        // given
+        let timestamp = 1709251199; // 2024-02-29T23:59:59Z
+
+        // when
+        let date = civil_date(timestamp);
+
+        // then
+        assert_eq!((2024, 2, 29), date);
+    }
+
+    #[test]
+    fn window_daily_and_monthly() {
+        // given
+        let timestamp = 1723550400; // 2024-08-13T12:00:00Z
+
+        // when
+        let daily = UsagePeriod::Daily.window(timestamp);
+        let monthly = UsagePeriod::Monthly.window(timestamp);
+
+        // then
+        assert_eq!("daily.2024-08-13", daily);
+        assert_eq!("monthly.2024-08", monthly);
+    }
+
+    #[test]
+    fn remaining_daily_and_monthly() {
+        // given
+        let timestamp = 1735646400; // 2024-12-31T12:00:00Z
+
+        // when
+        let daily = UsagePeriod::Daily.remaining(timestamp);
+        let monthly = UsagePeriod::Monthly.remaining(timestamp);
+
+        // then
+        assert_eq!(Duration::from_secs(43200), daily);
+        assert_eq!(Duration::from_secs(43200), monthly);
+    }
+}